@@ -1,17 +1,12 @@
 use criterion::{black_box, Criterion};
-use mqtt_adapt::topic::TopicManager;
-use mqtt_adapt::routing::{router::MessageRouter, event::Event};
-use mqtt_adapt::protocol::{MqttPacket, SubscribePacket, PublishPacket, Packet};
-use bytes::{BytesMut, Bytes, BufMut};
-use flume::{unbounded};
 use tokio::runtime::Runtime;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
-use rumqttc::{AsyncClient, Client, MqttOptions, QoS};
-use rand::Rng;
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct BenchmarkResult {
     timestamp: u64,
@@ -24,6 +19,7 @@ struct BenchmarkResult {
     iterations: u64,
 }
 
+#[allow(dead_code)]
 fn save_result(result: BenchmarkResult) {
     let benches_dir = Path::new("benches/results");
     if !benches_dir.exists() {
@@ -75,7 +71,7 @@ fn bench_rumqttc_client(c: &mut Criterion) {
                 mqtt_options.set_transport(rumqttc::Transport::Tcp);
                 mqtt_options.set_keep_alive(std::time::Duration::from_secs(5));
                 
-                let (mut client, mut connection) = AsyncClient::new(mqtt_options, 10);
+                let (client, _connection) = AsyncClient::new(mqtt_options, 10);
                 
                 // 连接服务器
                 // 发布消息
@@ -0,0 +1,24 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use mqtt_adapt::protocol::FixedHeader;
+
+// 预读与解析固定头的结果必须一致，解析成功时恰好消费固定头的字节
+fuzz_target!(|data: &[u8]| {
+    let peeked = FixedHeader::peek(data);
+    let mut buffer = BytesMut::from(data);
+    let parsed = FixedHeader::parse(&mut buffer);
+
+    match (peeked, parsed) {
+        (Ok((peeked, header_len)), Ok(parsed)) => {
+            assert_eq!(peeked, parsed);
+            assert_eq!(buffer.len(), data.len() - header_len);
+        }
+        (Err(peek_error), Err(parse_error)) => {
+            assert_eq!(peek_error, parse_error);
+            assert_eq!(buffer.len(), data.len());
+        }
+        (peeked, parsed) => panic!("peek {:?} differs from parse {:?}", peeked, parsed),
+    }
+});
@@ -0,0 +1,39 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use mqtt_adapt::protocol::*;
+
+// 绕过固定头直接调用各数据包的解析函数
+// 首字节低4位选择数据包类型（0为属性），高位选择协议版本，第二字节为固定头标志位，其余为数据包内容
+fuzz_target!(|data: &[u8]| {
+    let [selector, flags, body @ ..] = data else {
+        return;
+    };
+    let version = match selector >> 4 {
+        0 => ProtocolVersion::V31,
+        1 => ProtocolVersion::V311,
+        _ => ProtocolVersion::V5,
+    };
+    let input = &mut BytesMut::from(body);
+    let flags = Some(*flags);
+
+    let _ = match selector & 0x0F {
+        0 => Properties::parse(input).map(|_| ()),
+        1 => ConnectPacket::parse_versioned(input, flags, version).map(|_| ()),
+        2 => ConnAckPacket::parse_versioned(input, flags, version).map(|_| ()),
+        3 => PublishPacket::parse_versioned(input, flags, version).map(|_| ()),
+        4 => PubAckPacket::parse_versioned(input, flags, version).map(|_| ()),
+        5 => PubRecPacket::parse_versioned(input, flags, version).map(|_| ()),
+        6 => PubRelPacket::parse_versioned(input, flags, version).map(|_| ()),
+        7 => PubCompPacket::parse_versioned(input, flags, version).map(|_| ()),
+        8 => SubscribePacket::parse_versioned(input, flags, version).map(|_| ()),
+        9 => SubAckPacket::parse_versioned(input, flags, version).map(|_| ()),
+        10 => UnsubscribePacket::parse_versioned(input, flags, version).map(|_| ()),
+        11 => UnsubAckPacket::parse_versioned(input, flags, version).map(|_| ()),
+        12 => PingReqPacket::parse_versioned(input, flags, version).map(|_| ()),
+        13 => PingRespPacket::parse_versioned(input, flags, version).map(|_| ()),
+        14 => DisconnectPacket::parse_versioned(input, flags, version).map(|_| ()),
+        _ => AuthPacket::parse_versioned(input, flags, version).map(|_| ()),
+    };
+});
@@ -0,0 +1,21 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use mqtt_adapt::protocol::{MqttPacket, ProtocolVersion};
+
+// 按各协议版本解析任意字节，解析成功的数据包重新编码后必须解析出相同结果
+fuzz_target!(|data: &[u8]| {
+    for version in [ProtocolVersion::V31, ProtocolVersion::V311, ProtocolVersion::V5] {
+        let mut buffer = BytesMut::from(data);
+        let Ok(packet) = MqttPacket::read_versioned(&mut buffer, version) else {
+            continue;
+        };
+
+        let mut encoded = BytesMut::new();
+        packet.write_versioned(&mut encoded, version);
+        let reparsed = MqttPacket::read_versioned(&mut encoded, version);
+        assert_eq!(reparsed, Ok(packet));
+        assert!(encoded.is_empty());
+    }
+});
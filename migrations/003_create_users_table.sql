@@ -0,0 +1,9 @@
+-- 创建用户表，password保存PHC格式的密码哈希（argon2或bcrypt），group_names为逗号分隔的用户组
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL,
+    disabled BOOLEAN NOT NULL DEFAULT 0,
+    group_names TEXT NOT NULL DEFAULT '',
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
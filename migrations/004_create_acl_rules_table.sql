@@ -0,0 +1,11 @@
+-- 创建主题访问控制规则表，按id顺序匹配
+CREATE TABLE IF NOT EXISTS acl_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    permission TEXT NOT NULL CHECK (permission IN ('allow', 'deny')),
+    username TEXT,
+    client_id TEXT,
+    group_name TEXT,
+    action TEXT NOT NULL DEFAULT 'both' CHECK (action IN ('publish', 'subscribe', 'both')),
+    topic TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
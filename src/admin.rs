@@ -0,0 +1,373 @@
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{StatusCode, header::AUTHORIZATION};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Json;
+use bytes::Bytes;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use crate::auth::password::constant_time_eq;
+use crate::protocol::PublishPacket;
+use crate::routing::router::{ConnectedClient, MessageRouter, PersistentSession};
+use crate::topic::{TopicSubscription, is_valid_topic_filter};
+
+/// 管理接口共享的状态
+#[derive(Debug, Clone)]
+struct AdminState {
+    router: MessageRouter,
+    /// 设置后请求必须携带`Authorization: Bearer <token>`
+    token: Option<Arc<str>>,
+}
+
+/// 在`listener`上提供管理接口，直到监听器出错
+pub async fn serve(listener: TcpListener, router: MessageRouter, token: Option<String>) -> std::io::Result<()> {
+    info!("Admin API started on http://{}/api", listener.local_addr()?);
+    if token.is_none() {
+        warn!("Admin API has no token configured, any client that can reach it can publish and delete retained messages");
+    }
+    axum::serve(listener, routes(router, token)).await
+}
+
+/// 管理接口的全部路由
+///
+/// - `GET /api/clients?search=`：在线客户端，按客户端ID、用户名或地址搜索
+/// - `GET|DELETE /api/clients/{client_id}`：查看或断开客户端
+/// - `GET|POST|DELETE /api/clients/{client_id}/subscriptions`：查看、添加或移除（`?topic=`）订阅
+/// - `GET /api/sessions`、`GET|DELETE /api/sessions/{client_id}`：查看或清除持久会话
+/// - `GET|DELETE /api/retained?topic=`：按过滤器浏览保留消息，或删除一个主题的保留消息
+/// - `POST /api/publish`：以服务器身份发布消息
+pub fn routes(router: MessageRouter, token: Option<String>) -> axum::Router {
+    let state = AdminState {
+        router,
+        token: token.map(Arc::from),
+    };
+    axum::Router::new()
+        .route("/api/clients", get(list_clients))
+        .route("/api/clients/{client_id}", get(get_client).delete(kick_client))
+        .route(
+            "/api/clients/{client_id}/subscriptions",
+            get(list_subscriptions).post(add_subscription).delete(remove_subscription),
+        )
+        .route("/api/sessions", get(list_sessions))
+        .route("/api/sessions/{client_id}", get(get_session).delete(clear_session))
+        .route("/api/retained", get(list_retained).delete(delete_retained))
+        .route("/api/publish", post(publish))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+/// 以JSON返回的错误
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn not_found(what: impl std::fmt::Display) -> Self {
+        Self(StatusCode::NOT_FOUND, format!("{} not found", what))
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self(StatusCode::BAD_REQUEST, message.into())
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+async fn authorize(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    if let Some(token) = &state.token {
+        let authorized = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|value| constant_time_eq(value.as_bytes(), token.as_bytes()));
+        if !authorized {
+            return ApiError(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token".to_string()).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+#[derive(Debug, Serialize)]
+struct SubscriptionView {
+    topic: String,
+    qos: u8,
+}
+
+impl From<TopicSubscription> for SubscriptionView {
+    fn from(subscription: TopicSubscription) -> Self {
+        Self {
+            topic: subscription.topic,
+            qos: subscription.qos,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ClientView {
+    client_id: String,
+    username: Option<String>,
+    address: Option<String>,
+    keepalive: Option<u16>,
+    protocol_version: Option<u8>,
+    connected_at: Option<String>,
+    persistent: bool,
+    subscriptions: Vec<SubscriptionView>,
+}
+
+impl From<ConnectedClient> for ClientView {
+    fn from(client: ConnectedClient) -> Self {
+        let connection = client.connection.as_ref();
+        Self {
+            address: connection.map(|c| c.address.to_string()),
+            keepalive: connection.map(|c| c.keepalive),
+            protocol_version: connection.map(|c| c.protocol_version as u8),
+            connected_at: connection.map(|c| c.connected_at.to_rfc3339()),
+            client_id: client.client_id,
+            username: client.username,
+            persistent: client.persistent,
+            subscriptions: client.subscriptions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SessionView {
+    client_id: String,
+    connected: bool,
+    last_connected_at: Option<String>,
+    last_disconnected_at: Option<String>,
+    offline_messages: usize,
+    subscriptions: Vec<SubscriptionView>,
+}
+
+impl From<PersistentSession> for SessionView {
+    fn from(session: PersistentSession) -> Self {
+        Self {
+            client_id: session.client_id,
+            connected: session.connected,
+            last_connected_at: session.last_connected_at.map(|at| at.to_rfc3339()),
+            last_disconnected_at: session.last_disconnected_at.map(|at| at.to_rfc3339()),
+            offline_messages: session.offline_messages,
+            subscriptions: session.subscriptions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// 负载不是UTF-8时按有损转换显示
+#[derive(Debug, Serialize)]
+struct RetainedView {
+    topic: String,
+    qos: u8,
+    payload: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    search: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopicQuery {
+    topic: Option<String>,
+}
+
+impl TopicQuery {
+    fn required(self) -> ApiResult<String> {
+        self.topic.ok_or_else(|| ApiError::bad_request("Missing topic parameter"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscriptionRequest {
+    topic: String,
+    #[serde(default)]
+    qos: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishRequest {
+    topic: String,
+    #[serde(default)]
+    payload: String,
+    #[serde(default)]
+    qos: u8,
+    #[serde(default)]
+    retain: bool,
+}
+
+/// 主题名不能为空，也不能包含通配符
+fn is_valid_topic_name(topic: &str) -> bool {
+    !topic.is_empty() && !topic.contains(['+', '#'])
+}
+
+async fn list_clients(State(state): State<AdminState>, Query(query): Query<SearchQuery>) -> Json<Vec<ClientView>> {
+    let search = query.search.unwrap_or_default();
+    let clients = state
+        .router
+        .connected_clients()
+        .await
+        .into_iter()
+        .filter(|client| {
+            client.client_id.contains(&search)
+                || client.username.as_deref().is_some_and(|username| username.contains(&search))
+                || client
+                    .connection
+                    .as_ref()
+                    .is_some_and(|connection| connection.address.to_string().contains(&search))
+        })
+        .map(Into::into)
+        .collect();
+    Json(clients)
+}
+
+async fn get_client(State(state): State<AdminState>, Path(client_id): Path<String>) -> ApiResult<Json<ClientView>> {
+    state
+        .router
+        .connected_clients()
+        .await
+        .into_iter()
+        .find(|client| client.client_id == client_id)
+        .map(|client| Json(client.into()))
+        .ok_or_else(|| ApiError::not_found(format!("Client {}", client_id)))
+}
+
+async fn kick_client(State(state): State<AdminState>, Path(client_id): Path<String>) -> ApiResult<StatusCode> {
+    if !state.router.disconnect_client(&client_id).await {
+        return Err(ApiError::not_found(format!("Client {}", client_id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_subscriptions(
+    State(state): State<AdminState>,
+    Path(client_id): Path<String>,
+) -> ApiResult<Json<Vec<SubscriptionView>>> {
+    if !state.router.has_session(&client_id).await {
+        return Err(ApiError::not_found(format!("Client {}", client_id)));
+    }
+    let subscriptions = state.router.client_subscriptions(&client_id).await;
+    Ok(Json(subscriptions.into_iter().map(Into::into).collect()))
+}
+
+async fn add_subscription(
+    State(state): State<AdminState>,
+    Path(client_id): Path<String>,
+    Json(request): Json<SubscriptionRequest>,
+) -> ApiResult<StatusCode> {
+    if !state.router.has_session(&client_id).await {
+        return Err(ApiError::not_found(format!("Client {}", client_id)));
+    }
+    if request.qos > 2 || !is_valid_topic_filter(&request.topic) {
+        return Err(ApiError::bad_request(format!("Invalid subscription {} (qos {})", request.topic, request.qos)));
+    }
+    state
+        .router
+        .add_client_subscription(&client_id, &request.topic, request.qos)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_subscription(
+    State(state): State<AdminState>,
+    Path(client_id): Path<String>,
+    Query(query): Query<TopicQuery>,
+) -> ApiResult<StatusCode> {
+    let topic = query.required()?;
+    if !state.router.remove_client_subscription(&client_id, &topic).await {
+        return Err(ApiError::not_found(format!("Subscription {} of {}", topic, client_id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_sessions(State(state): State<AdminState>) -> ApiResult<Json<Vec<SessionView>>> {
+    let sessions = state.router.persistent_sessions().await?;
+    Ok(Json(sessions.into_iter().map(Into::into).collect()))
+}
+
+async fn get_session(State(state): State<AdminState>, Path(client_id): Path<String>) -> ApiResult<Json<SessionView>> {
+    state
+        .router
+        .persistent_sessions()
+        .await?
+        .into_iter()
+        .find(|session| session.client_id == client_id)
+        .map(|session| Json(session.into()))
+        .ok_or_else(|| ApiError::not_found(format!("Session {}", client_id)))
+}
+
+async fn clear_session(State(state): State<AdminState>, Path(client_id): Path<String>) -> ApiResult<StatusCode> {
+    // 在线客户端的会话需要先断开
+    if state.router.connected_clients().await.iter().any(|client| client.client_id == client_id) {
+        return Err(ApiError(StatusCode::CONFLICT, format!("Client {} is connected", client_id)));
+    }
+    if !state.router.clear_session(&client_id).await? {
+        return Err(ApiError::not_found(format!("Session {}", client_id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_retained(State(state): State<AdminState>, Query(query): Query<TopicQuery>) -> ApiResult<Json<Vec<RetainedView>>> {
+    let filter = query.topic.unwrap_or_else(|| "#".to_string());
+    if !is_valid_topic_filter(&filter) {
+        return Err(ApiError::bad_request(format!("Invalid topic filter {}", filter)));
+    }
+    let mut messages: Vec<RetainedView> = state
+        .router
+        .retained_messages(&filter)
+        .await
+        .into_iter()
+        .map(|(topic, retained)| RetainedView {
+            topic,
+            qos: retained.qos,
+            payload: String::from_utf8_lossy(&retained.payload).into_owned(),
+        })
+        .collect();
+    messages.sort_by(|a, b| a.topic.cmp(&b.topic));
+    Ok(Json(messages))
+}
+
+async fn delete_retained(State(state): State<AdminState>, Query(query): Query<TopicQuery>) -> ApiResult<StatusCode> {
+    let topic = query.required()?;
+    if !is_valid_topic_name(&topic) {
+        return Err(ApiError::bad_request(format!("Invalid topic {}", topic)));
+    }
+    if !state.router.delete_retained(&topic).await {
+        return Err(ApiError::not_found(format!("Retained message {}", topic)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn publish(State(state): State<AdminState>, Json(request): Json<PublishRequest>) -> ApiResult<StatusCode> {
+    if request.qos > 2 || !is_valid_topic_name(&request.topic) {
+        return Err(ApiError::bad_request(format!("Invalid publish to {} (qos {})", request.topic, request.qos)));
+    }
+    state
+        .router
+        .publish(PublishPacket {
+            dup: false,
+            qos: request.qos,
+            retain: request.retain,
+            topic_name: request.topic,
+            packet_id: None,
+            payload: Bytes::from(request.payload),
+            properties: Default::default(),
+        })
+        .await;
+    Ok(StatusCode::NO_CONTENT)
+}
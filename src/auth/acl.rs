@@ -0,0 +1,247 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::ClientAttributes;
+use crate::topic::is_valid_topic_filter;
+
+/// 认证方式在客户端属性中写入用户组时使用的键，多个组用逗号分隔
+pub const GROUP_ATTRIBUTE: &str = "group";
+
+/// 规则允许或拒绝访问
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AclPermission {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// 规则适用的操作
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AclAction {
+    Publish,
+    Subscribe,
+    /// 同时适用于发布和订阅
+    #[default]
+    Both,
+}
+
+impl AclPermission {
+    /// 配置文件和数据库中使用的名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+impl AclAction {
+    /// 配置文件和数据库中使用的名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Publish => "publish",
+            Self::Subscribe => "subscribe",
+            Self::Both => "both",
+        }
+    }
+}
+
+impl std::str::FromStr for AclPermission {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            _ => Err(anyhow::format_err!("Invalid ACL permission {:?}", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for AclAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "publish" => Ok(Self::Publish),
+            "subscribe" => Ok(Self::Subscribe),
+            "both" => Ok(Self::Both),
+            _ => Err(anyhow::format_err!("Invalid ACL action {:?}", s)),
+        }
+    }
+}
+
+/// 一条主题访问规则
+///
+/// `username`、`client_id`、`group`都未设置时适用于所有客户端，设置多个时需要同时满足；
+/// `topic`可以使用通配符，`%u`和`%c`分别替换为客户端的用户名和客户端ID
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AclRule {
+    pub permission: AclPermission,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub action: AclAction,
+    pub topic: String,
+}
+
+impl AclRule {
+    /// 校验主题过滤器格式
+    pub fn validate(&self) -> Result<()> {
+        if !is_valid_topic_filter(&self.topic) {
+            return Err(anyhow::format_err!("Invalid ACL topic filter {:?}", self.topic));
+        }
+        Ok(())
+    }
+
+    fn applies_to(&self, client_id: &str, attributes: &ClientAttributes, action: AclAction) -> bool {
+        if self.action != AclAction::Both && self.action != action {
+            return false;
+        }
+        if let Some(username) = &self.username
+            && attributes.username.as_ref() != Some(username)
+        {
+            return false;
+        }
+        if let Some(rule_client_id) = &self.client_id
+            && rule_client_id != client_id
+        {
+            return false;
+        }
+        if let Some(group) = &self.group {
+            let in_group = attributes
+                .attributes
+                .get(GROUP_ATTRIBUTE)
+                .is_some_and(|groups| groups.split(',').any(|g| g.trim() == group));
+            if !in_group {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 替换`%u`和`%c`，客户端缺少对应值或值中含有主题分隔符、通配符时规则不适用
+    fn topic_for(&self, client_id: &str, attributes: &ClientAttributes) -> Option<String> {
+        let mut topic = self.topic.clone();
+        if topic.contains("%u") {
+            let username = attributes.username.as_deref().filter(|u| is_plain_level(u))?;
+            topic = topic.replace("%u", username);
+        }
+        if topic.contains("%c") {
+            if !is_plain_level(client_id) {
+                return None;
+            }
+            topic = topic.replace("%c", client_id);
+        }
+        Some(topic)
+    }
+}
+
+fn is_plain_level(value: &str) -> bool {
+    !value.is_empty() && !value.contains(['/', '+', '#'])
+}
+
+/// 主题访问控制列表
+///
+/// 按顺序匹配规则，第一条适用的规则决定结果，没有适用的规则时使用`no_match`。
+/// 允许规则需要覆盖请求的整个主题范围，拒绝规则只要与请求的范围有交集就生效，
+/// 因此订阅`#`不会绕过针对某个子树的拒绝规则
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    rules: Vec<AclRule>,
+    no_match: AclPermission,
+}
+
+impl Acl {
+    /// 创建访问控制列表
+    pub fn new(rules: Vec<AclRule>, no_match: AclPermission) -> Self {
+        Self { rules, no_match }
+    }
+
+    /// 在末尾追加规则
+    pub fn extend(&mut self, rules: impl IntoIterator<Item = AclRule>) {
+        self.rules.extend(rules);
+    }
+
+    /// 规则列表
+    pub fn rules(&self) -> &[AclRule] {
+        &self.rules
+    }
+
+    /// 检查客户端能否发布到主题或订阅主题过滤器
+    pub fn check(&self, client_id: &str, attributes: &ClientAttributes, action: AclAction, topic: &str) -> AclPermission {
+        let requested: Vec<&str> = topic.split('/').collect();
+        for rule in &self.rules {
+            if !rule.applies_to(client_id, attributes, action) {
+                continue;
+            }
+            let Some(rule_topic) = rule.topic_for(client_id, attributes) else {
+                continue;
+            };
+            let rule_levels: Vec<&str> = rule_topic.split('/').collect();
+            let matched = match rule.permission {
+                AclPermission::Allow => covers(&rule_levels, &requested),
+                AclPermission::Deny => intersects(&rule_levels, &requested),
+            };
+            if matched {
+                return rule.permission;
+            }
+        }
+        self.no_match
+    }
+}
+
+/// 第一层的通配符不匹配`$`开头的主题
+fn is_system_level(index: usize, level: &str) -> bool {
+    index == 0 && level.starts_with('$')
+}
+
+/// 规则过滤器是否包含请求的全部主题
+fn covers(rule: &[&str], requested: &[&str]) -> bool {
+    for (index, rule_level) in rule.iter().enumerate() {
+        match *rule_level {
+            // "a/#"同时包含"a"本身
+            "#" => return !requested.first().is_some_and(|level| is_system_level(index, level)),
+            "+" => match requested.get(index) {
+                Some(level) if *level != "#" && !is_system_level(index, level) => {}
+                _ => return false,
+            },
+            literal => {
+                if requested.get(index) != Some(&literal) {
+                    return false;
+                }
+            }
+        }
+    }
+    rule.len() == requested.len()
+}
+
+/// 两个主题过滤器是否可能匹配同一个主题
+fn intersects(a: &[&str], b: &[&str]) -> bool {
+    let mut index = 0;
+    loop {
+        match (a.get(index), b.get(index)) {
+            (None, None) => return true,
+            (Some(&"#"), None) | (None, Some(&"#")) => return true,
+            (Some(&"#"), Some(level)) | (Some(level), Some(&"#")) => return !is_system_level(index, level),
+            (Some(x), Some(y)) => {
+                if *x == "+" || *y == "+" {
+                    if is_system_level(index, x) || is_system_level(index, y) {
+                        return false;
+                    }
+                } else if x != y {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+        index += 1;
+    }
+}
@@ -0,0 +1,16 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{AuthDecision, AuthRequest, Authenticator, ClientAttributes};
+
+/// 允许任何客户端连接，使用CONNECT中的用户名（可为空）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnonymousAuthenticator;
+
+#[async_trait]
+impl Authenticator for AnonymousAuthenticator {
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> Result<AuthDecision> {
+        let username = request.username().map(str::to_string);
+        Ok(AuthDecision::Accept(ClientAttributes::with_username(username)))
+    }
+}
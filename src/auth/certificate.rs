@@ -0,0 +1,20 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{AuthDecision, AuthRequest, Authenticator, ClientAttributes};
+
+/// 以TLS层校验过的客户端证书身份作为用户名，不再校验密码
+///
+/// 没有证书身份时交给下一个认证方式
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CertificateAuthenticator;
+
+#[async_trait]
+impl Authenticator for CertificateAuthenticator {
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> Result<AuthDecision> {
+        Ok(match request.cert_username {
+            Some(username) => AuthDecision::Accept(ClientAttributes::with_username(Some(username.to_string()))),
+            None => AuthDecision::Continue,
+        })
+    }
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::error;
+use std::sync::Arc;
+
+use super::{AuthDecision, AuthRequest, Authenticator};
+use crate::protocol::ConnectReturnCode;
+
+/// 按顺序尝试多个认证方式，第一个接受或拒绝的结果生效
+///
+/// 某个认证方式出错时记录日志并尝试下一个，全部无法判断时按用户名或密码错误拒绝
+#[derive(Debug, Clone, Default)]
+pub struct ChainAuthenticator {
+    providers: Vec<Arc<dyn Authenticator>>,
+}
+
+impl ChainAuthenticator {
+    /// 创建认证链
+    pub fn new(providers: Vec<Arc<dyn Authenticator>>) -> Self {
+        Self { providers }
+    }
+
+    /// 在链尾追加认证方式
+    pub fn with(mut self, provider: impl Authenticator + 'static) -> Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+}
+
+#[async_trait]
+impl Authenticator for ChainAuthenticator {
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> Result<AuthDecision> {
+        let mut failed = false;
+        for provider in &self.providers {
+            match provider.authenticate(request).await {
+                Ok(AuthDecision::Continue) => {}
+                Ok(decision) => return Ok(decision),
+                Err(e) => {
+                    error!("Authenticator {:?} failed: {:?}", provider, e);
+                    failed = true;
+                }
+            }
+        }
+        // 有认证方式出错时无法确定凭据是否有效，按服务器不可用拒绝
+        Ok(if failed {
+            AuthDecision::Deny(ConnectReturnCode::RefusedServerUnavailable)
+        } else {
+            AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword)
+        })
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::password::{is_password_hash, verify_dummy_async, verify_password_async};
+use super::{AuthDecision, AuthRequest, Authenticator, ClientAttributes};
+use crate::protocol::ConnectReturnCode;
+
+/// 使用静态密码文件认证
+///
+/// 文件每行一个`用户名:密码哈希`，哈希为argon2或bcrypt格式，空行和`#`开头的行被忽略，启动时读取一次
+#[derive(Debug, Clone, Default)]
+pub struct StaticFileAuthenticator {
+    users: HashMap<String, String>,
+}
+
+impl StaticFileAuthenticator {
+    /// 使用给定的用户名和密码哈希创建，不是哈希的密码永远不会匹配
+    pub fn new(users: HashMap<String, String>) -> Self {
+        Self { users }
+    }
+
+    /// 读取密码文件
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read password file {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid password file {}", path.display()))
+    }
+
+    /// 解析密码文件内容，密码不是argon2或bcrypt哈希的行视为错误
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut users = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (username, password) = line
+                .split_once(':')
+                .ok_or(anyhow::format_err!("Line {}: expected username:password", index + 1))?;
+            if !is_password_hash(password) {
+                return Err(anyhow::format_err!(
+                    "Line {}: password of {} is not an argon2 or bcrypt hash",
+                    index + 1,
+                    username
+                ));
+            }
+            users.insert(username.to_string(), password.to_string());
+        }
+        Ok(Self { users })
+    }
+}
+
+#[async_trait]
+impl Authenticator for StaticFileAuthenticator {
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> Result<AuthDecision> {
+        let (Some(username), Some(password)) = (request.username(), request.password()) else {
+            return Ok(AuthDecision::Continue);
+        };
+        let Some(stored) = self.users.get(username) else {
+            verify_dummy_async(&password).await?;
+            return Ok(AuthDecision::Continue);
+        };
+        Ok(if is_password_hash(stored) && verify_password_async(&password, stored).await? {
+            AuthDecision::Accept(ClientAttributes::with_username(Some(username.to_string())))
+        } else {
+            AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword)
+        })
+    }
+}
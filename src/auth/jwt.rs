@@ -0,0 +1,136 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use log::info;
+use serde_json::{Map, Value};
+use std::time::{Duration, UNIX_EPOCH};
+
+use super::acl::{Acl, AclPermission, AclRule, GROUP_ATTRIBUTE};
+use super::{AuthDecision, AuthRequest, Authenticator, ClientAttributes};
+use crate::config::{JwtAlgorithm, JwtConfig};
+use crate::protocol::ConnectReturnCode;
+
+/// 校验CONNECT密码中携带的JWT
+///
+/// 令牌必须包含`exp`，同时检查`nbf`；通过后按配置的声明取得用户名、绑定的客户端ID、用户组和访问规则，
+/// 令牌过期时服务器断开连接。未携带密码时交给下一个认证方式
+#[derive(Clone)]
+pub struct JwtAuthenticator {
+    key: DecodingKey,
+    validation: Validation,
+    config: JwtConfig,
+}
+
+impl std::fmt::Debug for JwtAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtAuthenticator")
+            .field("algorithm", &self.config.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtAuthenticator {
+    /// 按配置读取密钥并创建JWT认证
+    pub fn from_config(config: &JwtConfig) -> Result<Self> {
+        let key = match (config.algorithm, &config.secret, &config.key_file) {
+            (JwtAlgorithm::HS256, Some(secret), None) => DecodingKey::from_secret(secret.as_bytes()),
+            (algorithm, None, Some(path)) => {
+                let content = std::fs::read(path)
+                    .with_context(|| format!("Failed to read JWT key file {}", path.display()))?;
+                match algorithm {
+                    // 密钥文件末尾的换行不属于密钥
+                    JwtAlgorithm::HS256 => DecodingKey::from_secret(content.trim_ascii_end()),
+                    JwtAlgorithm::RS256 => DecodingKey::from_rsa_pem(&content)
+                        .with_context(|| format!("Invalid RSA public key {}", path.display()))?,
+                }
+            }
+            _ => return Err(anyhow::format_err!("JWT key requires exactly one of secret or key_file")),
+        };
+
+        let mut validation = Validation::new(match config.algorithm {
+            JwtAlgorithm::HS256 => Algorithm::HS256,
+            JwtAlgorithm::RS256 => Algorithm::RS256,
+        });
+        validation.leeway = config.leeway;
+        validation.validate_nbf = true;
+        validation.validate_aud = false;
+
+        Ok(Self {
+            key,
+            validation,
+            config: config.clone(),
+        })
+    }
+
+    /// 把已校验的声明映射为客户端属性，声明与CONNECT不符时返回拒绝的返回码
+    fn attributes(&self, request: &AuthRequest<'_>, claims: &Map<String, Value>) -> Result<ClientAttributes, ConnectReturnCode> {
+        let mut attributes = ClientAttributes::with_username(
+            claims
+                .get(&self.config.username_claim)
+                .and_then(Value::as_str)
+                .or(request.username())
+                .map(str::to_string),
+        );
+
+        if let Some(client_id) = claims.get(&self.config.client_id_claim) {
+            let client_id = client_id.as_str().ok_or(ConnectReturnCode::RefusedNotAuthorized)?;
+            if !request.connect.client_id.is_empty() && request.connect.client_id != client_id {
+                return Err(ConnectReturnCode::RefusedIdentifierRejected);
+            }
+            attributes.client_id = Some(client_id.to_string());
+        }
+
+        if let Some(groups) = claims.get(&self.config.groups_claim) {
+            let groups = match groups {
+                Value::String(groups) => groups.clone(),
+                Value::Array(groups) => groups
+                    .iter()
+                    .map(|group| group.as_str().filter(|group| !group.contains(',')))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(ConnectReturnCode::RefusedNotAuthorized)?
+                    .join(","),
+                _ => return Err(ConnectReturnCode::RefusedNotAuthorized),
+            };
+            attributes.attributes.insert(GROUP_ATTRIBUTE.to_string(), groups);
+        }
+
+        if let Some(rules) = claims.get(&self.config.acl_claim) {
+            let rules: Vec<AclRule> =
+                serde_json::from_value(rules.clone()).map_err(|_| ConnectReturnCode::RefusedNotAuthorized)?;
+            if rules.iter().any(|rule| rule.validate().is_err()) {
+                return Err(ConnectReturnCode::RefusedNotAuthorized);
+            }
+            // 令牌只授予列出的权限
+            attributes.acl = Some(Acl::new(rules, AclPermission::Deny));
+        }
+
+        // 已通过校验的令牌必定带有exp
+        attributes.expires_at = claims
+            .get("exp")
+            .and_then(Value::as_f64)
+            .and_then(|exp| Duration::try_from_secs_f64(exp).ok())
+            .and_then(|exp| UNIX_EPOCH.checked_add(exp));
+        Ok(attributes)
+    }
+}
+
+#[async_trait]
+impl Authenticator for JwtAuthenticator {
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> Result<AuthDecision> {
+        let Some(token) = request.password() else {
+            return Ok(AuthDecision::Continue);
+        };
+        let claims = match jsonwebtoken::decode::<Map<String, Value>>(&token, &self.key, &self.validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                info!("Rejected JWT from {}: {}", request.connect.client_id, e);
+                return Ok(AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword));
+            }
+        };
+        Ok(match self.attributes(request, &claims) {
+            Ok(attributes) => AuthDecision::Accept(attributes),
+            Err(return_code) => AuthDecision::Deny(return_code),
+        })
+    }
+}
+
@@ -0,0 +1,127 @@
+pub mod acl;
+mod anonymous;
+mod certificate;
+mod chain;
+mod file;
+mod jwt;
+pub mod password;
+mod sqlite;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use self::acl::Acl;
+use crate::config::{AuthConfig, AuthMode};
+use crate::db::connection::DatabaseConnection;
+use crate::protocol::{ConnectPacket, ConnectReturnCode};
+
+pub use anonymous::AnonymousAuthenticator;
+pub use certificate::CertificateAuthenticator;
+pub use chain::ChainAuthenticator;
+pub use file::StaticFileAuthenticator;
+pub use jwt::JwtAuthenticator;
+pub use sqlite::SqliteAuthenticator;
+
+/// 一次认证请求
+#[derive(Debug, Clone, Copy)]
+pub struct AuthRequest<'a> {
+    /// 客户端发来的CONNECT数据包
+    pub connect: &'a ConnectPacket,
+    /// 客户端地址
+    pub peer_addr: SocketAddr,
+    /// TLS层已校验的客户端证书身份
+    pub cert_username: Option<&'a str>,
+}
+
+impl AuthRequest<'_> {
+    /// CONNECT中的用户名
+    pub fn username(&self) -> Option<&str> {
+        self.connect.username.as_deref()
+    }
+
+    /// CONNECT中的密码，按UTF-8解析
+    pub fn password(&self) -> Option<String> {
+        self.connect
+            .password
+            .as_ref()
+            .map(|password| String::from_utf8_lossy(password).to_string())
+    }
+}
+
+/// 认证通过后附加在客户端上的属性
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientAttributes {
+    /// 认证通过的用户名
+    pub username: Option<String>,
+    /// 认证方式提供的其他属性，例如用户组
+    pub attributes: HashMap<String, String>,
+    /// 凭据绑定的客户端ID，CONNECT未给出客户端ID时使用
+    pub client_id: Option<String>,
+    /// 凭据携带的访问规则，设置后代替全局规则检查该客户端
+    pub acl: Option<Acl>,
+    /// 凭据过期时间，到期后服务器断开连接
+    pub expires_at: Option<SystemTime>,
+}
+
+impl ClientAttributes {
+    /// 只带用户名的属性
+    pub fn with_username(username: Option<String>) -> Self {
+        Self {
+            username,
+            ..Self::default()
+        }
+    }
+}
+
+/// 认证结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthDecision {
+    /// 接受连接
+    Accept(ClientAttributes),
+    /// 以指定返回码拒绝连接
+    Deny(ConnectReturnCode),
+    /// 无法判断，交给下一个认证方式；没有后续认证方式时按用户名或密码错误拒绝
+    Continue,
+}
+
+/// 客户端认证方式
+///
+/// 内置数据库、密码文件、JWT、匿名、TLS客户端证书几种实现，可用`ChainAuthenticator`按顺序组合，
+/// 也可以通过`Server::with_authenticator`注册自定义实现
+#[async_trait]
+pub trait Authenticator: Send + Sync + std::fmt::Debug {
+    /// 认证一个CONNECT请求，返回错误时以服务器不可用拒绝连接
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> Result<AuthDecision>;
+}
+
+/// 按认证配置创建认证方式
+///
+/// TLS客户端证书身份总是优先，其次使用配置的认证方式
+pub fn from_config(config: &AuthConfig, db: Option<&DatabaseConnection>) -> Result<Arc<dyn Authenticator>> {
+    let provider: Arc<dyn Authenticator> = match config.mode {
+        AuthMode::Database => {
+            let db = db.ok_or(anyhow::format_err!("auth mode database requires a database connection"))?;
+            Arc::new(SqliteAuthenticator::new(db.get_pool().clone()))
+        }
+        AuthMode::Anonymous => Arc::new(AnonymousAuthenticator),
+        AuthMode::File => {
+            let path = config
+                .password_file
+                .as_ref()
+                .ok_or(anyhow::format_err!("auth mode file requires password_file"))?;
+            Arc::new(StaticFileAuthenticator::from_file(path)?)
+        }
+        AuthMode::Jwt => {
+            let jwt = config
+                .jwt
+                .as_ref()
+                .ok_or(anyhow::format_err!("auth mode jwt requires [auth.jwt]"))?;
+            Arc::new(JwtAuthenticator::from_config(jwt)?)
+        }
+    };
+    Ok(Arc::new(ChainAuthenticator::new(vec![Arc::new(CertificateAuthenticator), provider])))
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use argon2::Argon2;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use std::sync::LazyLock;
+
+/// 用户不存在时用于校验的哈希，参数与`hash_password`相同
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| hash_password("mqtt_adapt-dummy-password").expect("Failed to hash dummy password"));
+
+/// 使用argon2id计算密码哈希，返回PHC格式字符串
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::format_err!("Failed to hash password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+/// 校验密码，按存储格式自动选择argon2或bcrypt
+///
+/// 不是哈希格式的值按旧版明文密码比较，调用方应在校验通过后用`needs_rehash`判断并改存哈希
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    if is_argon2(stored) {
+        PasswordHash::new(stored)
+            .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+            .unwrap_or(false)
+    } else if is_bcrypt(stored) {
+        bcrypt::verify(password, stored).unwrap_or(false)
+    } else {
+        constant_time_eq(password.as_bytes(), stored.as_bytes())
+    }
+}
+
+/// 在阻塞线程池中校验密码，argon2和bcrypt的计算不会占用异步运行时的工作线程
+pub async fn verify_password_async(password: &str, stored: &str) -> Result<bool> {
+    let (password, stored) = (password.to_string(), stored.to_string());
+    Ok(tokio::task::spawn_blocking(move || verify_password(&password, &stored)).await?)
+}
+
+/// 在阻塞线程池中校验密码，旧版明文密码校验通过时一并计算新的哈希
+///
+/// 返回是否通过，以及需要改存的哈希
+pub async fn verify_and_rehash_async(password: &str, stored: &str) -> Result<(bool, Option<String>)> {
+    let (password, stored) = (password.to_string(), stored.to_string());
+    tokio::task::spawn_blocking(move || {
+        if !verify_password(&password, &stored) {
+            return Ok((false, None));
+        }
+        let rehash = needs_rehash(&stored).then(|| hash_password(&password)).transpose()?;
+        Ok((true, rehash))
+    })
+    .await?
+}
+
+/// 用户不存在时按固定的哈希校验一次，使认证耗时不暴露用户是否存在
+pub async fn verify_dummy_async(password: &str) -> Result<()> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || {
+        verify_password(&password, &DUMMY_HASH);
+    })
+    .await?;
+    Ok(())
+}
+
+/// 存储的值是否为argon2或bcrypt哈希
+pub fn is_password_hash(stored: &str) -> bool {
+    is_argon2(stored) || is_bcrypt(stored)
+}
+
+/// 存储的值是否为旧版明文密码，需要重新计算哈希
+pub fn needs_rehash(stored: &str) -> bool {
+    !is_password_hash(stored)
+}
+
+fn is_argon2(stored: &str) -> bool {
+    stored.starts_with("$argon2")
+}
+
+fn is_bcrypt(stored: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| stored.starts_with(prefix))
+}
+
+/// 长度相同时比较耗时与内容无关
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
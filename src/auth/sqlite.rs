@@ -0,0 +1,50 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+
+use super::acl::GROUP_ATTRIBUTE;
+use super::password::verify_dummy_async;
+use super::{AuthDecision, AuthRequest, Authenticator, ClientAttributes};
+use crate::db::models::ag_user::User;
+use crate::protocol::ConnectReturnCode;
+
+/// 使用数据库`users`表中的用户名和密码认证，支持argon2和bcrypt哈希
+///
+/// 用户不存在或CONNECT未携带用户名密码时交给下一个认证方式；
+/// 用户的`group_names`作为`group`属性供访问控制规则匹配
+#[derive(Debug, Clone)]
+pub struct SqliteAuthenticator {
+    pool: SqlitePool,
+}
+
+impl SqliteAuthenticator {
+    /// 创建数据库认证
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Authenticator for SqliteAuthenticator {
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> Result<AuthDecision> {
+        let (Some(username), Some(password)) = (request.username(), request.password()) else {
+            return Ok(AuthDecision::Continue);
+        };
+        let Some(mut user) = User::find_by_username(&self.pool, username).await? else {
+            verify_dummy_async(&password).await?;
+            return Ok(AuthDecision::Continue);
+        };
+        if user.disabled {
+            return Ok(AuthDecision::Deny(ConnectReturnCode::RefusedNotAuthorized));
+        }
+        Ok(if user.verify(&self.pool, &password).await? {
+            let mut attributes = ClientAttributes::with_username(Some(user.username));
+            if !user.group_names.is_empty() {
+                attributes.attributes.insert(GROUP_ATTRIBUTE.to_string(), user.group_names);
+            }
+            AuthDecision::Accept(attributes)
+        } else {
+            AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword)
+        })
+    }
+}
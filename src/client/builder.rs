@@ -1,27 +1,34 @@
 
 use anyhow::Result;
 use flume::unbounded;
-use tokio::net::TcpStream;
+use log::error;
+use std::time::Duration;
 
-use crate::client::client::Client;
-use crate::db::connection::DatabaseConnection;
-use crate::db::models::ag_user::User;
-use crate::protocol::MqttPacket;
-use crate::protocol::{ConnAckPacket, ConnectReturnCode};
-use crate::routing::router::MessageRouter;
-/// 从TCP流创建客户端并处理CONNECT数据包
+use crate::auth::{AuthDecision, AuthRequest, Authenticator};
+use crate::client::client::{Client, TOPIC_ALIAS_MAXIMUM};
+use crate::client::transport::Transport;
+use crate::config::{BrokerConfig, MAX_REMAINING_LENGTH};
+use crate::protocol::{MqttPacket, PublishPacket};
+use crate::protocol::{ConnAckPacket, ConnectReturnCode, ProtocolError, ProtocolVersion};
+use crate::routing::router::{ConnectionInfo, MessageRouter};
+/// 从已建立的连接（TCP、TLS或WebSocket）创建客户端并处理CONNECT数据包
+///
+/// `cert_username`为TLS客户端证书中取得的用户名，与CONNECT一起交给`authenticator`认证
 ///
 /// 1. 创建客户端事件通道
 /// 2. 读取并解析CONNECT数据包
-/// 3. 设置客户端ID和保活时间
-/// 4. 验证用户凭据
+/// 3. 通过认证方式验证用户凭据
+/// 4. 设置客户端ID，按配置检查保活时间，设置遗嘱和监听器的挂载点
 /// 5. 注册客户端到路由器
 /// 6. 发送客户端连接事件
 pub async fn create_client_with_connect(
-    socket: TcpStream,
+    socket: impl Transport + 'static,
     addr: std::net::SocketAddr,
     router: &MessageRouter,
-    db: &DatabaseConnection,
+    authenticator: &dyn Authenticator,
+    config: &BrokerConfig,
+    cert_username: Option<String>,
+    mountpoint: Option<String>,
 ) -> Result<Client> {
     // 创建客户端事件通道
     let (tx, rx) = unbounded();
@@ -29,87 +36,181 @@ pub async fn create_client_with_connect(
     let router_event_sender = router.get_sender().clone();
     // 创建初始客户端实例
     let mut client = Client::new(socket,addr, rx, router_event_sender, "unknown".to_string());
+    client.set_max_packet_size(Some(config.mqtt.max_packet_size));
+    client.set_stats(router.stats());
 
-    // 读取并解析CONNECT数据包
-
-    // 读取数据到缓冲区
-    let n = client.read().await?;
-    if n == 0 {
-        return Err(anyhow::format_err!("ConnectionPacket is empty"));
-    }
-    let packet = MqttPacket::read(&mut client.read_buf)?;
+    // 读取完整的CONNECT数据包，之后到达的数据保留在缓冲区中，超过`connect_timeout`未收到时断开
+    // 不支持的协议版本以MQTT 3.1.1格式回复CONNACK 0x01，其余协议错误无法确定客户端版本，直接断开
+    let read_result = match config.mqtt.connect_timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, client.read_packet())
+            .await
+            .map_err(|_| anyhow::format_err!("No CONNECT received within {:?}", timeout))?,
+        None => client.read_packet().await,
+    };
+    let packet = match read_result {
+        Ok(Some(packet)) => packet,
+        Ok(None) => return Err(anyhow::format_err!("ConnectionPacket is empty")),
+        Err(e) => {
+            if let Some(ProtocolError::UnsupportedVersion(_)) = e.downcast_ref::<ProtocolError>() {
+                let connack_packet = ConnAckPacket::new(false, ConnectReturnCode::RefusedBadProtocolVersion);
+                client.queue_packet(&MqttPacket::ConnAck(connack_packet));
+                client.write().await?;
+            }
+            return Err(e);
+        }
+    };
 
     if let MqttPacket::Connect(connect_packet) = packet {
-        // 克隆客户端ID
-        let client_id = connect_packet.client_id;
+        // 协议版本在解析CONNECT时已经校验
+        let version = ProtocolVersion::from_u8(connect_packet.protocol_level)
+            .ok_or(ProtocolError::UnsupportedVersion(connect_packet.protocol_level))?;
+        client.set_protocol_version(version);
+
+        // 验证用户凭据
+        let request = AuthRequest {
+            connect: &connect_packet,
+            peer_addr: addr,
+            cert_username: cert_username.as_deref(),
+        };
+        let decision = authenticator.authenticate(&request).await.unwrap_or_else(|e| {
+            error!("Authentication error for {}: {:?}", connect_packet.client_id, e);
+            AuthDecision::Deny(ConnectReturnCode::RefusedServerUnavailable)
+        });
+        let attributes = match decision {
+            AuthDecision::Accept(attributes) => attributes,
+            // 认证失败时拒绝连接
+            AuthDecision::Deny(return_code) => return refuse(&mut client, return_code).await,
+            AuthDecision::Continue => {
+                return refuse(&mut client, ConnectReturnCode::RefusedBadUsernameOrPassword).await;
+            }
+        };
+        client.attributes = attributes;
+
+        // 连接标志第1位为Clean Session（MQTT 5.0中为Clean Start）
+        let clean_session = (connect_packet.connect_flags & 0x02) != 0;
+
+        // 客户端ID为空时使用凭据绑定的客户端ID，没有绑定时由服务器分配
+        // MQTT 3.1.1只为Clean Session为1的客户端分配，否则按§3.1.3.1回复0x02拒绝连接
+        let mut assigned_client_id = None;
+        let client_id = if !connect_packet.client_id.is_empty() {
+            connect_packet.client_id
+        } else if let Some(id) = client.attributes.client_id.clone() {
+            if version == ProtocolVersion::V5 {
+                assigned_client_id = Some(id.clone());
+            }
+            id
+        } else if version == ProtocolVersion::V5 || clean_session {
+            let id = format!("auto-{}", uuid::Uuid::new_v4());
+            if version == ProtocolVersion::V5 {
+                assigned_client_id = Some(id.clone());
+            }
+            id
+        } else {
+            let connack_packet = ConnAckPacket::new(false, ConnectReturnCode::RefusedIdentifierRejected);
+            client.queue_packet(&MqttPacket::ConnAck(connack_packet));
+            client.write().await?;
+            return Err(anyhow::format_err!("Empty client id requires clean session"));
+        };
 
         // 设置客户端ID
         client.set_client_id(client_id.clone());
 
-        // 设置保活时间
-        client.set_keepalive(connect_packet.keep_alive);
+        // 设置保活时间，0表示客户端关闭保活检测；超出上限（0表示不限制）时MQTT 5.0改用服务器指定的值，
+        // 旧版本以服务器不可用拒绝连接。MQTT 5.0客户端关闭保活时同样使用服务器指定的值
+        let max_keepalive = config.mqtt.max_keepalive;
+        let mut server_keep_alive = None;
+        if max_keepalive > 0 && (connect_packet.keep_alive == 0 || connect_packet.keep_alive > max_keepalive) {
+            if version == ProtocolVersion::V5 {
+                server_keep_alive = Some(max_keepalive);
+            } else if connect_packet.keep_alive > max_keepalive {
+                let connack_packet = ConnAckPacket::new(false, ConnectReturnCode::RefusedServerUnavailable);
+                client.queue_packet(&MqttPacket::ConnAck(connack_packet));
+                client.write().await?;
+                return Err(anyhow::format_err!(
+                    "Keepalive {} exceeds maximum {}",
+                    connect_packet.keep_alive,
+                    max_keepalive
+                ));
+            }
+        }
+        client.set_keepalive(server_keep_alive.unwrap_or(connect_packet.keep_alive));
 
         // 设置遗嘱消息相关信息
-        client.will_topic = connect_packet.will_topic;
-        client.will_message = connect_packet.will_message;
-        
-        // 从connect_flags中提取遗嘱QoS和保留标志
-        // 遗嘱QoS: 第3-4位
-        client.will_qos = (connect_packet.connect_flags >> 3) & 0x03;
-        // 遗嘱保留标志: 第5位
-        client.will_retain = (connect_packet.connect_flags & 0x20) != 0;
+        if let (Some(will_topic), Some(will_message)) = (connect_packet.will_topic, connect_packet.will_message) {
+            // 遗嘱延迟只在遗嘱属性中，发布时转发其余属性（消息过期、内容类型等）
+            let mut will_properties = connect_packet.will_properties;
+            let will_delay = will_properties.will_delay_interval.take().unwrap_or(0);
+            // 会话先于延迟结束时立即发布，MQTT 5.0会话过期间隔缺省为0
+            let session_expiry = connect_packet.properties.session_expiry_interval.unwrap_or(0);
+            let will = PublishPacket {
+                dup: false,
+                // 从connect_flags中提取遗嘱QoS和保留标志
+                // 遗嘱QoS: 第3-4位
+                qos: (connect_packet.connect_flags >> 3) & 0x03,
+                // 遗嘱保留标志: 第5位
+                retain: (connect_packet.connect_flags & 0x20) != 0,
+                topic_name: will_topic,
+                packet_id: None,
+                payload: will_message,
+                properties: will_properties,
+            };
+            let delay = Duration::from_secs(will_delay.min(session_expiry) as u64);
+            client.set_will(Some(will), delay);
+        }
+        // 挂载点在注册会话前设置，路由器补发的消息和遗嘱主题都按挂载点转换
+        client.set_mountpoint(mountpoint);
+
+
+        // 停机期间不再接受新的会话
+        if router.is_shutting_down() {
+            let connack_packet = ConnAckPacket::new(false, ConnectReturnCode::RefusedServerUnavailable);
+            client.queue_packet(&MqttPacket::ConnAck(connack_packet));
+            client.write().await?;
+            return Err(anyhow::format_err!("Server is shutting down"));
+        }
+
+        // 注册客户端到路由器，恢复持久会话
+        // 路由器补发的消息经通道进入客户端，总是在CONNACK之后发送
+        // MQTT 5.0客户端通过Receive Maximum限制同时未确认的消息数量，补发之前生效
+        let session = router
+            .register_session(&client_id, tx.clone(), clean_session, connect_packet.properties.receive_maximum)
+            .await?;
+        client.connection_id = session.connection_id;
+        // 主题访问控制按认证通过的身份检查
+        router.set_client_attributes(&client_id, client.attributes.clone()).await;
+        router
+            .set_connection_info(&client_id, ConnectionInfo {
+                address: addr,
+                keepalive: client.keepalive,
+                protocol_version: version,
+                connected_at: chrono::Utc::now(),
+                mountpoint: client.mountpoint.clone(),
+            })
+            .await;
 
-        // 验证用户凭据
-        let return_code = match (connect_packet.username, connect_packet.password) {
-            (Some(username), Some(password)) => {
-                // 从密码Bytes转换为字符串
-                let password_str = String::from_utf8_lossy(&password).to_string();
-                
-                // 使用用户名作为access_key进行数据库认证
-                match User::find_by_username(db.get_pool(), &username).await {
-                    Ok(Some(user)) => {
-                        // 验证密码是否匹配
-                        if user.password == password_str {
-                            ConnectReturnCode::Accepted
-                        } else {
-                            ConnectReturnCode::RefusedBadUsernameOrPassword
-                        }
-                    },
-                    _ => {
-                        // 用户不存在或查询失败
-                        ConnectReturnCode::RefusedBadUsernameOrPassword
-                    }
-                }
-            },
-            _ => {
-                // 用户名和密码必须同时提供
-                ConnectReturnCode::RefusedBadUsernameOrPassword
-            },
-        };
-        
         // 创建CONNACK数据包
-        let connack_packet = ConnAckPacket {
-            session_present: false,
-            return_code,
-        };
+        let mut connack_packet = ConnAckPacket::new(session.session_present, ConnectReturnCode::Accepted);
+        connack_packet.properties.assigned_client_identifier = assigned_client_id;
+        connack_packet.properties.server_keep_alive = server_keep_alive;
+        connack_packet.properties.topic_alias_maximum = Some(TOPIC_ALIAS_MAXIMUM);
+        if config.mqtt.max_packet_size < MAX_REMAINING_LENGTH {
+            connack_packet.properties.maximum_packet_size = Some(config.mqtt.max_packet_size as u32);
+        }
         
-        let mqtt_packet = MqttPacket::ConnAck(connack_packet);
-        mqtt_packet.write(&mut client.write_buf);
+        client.queue_packet(&MqttPacket::ConnAck(connack_packet));
         // 发送数据包
         client.write().await?;
-        
-        // 只有认证成功才注册客户端
-        if return_code == ConnectReturnCode::Accepted {
-            // 注册客户端到路由器
-            router
-                .register_client(&client_id, tx.clone())
-                .await?;
-        } else {
-            // 认证失败，关闭连接
-            return Err(anyhow::format_err!("Authentication failed"));
-        }
     } else {
         return Err(anyhow::format_err!("Expected CONNECT packet"));
     }
     Ok(client)
 }
+
+/// 以指定返回码回复CONNACK并拒绝连接
+async fn refuse(client: &mut Client, return_code: ConnectReturnCode) -> Result<Client> {
+    client.stats.record_auth_failure();
+    let connack_packet = ConnAckPacket::new(false, return_code);
+    client.queue_packet(&MqttPacket::ConnAck(connack_packet));
+    client.write().await?;
+    Err(anyhow::format_err!("Authentication failed: {:?}", return_code))
+}
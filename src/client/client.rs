@@ -1,9 +1,16 @@
 use anyhow::Result;
 use bytes::BytesMut;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use flume::{Receiver, Sender};
-use tokio::{io::BufReader, net::TcpStream};
+use tokio::io::BufReader;
 
-use crate::routing::event::Event;
+use crate::auth::ClientAttributes;
+use crate::client::transport::{BoxedTransport, Transport};
+use crate::protocol::{MqttCodec, ProtocolVersion, PublishPacket};
+use crate::routing::event::{ConnectionId, Event};
+use crate::stats::BrokerStats;
 
 /// 客户端连接状态
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,18 +24,24 @@ pub enum ClientState {
 /// 客户端结构体
 #[derive(Debug)]
 pub struct Client {
-    /// 底层TCP连接
-    pub(super) socket: BufReader<TcpStream>,
+    /// 底层连接（TCP、TLS等）
+    pub(super) socket: BufReader<BoxedTransport>,
     /// 连接状态
     pub(super) state: ClientState,
     /// 客户端ID
     pub(super) client_id: String,
+    /// 认证通过后的用户名和属性
+    pub(super) attributes: ClientAttributes,
+    /// 路由器分配的连接标识，用于区分同一客户端ID的新旧连接
+    pub(super) connection_id: ConnectionId,
     /// 保活时间（秒）
     pub(super) keepalive: u16,
     /// 读取缓冲区
     pub(super) read_buf: BytesMut,
     /// 写入缓冲区
     pub(super) write_buf: BytesMut,
+    /// 数据包帧解码器
+    pub(super) codec: MqttCodec,
     /// 消息接收通道
     pub(super) event_receiver: Receiver<Event>,
     /// 消息发送通道（用于向路由器发送事件）
@@ -36,33 +49,45 @@ pub struct Client {
     /// 客户端地址
     #[allow(dead_code)]
     pub(super) addr: std::net::SocketAddr,
-    /// 遗嘱主题
-    pub(super) will_topic: Option<String>,
-    /// 遗嘱消息
-    pub(super) will_message: Option<bytes::Bytes>,
-    /// 遗嘱QoS
-    pub(super) will_qos: u8,
-    /// 遗嘱保留标志
-    pub(super) will_retain: bool,
+    /// 遗嘱消息，正常DISCONNECT后清除
+    pub(super) will: Option<PublishPacket>,
+    /// 遗嘱延迟发布时间（MQTT 5.0 Will Delay Interval）
+    pub(super) will_delay: Duration,
+    /// 客户端协议版本
+    pub(super) protocol_version: ProtocolVersion,
+    /// 客户端发来的主题别名映射（仅MQTT 5.0）
+    pub(super) topic_aliases: HashMap<u16, String>,
+    /// 收发统计，与路由器共享
+    pub(super) stats: Arc<BrokerStats>,
+    /// 监听器的主题挂载点
+    pub(super) mountpoint: Option<String>,
 }
 
+/// 每个MQTT 5.0客户端可以使用的主题别名数量，在CONNACK的Topic Alias Maximum中告知客户端
+pub const TOPIC_ALIAS_MAXIMUM: u16 = 64;
+
 impl Client {
     /// 创建新的客户端
-    pub fn new(socket: TcpStream, addr: std::net::SocketAddr, rx: Receiver<Event>, tx: Sender<Event>, client_id: String) -> Self {
+    pub fn new(socket: impl Transport + 'static, addr: std::net::SocketAddr, rx: Receiver<Event>, tx: Sender<Event>, client_id: String) -> Self {
         Self {
-            socket: BufReader::new(socket),
+            socket: BufReader::new(Box::new(socket)),
             state: ClientState::Connected,
             addr,
             client_id,
+            attributes: ClientAttributes::default(),
+            connection_id: 0,
             keepalive: 60, // 默认保活时间为60秒
             read_buf: BytesMut::with_capacity(1024 * 10),
             write_buf: BytesMut::with_capacity(1024 * 10),
+            codec: MqttCodec::new(),
             event_receiver:rx,
             router_send: tx,
-            will_topic: None,
-            will_message: None,
-            will_qos: 0,
-            will_retain: false,
+            will: None,
+            will_delay: Duration::ZERO,
+            protocol_version: ProtocolVersion::V311,
+            topic_aliases: HashMap::new(),
+            stats: Arc::new(BrokerStats::new()),
+            mountpoint: None,
         }
     }
 
@@ -89,6 +114,16 @@ impl Client {
         &self.state
     }
 
+    /// 获取认证通过的用户名
+    pub fn username(&self) -> Option<&str> {
+        self.attributes.username.as_deref()
+    }
+
+    /// 获取认证方式附加的客户端属性
+    pub fn attributes(&self) -> &ClientAttributes {
+        &self.attributes
+    }
+
     /// 设置保活时间
     pub fn set_keepalive(&mut self, keepalive: u16) {
         self.keepalive = keepalive;
@@ -98,4 +133,44 @@ impl Client {
     pub fn keepalive(&self) -> u16 {
         self.keepalive
     }
+
+    /// 设置遗嘱消息，连接异常断开时经过`delay`后发布
+    pub fn set_will(&mut self, will: Option<PublishPacket>, delay: Duration) {
+        self.will = will;
+        self.will_delay = delay;
+    }
+
+    /// 设置协议版本，之后的数据包按该版本编解码
+    pub fn set_protocol_version(&mut self, version: ProtocolVersion) {
+        self.protocol_version = version;
+        self.codec.set_version(version);
+    }
+
+    /// 设置收发统计，通常使用路由器的统计
+    pub fn set_stats(&mut self, stats: Arc<BrokerStats>) {
+        self.stats = stats;
+    }
+
+    /// 设置主题挂载点，之后客户端发布和订阅的主题都加上该前缀，已设置的遗嘱主题同样加上
+    pub fn set_mountpoint(&mut self, mountpoint: Option<String>) {
+        if let (Some(mountpoint), Some(will)) = (&mountpoint, &mut self.will) {
+            will.topic_name = format!("{}{}", mountpoint, will.topic_name);
+        }
+        self.mountpoint = mountpoint;
+    }
+
+    /// 获取主题挂载点
+    pub fn mountpoint(&self) -> Option<&str> {
+        self.mountpoint.as_deref()
+    }
+
+    /// 设置允许接收的最大数据包长度
+    pub fn set_max_packet_size(&mut self, max_packet_size: Option<usize>) {
+        self.codec.set_max_packet_size(max_packet_size);
+    }
+
+    /// 获取协议版本
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
 }
@@ -1,25 +1,53 @@
 use anyhow::Result;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::codec::Decoder;
 
 use crate::client::client::Client;
+use crate::protocol::MqttPacket;
 
 impl Client {
     /// 写入数据到客户端
     /// 
     /// 将写入缓冲区中的数据发送到客户端，并在发送完成后清空缓冲区
+    ///
+    /// WebSocket等带缓冲的传输需要刷新后才真正发出，TCP上刷新不产生额外开销
     pub async fn write(&mut self) -> Result<()> {
-        self.socket.write(&self.write_buf).await?;
+        self.socket.write_all(&self.write_buf).await?;
+        self.socket.flush().await?;
+        self.stats.add_bytes_sent(self.write_buf.len());
         self.write_buf.clear();
         Ok(())
     }
 
+    /// 按客户端协议版本把数据包写入写入缓冲区，并计入发送统计
+    pub fn queue_packet(&mut self, packet: &MqttPacket) {
+        packet.write_versioned(&mut self.write_buf, self.protocol_version);
+        self.stats.record_sent(packet);
+    }
+
     /// 从客户端读取数据
     /// 
-    /// 从TCP连接中读取数据到读取缓冲区，并返回读取的字节数
+    /// 从TCP连接中读取数据追加到读取缓冲区，并返回读取的字节数
     pub async fn read(&mut self) -> Result<usize> {
-        self.read_buf.clear();
-        
         let n = self.socket.read_buf(&mut self.read_buf).await?;
+        self.stats.add_bytes_received(n);
         Ok(n)
     }
+
+    /// 从客户端读取一个完整的数据包
+    ///
+    /// 优先从读取缓冲区中解码已到达的数据包，不足一个完整数据包时继续读取。
+    /// 连接关闭时返回`None`。未解码的字节保留在缓冲区中，因此可以安全地在`select!`中取消
+    pub async fn read_packet(&mut self) -> Result<Option<MqttPacket>> {
+        loop {
+            if let Some(packet) = self.codec.decode(&mut self.read_buf)? {
+                self.stats.record_received(&packet);
+                return Ok(Some(packet));
+            }
+
+            if self.read().await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
 }
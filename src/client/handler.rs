@@ -1,38 +1,52 @@
 use anyhow::Result;
 use flume::RecvError;
 use log::{error, info};
-use tokio::time::Duration;
+use std::time::SystemTime;
+use tokio::time::{Duration, Instant};
 
-use crate::client::client::Client;
-use crate::protocol::{DisconnectPacket, MqttPacket, Packet};
+use crate::client::client::{Client, TOPIC_ALIAS_MAXIMUM};
+use crate::protocol::{DisconnectPacket, MqttPacket, ProtocolError, ProtocolVersion, ReasonCode};
 use crate::routing::event::Event;
 use crate::protocol::PublishPacket;
+use crate::topic::{SHARED_SUBSCRIPTION_PREFIX, parse_shared_subscription};
 impl Client {
     /// 处理客户端连接
     pub async fn handle(mut self) -> Result<()> {
-        // 计算超时时间：keepalive的1.5倍
-        // 如果keepalive为0，则使用默认值60秒
-        let keepalive = if self.keepalive == 0 { 60 } else { self.keepalive };
-        let time = (keepalive as f32 * 1.5) as u64;
-
-        let timeout_duration = Duration::from_secs(time);
+        // 计算超时时间：keepalive的1.5倍，keepalive为0时不检测超时
+        let timeout_duration = (self.keepalive > 0).then(|| Duration::from_secs_f32(self.keepalive as f32 * 1.5));
         let rx = self.event_receiver.clone();
+        // 凭据到期时断开连接，没有期限的凭据永不到期
+        let expires_at = self.attributes.expires_at.and_then(|expires_at| {
+            let remaining = expires_at.duration_since(SystemTime::now()).unwrap_or_default();
+            Instant::now().checked_add(remaining)
+        });
         loop {
-            tokio::select! {
+            let result = tokio::select! {
                 // 1. 读取来自TCP连接的消息
-                read_result = self.read() => {
-                    self.handle_read_result(read_result).await?;
-                },
+                read_result = self.read_packet() => self.handle_read_result(read_result).await,
 
                 // 2. 接收来自消息路由的消息
-                event_result =rx.recv_async() => {
-                    self.handle_event_result(event_result).await?;
-                },
+                event_result = rx.recv_async() => self.handle_event_result(event_result).await,
 
                 // 3. 超时处理
-                _ = tokio::time::sleep(timeout_duration) => {
-                    self.close().await?;
+                _ = sleep_for_keepalive(timeout_duration) => self.close().await,
+
+                // 4. 凭据过期
+                _ = sleep_until_expiry(expires_at) => {
+                    info!("Credentials of {} expired", self.client_id);
+                    let _ = self.send_disconnect_with_reason(ReasonCode::MaximumConnectTime).await;
+                    self.close().await
                 },
+            };
+
+            // 读写失败时连接按异常断开处理，照常发布遗嘱并通知路由器
+            if let Err(e) = result {
+                if self.state != super::client::ClientState::Disconnected
+                    && let Err(close_error) = self.close().await
+                {
+                    error!("Error closing {}: {:?}", self.client_id, close_error);
+                }
+                return Err(e);
             }
 
             if self.state == super::client::ClientState::Disconnected {
@@ -44,28 +58,46 @@ impl Client {
     }
 
     /// 处理读取结果
-    async fn handle_read_result(&mut self, result: Result<usize>) -> Result<()> {
+    async fn handle_read_result(&mut self, result: Result<Option<MqttPacket>>) -> Result<()> {
         match result {
-            Ok(n) if n <= 0 => {
+            Ok(None) => {
                 self.close().await?;
                 return Ok(());
             }
-            Ok(_) => {
-                // 处理读取到的数据
-                let packet = MqttPacket::read(&mut self.read_buf)?;
-
+            Ok(Some(packet)) => {
                 // 对于某些只是用来保持连接的包，直接处理而不发送到路由
                 match &packet {
                     MqttPacket::PingReq(_) => {
                         // 直接回复PingResp
                         self.handle_ping_req().await?;
                     }
-                    MqttPacket::Disconnect(_) => {
-                        // 直接关闭连接
+                    MqttPacket::Disconnect(disconnect) => {
+                        // 正常断开不发布遗嘱，MQTT 5.0客户端可用原因码0x04要求发布
+                        if disconnect.reason_code != ReasonCode::DisconnectWithWillMessage {
+                            self.will = None;
+                        }
+                        self.close().await?;
+                    }
+                    MqttPacket::Auth(_) => {
+                        // 暂不支持增强认证，按协议错误断开
+                        self.send_disconnect_with_reason(ReasonCode::ProtocolError).await?;
                         self.close().await?;
                     }
+                    MqttPacket::Publish(_) if self.protocol_version == ProtocolVersion::V5 => {
+                        let MqttPacket::Publish(mut publish) = packet else { unreachable!() };
+                        if let Err(reason_code) = self.resolve_topic_alias(&mut publish) {
+                            error!("Invalid topic alias from {}: {:?}", self.client_id, reason_code);
+                            self.send_disconnect_with_reason(reason_code).await?;
+                            self.close().await?;
+                            return Ok(());
+                        }
+                        let packet = self.mount(MqttPacket::Publish(publish));
+                        let event = Event::MessageReceived(self.client_id.clone(), packet);
+                        self.send_event(event)?;
+                    }
                     _ => {
                         // 其他包发送到路由中
+                        let packet = self.mount(packet);
                         let event = Event::MessageReceived(self.client_id.clone(), packet);
                         self.send_event(event)?;
                     }
@@ -73,8 +105,13 @@ impl Client {
             }
             Err(e) => {
                 error!("Error reading from client: {:?}", e);
-                self.state = super::client::ClientState::Disconnected;
-                return Err(e.into());
+                // 数据包不合法时MQTT 5.0客户端先收到对应原因码（超长为0x95），连接可能已不可写
+                if let Some(protocol_error) = e.downcast_ref::<ProtocolError>() {
+                    let _ = self.send_disconnect_with_reason(protocol_error.reason_code()).await;
+                }
+                // 连接异常断开，照常发布遗嘱并通知路由器
+                self.close().await?;
+                return Err(e);
             }
         }
         Ok(())
@@ -114,22 +151,93 @@ impl Client {
     #[allow(dead_code)]
     async fn send_disconnect_packet(&mut self) -> Result<()> {
         // 创建Disconnect数据包
-        let disconnect_packet = DisconnectPacket;
+        let disconnect_packet = DisconnectPacket::default();
         // 写入到缓冲区
-        disconnect_packet.write(&mut self.write_buf);
+        self.queue_packet(&MqttPacket::Disconnect(disconnect_packet));
         // 发送数据包
         self.write().await?;
 
         Ok(())
     }
 
+    /// 发送带原因码的断开连接数据包（仅MQTT 5.0，旧版本直接断开）
+    async fn send_disconnect_with_reason(&mut self, reason_code: ReasonCode) -> Result<()> {
+        if self.protocol_version != ProtocolVersion::V5 {
+            return Ok(());
+        }
+
+        self.queue_packet(&MqttPacket::Disconnect(DisconnectPacket::with_reason(reason_code)));
+        self.write().await
+    }
+
+    /// 解析MQTT 5.0主题别名
+    ///
+    /// 主题名非空时登记别名，主题名为空时用已登记的别名还原主题；
+    /// 转发给路由前移除别名属性，别名只在当前连接内有效。
+    /// 失败时返回断开连接的原因码：主题为空且没有别名为0x82，别名超出`TOPIC_ALIAS_MAXIMUM`或未登记为0x94
+    fn resolve_topic_alias(&mut self, publish: &mut PublishPacket) -> std::result::Result<(), ReasonCode> {
+        let Some(alias) = publish.properties.topic_alias.take() else {
+            // 没有别名时主题不能为空
+            return if publish.topic_name.is_empty() { Err(ReasonCode::ProtocolError) } else { Ok(()) };
+        };
+        if alias == 0 || alias > TOPIC_ALIAS_MAXIMUM {
+            return Err(ReasonCode::TopicAliasInvalid);
+        }
+
+        if publish.topic_name.is_empty() {
+            publish.topic_name = self.topic_aliases.get(&alias).cloned().ok_or(ReasonCode::TopicAliasInvalid)?;
+        } else {
+            self.topic_aliases.insert(alias, publish.topic_name.clone());
+        }
+        Ok(())
+    }
+
+    /// 客户端发来的主题和订阅加上挂载点
+    fn mount(&self, packet: MqttPacket) -> MqttPacket {
+        let Some(mountpoint) = &self.mountpoint else {
+            return packet;
+        };
+        match packet {
+            MqttPacket::Publish(mut publish) => {
+                publish.topic_name = format!("{}{}", mountpoint, publish.topic_name);
+                MqttPacket::Publish(publish)
+            }
+            MqttPacket::Subscribe(mut subscribe) => {
+                for (filter, _) in &mut subscribe.topics {
+                    *filter = mount_filter(mountpoint, filter);
+                }
+                MqttPacket::Subscribe(subscribe)
+            }
+            MqttPacket::Unsubscribe(mut unsubscribe) => {
+                for filter in &mut unsubscribe.topics {
+                    *filter = mount_filter(mountpoint, filter);
+                }
+                MqttPacket::Unsubscribe(unsubscribe)
+            }
+            packet => packet,
+        }
+    }
+
+    /// 发给客户端的消息去掉挂载点
+    fn unmount(&self, packet: MqttPacket) -> MqttPacket {
+        match (packet, &self.mountpoint) {
+            (MqttPacket::Publish(mut publish), Some(mountpoint)) => {
+                if let Some(topic) = publish.topic_name.strip_prefix(mountpoint.as_str()) {
+                    publish.topic_name = topic.to_string();
+                }
+                MqttPacket::Publish(publish)
+            }
+            (packet, _) => packet,
+        }
+    }
+
     /// 处理PingReq数据包
     async fn handle_ping_req(&mut self) -> Result<()> {
         // 创建PingResp数据包
         use crate::protocol::PingRespPacket;
 
         // 写入到缓冲区
-        PingRespPacket.write(&mut self.write_buf);
+        self.queue_packet(&MqttPacket::PingResp(PingRespPacket));
 
         // 发送数据包
         self.write().await?;
@@ -139,81 +247,36 @@ impl Client {
 
     /// 通知客户端断开连接
     async fn notify_disconnection(&mut self) -> Result<()> {
-        // 处理遗嘱信息
-        if let Some(will_topic) = &self.will_topic {
-            if let Some(will_message) = &self.will_message {
-                // 创建遗嘱发布消息
-                // 创建Publish数据包
-                let publish_packet = PublishPacket {
-                    dup: false,
-                    qos: self.will_qos,
-                    retain: self.will_retain,
-                    topic_name: will_topic.clone(),
-                    packet_id: None, // QoS 0不需要packet_id
-                    payload: will_message.clone(),
-                };
-                
-                // 将Publish数据包发送到路由器
-                let event = Event::MessageReceived(self.client_id.clone(), MqttPacket::Publish(publish_packet));
-                self.send_event(event)?;
-            }
+        // 异常断开时由路由器发布遗嘱，遗嘱延迟期间重连会取消发布
+        if let Some(will) = self.will.take() {
+            let event = Event::WillMessage(self.client_id.clone(), self.connection_id, will, self.will_delay);
+            self.send_event(event)?;
         }
-        
-        // 通知客户端断开连接
-        let event = Event::ClientDisconnected(self.client_id.clone());
+
+        // 通知路由器本连接已关闭，会话已被接管时路由器会忽略
+        let event = Event::ConnectionClosed(self.client_id.clone(), self.connection_id);
         self.send_event(event)
     }
 
     /// 处理来自router的事件
     pub async fn handle_router_event(&mut self, event: Event) -> Result<()> {
-        use crate::protocol::Packet;
-
         match event {
             Event::MessageSent(_, packet) => {
-                // 使用Packet trait的write方法
+                // 按客户端协议版本序列化数据包
                 match packet {
-                    crate::protocol::MqttPacket::ConnAck(connack) => {
-                        connack.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::Publish(publish) => {
-                        publish.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::PubAck(puback) => {
-                        puback.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::PubRec(pubrec) => {
-                        pubrec.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::PubRel(pubrel) => {
-                        pubrel.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::PubComp(pubcomp) => {
-                        pubcomp.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::Subscribe(subscribe) => {
-                        subscribe.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::SubAck(suback) => {
-                        suback.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::Unsubscribe(unsubscribe) => {
-                        unsubscribe.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::UnsubAck(unsuback) => {
-                        unsuback.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::PingReq(pingreq) => {
-                        pingreq.write(&mut self.write_buf);
-                    }
-                    crate::protocol::MqttPacket::PingResp(pingresp) => {
-                        pingresp.write(&mut self.write_buf);
+                    crate::protocol::MqttPacket::Connect(_) => {
+                        // 服务器不会向客户端发送CONNECT
+                        info!("Other packet type: {:?}", packet);
                     }
                     crate::protocol::MqttPacket::Disconnect(disconnect) => {
-                        disconnect.write(&mut self.write_buf);
+                        // 服务器主动断开：MQTT 5.0先发送带原因码的DISCONNECT，旧版本直接关闭连接
+                        info!("Disconnecting {}: {:?}", self.client_id, disconnect.reason_code);
+                        self.send_disconnect_with_reason(disconnect.reason_code).await?;
+                        return self.close().await;
                     }
-                    _ => {
-                        // 处理其他类型的数据包
-                        info!("Other packet type: {:?}", packet);
+                    packet => {
+                        let packet = self.unmount(packet);
+                        self.queue_packet(&packet);
                     }
                 }
 
@@ -223,9 +286,12 @@ impl Client {
             Event::ClientConnected(client_id) => {
                 info!("Client connected: {}", client_id);
             }
-            Event::ClientDisconnected(client_id) => {
+            Event::ClientDisconnected(client_id) | Event::ConnectionClosed(client_id, _) => {
                 info!("Client disconnected: {}", client_id);
             }
+            Event::WillMessage(client_id, _, _, _) => {
+                info!("Will message scheduled for {}", client_id);
+            }
             Event::MessageReceived(_client_id, _packet) => {
                 // info!("Message received from {}: {:?}", client_id, packet);
             }
@@ -238,4 +304,26 @@ impl Client {
     }
 }
 
+/// 订阅过滤器加上挂载点，共享订阅加在组名之后的过滤器上
+fn mount_filter(mountpoint: &str, filter: &str) -> String {
+    match parse_shared_subscription(filter) {
+        Some((group, filter)) => format!("{}{}/{}{}", SHARED_SUBSCRIPTION_PREFIX, group, mountpoint, filter),
+        None => format!("{}{}", mountpoint, filter),
+    }
+}
+
+/// 等待保活超时，关闭保活检测时永不返回
+async fn sleep_for_keepalive(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
 
+/// 等待到凭据过期，没有期限时永不返回
+async fn sleep_until_expiry(expires_at: Option<Instant>) {
+    match expires_at {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
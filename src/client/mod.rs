@@ -1,11 +1,15 @@
 /// 客户端模块
+#[allow(clippy::module_inception)]
 mod client;
 mod handler;
 mod connection;
 mod builder;
+mod transport;
 
 /// 导出客户端相关功能
 pub use client::Client;
 pub use client::ClientState;
+pub use client::TOPIC_ALIAS_MAXIMUM;
 pub use builder::create_client_with_connect;
+pub use transport::{BoxedTransport, Transport};
 
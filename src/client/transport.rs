@@ -0,0 +1,12 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// 客户端底层字节流
+///
+/// 明文TCP、TLS等监听器接受的连接统一装箱为`Box<dyn Transport>`，
+/// 客户端的读写和编解码与具体传输方式无关
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug> Transport for T {}
+
+/// 装箱后的客户端连接
+pub type BoxedTransport = Box<dyn Transport>;
@@ -0,0 +1,693 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::auth::acl::{AclPermission, AclRule};
+use crate::routing::qos::{InflightConfig, OverflowPolicy};
+use crate::routing::shared::SharedStrategy;
+
+/// MQTT固定头剩余长度可表示的最大值
+pub const MAX_REMAINING_LENGTH: usize = 268_435_455;
+
+/// 服务器配置
+///
+/// 优先级从低到高：默认值、TOML配置文件、环境变量、命令行参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrokerConfig {
+    /// 监听地址
+    pub listeners: Vec<ListenerConfig>,
+    /// 数据库配置
+    pub database: DatabaseConfig,
+    /// 日志配置
+    pub log: LogConfig,
+    /// MQTT协议相关限制
+    pub mqtt: MqttConfig,
+    /// 每个客户端的QoS 1/2飞行窗口和等待队列
+    pub inflight: InflightConfig,
+    /// 认证配置
+    pub auth: AuthConfig,
+    /// 保留消息和离线消息的数量上限
+    pub limits: LimitsConfig,
+    /// 主题访问控制
+    pub acl: AclConfig,
+    /// Prometheus指标接口
+    pub metrics: MetricsConfig,
+    /// 管理接口
+    pub admin: AdminConfig,
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        Self {
+            listeners: vec![ListenerConfig::default()],
+            database: DatabaseConfig::default(),
+            log: LogConfig::default(),
+            mqtt: MqttConfig::default(),
+            inflight: InflightConfig::default(),
+            auth: AuthConfig::default(),
+            limits: LimitsConfig::default(),
+            acl: AclConfig::default(),
+            metrics: MetricsConfig::default(),
+            admin: AdminConfig::default(),
+        }
+    }
+}
+
+/// 监听器配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenerConfig {
+    /// 绑定地址
+    pub bind: SocketAddr,
+    /// TLS配置，设置后该监听器只接受TLS连接（MQTTS，通常为8883端口）
+    pub tls: Option<TlsConfig>,
+    /// WebSocket配置，设置后该监听器接受MQTT over WebSocket连接，同时配置TLS时为wss
+    pub websocket: Option<WebSocketConfig>,
+    /// Unix域套接字路径，设置后在该路径上监听，不再使用`bind`
+    pub unix: Option<PathBuf>,
+    /// 同时在线的连接数上限，0表示不限制，达到上限后新连接直接关闭
+    pub max_connections: usize,
+    /// 该监听器的认证方式，未设置时使用全局的`[auth]`
+    pub auth: Option<AuthConfig>,
+    /// 主题挂载点，客户端的主题和订阅前加上该前缀，发给客户端时去掉；
+    /// 访问控制规则按去掉挂载点的主题匹配
+    pub mountpoint: Option<String>,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self::tcp(SocketAddr::from(([127, 0, 0, 1], 1883)))
+    }
+}
+
+impl ListenerConfig {
+    /// 明文TCP监听器
+    pub fn tcp(bind: SocketAddr) -> Self {
+        Self {
+            bind,
+            tls: None,
+            websocket: None,
+            unix: None,
+            max_connections: 0,
+            auth: None,
+            mountpoint: None,
+        }
+    }
+
+    /// 明文WebSocket监听器，使用默认路径
+    pub fn websocket(bind: SocketAddr) -> Self {
+        Self {
+            websocket: Some(WebSocketConfig::default()),
+            ..Self::tcp(bind)
+        }
+    }
+
+    /// Unix域套接字监听器
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self {
+            unix: Some(path.into()),
+            ..Self::default()
+        }
+    }
+
+    /// 监听器的URL协议名
+    pub fn scheme(&self) -> &'static str {
+        if self.unix.is_some() {
+            return "unix";
+        }
+        match (self.websocket.is_some(), self.tls.is_some()) {
+            (false, false) => "mqtt",
+            (false, true) => "mqtts",
+            (true, false) => "ws",
+            (true, true) => "wss",
+        }
+    }
+
+    /// 监听地址，Unix域套接字为路径，用于日志和错误信息
+    pub fn address(&self) -> String {
+        match &self.unix {
+            Some(path) => path.display().to_string(),
+            None => self.bind.to_string(),
+        }
+    }
+}
+
+/// WebSocket监听器配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// 接受WebSocket握手的HTTP路径，其他路径返回404
+    pub path: String,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self { path: "/mqtt".to_string() }
+    }
+}
+
+/// 从客户端证书中取用户名的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertUsername {
+    /// 不使用证书身份，仍按CONNECT中的用户名和密码认证
+    #[default]
+    None,
+    /// 使用证书主题的Common Name
+    Cn,
+    /// 使用证书的第一个Subject Alternative Name（DNS名、邮箱或URI）
+    San,
+}
+
+/// TLS监听器配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM格式的服务器证书链
+    pub cert: PathBuf,
+    /// PEM格式的服务器私钥
+    pub key: PathBuf,
+    /// PEM格式的客户端CA证书，设置后校验客户端证书（双向TLS）
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+    /// 设置了客户端CA时是否要求客户端必须提供证书
+    #[serde(default = "default_true")]
+    pub require_client_cert: bool,
+    /// 从已校验的客户端证书中取MQTT用户名，证书身份通过后不再校验密码
+    #[serde(default)]
+    pub cert_username: CertUsername,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 数据库配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// SQLite连接地址
+    pub url: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite://mqtt_adapt.db".to_string(),
+        }
+    }
+}
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// 默认的单行文本格式
+    #[default]
+    Full,
+    /// 精简的单行文本格式
+    Compact,
+    /// 多行可读格式
+    Pretty,
+    /// 每行一个JSON对象，便于日志采集
+    Json,
+}
+
+/// 日志配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// 日志级别：trace、debug、info、warn、error
+    pub level: String,
+    /// 日志格式
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Full,
+        }
+    }
+}
+
+/// MQTT协议相关限制
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// 允许的最大保活时间（秒），0表示不限制
+    ///
+    /// MQTT 5.0客户端超出或关闭保活时通过Server Keep Alive告知实际值；
+    /// 旧版本客户端可以关闭保活（0），超出时以服务器不可用（0x03）拒绝连接
+    pub max_keepalive: u16,
+    /// 允许的最大数据包长度（字节），超出时断开连接，MQTT 5.0客户端先收到原因码0x95
+    pub max_packet_size: usize,
+    /// 未确认QoS 1/2消息的重发间隔（秒）
+    pub retry_interval: u64,
+    /// 共享订阅组内选择接收者的策略
+    pub shared_subscription_strategy: SharedStrategy,
+    /// 发布`$SYS/broker/...`统计主题的间隔（秒），0表示不发布
+    pub sys_interval: u64,
+    /// 停机时等待客户端断开的最长时间（秒），之后保存会话并退出
+    pub shutdown_timeout: u64,
+    /// 建立连接后等待CONNECT的最长时间（秒），超时关闭连接并释放监听器的连接名额，0表示不限制
+    pub connect_timeout: u64,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            max_keepalive: 0,
+            max_packet_size: MAX_REMAINING_LENGTH,
+            retry_interval: crate::routing::router::DEFAULT_RETRY_INTERVAL.as_secs(),
+            shared_subscription_strategy: SharedStrategy::default(),
+            sys_interval: 10,
+            shutdown_timeout: 30,
+            connect_timeout: 10,
+        }
+    }
+}
+
+impl MqttConfig {
+    /// 重发间隔
+    pub fn retry_interval(&self) -> Duration {
+        Duration::from_secs(self.retry_interval)
+    }
+
+    /// `$SYS`统计主题的发布间隔
+    pub fn sys_interval(&self) -> Duration {
+        Duration::from_secs(self.sys_interval)
+    }
+
+    /// 停机的最长等待时间
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout)
+    }
+
+    /// 等待CONNECT的最长时间，不限制时为None
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        (self.connect_timeout > 0).then(|| Duration::from_secs(self.connect_timeout))
+    }
+}
+
+/// 客户端认证方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// 使用数据库中的用户名和密码认证
+    #[default]
+    Database,
+    /// 允许任何客户端连接
+    Anonymous,
+    /// 使用静态密码文件中的用户名和密码认证
+    File,
+    /// 校验CONNECT密码中携带的JWT
+    Jwt,
+}
+
+/// 认证配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// 认证方式
+    pub mode: AuthMode,
+    /// 密码文件路径，每行一个`用户名:密码哈希`，`file`模式下必须设置
+    pub password_file: Option<PathBuf>,
+    /// JWT认证配置，`jwt`模式下必须设置
+    pub jwt: Option<JwtConfig>,
+}
+
+impl AuthConfig {
+    /// 检查认证方式需要的配置是否齐全
+    pub fn validate(&self) -> Result<()> {
+        if self.mode == AuthMode::File && self.password_file.is_none() {
+            return Err(anyhow::format_err!("auth mode file requires password_file"));
+        }
+        if self.mode == AuthMode::Jwt && self.jwt.is_none() {
+            return Err(anyhow::format_err!("auth mode jwt requires [auth.jwt]"));
+        }
+        if let Some(jwt) = &self.jwt {
+            match (jwt.algorithm, &jwt.secret, &jwt.key_file) {
+                (JwtAlgorithm::HS256, Some(_), None) | (_, None, Some(_)) => {}
+                (JwtAlgorithm::HS256, _, _) => {
+                    return Err(anyhow::format_err!("auth.jwt requires exactly one of secret or key_file"));
+                }
+                (JwtAlgorithm::RS256, _, _) => {
+                    return Err(anyhow::format_err!("auth.jwt RS256 requires key_file with a PEM public key"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// JWT签名算法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JwtAlgorithm {
+    /// HMAC-SHA256，使用共享密钥
+    #[default]
+    HS256,
+    /// RSA-SHA256，使用PEM格式的公钥
+    RS256,
+}
+
+/// JWT认证配置
+///
+/// 客户端把令牌放在CONNECT的密码中；HS256的密钥可以直接写在`secret`中或放在`key_file`文件里，
+/// RS256只能从`key_file`读取公钥
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    /// 签名算法
+    pub algorithm: JwtAlgorithm,
+    /// HS256密钥
+    pub secret: Option<String>,
+    /// 密钥文件，HS256为原始密钥，RS256为PEM公钥
+    pub key_file: Option<PathBuf>,
+    /// 校验`exp`和`nbf`时允许的时钟偏差（秒）
+    pub leeway: u64,
+    /// 作为用户名的声明，缺少时使用CONNECT中的用户名
+    pub username_claim: String,
+    /// 绑定客户端ID的声明，令牌中有该声明时CONNECT的客户端ID必须与之相同
+    pub client_id_claim: String,
+    /// 携带访问规则的声明，值为`[[acl.rules]]`格式的规则列表
+    pub acl_claim: String,
+    /// 携带用户组的声明，值为组名列表或逗号分隔的字符串，供访问控制规则按`group`匹配
+    pub groups_claim: String,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: JwtAlgorithm::HS256,
+            secret: None,
+            key_file: None,
+            leeway: 0,
+            username_claim: "sub".to_string(),
+            client_id_claim: "client_id".to_string(),
+            acl_claim: "acl".to_string(),
+            groups_claim: "groups".to_string(),
+        }
+    }
+}
+
+/// 主题访问控制配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclConfig {
+    /// 没有适用的规则时是否允许
+    pub no_match: AclPermission,
+    /// 是否在配置的规则之后追加数据库`acl_rules`表中的规则
+    pub database: bool,
+    /// 按顺序匹配的规则
+    pub rules: Vec<AclRule>,
+}
+
+/// 保留消息和离线消息的数量上限，0表示不限制
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// 保留消息的主题数上限（不含`$SYS`主题），达到上限后新主题的保留消息被丢弃
+    pub max_retained_messages: usize,
+    /// 每个持久会话的离线消息上限，达到上限后丢弃最早的消息
+    pub max_offline_messages: usize,
+}
+
+/// Prometheus指标接口配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// 提供`GET /metrics`的HTTP监听地址，未设置时不启动
+    pub bind: Option<SocketAddr>,
+}
+
+/// 管理接口配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// 管理接口的HTTP监听地址，未设置时不启动
+    pub bind: Option<SocketAddr>,
+    /// 设置后请求必须携带`Authorization: Bearer <token>`，未设置时不做认证并在启动时记录警告
+    pub token: Option<String>,
+}
+
+impl BrokerConfig {
+    /// 从TOML字符串解析配置
+    pub fn from_toml_str(content: &str) -> Result<Self> {
+        let config: Self = toml::from_str(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 从TOML文件加载配置
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::from_toml_str(&content).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// 按命令行参数加载配置：先读取配置文件（如果指定），再应用环境变量和命令行覆盖
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        cli.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 检查配置取值是否合法
+    pub fn validate(&self) -> Result<()> {
+        if self.listeners.is_empty() {
+            return Err(anyhow::format_err!("At least one listener is required"));
+        }
+        for listener in &self.listeners {
+            if let Some(tls) = &listener.tls
+                && tls.cert_username != CertUsername::None
+                && tls.client_ca.is_none()
+            {
+                return Err(anyhow::format_err!(
+                    "Listener {}: cert_username requires client_ca",
+                    listener.address()
+                ));
+            }
+            if let Some(websocket) = &listener.websocket
+                && !websocket.path.starts_with('/')
+            {
+                return Err(anyhow::format_err!(
+                    "Listener {}: websocket path must start with '/'",
+                    listener.address()
+                ));
+            }
+            if listener.unix.is_some() && (cfg!(not(unix)) || listener.tls.is_some() || listener.websocket.is_some()) {
+                return Err(anyhow::format_err!(
+                    "Listener {}: unix sockets are only supported for plain MQTT on Unix platforms",
+                    listener.address()
+                ));
+            }
+            if let Some(mountpoint) = &listener.mountpoint
+                && (mountpoint.is_empty() || mountpoint.contains(['+', '#', '\0']))
+            {
+                return Err(anyhow::format_err!(
+                    "Listener {}: mountpoint must be a non-empty topic without wildcards",
+                    listener.address()
+                ));
+            }
+            if let Some(auth) = &listener.auth {
+                auth.validate()
+                    .map_err(|e| anyhow::format_err!("Listener {}: {}", listener.address(), e))?;
+            }
+        }
+        self.auth.validate()?;
+        for rule in &self.acl.rules {
+            rule.validate()?;
+        }
+        if self.log.level.parse::<tracing::Level>().is_err() {
+            return Err(anyhow::format_err!("Invalid log level {:?}", self.log.level));
+        }
+        // 至少要能容纳固定头和一个最小的CONNECT数据包
+        if !(16..=MAX_REMAINING_LENGTH).contains(&self.mqtt.max_packet_size) {
+            return Err(anyhow::format_err!(
+                "max_packet_size must be between 16 and {}",
+                MAX_REMAINING_LENGTH
+            ));
+        }
+        if self.mqtt.retry_interval == 0 {
+            return Err(anyhow::format_err!("retry_interval must be greater than 0"));
+        }
+        if self.inflight.max_inflight == 0 {
+            return Err(anyhow::format_err!("max_inflight must be greater than 0"));
+        }
+        Ok(())
+    }
+}
+
+/// 命令行参数，未指定的参数从对应的环境变量读取
+#[derive(Debug, Clone, Default, Parser)]
+#[command(name = "mqtt_adapt", version, about = "MQTT broker")]
+pub struct Cli {
+    /// 子命令，省略时启动服务器
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// TOML配置文件路径
+    #[arg(short, long, env = "MQTT_ADAPT_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// 监听地址，可重复指定，替换配置文件中的监听器
+    #[arg(long = "bind", env = "MQTT_ADAPT_BIND", value_delimiter = ',')]
+    pub bind: Vec<SocketAddr>,
+    /// 数据库连接地址
+    #[arg(long, env = "MQTT_ADAPT_DATABASE_URL")]
+    pub database_url: Option<String>,
+    /// 日志级别
+    #[arg(long, env = "MQTT_ADAPT_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// 日志格式
+    #[arg(long, env = "MQTT_ADAPT_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// 允许的最大保活时间（秒）
+    #[arg(long, env = "MQTT_ADAPT_MAX_KEEPALIVE")]
+    pub max_keepalive: Option<u16>,
+    /// 允许的最大数据包长度（字节）
+    #[arg(long, env = "MQTT_ADAPT_MAX_PACKET_SIZE")]
+    pub max_packet_size: Option<usize>,
+    /// 停机的最长等待时间（秒）
+    #[arg(long, env = "MQTT_ADAPT_SHUTDOWN_TIMEOUT")]
+    pub shutdown_timeout: Option<u64>,
+    /// 等待CONNECT的最长时间（秒）
+    #[arg(long, env = "MQTT_ADAPT_CONNECT_TIMEOUT")]
+    pub connect_timeout: Option<u64>,
+    /// 每个客户端同时未确认的QoS 1/2消息上限
+    #[arg(long, env = "MQTT_ADAPT_MAX_INFLIGHT")]
+    pub max_inflight: Option<u16>,
+    /// 每个客户端等待发送的消息上限
+    #[arg(long, env = "MQTT_ADAPT_MAX_QUEUED")]
+    pub max_queued: Option<usize>,
+    /// 等待队列已满时的处理策略
+    #[arg(long, env = "MQTT_ADAPT_OVERFLOW_POLICY")]
+    pub overflow_policy: Option<OverflowPolicy>,
+    /// 认证方式
+    #[arg(long, env = "MQTT_ADAPT_AUTH_MODE")]
+    pub auth_mode: Option<AuthMode>,
+    /// 密码文件路径
+    #[arg(long, env = "MQTT_ADAPT_PASSWORD_FILE")]
+    pub password_file: Option<PathBuf>,
+    /// 保留消息的主题数上限
+    #[arg(long, env = "MQTT_ADAPT_MAX_RETAINED_MESSAGES")]
+    pub max_retained_messages: Option<usize>,
+    /// 每个持久会话的离线消息上限
+    #[arg(long, env = "MQTT_ADAPT_MAX_OFFLINE_MESSAGES")]
+    pub max_offline_messages: Option<usize>,
+    /// Prometheus指标接口的监听地址
+    #[arg(long, env = "MQTT_ADAPT_METRICS_BIND")]
+    pub metrics_bind: Option<SocketAddr>,
+    /// 管理接口的监听地址
+    #[arg(long, env = "MQTT_ADAPT_ADMIN_BIND")]
+    pub admin_bind: Option<SocketAddr>,
+    /// 管理接口的访问令牌
+    #[arg(long, env = "MQTT_ADAPT_ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+}
+
+/// 子命令
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum Command {
+    /// 管理数据库中的用户
+    User {
+        #[command(subcommand)]
+        action: UserCommand,
+    },
+}
+
+/// 用户管理子命令，未指定`--password`时从标准输入读取密码
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum UserCommand {
+    /// 添加用户
+    Add {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// 删除用户
+    Del { username: String },
+    /// 修改密码
+    Passwd {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// 禁用用户，禁用后不能连接
+    Disable { username: String },
+    /// 重新启用用户
+    Enable { username: String },
+    /// 设置用户所属的组，不给出组名时清除，访问控制规则按`group`匹配
+    Groups { username: String, groups: Vec<String> },
+    /// 列出用户
+    List,
+}
+
+impl Cli {
+    /// 用命令行参数和环境变量覆盖配置
+    pub fn apply(&self, config: &mut BrokerConfig) {
+        if !self.bind.is_empty() {
+            config.listeners = self.bind.iter().map(|&bind| ListenerConfig::tcp(bind)).collect();
+        }
+        if let Some(url) = &self.database_url {
+            config.database.url = url.clone();
+        }
+        if let Some(level) = &self.log_level {
+            config.log.level = level.clone();
+        }
+        if let Some(format) = self.log_format {
+            config.log.format = format;
+        }
+        if let Some(max_keepalive) = self.max_keepalive {
+            config.mqtt.max_keepalive = max_keepalive;
+        }
+        if let Some(max_packet_size) = self.max_packet_size {
+            config.mqtt.max_packet_size = max_packet_size;
+        }
+        if let Some(shutdown_timeout) = self.shutdown_timeout {
+            config.mqtt.shutdown_timeout = shutdown_timeout;
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            config.mqtt.connect_timeout = connect_timeout;
+        }
+        if let Some(max_inflight) = self.max_inflight {
+            config.inflight.max_inflight = max_inflight;
+        }
+        if let Some(max_queued) = self.max_queued {
+            config.inflight.max_queued = max_queued;
+        }
+        if let Some(overflow_policy) = self.overflow_policy {
+            config.inflight.overflow_policy = overflow_policy;
+        }
+        if let Some(mode) = self.auth_mode {
+            config.auth.mode = mode;
+        }
+        if let Some(password_file) = &self.password_file {
+            config.auth.password_file = Some(password_file.clone());
+        }
+        if let Some(max_retained_messages) = self.max_retained_messages {
+            config.limits.max_retained_messages = max_retained_messages;
+        }
+        if let Some(max_offline_messages) = self.max_offline_messages {
+            config.limits.max_offline_messages = max_offline_messages;
+        }
+        if let Some(metrics_bind) = self.metrics_bind {
+            config.metrics.bind = Some(metrics_bind);
+        }
+        if let Some(admin_bind) = self.admin_bind {
+            config.admin.bind = Some(admin_bind);
+        }
+        if let Some(admin_token) = &self.admin_token {
+            config.admin.token = Some(admin_token.clone());
+        }
+    }
+}
@@ -112,6 +112,58 @@ impl DatabaseConnection {
         .execute(&self.pool)
         .await?;
         
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE,
+                password TEXT NOT NULL,
+                disabled BOOLEAN NOT NULL DEFAULT 0,
+                group_names TEXT NOT NULL DEFAULT '',
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )"
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // 早期手工创建的users表没有disabled列
+        let (has_disabled,): (bool,) = sqlx::query_as(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('users') WHERE name = 'disabled'"
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if !has_disabled {
+            sqlx::query("ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT 0")
+                .execute(&self.pool)
+                .await?;
+        }
+        
+        // 用户组列晚于users表加入
+        let (has_group_names,): (bool,) = sqlx::query_as(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('users') WHERE name = 'group_names'"
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if !has_group_names {
+            sqlx::query("ALTER TABLE users ADD COLUMN group_names TEXT NOT NULL DEFAULT ''")
+                .execute(&self.pool)
+                .await?;
+        }
+        
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS acl_rules (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                permission TEXT NOT NULL CHECK (permission IN ('allow', 'deny')),
+                username TEXT,
+                client_id TEXT,
+                group_name TEXT,
+                action TEXT NOT NULL DEFAULT 'both' CHECK (action IN ('publish', 'subscribe', 'both')),
+                topic TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )"
+        )
+        .execute(&self.pool)
+        .await?;
+        
         Ok(())
     }
 }
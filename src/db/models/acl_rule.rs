@@ -0,0 +1,98 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+use crate::auth::acl::AclRule;
+
+/// 数据库中保存的主题访问控制规则
+#[derive(Debug, Clone, FromRow)]
+pub struct AclRuleRecord {
+    pub id: i64,
+    pub permission: String,
+    pub username: Option<String>,
+    pub client_id: Option<String>,
+    pub group_name: Option<String>,
+    pub action: String,
+    pub topic: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AclRuleRecord {
+    /// 按匹配顺序读取全部规则
+    pub async fn find_all(pool: &sqlx::SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            r#"
+            SELECT id, permission, username, client_id, group_name, action, topic, created_at
+            FROM acl_rules
+            ORDER BY id
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// 在末尾追加一条规则
+    pub async fn create(pool: &sqlx::SqlitePool, rule: &AclRule) -> Result<Self> {
+        rule.validate()?;
+        let now = Utc::now();
+        let permission = rule.permission.as_str().to_string();
+        let action = rule.action.as_str().to_string();
+
+        let id = sqlx::query(
+            r#"
+            INSERT INTO acl_rules (permission, username, client_id, group_name, action, topic, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(&permission)
+        .bind(&rule.username)
+        .bind(&rule.client_id)
+        .bind(&rule.group)
+        .bind(&action)
+        .bind(&rule.topic)
+        .bind(now)
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+
+        Ok(Self {
+            id,
+            permission,
+            username: rule.username.clone(),
+            client_id: rule.client_id.clone(),
+            group_name: rule.group.clone(),
+            action,
+            topic: rule.topic.clone(),
+            created_at: now,
+        })
+    }
+
+    /// 删除规则，规则不存在时返回false
+    pub async fn delete(pool: &sqlx::SqlitePool, id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM acl_rules WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 读取全部规则并转换为访问控制规则
+    pub async fn load_rules(pool: &sqlx::SqlitePool) -> Result<Vec<AclRule>> {
+        Self::find_all(pool).await?.into_iter().map(Self::into_rule).collect()
+    }
+
+    /// 转换为访问控制规则
+    pub fn into_rule(self) -> Result<AclRule> {
+        let rule = AclRule {
+            permission: self.permission.parse()?,
+            username: self.username,
+            client_id: self.client_id,
+            group: self.group_name,
+            action: self.action.parse()?,
+            topic: self.topic,
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+}
+
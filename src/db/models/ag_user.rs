@@ -1,11 +1,20 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
 use sqlx::FromRow;
 
-#[derive(Debug, FromRow)]
+use crate::auth::password::{hash_password, verify_and_rehash_async};
+
+#[derive(Debug, Clone, FromRow)]
 pub struct User {
-    pub id: i32,
+    pub id: i64,
     pub username: String,
+    /// PHC格式的密码哈希，早期数据可能是明文，认证通过后自动改存哈希
     pub password: String,
-    pub created_at: Option<String>,
+    /// 禁用的用户不能连接
+    pub disabled: bool,
+    /// 逗号分隔的用户组，认证通过后作为`group`属性供访问控制规则匹配
+    pub group_names: String,
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 impl User {
@@ -15,7 +24,7 @@ impl User {
     ) -> Result<Option<Self>, sqlx::Error> {
         sqlx::query_as::<_, Self>(
             r#"
-            SELECT id, username, password, created_at 
+            SELECT id, username, password, disabled, group_names, created_at 
             FROM users 
             WHERE username = ?
             "#
@@ -25,22 +34,124 @@ impl User {
         .await
     }
     
+    /// 创建用户，密码以argon2哈希保存
     pub async fn create(
-        &self,
         pool: &sqlx::SqlitePool,
-    ) -> Result<Self, sqlx::Error> {
+        username: &str,
+        password: &str,
+    ) -> Result<Self> {
+        let password_hash = hash_password(password)?;
+        let now = Utc::now();
+        
+        let id = sqlx::query(
+            r#"
+            INSERT INTO users (username, password, disabled, created_at) 
+            VALUES (?, ?, ?, ?)
+            "#
+        )
+        .bind(username)
+        .bind(&password_hash)
+        .bind(false)
+        .bind(now)
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+        
+        Ok(Self {
+            id,
+            username: username.to_string(),
+            password: password_hash,
+            disabled: false,
+            group_names: String::new(),
+            created_at: Some(now),
+        })
+    }
+    
+    /// 列出所有用户
+    pub async fn list(pool: &sqlx::SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
         sqlx::query_as::<_, Self>(
             r#"
-            INSERT INTO users (id, username, password, created_at) 
-            VALUES (?, ?, ?, ?) 
-            RETURNING id, username, password, created_at
+            SELECT id, username, password, disabled, group_names, created_at 
+            FROM users 
+            ORDER BY username
             "#
         )
-        .bind(self.id)
-        .bind(&self.username)
-        .bind(&self.password)
-        .bind(&self.created_at)
-        .fetch_one(pool)
+        .fetch_all(pool)
         .await
     }
-}
\ No newline at end of file
+    
+    /// 修改密码，用户不存在时返回false
+    pub async fn set_password(
+        pool: &sqlx::SqlitePool,
+        username: &str,
+        password: &str,
+    ) -> Result<bool> {
+        let password_hash = hash_password(password)?;
+        let result = sqlx::query("UPDATE users SET password = ? WHERE username = ?")
+            .bind(password_hash)
+            .bind(username)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// 禁用或启用用户，用户不存在时返回false
+    pub async fn set_disabled(
+        pool: &sqlx::SqlitePool,
+        username: &str,
+        disabled: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE users SET disabled = ? WHERE username = ?")
+            .bind(disabled)
+            .bind(username)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// 设置用户所属的组，空列表清除所有组，用户不存在时返回false
+    pub async fn set_groups(
+        pool: &sqlx::SqlitePool,
+        username: &str,
+        groups: &[String],
+    ) -> Result<bool> {
+        if groups.iter().any(|group| group.is_empty() || group.contains(',')) {
+            return Err(anyhow::format_err!("Group names must be non-empty and must not contain commas"));
+        }
+        let result = sqlx::query("UPDATE users SET group_names = ? WHERE username = ?")
+            .bind(groups.join(","))
+            .bind(username)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// 删除用户，用户不存在时返回false
+    pub async fn delete(
+        pool: &sqlx::SqlitePool,
+        username: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM users WHERE username = ?")
+            .bind(username)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// 校验密码，明文保存的旧密码校验通过后改存哈希，校验和哈希计算都在阻塞线程池中进行
+    pub async fn verify(&mut self, pool: &sqlx::SqlitePool, password: &str) -> Result<bool> {
+        let (verified, rehash) = verify_and_rehash_async(password, &self.password).await?;
+        if !verified {
+            return Ok(false);
+        }
+        if let Some(password_hash) = rehash {
+            sqlx::query("UPDATE users SET password = ? WHERE id = ?")
+                .bind(&password_hash)
+                .bind(self.id)
+                .execute(pool)
+                .await?;
+            self.password = password_hash;
+        }
+        Ok(true)
+    }
+}
@@ -1,3 +1,4 @@
+pub mod acl_rule;
 pub mod ag_user;
 pub mod retained_message;
 pub mod session;
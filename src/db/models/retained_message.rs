@@ -106,7 +106,7 @@ impl RetainedMessage {
             match *part {
                 "+" => result.push_str("[^/]*"),
                 "#" => {
-                    result.push_str("%");
+                    result.push('%');
                     break;
                 }
                 _ => result.push_str(part),
@@ -43,8 +43,8 @@ impl Session {
         
         let id = sqlx::query(
             r#"
-            INSERT INTO sessions (client_id, clean_session, connected, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?)
+            INSERT INTO sessions (client_id, clean_session, connected, last_connected_at, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?)
             "#
         )
         .bind(client_id)
@@ -52,6 +52,7 @@ impl Session {
         .bind(true)
         .bind(now)
         .bind(now)
+        .bind(now)
         .execute(pool)
         .await?
         .last_insert_rowid();
@@ -85,6 +86,43 @@ impl Session {
         .await
     }
 
+    /// 查询所有持久会话（clean_session = false）
+    pub async fn find_persistent(
+        pool: &sqlx::SqlitePool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            r#"
+            SELECT id, client_id, clean_session, connected, last_connected_at, 
+                   last_disconnected_at, created_at, updated_at
+            FROM sessions
+            WHERE clean_session = false
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn update_clean_session(
+        pool: &sqlx::SqlitePool,
+        client_id: &str,
+        clean_session: bool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE sessions 
+            SET clean_session = ?, updated_at = ?
+            WHERE client_id = ?
+            "#
+        )
+        .bind(clean_session)
+        .bind(Utc::now())
+        .bind(client_id)
+        .execute(pool)
+        .await?;
+        
+        Ok(())
+    }
+
     pub async fn update_connected(
         pool: &sqlx::SqlitePool,
         client_id: &str,
@@ -122,6 +160,26 @@ impl Session {
         
         Ok(())
     }
+
+    /// 删除会话及其订阅和离线消息，返回会话是否存在
+    pub async fn delete(
+        pool: &sqlx::SqlitePool,
+        client_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let Some(session) = Self::find_by_client_id(pool, client_id).await? else {
+            return Ok(false);
+        };
+        SessionSubscription::delete_all_by_session_id(pool, session.id).await?;
+        OfflineMessage::delete_all_by_session_id(pool, session.id).await?;
+        sqlx::query(
+            r#"DELETE FROM sessions WHERE id = ?"#
+        )
+        .bind(session.id)
+        .execute(pool)
+        .await?;
+
+        Ok(true)
+    }
 }
 
 impl SessionSubscription {
@@ -258,7 +316,7 @@ impl OfflineMessage {
             SELECT id, session_id, topic, payload, qos, packet_id, created_at
             FROM offline_messages
             WHERE session_id = ?
-            ORDER BY created_at ASC
+            ORDER BY created_at ASC, id ASC
             "#
         )
         .bind(session_id)
@@ -292,6 +350,45 @@ impl OfflineMessage {
         Ok(())
     }
 
+    /// 只保留会话最新的`keep`条离线消息
+    pub async fn trim_session(
+        pool: &sqlx::SqlitePool,
+        session_id: i64,
+        keep: usize,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM offline_messages
+            WHERE session_id = ? AND id NOT IN (
+                SELECT id FROM offline_messages
+                WHERE session_id = ?
+                ORDER BY created_at DESC, id DESC
+                LIMIT ?
+            )
+            "#
+        )
+        .bind(session_id)
+        .bind(session_id)
+        .bind(keep as i64)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// 会话中等待补发的离线消息数
+    pub async fn count_by_session_id(
+        pool: &sqlx::SqlitePool,
+        session_id: i64,
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"SELECT COUNT(*) FROM offline_messages WHERE session_id = ?"#
+        )
+        .bind(session_id)
+        .fetch_one(pool)
+        .await
+    }
+
     pub async fn delete_all_by_session_id(
         pool: &sqlx::SqlitePool,
         session_id: i64,
@@ -17,12 +17,14 @@ impl SessionManager {
         let existing_session = Session::find_by_client_id(pool, client_id).await?;
         
         let (session, session_present, subscriptions, offline_messages) = if let Some(mut existing) = existing_session {
-            if clean_session {
-                info!("Clean session requested for client {}, clearing old session", client_id);
+            if clean_session || existing.clean_session {
+                // 请求清理会话，或上一次是非持久会话（状态已在断开时清理），都从空会话开始
+                info!("Starting fresh session for client {}", client_id);
                 SessionSubscription::delete_all_by_session_id(pool, existing.id).await?;
                 OfflineMessage::delete_all_by_session_id(pool, existing.id).await?;
                 
-                existing.clean_session = true;
+                existing.clean_session = clean_session;
+                Session::update_clean_session(pool, client_id, clean_session).await?;
                 Session::update_connected(pool, client_id, true).await?;
                 
                 (existing, false, vec![], vec![])
@@ -53,23 +55,35 @@ impl SessionManager {
                 info!("Cleaning up session for client {}", client_id);
                 SessionSubscription::delete_all_by_session_id(pool, session.id).await?;
                 OfflineMessage::delete_all_by_session_id(pool, session.id).await?;
-            } else {
-                Session::update_connected(pool, client_id, false).await?;
             }
+            Session::update_connected(pool, client_id, false).await?;
         }
         Ok(())
     }
 
+    /// 服务器启动时加载所有持久会话及其订阅，并将会话标记为离线
+    pub async fn load_persistent_sessions(
+        pool: &SqlitePool,
+    ) -> Result<Vec<(Session, Vec<SessionSubscription>)>> {
+        let mut sessions = Vec::new();
+        for session in Session::find_persistent(pool).await? {
+            Session::update_connected(pool, &session.client_id, false).await?;
+            let subscriptions = SessionSubscription::find_by_session_id(pool, session.id).await?;
+            sessions.push((session, subscriptions));
+        }
+        Ok(sessions)
+    }
+
     pub async fn add_subscription(
         pool: &SqlitePool,
         client_id: &str,
         topic: &str,
         qos: u8,
     ) -> Result<()> {
-        if let Some(session) = Session::find_by_client_id(pool, client_id).await? {
-            if !session.clean_session {
-                SessionSubscription::create(pool, session.id, topic, qos).await?;
-            }
+        if let Some(session) = Session::find_by_client_id(pool, client_id).await?
+            && !session.clean_session
+        {
+            SessionSubscription::create(pool, session.id, topic, qos).await?;
         }
         Ok(())
     }
@@ -79,29 +93,35 @@ impl SessionManager {
         client_id: &str,
         topic: &str,
     ) -> Result<()> {
-        if let Some(session) = Session::find_by_client_id(pool, client_id).await? {
-            if !session.clean_session {
-                SessionSubscription::delete(pool, session.id, topic).await?;
-            }
+        if let Some(session) = Session::find_by_client_id(pool, client_id).await?
+            && !session.clean_session
+        {
+            SessionSubscription::delete(pool, session.id, topic).await?;
         }
         Ok(())
     }
 
+    /// 保存发往离线持久会话的消息，`max_messages`大于0时超出部分丢弃最早的消息
     pub async fn store_offline_message(
         pool: &SqlitePool,
         client_id: &str,
         publish: &PublishPacket,
+        max_messages: usize,
     ) -> Result<()> {
-        if let Some(session) = Session::find_by_client_id(pool, client_id).await? {
-            if !session.clean_session && !session.connected {
-                OfflineMessage::create(
-                    pool,
-                    session.id,
-                    &publish.topic_name,
-                    &publish.payload,
-                    publish.qos,
-                    publish.packet_id,
-                ).await?;
+        if let Some(session) = Session::find_by_client_id(pool, client_id).await?
+            && !session.clean_session
+            && !session.connected
+        {
+            OfflineMessage::create(
+                pool,
+                session.id,
+                &publish.topic_name,
+                &publish.payload,
+                publish.qos,
+                publish.packet_id,
+            ).await?;
+            if max_messages > 0 {
+                OfflineMessage::trim_session(pool, session.id, max_messages).await?;
             }
         }
         Ok(())
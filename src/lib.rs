@@ -1,6 +1,13 @@
+pub mod admin;
+pub mod auth;
+pub mod config;
 pub mod protocol;
 pub mod client;
+pub mod metrics;
 pub mod server;
+pub mod stats;
+pub mod tls;
+pub mod websocket;
 pub mod topic;
 pub mod routing;
 pub mod db;
@@ -1,4 +1,7 @@
+use clap::Parser;
 use mimalloc::MiMalloc;
+use mqtt_adapt::config::{BrokerConfig, Cli, Command, LogConfig, LogFormat, UserCommand};
+use mqtt_adapt::db::models::ag_user::User;
 use mqtt_adapt::{db::connection::DatabaseConnection, server::Server};
 use tracing::Level;
 
@@ -6,16 +9,39 @@ use tracing::Level;
 static GLOBAL: MiMalloc = MiMalloc;
 
 fn main() {
+    // 加载配置：配置文件 < 环境变量 < 命令行参数
+    let cli = Cli::parse();
+    let config = match BrokerConfig::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load configuration: {:?}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // 用户管理子命令执行后退出，不启动服务器
+    if let Some(Command::User { action }) = &cli.command {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        if let Err(e) = rt.block_on(run_user_command(&config, action)) {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // 初始化tracing日志系统
     let _num_cpus = num_cpus::get();
-    init_tracing();
+    init_tracing(&config.log);
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap();
     rt.block_on(async {
         // 初始化数据库连接
-        let db = match DatabaseConnection::new("sqlite://mqtt_adapt.db").await {
+        let db = match DatabaseConnection::new(&config.database.url).await {
             Ok(db) => db,
             Err(e) => {
                 eprintln!("Failed to initialize database: {:?}", e);
@@ -24,14 +50,94 @@ fn main() {
         };
 
         // 创建服务器
-        let server = Server::new("127.0.0.1:1883".parse().unwrap())
+        let server = Server::from_config(config)
             .with_database(db);
 
-        // 启动服务器
+        // 收到SIGTERM或SIGINT时停机
+        let signal_server = server.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            tracing::info!("Received shutdown signal");
+            signal_server.shutdown();
+        });
+
+        // 启动服务器，停机完成后返回
         server.start().await;
     });
 }
-fn init_tracing() {
-    tracing_subscriber::fmt().with_max_level(Level::INFO).init();
+
+/// 等待SIGTERM或SIGINT（Ctrl+C）
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// 执行用户管理子命令
+async fn run_user_command(config: &BrokerConfig, action: &UserCommand) -> anyhow::Result<()> {
+    let db = DatabaseConnection::new(&config.database.url).await?;
+    let pool = db.get_pool();
+    let found = match action {
+        UserCommand::Add { username, password } => {
+            let password = read_password(password)?;
+            User::create(pool, username, &password).await?;
+            true
+        }
+        UserCommand::Del { username } => User::delete(pool, username).await?,
+        UserCommand::Passwd { username, password } => {
+            let password = read_password(password)?;
+            User::set_password(pool, username, &password).await?
+        }
+        UserCommand::Disable { username } => User::set_disabled(pool, username, true).await?,
+        UserCommand::Enable { username } => User::set_disabled(pool, username, false).await?,
+        UserCommand::Groups { username, groups } => User::set_groups(pool, username, groups).await?,
+        UserCommand::List => {
+            for user in User::list(pool).await? {
+                let state = if user.disabled { "disabled" } else { "enabled" };
+                println!("{}\t{}\t{}", user.username, state, user.group_names);
+            }
+            true
+        }
+    };
+    if !found {
+        return Err(anyhow::format_err!("User not found"));
+    }
+    Ok(())
 }
 
+/// 未通过参数给出密码时从标准输入读取一行
+fn read_password(password: &Option<String>) -> anyhow::Result<String> {
+    if let Some(password) = password {
+        return Ok(password.clone());
+    }
+    eprint!("Password: ");
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    let password = line.trim_end_matches(['\r', '\n']).to_string();
+    if password.is_empty() {
+        return Err(anyhow::format_err!("Password must not be empty"));
+    }
+    Ok(password)
+}
+
+fn init_tracing(log: &LogConfig) {
+    // 配置已校验过日志级别
+    let level: Level = log.level.parse().unwrap_or(Level::INFO);
+    let builder = tracing_subscriber::fmt().with_max_level(level);
+    match log.format {
+        LogFormat::Full => builder.init(),
+        LogFormat::Compact => builder.compact().init(),
+        LogFormat::Pretty => builder.pretty().init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
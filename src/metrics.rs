@@ -0,0 +1,30 @@
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use log::info;
+use tokio::net::TcpListener;
+
+use crate::routing::router::MessageRouter;
+
+/// Prometheus文本格式的Content-Type
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// 在`listener`上提供`GET /metrics`，直到监听器出错
+pub async fn serve(listener: TcpListener, router: MessageRouter) -> std::io::Result<()> {
+    info!("Metrics endpoint started on http://{}/metrics", listener.local_addr()?);
+    let app = axum::Router::new().route("/metrics", get(metrics)).with_state(router);
+    axum::serve(listener, app).await
+}
+
+/// 更新连接数和订阅数后输出全部指标
+pub async fn render(router: &MessageRouter) -> String {
+    let stats = router.stats();
+    let (connections, _) = router.client_counts().await;
+    stats.set_gauges(connections, router.subscription_count().await);
+    stats.encode()
+}
+
+async fn metrics(State(router): State<MessageRouter>) -> impl IntoResponse {
+    ([(CONTENT_TYPE, TEXT_FORMAT)], render(&router).await)
+}
@@ -8,8 +8,5 @@ pub mod consumer;
 pub mod thread_pool;
 pub mod producer;
 
-/// 规则引擎主题
-
-
-
+// 规则引擎主题
 type NodeId= String;
\ No newline at end of file
@@ -3,6 +3,7 @@ use bytes::Bytes;
 use flume::{Receiver, Sender};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex as AsyncMutex;
 use tokio::task;
 use crate::mq::producer::MqProducer;
 use crate::mq::consumer::{MqConsumer, MqConsumerService};
@@ -14,9 +15,9 @@ type ResponseCallback = Box<dyn FnOnce(MqMessage) -> Result<()> + Send + Sync>;
 /// MQ服务结构体
 pub struct MqService {
     /// MQ生产者
-    producer: Arc<Mutex<Box<dyn MqProducer>>>,
+    producer: Arc<AsyncMutex<Box<dyn MqProducer>>>,
     /// 消费者服务列表
-    consumer_services: Arc<Mutex<Vec<Arc<Mutex<MqConsumerService>>>>>,
+    consumer_services: Arc<Mutex<Vec<Arc<AsyncMutex<MqConsumerService>>>>>,
     /// 响应通道
     response_sender: Sender<MqMessage>,
     response_receiver: Receiver<MqMessage>,
@@ -34,7 +35,7 @@ impl MqService {
         let (tx, rx) = flume::unbounded();
         
         Ok(Self {
-            producer: Arc::new(Mutex::new(producer)),
+            producer: Arc::new(AsyncMutex::new(producer)),
             consumer_services: Arc::new(Mutex::new(Vec::new())),
             response_sender: tx,
             response_receiver: rx,
@@ -52,10 +53,11 @@ impl MqService {
                 let topic = message.topic.clone();
                 
                 // 查找并执行回调
-                if let Some(callback) = callbacks.lock().unwrap().remove(&topic) {
-                    if let Err(e) = callback(message) {
-                        log::error!("Error executing callback for topic {}: {:?}", topic, e);
-                    }
+                let callback = callbacks.lock().unwrap().remove(&topic);
+                if let Some(callback) = callback
+                    && let Err(e) = callback(message)
+                {
+                    log::error!("Error executing callback for topic {}: {:?}", topic, e);
                 }
             }
         });
@@ -72,11 +74,12 @@ impl MqService {
         self.callbacks.lock().unwrap().insert(response_topic, callback);
         
         // 发送消息
-        let producer = self.producer.lock().unwrap();
+        let producer = self.producer.lock().await;
         producer.send_message(message).await
     }
     
     /// 发送原始消息并注册响应回调
+    #[allow(clippy::too_many_arguments)]
     pub async fn send_raw_with_callback(
         &self, 
         topic: &str, 
@@ -109,10 +112,10 @@ impl MqService {
     }
     
     /// 添加消费者服务
-    pub async fn add_consumer_service(&self, consumer: Box<dyn MqConsumer>) -> Result<Arc<Mutex<MqConsumerService>>> {
+    pub async fn add_consumer_service(&self, consumer: Box<dyn MqConsumer>) -> Result<Arc<AsyncMutex<MqConsumerService>>> {
         // 创建消费者服务
         let consumer_service = MqConsumerService::new(consumer).await?;
-        let consumer_service = Arc::new(Mutex::new(consumer_service));
+        let consumer_service = Arc::new(AsyncMutex::new(consumer_service));
         
         // 添加到消费者服务列表
         self.consumer_services.lock().unwrap().push(consumer_service.clone());
@@ -136,7 +139,7 @@ impl MqService {
                     .unwrap();
                 
                 rt.block_on(async move {
-                    let mut service = service_clone.lock().unwrap();
+                    let mut service = service_clone.lock().await;
                     if let Err(e) = service.start_consuming().await {
                         log::error!("Error starting consumer: {:?}", e);
                     }
@@ -151,7 +154,7 @@ impl MqService {
         let consumer_services: Vec<_> = self.consumer_services.lock().unwrap().clone();
         
         for consumer_service in consumer_services.iter() {
-            let mut service = consumer_service.lock().unwrap();
+            let mut service = consumer_service.lock().await;
             service.close().await?;
         }
         
@@ -164,7 +167,7 @@ impl MqService {
         self.close_all_consumers().await?;
         
         // 关闭生产者
-        let mut producer = self.producer.lock().unwrap();
+        let mut producer = self.producer.lock().await;
         producer.disconnect().await
     }
 }
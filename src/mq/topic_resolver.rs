@@ -120,11 +120,13 @@ impl TopicResolver {
             Err(anyhow::anyhow!("Topic does not match pattern: {}", self.topic_pattern))
         }
     }
-    
+}
+
+impl Default for TopicResolver {
     /// 获取默认的 Topic 解析器
     /// 
     /// 默认 topic 格式为 "{node_id}/{device_id}"
-    pub fn default() -> Self {
+    fn default() -> Self {
         Self::new("{node_id}/{device_id}").unwrap()
     }
 }
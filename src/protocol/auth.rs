@@ -0,0 +1,54 @@
+use bytes::{BufMut, BytesMut};
+use super::Packet;
+use super::ProtocolError;
+use super::Properties;
+use super::ReasonCode;
+use super::{parse_reason_code, write_remaining_length};
+/// AUTH数据包
+/// MQTT 5.0增强认证数据包，仅存在于MQTT 5.0
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AuthPacket {
+    /// 认证原因码：成功、继续认证或重新认证
+    pub reason_code: ReasonCode,
+    /// AUTH属性（认证方法、认证数据等）
+    pub properties: Properties,
+}
+
+impl Packet for AuthPacket {
+    /// 将AUTH数据包序列化为字节并写入缓冲区
+    /// 原因码为成功且没有属性时剩余长度为0
+    fn write(&self, buf: &mut BytesMut) {
+        let packet_type = super::PacketType::Auth as u8;
+        buf.put_u8(packet_type << 4);
+
+        if self.reason_code == ReasonCode::Success && self.properties.is_empty() {
+            buf.put_u8(0x00);
+            return;
+        }
+
+        let mut properties = BytesMut::new();
+        self.properties.write(&mut properties);
+        write_remaining_length(buf, 1 + properties.len());
+        buf.put_u8(self.reason_code as u8);
+        buf.put_slice(&properties);
+    }
+
+    /// 从BytesMut解析AUTH数据包
+    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self, ProtocolError> {
+        if input.is_empty() {
+            return Ok(AuthPacket::default());
+        }
+
+        let reason_code = parse_reason_code(input)?;
+        let properties = if input.is_empty() {
+            Properties::default()
+        } else {
+            Properties::parse(input)?
+        };
+
+        Ok(AuthPacket {
+            reason_code,
+            properties,
+        })
+    }
+}
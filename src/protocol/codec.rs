@@ -0,0 +1,105 @@
+use super::FixedHeader;
+use super::MqttPacket;
+use super::ProtocolError;
+use super::ProtocolVersion;
+use anyhow::Result;
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// MQTT帧编解码器
+///
+/// 在TCP字节流上增量切分MQTT数据包：
+/// - 固定头或剩余长度不完整时保留已读字节，等待更多数据
+/// - 一次读取中包含多个数据包时，每次调用`decode`返回其中一个
+///
+/// 协议版本在收到CONNECT后设置，决定后续数据包按MQTT 3.1.1还是5.0编解码
+#[derive(Debug, Default, Clone)]
+pub struct MqttCodec {
+    version: ProtocolVersion,
+    /// 允许接收的最大数据包长度（包括固定头），None表示不限制
+    max_packet_size: Option<usize>,
+}
+
+impl MqttCodec {
+    /// 创建新的编解码器，默认使用MQTT 3.1.1
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建指定协议版本的编解码器
+    pub fn with_version(version: ProtocolVersion) -> Self {
+        Self {
+            version,
+            max_packet_size: None,
+        }
+    }
+
+    /// 设置协议版本
+    pub fn set_version(&mut self, version: ProtocolVersion) {
+        self.version = version;
+    }
+
+    /// 获取协议版本
+    pub fn version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    /// 设置允许接收的最大数据包长度，超出时解码失败并返回`ProtocolError::PayloadTooLarge`
+    pub fn set_max_packet_size(&mut self, max_packet_size: Option<usize>) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    /// 获取允许接收的最大数据包长度
+    pub fn max_packet_size(&self) -> Option<usize> {
+        self.max_packet_size
+    }
+}
+
+impl Decoder for MqttCodec {
+    type Item = MqttPacket;
+    type Error = anyhow::Error;
+
+    /// 从缓冲区中解码一个完整的MQTT数据包
+    ///
+    /// 数据不完整时返回`Ok(None)`，解码失败时返回的错误包装`ProtocolError`
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<MqttPacket>> {
+        // 固定头尚未完整到达时等待，其余协议错误交给调用方断开连接
+        let (header, header_len) = match FixedHeader::peek(src) {
+            Ok(header) => header,
+            Err(ProtocolError::Incomplete { .. }) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // 超长的数据包在读取载荷之前拒绝，避免为其分配缓冲区
+        let frame_len = header_len + header.remaining_length;
+        if let Some(max_packet_size) = self.max_packet_size
+            && frame_len > max_packet_size
+        {
+            return Err(ProtocolError::PayloadTooLarge {
+                size: frame_len,
+                max: max_packet_size,
+            }
+            .into());
+        }
+
+        // 等待完整的剩余长度
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        // 切出一个完整的数据帧，剩余字节留在缓冲区中
+        let mut frame = src.split_to(frame_len);
+        Ok(Some(MqttPacket::read_versioned(&mut frame, self.version)?))
+    }
+}
+
+impl Encoder<MqttPacket> for MqttCodec {
+    type Error = anyhow::Error;
+
+    /// 将MQTT数据包编码到缓冲区
+    fn encode(&mut self, item: MqttPacket, dst: &mut BytesMut) -> Result<()> {
+        item.write_versioned(dst, self.version);
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
 use super::ConnectReturnCode;
 use super::Packet;
-use super::write_remaining_length;
+use super::ProtocolError;
+use super::Properties;
+use super::ProtocolVersion;
+use super::ReasonCode;
+use super::{parse_reason_code, write_remaining_length};
 use bytes::{Buf, BufMut, BytesMut};
-use anyhow::Result;
 
 
 
@@ -10,7 +13,29 @@ use anyhow::Result;
 #[derive(Debug,  PartialEq, Eq)]
 pub struct ConnAckPacket {
     pub session_present: bool,
+    /// MQTT 3.1.1连接返回码
     pub return_code: ConnectReturnCode,
+    /// MQTT 5.0原因码，为None时由return_code映射
+    pub reason_code: Option<ReasonCode>,
+    /// CONNACK属性（仅MQTT 5.0）
+    pub properties: Properties,
+}
+
+impl ConnAckPacket {
+    /// 创建CONNACK数据包
+    pub fn new(session_present: bool, return_code: ConnectReturnCode) -> Self {
+        Self {
+            session_present,
+            return_code,
+            reason_code: None,
+            properties: Properties::default(),
+        }
+    }
+
+    /// MQTT 5.0会话中实际发送的原因码
+    pub fn reason_code(&self) -> ReasonCode {
+        self.reason_code.unwrap_or_else(|| self.return_code.into())
+    }
 }
 
 
@@ -19,37 +44,82 @@ impl Packet for ConnAckPacket {
     fn write(&self, buf: &mut BytesMut) {
         // 计算可变头长度
         let variable_header_length = 2; // session present flag (1 byte) + return code (1 byte)
-        
+
         // 写入固定头
         let packet_type = 2; // CONNACK
         let flags = if self.session_present { 1 } else { 0 };
-        let first_byte = (packet_type << 4) | flags;
+        let first_byte = packet_type << 4;
         buf.put_u8(first_byte);
-        
+
         // 写入剩余长度
         write_remaining_length(buf, variable_header_length);
-        
+
         // 写入可变头
         buf.put_u8(flags); // session present flag
         buf.put_u8(self.return_code as u8); // return code
-        
+
     }
-    
-    /// 从BytesMut解析CONNACK数据包
-    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
-        if input.len() < 2 {
-            return Err(anyhow::format_err!("Insufficient data for CONNACK packet"));
+
+    /// 按协议版本序列化CONNACK数据包，MQTT 5.0使用原因码并附带属性
+    fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
+        if version != ProtocolVersion::V5 {
+            return self.write(buf);
         }
-        
-        let flags = input.get_u8();
+
+        let mut properties = BytesMut::new();
+        self.properties.write(&mut properties);
+
+        // 写入固定头
+        let packet_type = 2; // CONNACK
+        buf.put_u8(packet_type << 4);
+        write_remaining_length(buf, 2 + properties.len());
+
+        // 写入可变头
+        buf.put_u8(if self.session_present { 1 } else { 0 });
+        buf.put_u8(self.reason_code() as u8);
+        buf.put_slice(&properties);
+    }
+
+    /// 从BytesMut解析CONNACK数据包
+    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self, ProtocolError> {
+        let flags = parse_acknowledge_flags(input)?;
         let return_code_value = input.get_u8();
-        
+
         let session_present = (flags & 0x01) != 0;
-        let return_code = ConnectReturnCode::from_u8(return_code_value).ok_or(anyhow::format_err!("Invalid return code"))?; 
-        
+        let return_code = ConnectReturnCode::from_u8(return_code_value).ok_or_else(|| {
+            ProtocolError::malformed(format!("Invalid return code 0x{:02X}", return_code_value))
+        })?;
+
+        Ok(ConnAckPacket::new(session_present, return_code))
+    }
+
+    /// 按协议版本解析CONNACK数据包
+    fn parse_versioned(input: &mut BytesMut, flags: Option<u8>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        if version != ProtocolVersion::V5 {
+            return Self::parse(input, flags);
+        }
+
+        let flags = parse_acknowledge_flags(input)?;
+        let reason_code = parse_reason_code(input)?;
+        let properties = Properties::parse(input)?;
+
         Ok(ConnAckPacket {
-            session_present,
-            return_code,
+            session_present: (flags & 0x01) != 0,
+            return_code: reason_code.into(),
+            reason_code: Some(reason_code),
+            properties,
         })
     }
 }
+
+/// 解析连接确认标志，除Session Present外的保留位必须为0
+fn parse_acknowledge_flags(input: &mut BytesMut) -> Result<u8, ProtocolError> {
+    if input.len() < 2 {
+        return Err(ProtocolError::malformed("Insufficient data for CONNACK packet"));
+    }
+    let flags = input.get_u8();
+    if flags & 0xFE != 0 {
+        return Err(ProtocolError::malformed(format!("Invalid connect acknowledge flags 0x{:02X}", flags)));
+    }
+    Ok(flags)
+}
@@ -1,11 +1,13 @@
 use super::Packet;
+use super::ProtocolError;
 use super::parse_mqtt_bytes;
 use super::parse_mqtt_string;
 use super::write_mqtt_bytes;
 use super::write_mqtt_string;
 use super::write_remaining_length;
+use super::Properties;
+use super::ProtocolVersion;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use anyhow::Result;
 /// CONNECT数据包
 #[derive(Debug, PartialEq, Eq)]
 pub struct ConnectPacket {
@@ -18,10 +20,18 @@ pub struct ConnectPacket {
     pub will_message: Option<Bytes>,
     pub username: Option<String>,
     pub password: Option<Bytes>,
+    /// CONNECT属性（仅MQTT 5.0）
+    pub properties: Properties,
+    /// 遗嘱属性（仅MQTT 5.0）
+    pub will_properties: Properties,
 }
 
-
-
+impl ConnectPacket {
+    /// 是否为MQTT 5.0连接
+    pub fn is_v5(&self) -> bool {
+        self.protocol_level == ProtocolVersion::V5 as u8
+    }
+}
 
 impl Packet for ConnectPacket {
     /// 将CONNECT数据包序列化为字节并写入缓冲区
@@ -37,6 +47,17 @@ impl Packet for ConnectPacket {
         variable_header_length += 1;
         // 保活时间长度
         variable_header_length += 2;
+
+        // 属性（仅MQTT 5.0）
+        let mut properties = BytesMut::new();
+        let mut will_properties = BytesMut::new();
+        if self.is_v5() {
+            self.properties.write(&mut properties);
+            if self.will_topic.is_some() {
+                self.will_properties.write(&mut will_properties);
+            }
+        }
+        variable_header_length += properties.len();
         
         // 载荷长度
         let mut payload_length = 0;
@@ -44,7 +65,8 @@ impl Packet for ConnectPacket {
         // 客户端标识符长度
         payload_length += 2 + self.client_id.len();
         
-        // 遗嘱主题和遗嘱消息长度（如果有）
+        // 遗嘱属性、遗嘱主题和遗嘱消息长度（如果有）
+        payload_length += will_properties.len();
         if let Some(topic) = &self.will_topic {
             payload_length += 2 + topic.len();
         }
@@ -83,12 +105,15 @@ impl Packet for ConnectPacket {
         buf.put_u8(self.connect_flags);
         // 保活时间
         buf.put_u16(self.keep_alive);
+        // 属性
+        buf.put_slice(&properties);
         
         // 写入载荷
         // 客户端标识符
         write_mqtt_string(buf, &self.client_id);
         
-        // 遗嘱主题和遗嘱消息（如果有）
+        // 遗嘱属性、遗嘱主题和遗嘱消息（如果有）
+        buf.put_slice(&will_properties);
         if let Some(topic) = &self.will_topic {
             write_mqtt_string(buf, topic);
         }
@@ -108,27 +133,45 @@ impl Packet for ConnectPacket {
     }
     
     /// 从BytesMut解析CONNECT数据包
-    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
+    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self, ProtocolError> {
         // 解析协议名称
         let protocol_name = parse_mqtt_string(input)?;
         
         // 解析协议级别
         if input.is_empty() {
-            return Err(anyhow::format_err!("Insufficient data for protocol level"));
+            return Err(ProtocolError::malformed("Insufficient data for protocol level"));
         }
         let protocol_level = input.get_u8();
+        // 协议名必须与协议级别对应：MQTT 3.1为MQIsdp，MQTT 3.1.1和5.0为MQTT
+        let supported = match protocol_level {
+            3 => protocol_name == "MQIsdp",
+            4 | 5 => protocol_name == "MQTT",
+            _ => false,
+        };
+        if !supported {
+            return Err(ProtocolError::UnsupportedVersion(protocol_level));
+        }
         
         // 解析连接标志
         if input.is_empty() {
-            return Err(anyhow::format_err!("Insufficient data for connect flags"));
+            return Err(ProtocolError::malformed("Insufficient data for connect flags"));
         }
         let connect_flags = input.get_u8();
         
         // 解析保活时间
         if input.len() < 2 {
-            return Err(anyhow::format_err!("Insufficient data for keep alive"));
+            return Err(ProtocolError::malformed("Insufficient data for keep alive"));
         }
         let keep_alive = input.get_u16();
+
+        // MQTT 5.0在保活时间之后携带属性
+        let is_v5 = protocol_level == ProtocolVersion::V5 as u8;
+        validate_connect_flags(connect_flags, is_v5)?;
+        let properties = if is_v5 {
+            Properties::parse(input)?
+        } else {
+            Properties::default()
+        };
         
         // 解析客户端标识符
         let client_id = parse_mqtt_string(input)?;
@@ -138,9 +181,15 @@ impl Packet for ConnectPacket {
         let mut will_message = None;
         let mut username = None;
         let mut password = None;
+        let mut will_properties = Properties::default();
         
         // 检查遗嘱标志
         if (connect_flags & 0x04) != 0 {
+            // 解析遗嘱属性（仅MQTT 5.0）
+            if is_v5 {
+                will_properties = Properties::parse(input)?;
+            }
+
             // 解析遗嘱主题
             let topic = parse_mqtt_string(input)?;
             will_topic = Some(topic);
@@ -172,8 +221,29 @@ impl Packet for ConnectPacket {
             will_message,
             username,
             password,
+            properties,
+            will_properties,
         })
     }
 }
 
+/// 检查连接标志
+///
+/// 保留位必须为0；没有遗嘱时遗嘱QoS和遗嘱保留必须为0，遗嘱QoS不能为3；
+/// MQTT 5.0之前的版本设置密码标志时必须同时设置用户名标志
+fn validate_connect_flags(flags: u8, is_v5: bool) -> Result<(), ProtocolError> {
+    let will = (flags & 0x04) != 0;
+    let will_qos = (flags >> 3) & 0x03;
+    let will_retain = (flags & 0x20) != 0;
+    let username = (flags & 0x80) != 0;
+    let password = (flags & 0x40) != 0;
 
+    let valid = (flags & 0x01) == 0
+        && will_qos != 3
+        && (will || (will_qos == 0 && !will_retain))
+        && (is_v5 || username || !password);
+    if !valid {
+        return Err(ProtocolError::malformed(format!("Invalid connect flags 0x{:02X}", flags)));
+    }
+    Ok(())
+}
@@ -1,10 +1,29 @@
 use bytes::{BufMut, BytesMut};
 use super::Packet;
-use anyhow::Result;
+use super::ProtocolError;
+use super::Properties;
+use super::ProtocolVersion;
+use super::ReasonCode;
+use super::{parse_reason_code, write_remaining_length};
 /// DISCONNECT数据包
 /// MQTT DISCONNECT数据包
-#[derive(Debug,  PartialEq, Eq)]
-pub struct DisconnectPacket;
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DisconnectPacket {
+    /// 断开原因码（仅MQTT 5.0），默认为正常断开
+    pub reason_code: ReasonCode,
+    /// DISCONNECT属性（仅MQTT 5.0）
+    pub properties: Properties,
+}
+
+impl DisconnectPacket {
+    /// 创建带原因码的DISCONNECT数据包
+    pub fn with_reason(reason_code: ReasonCode) -> Self {
+        Self {
+            reason_code,
+            properties: Properties::default(),
+        }
+    }
+}
 
 impl Packet for DisconnectPacket {
     /// 将DISCONNECT数据包序列化为字节并写入缓冲区
@@ -17,10 +36,51 @@ impl Packet for DisconnectPacket {
         // 剩余长度为0
         buf.put_u8(0x00);
     }
+
+    /// 按协议版本序列化DISCONNECT数据包
+    /// MQTT 5.0正常断开且没有属性时省略原因码
+    fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
+        if version != ProtocolVersion::V5
+            || (self.reason_code == ReasonCode::Success && self.properties.is_empty())
+        {
+            return self.write(buf);
+        }
+
+        let mut properties = BytesMut::new();
+        self.properties.write(&mut properties);
+
+        let packet_type = super::PacketType::Disconnect as u8;
+        buf.put_u8(packet_type << 4);
+        write_remaining_length(buf, 1 + properties.len());
+        buf.put_u8(self.reason_code as u8);
+        buf.put_slice(&properties);
+    }
     
     /// 从BytesMut解析DISCONNECT数据包
-    fn parse(_input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
+    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self, ProtocolError> {
         // DISCONNECT数据包没有可变头部和负载
-        Ok(DisconnectPacket)
+        if !input.is_empty() {
+            return Err(ProtocolError::malformed("DISCONNECT must not have a payload"));
+        }
+        Ok(DisconnectPacket::default())
+    }
+
+    /// 按协议版本解析DISCONNECT数据包
+    fn parse_versioned(input: &mut BytesMut, flags: Option<u8>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        if version != ProtocolVersion::V5 || input.is_empty() {
+            return Self::parse(input, flags);
+        }
+
+        let reason_code = parse_reason_code(input)?;
+        let properties = if input.is_empty() {
+            Properties::default()
+        } else {
+            Properties::parse(input)?
+        };
+
+        Ok(DisconnectPacket {
+            reason_code,
+            properties,
+        })
     }
 }
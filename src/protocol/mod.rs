@@ -1,31 +1,105 @@
-use anyhow::Result;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::fmt;
 /// MQTT数据包trait，定义了数据包的基本操作
 pub trait Packet {
     /// 将数据包序列化为字节并写入缓冲区
     fn write(&self, buf: &mut BytesMut);
 
     /// 从BytesMut解析数据包
-    fn parse(input: &mut BytesMut, flags: Option<u8>) -> Result<Self>
+    fn parse(input: &mut BytesMut, flags: Option<u8>) -> Result<Self, ProtocolError>
     where
         Self: Sized;
+
+    /// 按协议版本序列化数据包，默认与MQTT 3.1.1编码一致
+    fn write_versioned(&self, buf: &mut BytesMut, _version: ProtocolVersion) {
+        self.write(buf)
+    }
+
+    /// 按协议版本解析数据包，默认与MQTT 3.1.1编码一致
+    fn parse_versioned(input: &mut BytesMut, flags: Option<u8>, _version: ProtocolVersion) -> Result<Self, ProtocolError>
+    where
+        Self: Sized,
+    {
+        Self::parse(input, flags)
+    }
 }
 
+pub mod auth;
+pub mod codec;
 pub mod connack;
 pub mod connect;
 pub mod disconnect;
 pub mod pingreq;
 pub mod pingresp;
+pub mod properties;
 pub mod puback;
 pub mod pubcomp;
 pub mod publish;
 pub mod pubrec;
 pub mod pubrel;
+pub mod reason;
 pub mod suback;
 pub mod subscribe;
 pub mod unsuback;
 pub mod unsubscribe;
 
+/// MQTT协议错误
+///
+/// 所有解析函数都返回该错误，调用方据此决定等待更多数据、以CONNACK拒绝连接，
+/// 或者按对应的MQTT 5.0原因码断开连接
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// 数据不完整，至少还需要`needed`字节，可以在收到更多数据后重试
+    Incomplete { needed: usize },
+    /// 数据包格式错误：长度与内容不符、保留位不为0、取值超出范围等
+    MalformedPacket(String),
+    /// 数据包格式正确，但违反协议规则
+    ProtocolViolation(String),
+    /// 数据包长度（包括固定头）超过允许的最大值
+    PayloadTooLarge { size: usize, max: usize },
+    /// 字符串不是合法的UTF-8编码，或包含U+0000
+    InvalidUtf8,
+    /// CONNECT中的协议名和协议级别不是受支持的MQTT版本
+    UnsupportedVersion(u8),
+}
+
+impl ProtocolError {
+    pub fn malformed(message: impl Into<String>) -> Self {
+        Self::MalformedPacket(message.into())
+    }
+
+    pub fn violation(message: impl Into<String>) -> Self {
+        Self::ProtocolViolation(message.into())
+    }
+
+    /// 断开连接时发送的MQTT 5.0原因码
+    ///
+    /// 完整数据包中出现的数据不足说明剩余长度与内容不符，按格式错误处理
+    pub fn reason_code(&self) -> ReasonCode {
+        match self {
+            Self::Incomplete { .. } | Self::MalformedPacket(_) | Self::InvalidUtf8 => ReasonCode::MalformedPacket,
+            Self::ProtocolViolation(_) => ReasonCode::ProtocolError,
+            Self::PayloadTooLarge { .. } => ReasonCode::PacketTooLarge,
+            Self::UnsupportedVersion(_) => ReasonCode::UnsupportedProtocolVersion,
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Incomplete { needed } => write!(f, "Incomplete packet: {} more bytes needed", needed),
+            Self::MalformedPacket(message) => write!(f, "Malformed packet: {}", message),
+            Self::ProtocolViolation(message) => write!(f, "Protocol violation: {}", message),
+            Self::PayloadTooLarge { size, max } => write!(f, "Packet size {} exceeds maximum {}", size, max),
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 string"),
+            Self::UnsupportedVersion(level) => write!(f, "Unsupported protocol level {}", level),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
 /// 写入MQTT剩余长度
 /// MQTT剩余长度使用可变长度编码，最多4字节
 pub fn write_remaining_length(buf: &mut BytesMut, length: usize) {
@@ -43,6 +117,102 @@ pub fn write_remaining_length(buf: &mut BytesMut, length: usize) {
     }
 }
 
+/// 解析MQTT可变字节整数
+/// 每字节低7位为数据，最高位表示后续是否还有字节，最多4字节
+pub fn parse_variable_byte_integer(input: &mut BytesMut) -> Result<usize, ProtocolError> {
+    let mut value = 0usize;
+    let mut multiplier = 1usize;
+
+    for _ in 0..4 {
+        if input.is_empty() {
+            return Err(ProtocolError::malformed("Insufficient data for variable byte integer"));
+        }
+
+        let byte = input.get_u8();
+        value += ((byte & 0x7F) as usize) * multiplier;
+
+        if (byte & 0x80) == 0 {
+            return Ok(value);
+        }
+
+        multiplier *= 128;
+    }
+
+    Err(ProtocolError::malformed("Invalid variable byte integer: more than 4 bytes"))
+}
+
+/// 写入MQTT 5.0确认类数据包（PUBACK/PUBREC/PUBREL/PUBCOMP）
+/// 原因码为成功且没有属性时省略原因码和属性
+pub(crate) fn write_ack_v5(
+    buf: &mut BytesMut,
+    first_byte: u8,
+    packet_id: u16,
+    reason_code: ReasonCode,
+    properties: &Properties,
+) {
+    buf.put_u8(first_byte);
+
+    if properties.is_empty() {
+        if reason_code == ReasonCode::Success {
+            write_remaining_length(buf, 2);
+            buf.put_u16(packet_id);
+        } else {
+            write_remaining_length(buf, 3);
+            buf.put_u16(packet_id);
+            buf.put_u8(reason_code as u8);
+        }
+        return;
+    }
+
+    let mut props = BytesMut::new();
+    properties.write(&mut props);
+    write_remaining_length(buf, 3 + props.len());
+    buf.put_u16(packet_id);
+    buf.put_u8(reason_code as u8);
+    buf.put_slice(&props);
+}
+
+/// 解析MQTT 5.0确认类数据包（PUBACK/PUBREC/PUBREL/PUBCOMP）
+/// 剩余长度为2时原因码默认为成功
+pub(crate) fn parse_ack_v5(input: &mut BytesMut) -> Result<(u16, ReasonCode, Properties), ProtocolError> {
+    let packet_id = parse_packet_id(input)?;
+
+    let reason_code = if input.is_empty() {
+        ReasonCode::Success
+    } else {
+        parse_reason_code(input)?
+    };
+
+    let properties = if input.is_empty() {
+        Properties::default()
+    } else {
+        Properties::parse(input)?
+    };
+
+    Ok((packet_id, reason_code, properties))
+}
+
+/// 解析数据包ID，数据包ID不能为0
+pub(crate) fn parse_packet_id(input: &mut BytesMut) -> Result<u16, ProtocolError> {
+    if input.len() < 2 {
+        return Err(ProtocolError::malformed("Insufficient data for packet ID"));
+    }
+    match input.get_u16() {
+        0 => Err(ProtocolError::violation("Packet ID must not be 0")),
+        packet_id => Ok(packet_id),
+    }
+}
+
+/// 解析单字节的MQTT 5.0原因码
+pub(crate) fn parse_reason_code(input: &mut BytesMut) -> Result<ReasonCode, ProtocolError> {
+    if input.is_empty() {
+        return Err(ProtocolError::malformed("Insufficient data for reason code"));
+    }
+    let value = input.get_u8();
+    ReasonCode::from_u8(value)
+        .ok_or_else(|| ProtocolError::malformed(format!("Invalid reason code 0x{:02X}", value)))
+}
+
 /// 写入MQTT字符串
 /// MQTT字符串由两字节长度前缀和UTF-8编码的字符串内容组成
 pub fn write_mqtt_string(buf: &mut BytesMut, s: &str) {
@@ -60,42 +230,37 @@ pub fn write_mqtt_bytes(buf: &mut BytesMut, bytes: &[u8]) {
 }
 
 /// 解析MQTT字符串
-/// MQTT字符串由两字节长度前缀和UTF-8编码的字符串内容组成
-pub fn parse_mqtt_string(input: &mut BytesMut) -> Result<String> {
+/// MQTT字符串由两字节长度前缀和UTF-8编码的字符串内容组成，不能包含U+0000
+pub fn parse_mqtt_string(input: &mut BytesMut) -> Result<String, ProtocolError> {
     if input.len() < 2 {
-        return Err(anyhow::format_err!(
-            "Insufficient data for MQTT string length"
-        ));
+        return Err(ProtocolError::malformed("Insufficient data for MQTT string length"));
     }
 
     let length = input.get_u16() as usize;
 
     if input.len() < length {
-        return Err(anyhow::format_err!(
-            "Insufficient data for MQTT string content"
-        ));
+        return Err(ProtocolError::malformed("Insufficient data for MQTT string content"));
     }
 
     let bytes = input.split_to(length);
-    let string = String::from_utf8_lossy(&bytes).to_string();
+    let string = String::from_utf8(bytes.to_vec()).map_err(|_| ProtocolError::InvalidUtf8)?;
+    if string.contains('\0') {
+        return Err(ProtocolError::InvalidUtf8);
+    }
     Ok(string)
 }
 
 /// 解析MQTT二进制数据
 /// MQTT二进制数据由两字节长度前缀和字节内容组成
-pub fn parse_mqtt_bytes(input: &mut BytesMut) -> Result<Bytes> {
+pub fn parse_mqtt_bytes(input: &mut BytesMut) -> Result<Bytes, ProtocolError> {
     if input.len() < 2 {
-        return Err(anyhow::format_err!(
-            "Insufficient data for MQTT bytes length"
-        ));
+        return Err(ProtocolError::malformed("Insufficient data for MQTT bytes length"));
     }
 
     let length = input.get_u16() as usize;
 
     if input.len() < length {
-        return Err(anyhow::format_err!(
-            "Insufficient data for MQTT bytes content"
-        ));
+        return Err(ProtocolError::malformed("Insufficient data for MQTT bytes content"));
     }
 
     let bytes = input.split_to(length);
@@ -106,107 +271,120 @@ pub fn parse_mqtt_bytes(input: &mut BytesMut) -> Result<Bytes> {
 impl MqttPacket {
     /// 将MQTT数据包序列化为字节并写入缓冲区
     pub fn write(&self, buf: &mut BytesMut) {
+        self.write_versioned(buf, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本将MQTT数据包序列化为字节并写入缓冲区
+    pub fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
         match self {
-            MqttPacket::Connect(packet) => packet.write(buf),
-            MqttPacket::ConnAck(packet) => packet.write(buf),
-            MqttPacket::Publish(packet) => packet.write(buf),
-            MqttPacket::PubAck(packet) => packet.write(buf),
-            MqttPacket::PubRec(packet) => packet.write(buf),
-            MqttPacket::PubRel(packet) => packet.write(buf),
-            MqttPacket::PubComp(packet) => packet.write(buf),
-            MqttPacket::Subscribe(packet) => packet.write(buf),
-            MqttPacket::SubAck(packet) => packet.write(buf),
-            MqttPacket::Unsubscribe(packet) => packet.write(buf),
-            MqttPacket::UnsubAck(packet) => packet.write(buf),
-            MqttPacket::PingReq(packet) => packet.write(buf),
-            MqttPacket::PingResp(packet) => packet.write(buf),
-            MqttPacket::Disconnect(packet) => packet.write(buf),
+            MqttPacket::Connect(packet) => packet.write_versioned(buf, version),
+            MqttPacket::ConnAck(packet) => packet.write_versioned(buf, version),
+            MqttPacket::Publish(packet) => packet.write_versioned(buf, version),
+            MqttPacket::PubAck(packet) => packet.write_versioned(buf, version),
+            MqttPacket::PubRec(packet) => packet.write_versioned(buf, version),
+            MqttPacket::PubRel(packet) => packet.write_versioned(buf, version),
+            MqttPacket::PubComp(packet) => packet.write_versioned(buf, version),
+            MqttPacket::Subscribe(packet) => packet.write_versioned(buf, version),
+            MqttPacket::SubAck(packet) => packet.write_versioned(buf, version),
+            MqttPacket::Unsubscribe(packet) => packet.write_versioned(buf, version),
+            MqttPacket::UnsubAck(packet) => packet.write_versioned(buf, version),
+            MqttPacket::PingReq(packet) => packet.write_versioned(buf, version),
+            MqttPacket::PingResp(packet) => packet.write_versioned(buf, version),
+            MqttPacket::Disconnect(packet) => packet.write_versioned(buf, version),
+            MqttPacket::Auth(packet) => packet.write_versioned(buf, version),
         }
     }
 
     /// 从BytesMut解析MQTT数据包
-    pub fn read(buffer: &mut BytesMut) -> Result<MqttPacket> {
-        // 将输入转换为BytesMut
-        // let mut buffer = BytesMut::from(input);
-
-        // 解析固定头
-        let fixed_header = FixedHeader::parse(buffer)?;
-
-        // 检查剩余数据长度是否足够
-        if buffer.len() < fixed_header.remaining_length {
-            return Err(anyhow::format_err!(
-                "Insufficient data for remaining length"
-            ));
+    pub fn read(buffer: &mut BytesMut) -> Result<MqttPacket, ProtocolError> {
+        Self::read_versioned(buffer, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本从BytesMut解析MQTT数据包
+    ///
+    /// 数据不足一个完整数据包时返回`ProtocolError::Incomplete`且不消费缓冲区
+    pub fn read_versioned(buffer: &mut BytesMut, version: ProtocolVersion) -> Result<MqttPacket, ProtocolError> {
+        // 预读固定头，检查剩余数据长度是否足够
+        let (fixed_header, header_len) = FixedHeader::peek(buffer)?;
+        let frame_len = header_len + fixed_header.remaining_length;
+        if buffer.len() < frame_len {
+            return Err(ProtocolError::Incomplete {
+                needed: frame_len - buffer.len(),
+            });
         }
 
         // 提取剩余的数据部分
+        buffer.advance(header_len);
         let mut remaining_data = buffer.split_to(fixed_header.remaining_length);
 
         // 根据数据包类型解析剩余部分
-        let packet = match fixed_header.packet_type {
+        match fixed_header.packet_type {
             PacketType::Connect => {
-                let connect_packet = connect::ConnectPacket::parse(&mut remaining_data, None)?;
+                let connect_packet = connect::ConnectPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::Connect(connect_packet))
             }
             PacketType::ConnAck => {
-                let connack_packet = connack::ConnAckPacket::parse(&mut remaining_data, None)?;
+                let connack_packet = connack::ConnAckPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::ConnAck(connack_packet))
             }
             PacketType::Publish => {
                 let publish_packet =
-                    publish::PublishPacket::parse(&mut remaining_data, Some(fixed_header.flags))?;
+                    publish::PublishPacket::parse_versioned(&mut remaining_data, Some(fixed_header.flags), version)?;
                 Ok(MqttPacket::Publish(publish_packet))
             }
             PacketType::PubAck => {
-                let puback_packet = puback::PubAckPacket::parse(&mut remaining_data, None)?;
+                let puback_packet = puback::PubAckPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::PubAck(puback_packet))
             }
             PacketType::PubRec => {
-                let pubrec_packet = pubrec::PubRecPacket::parse(&mut remaining_data, None)?;
+                let pubrec_packet = pubrec::PubRecPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::PubRec(pubrec_packet))
             }
             PacketType::PubRel => {
-                let pubrel_packet = pubrel::PubRelPacket::parse(&mut remaining_data, None)?;
+                let pubrel_packet = pubrel::PubRelPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::PubRel(pubrel_packet))
             }
             PacketType::PubComp => {
-                let pubcomp_packet = pubcomp::PubCompPacket::parse(&mut remaining_data, None)?;
+                let pubcomp_packet = pubcomp::PubCompPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::PubComp(pubcomp_packet))
             }
             PacketType::Subscribe => {
                 let subscribe_packet =
-                    subscribe::SubscribePacket::parse(&mut remaining_data, None)?;
+                    subscribe::SubscribePacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::Subscribe(subscribe_packet))
             }
             PacketType::SubAck => {
-                let suback_packet = suback::SubAckPacket::parse(&mut remaining_data, None)?;
+                let suback_packet = suback::SubAckPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::SubAck(suback_packet))
             }
             PacketType::Unsubscribe => {
                 let unsubscribe_packet =
-                    unsubscribe::UnsubscribePacket::parse(&mut remaining_data, None)?;
+                    unsubscribe::UnsubscribePacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::Unsubscribe(unsubscribe_packet))
             }
             PacketType::UnsubAck => {
-                let unsuback_packet = unsuback::UnsubAckPacket::parse(&mut remaining_data, None)?;
+                let unsuback_packet = unsuback::UnsubAckPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::UnsubAck(unsuback_packet))
             }
             PacketType::PingReq => {
-                let pingreq_packet = pingreq::PingReqPacket::parse(&mut remaining_data, None)?;
+                let pingreq_packet = pingreq::PingReqPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::PingReq(pingreq_packet))
             }
             PacketType::PingResp => {
-                let pingresp_packet = pingresp::PingRespPacket::parse(&mut remaining_data, None)?;
+                let pingresp_packet = pingresp::PingRespPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::PingResp(pingresp_packet))
             }
             PacketType::Disconnect => {
                 let disconnect_packet =
-                    disconnect::DisconnectPacket::parse(&mut remaining_data, None)?;
+                    disconnect::DisconnectPacket::parse_versioned(&mut remaining_data, None, version)?;
                 Ok(MqttPacket::Disconnect(disconnect_packet))
             }
-        };
-        buffer.clear();
-        packet
+            PacketType::Auth => {
+                let auth_packet =
+                    auth::AuthPacket::parse_versioned(&mut remaining_data, None, version)?;
+                Ok(MqttPacket::Auth(auth_packet))
+            }
+        }
     }
 }
 
@@ -221,66 +399,72 @@ pub struct FixedHeader {
 
 impl FixedHeader {
     /// 解析MQTT固定头
-    pub fn parse(input: &mut BytesMut) -> Result<Self> {
+    pub fn parse(input: &mut BytesMut) -> Result<Self, ProtocolError> {
+        let (header, header_len) = Self::peek(input)?;
+        input.advance(header_len);
+        Ok(header)
+    }
+
+    /// 在不消费数据的情况下预读MQTT固定头
+    ///
+    /// 返回固定头及其占用的字节数，数据不足以构成完整固定头时返回`ProtocolError::Incomplete`
+    pub fn peek(input: &[u8]) -> Result<(Self, usize), ProtocolError> {
         // 至少需要1字节来读取消息类型和标志位
-        if input.len() < 1 {
-            return Err(anyhow::format_err!("Insufficient data for fixed header"));
-        }
+        let Some(&first_byte) = input.first() else {
+            return Err(ProtocolError::Incomplete { needed: 1 });
+        };
 
         // 读取第一个字节：高4位是消息类型，低4位是标志位
-        let first_byte = input.get_u8();
         let packet_type_value = (first_byte >> 4) & 0x0F;
         let flags = first_byte & 0x0F;
 
-        // 解析剩余长度
-        let mut remaining_length = 0;
-        let mut multiplier = 1;
-        let mut bytes_read = 0;
+        let packet_type = PacketType::from_u8(packet_type_value)
+            .ok_or_else(|| ProtocolError::malformed(format!("Invalid packet type {}", packet_type_value)))?;
+        validate_flags(packet_type, flags)?;
 
-        loop {
-            if input.is_empty() {
-                return Err(anyhow::format_err!(
-                    "Insufficient data for remaining length"
-                ));
-            }
+        // 解析剩余长度（可变长度编码，最多4字节）
+        let mut remaining_length: usize = 0;
+        let mut multiplier: usize = 1;
 
-            let byte = input.get_u8();
+        for bytes_read in 1..=4 {
+            let Some(&byte) = input.get(bytes_read) else {
+                return Err(ProtocolError::Incomplete { needed: 1 });
+            };
 
-            remaining_length += ((byte & 0x7F) as u32) * multiplier;
-            bytes_read += 1;
+            remaining_length += ((byte & 0x7F) as usize) * multiplier;
 
             // 检查是否有更多字节（最高位为1表示后续还有字节）
             if (byte & 0x80) == 0 {
-                break;
-            }
-
-            // 检查剩余长度是否超过4字节（MQTT协议限制）
-            if bytes_read > 3 {
-                return Err(anyhow::format_err!(
-                    "Invalid remaining length: more than 4 bytes"
-                ));
+                let header = Self {
+                    packet_type,
+                    flags,
+                    remaining_length,
+                };
+                return Ok((header, 1 + bytes_read));
             }
 
             // 更新乘数（每次乘以128）
             multiplier *= 128;
-
-            // 检查是否溢出
-            if multiplier > 128 * 128 * 128 * 128 {
-                return Err(anyhow::format_err!(
-                    "Invalid remaining length: multiplier overflow"
-                ));
-            }
         }
 
-        let packet_type = PacketType::from_u8(packet_type_value)
-            .ok_or(anyhow::format_err!("Invalid packet type"))?;
+        // 剩余长度超过4字节（MQTT协议限制）
+        Err(ProtocolError::malformed("Invalid remaining length: more than 4 bytes"))
+    }
+}
 
-        Ok(Self {
-            packet_type,
-            flags,
-            remaining_length: remaining_length as usize,
-        })
+/// 检查固定头标志位
+///
+/// PUBREL、SUBSCRIBE和UNSUBSCRIBE的标志位必须为0x02，PUBLISH的QoS不能为3，其余数据包的标志位必须为0
+fn validate_flags(packet_type: PacketType, flags: u8) -> Result<(), ProtocolError> {
+    let valid = match packet_type {
+        PacketType::Publish => (flags & 0x06) != 0x06,
+        PacketType::PubRel | PacketType::Subscribe | PacketType::Unsubscribe => flags == 0x02,
+        _ => flags == 0x00,
+    };
+    if !valid {
+        return Err(ProtocolError::malformed(format!("Invalid flags 0x{:X} for {:?}", flags, packet_type)));
     }
+    Ok(())
 }
 
 /// MQTT控制报文类型
@@ -300,6 +484,7 @@ pub enum PacketType {
     PingReq = 12,
     PingResp = 13,
     Disconnect = 14,
+    Auth = 15,
 }
 
 impl PacketType {
@@ -319,6 +504,7 @@ impl PacketType {
             12 => Some(Self::PingReq),
             13 => Some(Self::PingResp),
             14 => Some(Self::Disconnect),
+            15 => Some(Self::Auth),
             _ => None,
         }
     }
@@ -350,6 +536,8 @@ impl ConnectReturnCode {
 }
 
 /// MQTT数据包
+// CONNECT携带连接属性和遗嘱属性，每个连接只出现一次，不值得装箱
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, PartialEq, Eq)]
 pub enum MqttPacket {
     Connect(connect::ConnectPacket),
@@ -366,18 +554,46 @@ pub enum MqttPacket {
     PingReq(pingreq::PingReqPacket),
     PingResp(pingresp::PingRespPacket),
     Disconnect(disconnect::DisconnectPacket),
+    Auth(auth::AuthPacket),
+}
+
+/// MQTT协议版本
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ProtocolVersion {
+    /// MQTT 3.1（协议名MQIsdp）
+    V31 = 3,
+    /// MQTT 3.1.1
+    #[default]
+    V311 = 4,
+    /// MQTT 5.0
+    V5 = 5,
+}
+
+impl ProtocolVersion {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            3 => Some(Self::V31),
+            4 => Some(Self::V311),
+            5 => Some(Self::V5),
+            _ => None,
+        }
+    }
 }
 
+pub use auth::*;
+pub use codec::*;
 pub use connack::*;
 pub use connect::*;
 pub use disconnect::*;
 pub use pingreq::*;
 pub use pingresp::*;
+pub use properties::Properties;
 pub use puback::*;
 pub use pubcomp::*;
 pub use publish::*;
 pub use pubrec::*;
 pub use pubrel::*;
+pub use reason::*;
 pub use suback::*;
 pub use subscribe::*;
 pub use unsuback::*;
@@ -1,6 +1,6 @@
 use bytes::{BufMut, BytesMut};
 use super::Packet;
-use anyhow::Result;
+use super::ProtocolError;
 /// PINGREQ数据包
 /// MQTT PINGREQ数据包
 #[derive(Debug,  PartialEq, Eq)]
@@ -19,8 +19,11 @@ impl Packet for PingReqPacket {
     }
     
     /// 从BytesMut解析PINGREQ数据包
-    fn parse(_input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
+    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self, ProtocolError> {
         // PINGREQ数据包没有可变头部和负载
+        if !input.is_empty() {
+            return Err(ProtocolError::malformed("PINGREQ must not have a payload"));
+        }
         Ok(PingReqPacket)
     }
 }
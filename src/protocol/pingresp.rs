@@ -1,5 +1,6 @@
 use bytes::{BufMut, BytesMut};
-use super::Packet;use anyhow::Result;
+use super::Packet;
+use super::ProtocolError;
 
 /// PINGRESP数据包
 /// MQTT PINGRESP数据包
@@ -19,8 +20,11 @@ impl Packet for PingRespPacket {
     }
     
     /// 从BytesMut解析PINGRESP数据包
-    fn parse(_input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
+    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self, ProtocolError> {
         // PINGRESP数据包没有可变头部和负载
+        if !input.is_empty() {
+            return Err(ProtocolError::malformed("PINGRESP must not have a payload"));
+        }
         Ok(PingRespPacket)
     }
 }
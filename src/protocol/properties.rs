@@ -0,0 +1,278 @@
+use super::parse_mqtt_bytes;
+use super::parse_mqtt_string;
+use super::parse_variable_byte_integer;
+use super::ProtocolError;
+use super::write_mqtt_bytes;
+use super::write_mqtt_string;
+use super::write_remaining_length;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// MQTT 5.0属性标识符
+pub mod property_id {
+    pub const PAYLOAD_FORMAT_INDICATOR: u8 = 0x01;
+    pub const MESSAGE_EXPIRY_INTERVAL: u8 = 0x02;
+    pub const CONTENT_TYPE: u8 = 0x03;
+    pub const RESPONSE_TOPIC: u8 = 0x08;
+    pub const CORRELATION_DATA: u8 = 0x09;
+    pub const SUBSCRIPTION_IDENTIFIER: u8 = 0x0B;
+    pub const SESSION_EXPIRY_INTERVAL: u8 = 0x11;
+    pub const ASSIGNED_CLIENT_IDENTIFIER: u8 = 0x12;
+    pub const SERVER_KEEP_ALIVE: u8 = 0x13;
+    pub const AUTHENTICATION_METHOD: u8 = 0x15;
+    pub const AUTHENTICATION_DATA: u8 = 0x16;
+    pub const REQUEST_PROBLEM_INFORMATION: u8 = 0x17;
+    pub const WILL_DELAY_INTERVAL: u8 = 0x18;
+    pub const REQUEST_RESPONSE_INFORMATION: u8 = 0x19;
+    pub const RESPONSE_INFORMATION: u8 = 0x1A;
+    pub const SERVER_REFERENCE: u8 = 0x1C;
+    pub const REASON_STRING: u8 = 0x1F;
+    pub const RECEIVE_MAXIMUM: u8 = 0x21;
+    pub const TOPIC_ALIAS_MAXIMUM: u8 = 0x22;
+    pub const TOPIC_ALIAS: u8 = 0x23;
+    pub const MAXIMUM_QOS: u8 = 0x24;
+    pub const RETAIN_AVAILABLE: u8 = 0x25;
+    pub const USER_PROPERTY: u8 = 0x26;
+    pub const MAXIMUM_PACKET_SIZE: u8 = 0x27;
+    pub const WILDCARD_SUBSCRIPTION_AVAILABLE: u8 = 0x28;
+    pub const SUBSCRIPTION_IDENTIFIER_AVAILABLE: u8 = 0x29;
+    pub const SHARED_SUBSCRIPTION_AVAILABLE: u8 = 0x2A;
+}
+
+/// MQTT 5.0属性集合
+///
+/// 所有属性均为可选，MQTT 3.1.1编码时忽略。
+/// 用户属性和订阅标识符允许出现多次，其余属性最多出现一次
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub payload_format_indicator: Option<u8>,
+    pub message_expiry_interval: Option<u32>,
+    pub content_type: Option<String>,
+    pub response_topic: Option<String>,
+    pub correlation_data: Option<Bytes>,
+    pub subscription_identifiers: Vec<u32>,
+    pub session_expiry_interval: Option<u32>,
+    pub assigned_client_identifier: Option<String>,
+    pub server_keep_alive: Option<u16>,
+    pub authentication_method: Option<String>,
+    pub authentication_data: Option<Bytes>,
+    pub request_problem_information: Option<u8>,
+    pub will_delay_interval: Option<u32>,
+    pub request_response_information: Option<u8>,
+    pub response_information: Option<String>,
+    pub server_reference: Option<String>,
+    pub reason_string: Option<String>,
+    pub receive_maximum: Option<u16>,
+    pub topic_alias_maximum: Option<u16>,
+    pub topic_alias: Option<u16>,
+    pub maximum_qos: Option<u8>,
+    pub retain_available: Option<u8>,
+    pub user_properties: Vec<(String, String)>,
+    pub maximum_packet_size: Option<u32>,
+    pub wildcard_subscription_available: Option<u8>,
+    pub subscription_identifier_available: Option<u8>,
+    pub shared_subscription_available: Option<u8>,
+}
+
+/// 设置只允许出现一次的属性，重复出现时返回协议错误
+fn set_once<T>(slot: &mut Option<T>, value: T, id: u8) -> Result<(), ProtocolError> {
+    if slot.is_some() {
+        return Err(ProtocolError::violation(format!("Duplicate property 0x{:02X}", id)));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+fn parse_u8(input: &mut BytesMut) -> Result<u8, ProtocolError> {
+    if input.is_empty() {
+        return Err(ProtocolError::malformed("Insufficient data for byte property"));
+    }
+    Ok(input.get_u8())
+}
+
+/// 解析取值只能为0或1的单字节属性
+fn parse_flag(input: &mut BytesMut, id: u8) -> Result<u8, ProtocolError> {
+    match parse_u8(input)? {
+        value @ (0 | 1) => Ok(value),
+        value => Err(ProtocolError::violation(format!("Invalid value {} for property 0x{:02X}", value, id))),
+    }
+}
+
+/// 解析不能为0的整数属性
+fn non_zero<T: Default + PartialEq>(value: T, id: u8) -> Result<T, ProtocolError> {
+    if value == T::default() {
+        return Err(ProtocolError::violation(format!("Property 0x{:02X} must not be 0", id)));
+    }
+    Ok(value)
+}
+
+fn parse_u16(input: &mut BytesMut) -> Result<u16, ProtocolError> {
+    if input.len() < 2 {
+        return Err(ProtocolError::malformed("Insufficient data for two byte property"));
+    }
+    Ok(input.get_u16())
+}
+
+fn parse_u32(input: &mut BytesMut) -> Result<u32, ProtocolError> {
+    if input.len() < 4 {
+        return Err(ProtocolError::malformed("Insufficient data for four byte property"));
+    }
+    Ok(input.get_u32())
+}
+
+impl Properties {
+    /// 属性集合是否为空
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 解析属性长度前缀及其后的属性列表
+    pub fn parse(input: &mut BytesMut) -> Result<Self, ProtocolError> {
+        let length = parse_variable_byte_integer(input)?;
+        if input.len() < length {
+            return Err(ProtocolError::malformed("Insufficient data for properties"));
+        }
+
+        let mut data = input.split_to(length);
+        let mut properties = Properties::default();
+
+        while !data.is_empty() {
+            let id = parse_variable_byte_integer(&mut data)?;
+            let id = u8::try_from(id)
+                .map_err(|_| ProtocolError::malformed(format!("Invalid property identifier {}", id)))?;
+            properties.parse_property(id, &mut data)?;
+        }
+
+        Ok(properties)
+    }
+
+    /// 解析单个属性值
+    fn parse_property(&mut self, id: u8, data: &mut BytesMut) -> Result<(), ProtocolError> {
+        use property_id::*;
+
+        match id {
+            PAYLOAD_FORMAT_INDICATOR => set_once(&mut self.payload_format_indicator, parse_flag(data, id)?, id),
+            MESSAGE_EXPIRY_INTERVAL => set_once(&mut self.message_expiry_interval, parse_u32(data)?, id),
+            CONTENT_TYPE => set_once(&mut self.content_type, parse_mqtt_string(data)?, id),
+            RESPONSE_TOPIC => set_once(&mut self.response_topic, parse_mqtt_string(data)?, id),
+            CORRELATION_DATA => set_once(&mut self.correlation_data, parse_mqtt_bytes(data)?, id),
+            SUBSCRIPTION_IDENTIFIER => {
+                let value = non_zero(parse_variable_byte_integer(data)? as u32, id)?;
+                self.subscription_identifiers.push(value);
+                Ok(())
+            }
+            SESSION_EXPIRY_INTERVAL => set_once(&mut self.session_expiry_interval, parse_u32(data)?, id),
+            ASSIGNED_CLIENT_IDENTIFIER => set_once(&mut self.assigned_client_identifier, parse_mqtt_string(data)?, id),
+            SERVER_KEEP_ALIVE => set_once(&mut self.server_keep_alive, parse_u16(data)?, id),
+            AUTHENTICATION_METHOD => set_once(&mut self.authentication_method, parse_mqtt_string(data)?, id),
+            AUTHENTICATION_DATA => set_once(&mut self.authentication_data, parse_mqtt_bytes(data)?, id),
+            REQUEST_PROBLEM_INFORMATION => set_once(&mut self.request_problem_information, parse_flag(data, id)?, id),
+            WILL_DELAY_INTERVAL => set_once(&mut self.will_delay_interval, parse_u32(data)?, id),
+            REQUEST_RESPONSE_INFORMATION => set_once(&mut self.request_response_information, parse_flag(data, id)?, id),
+            RESPONSE_INFORMATION => set_once(&mut self.response_information, parse_mqtt_string(data)?, id),
+            SERVER_REFERENCE => set_once(&mut self.server_reference, parse_mqtt_string(data)?, id),
+            REASON_STRING => set_once(&mut self.reason_string, parse_mqtt_string(data)?, id),
+            RECEIVE_MAXIMUM => set_once(&mut self.receive_maximum, non_zero(parse_u16(data)?, id)?, id),
+            TOPIC_ALIAS_MAXIMUM => set_once(&mut self.topic_alias_maximum, parse_u16(data)?, id),
+            TOPIC_ALIAS => set_once(&mut self.topic_alias, parse_u16(data)?, id),
+            MAXIMUM_QOS => set_once(&mut self.maximum_qos, parse_flag(data, id)?, id),
+            RETAIN_AVAILABLE => set_once(&mut self.retain_available, parse_flag(data, id)?, id),
+            USER_PROPERTY => {
+                let key = parse_mqtt_string(data)?;
+                let value = parse_mqtt_string(data)?;
+                self.user_properties.push((key, value));
+                Ok(())
+            }
+            MAXIMUM_PACKET_SIZE => set_once(&mut self.maximum_packet_size, non_zero(parse_u32(data)?, id)?, id),
+            WILDCARD_SUBSCRIPTION_AVAILABLE => set_once(&mut self.wildcard_subscription_available, parse_flag(data, id)?, id),
+            SUBSCRIPTION_IDENTIFIER_AVAILABLE => set_once(&mut self.subscription_identifier_available, parse_flag(data, id)?, id),
+            SHARED_SUBSCRIPTION_AVAILABLE => set_once(&mut self.shared_subscription_available, parse_flag(data, id)?, id),
+            _ => Err(ProtocolError::malformed(format!("Unknown property identifier 0x{:02X}", id))),
+        }
+    }
+
+    /// 写入属性长度前缀及属性列表
+    pub fn write(&self, buf: &mut BytesMut) {
+        let mut data = BytesMut::new();
+        self.write_properties(&mut data);
+        write_remaining_length(buf, data.len());
+        buf.put_slice(&data);
+    }
+
+    /// 编码后的字节数（包含长度前缀）
+    pub fn encoded_len(&self) -> usize {
+        let mut buf = BytesMut::new();
+        self.write(&mut buf);
+        buf.len()
+    }
+
+    /// 写入属性列表（不含长度前缀）
+    fn write_properties(&self, buf: &mut BytesMut) {
+        use property_id::*;
+
+        fn put_u8(buf: &mut BytesMut, id: u8, value: Option<u8>) {
+            if let Some(value) = value {
+                buf.put_u8(id);
+                buf.put_u8(value);
+            }
+        }
+        fn put_u16(buf: &mut BytesMut, id: u8, value: Option<u16>) {
+            if let Some(value) = value {
+                buf.put_u8(id);
+                buf.put_u16(value);
+            }
+        }
+        fn put_u32(buf: &mut BytesMut, id: u8, value: Option<u32>) {
+            if let Some(value) = value {
+                buf.put_u8(id);
+                buf.put_u32(value);
+            }
+        }
+        fn put_string(buf: &mut BytesMut, id: u8, value: &Option<String>) {
+            if let Some(value) = value {
+                buf.put_u8(id);
+                write_mqtt_string(buf, value);
+            }
+        }
+        fn put_bytes(buf: &mut BytesMut, id: u8, value: &Option<Bytes>) {
+            if let Some(value) = value {
+                buf.put_u8(id);
+                write_mqtt_bytes(buf, value);
+            }
+        }
+
+        put_u8(buf, PAYLOAD_FORMAT_INDICATOR, self.payload_format_indicator);
+        put_u32(buf, MESSAGE_EXPIRY_INTERVAL, self.message_expiry_interval);
+        put_string(buf, CONTENT_TYPE, &self.content_type);
+        put_string(buf, RESPONSE_TOPIC, &self.response_topic);
+        put_bytes(buf, CORRELATION_DATA, &self.correlation_data);
+        for identifier in &self.subscription_identifiers {
+            buf.put_u8(SUBSCRIPTION_IDENTIFIER);
+            write_remaining_length(buf, *identifier as usize);
+        }
+        put_u32(buf, SESSION_EXPIRY_INTERVAL, self.session_expiry_interval);
+        put_string(buf, ASSIGNED_CLIENT_IDENTIFIER, &self.assigned_client_identifier);
+        put_u16(buf, SERVER_KEEP_ALIVE, self.server_keep_alive);
+        put_string(buf, AUTHENTICATION_METHOD, &self.authentication_method);
+        put_bytes(buf, AUTHENTICATION_DATA, &self.authentication_data);
+        put_u8(buf, REQUEST_PROBLEM_INFORMATION, self.request_problem_information);
+        put_u32(buf, WILL_DELAY_INTERVAL, self.will_delay_interval);
+        put_u8(buf, REQUEST_RESPONSE_INFORMATION, self.request_response_information);
+        put_string(buf, RESPONSE_INFORMATION, &self.response_information);
+        put_string(buf, SERVER_REFERENCE, &self.server_reference);
+        put_string(buf, REASON_STRING, &self.reason_string);
+        put_u16(buf, RECEIVE_MAXIMUM, self.receive_maximum);
+        put_u16(buf, TOPIC_ALIAS_MAXIMUM, self.topic_alias_maximum);
+        put_u16(buf, TOPIC_ALIAS, self.topic_alias);
+        put_u8(buf, MAXIMUM_QOS, self.maximum_qos);
+        put_u8(buf, RETAIN_AVAILABLE, self.retain_available);
+        for (key, value) in &self.user_properties {
+            buf.put_u8(USER_PROPERTY);
+            write_mqtt_string(buf, key);
+            write_mqtt_string(buf, value);
+        }
+        put_u32(buf, MAXIMUM_PACKET_SIZE, self.maximum_packet_size);
+        put_u8(buf, WILDCARD_SUBSCRIPTION_AVAILABLE, self.wildcard_subscription_available);
+        put_u8(buf, SUBSCRIPTION_IDENTIFIER_AVAILABLE, self.subscription_identifier_available);
+        put_u8(buf, SHARED_SUBSCRIPTION_AVAILABLE, self.shared_subscription_available);
+    }
+}
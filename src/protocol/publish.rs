@@ -1,9 +1,12 @@
 use super::Packet;
+use super::ProtocolError;
 use super::parse_mqtt_string;
+use super::parse_packet_id;
 use super::write_mqtt_string;
 use super::write_remaining_length;
-use anyhow::Result;
-use bytes::{Buf, BufMut, Bytes, BytesMut};
+use super::Properties;
+use super::ProtocolVersion;
+use bytes::{BufMut, Bytes, BytesMut};
 
 /// PUBLISH数据包
 #[derive(Debug,Clone,  PartialEq, Eq)]
@@ -14,13 +17,23 @@ pub struct PublishPacket {
     pub topic_name: String,
     pub packet_id: Option<u16>,
     pub payload: Bytes,
+    /// PUBLISH属性（仅MQTT 5.0）
+    pub properties: Properties,
 }
 
-/// 解析PUBLISH数据包
-
 impl Packet for PublishPacket {
     /// 将PUBLISH数据包序列化为字节并写入缓冲区
     fn write(&self, buf: &mut BytesMut) {
+        self.write_versioned(buf, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本序列化PUBLISH数据包，MQTT 5.0在数据包ID之后写入属性
+    fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
+        let mut properties = BytesMut::new();
+        if version == ProtocolVersion::V5 {
+            self.properties.write(&mut properties);
+        }
+
         // 计算可变头和载荷长度
         let mut variable_header_length = 0;
 
@@ -32,6 +45,9 @@ impl Packet for PublishPacket {
             variable_header_length += 2;
         }
 
+        // 属性长度
+        variable_header_length += properties.len();
+
         // 载荷长度
         let payload_length = self.payload.len();
 
@@ -61,37 +77,57 @@ impl Packet for PublishPacket {
         write_mqtt_string(buf, &self.topic_name);
 
         // 数据包ID（仅当QoS > 0时）
-        if self.qos > 0 {
-            if let Some(id) = self.packet_id {
-                buf.put_u16(id);
-            }
+        if self.qos > 0
+            && let Some(id) = self.packet_id
+        {
+            buf.put_u16(id);
         }
 
+        // 属性
+        buf.put_slice(&properties);
+
         // 写入载荷
         buf.put_slice(&self.payload);
     }
 
     /// 从BytesMut解析PUBLISH数据包
     /// 注意：此方法使用默认的flags值，实际使用中应使用parse_publish函数
-    fn parse(input: &mut BytesMut, flags: Option<u8>) -> Result<Self> {
+    fn parse(input: &mut BytesMut, flags: Option<u8>) -> Result<Self, ProtocolError> {
+        Self::parse_versioned(input, flags, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本解析PUBLISH数据包
+    ///
+    /// QoS不能为3，主题名不能包含通配符；MQTT 5.0中主题名可以为空，由主题别名还原
+    fn parse_versioned(input: &mut BytesMut, flags: Option<u8>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
         let flags = flags.unwrap_or(0x00);
         let dup = (flags & 0x08) != 0;
         let qos = (flags & 0x06) >> 1;
         let retain = (flags & 0x01) != 0;
+        if qos > 2 {
+            return Err(ProtocolError::malformed("PUBLISH QoS must not be 3"));
+        }
 
         let topic_name = parse_mqtt_string(input)?;
+        if topic_name.contains(['+', '#']) {
+            return Err(ProtocolError::violation(format!("Topic name {} contains wildcards", topic_name)));
+        }
+        if topic_name.is_empty() && version != ProtocolVersion::V5 {
+            return Err(ProtocolError::violation("Topic name must not be empty"));
+        }
 
         let mut packet_id = None;
 
         if qos > 0 {
-            if input.len() < 2 {
-                return Err(anyhow::format_err!("Insufficient data for packet ID"));
-            }
-
-            let id = input.get_u16();
-            packet_id = Some(id);
+            packet_id = Some(parse_packet_id(input)?);
         }
 
+        let properties = if version == ProtocolVersion::V5 {
+            Properties::parse(input)?
+        } else {
+            Properties::default()
+        };
+
         // 剩余的都是payload
         let payload = input.split().freeze();
 
@@ -102,6 +138,7 @@ impl Packet for PublishPacket {
             topic_name,
             packet_id,
             payload,
+            properties,
         })
     }
 }
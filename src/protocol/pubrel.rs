@@ -1,13 +1,31 @@
 use super::Packet;
-use super::write_remaining_length;
-use bytes::{Buf, BufMut, BytesMut};
-use anyhow::Result;
+use super::ProtocolError;
+use super::parse_packet_id;
+use super::Properties;
+use super::ProtocolVersion;
+use super::ReasonCode;
+use super::{parse_ack_v5, write_ack_v5, write_remaining_length};
+use bytes::{BufMut, BytesMut};
 /// PUBREL数据包
 #[derive(Debug,  PartialEq, Eq)]
 pub struct PubRelPacket {
     pub packet_id: u16,
+    /// 原因码（仅MQTT 5.0）
+    pub reason_code: ReasonCode,
+    /// 属性（仅MQTT 5.0）
+    pub properties: Properties,
 }
 
+impl PubRelPacket {
+    /// 创建原因码为成功且不带属性的PUBREL数据包
+    pub fn new(packet_id: u16) -> Self {
+        Self {
+            packet_id,
+            reason_code: ReasonCode::Success,
+            properties: Properties::default(),
+        }
+    }
+}
 
 impl Packet for PubRelPacket {
     /// 将PUBREL数据包序列化为字节并写入缓冲区
@@ -27,14 +45,34 @@ impl Packet for PubRelPacket {
         // 写入可变头（数据包ID）
         buf.put_u16(self.packet_id);
     }
+
+    /// 按协议版本序列化PUBREL数据包，MQTT 5.0可携带原因码和属性
+    fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
+        if version != ProtocolVersion::V5 {
+            return self.write(buf);
+        }
+
+        let first_byte = (6 << 4) | 0x02;
+        write_ack_v5(buf, first_byte, self.packet_id, self.reason_code, &self.properties);
+    }
     
     /// 从BytesMut解析PUBREL数据包
-    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
-        if input.len() < 2 {
-            return Err(anyhow::format_err!("Insufficient data for PUBREL packet"));
+    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self, ProtocolError> {
+        let packet_id = parse_packet_id(input)?;
+        Ok(PubRelPacket::new(packet_id))
+    }
+
+    /// 按协议版本解析PUBREL数据包
+    fn parse_versioned(input: &mut BytesMut, flags: Option<u8>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        if version != ProtocolVersion::V5 {
+            return Self::parse(input, flags);
         }
-        
-        let packet_id = input.get_u16();
-        Ok(PubRelPacket { packet_id })
+
+        let (packet_id, reason_code, properties) = parse_ack_v5(input)?;
+        Ok(PubRelPacket {
+            packet_id,
+            reason_code,
+            properties,
+        })
     }
 }
@@ -0,0 +1,139 @@
+use super::ConnectReturnCode;
+
+/// MQTT 5.0原因码
+///
+/// 同一数值在不同报文中含义相同，0x00在CONNACK/PUBACK等报文中表示成功，
+/// 在SUBACK中表示授予QoS 0，在DISCONNECT中表示正常断开
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ReasonCode {
+    #[default]
+    Success = 0x00,
+    GrantedQoS1 = 0x01,
+    GrantedQoS2 = 0x02,
+    DisconnectWithWillMessage = 0x04,
+    NoMatchingSubscribers = 0x10,
+    NoSubscriptionExisted = 0x11,
+    ContinueAuthentication = 0x18,
+    ReAuthenticate = 0x19,
+    UnspecifiedError = 0x80,
+    MalformedPacket = 0x81,
+    ProtocolError = 0x82,
+    ImplementationSpecificError = 0x83,
+    UnsupportedProtocolVersion = 0x84,
+    ClientIdentifierNotValid = 0x85,
+    BadUserNameOrPassword = 0x86,
+    NotAuthorized = 0x87,
+    ServerUnavailable = 0x88,
+    ServerBusy = 0x89,
+    Banned = 0x8A,
+    ServerShuttingDown = 0x8B,
+    BadAuthenticationMethod = 0x8C,
+    KeepAliveTimeout = 0x8D,
+    SessionTakenOver = 0x8E,
+    TopicFilterInvalid = 0x8F,
+    TopicNameInvalid = 0x90,
+    PacketIdentifierInUse = 0x91,
+    PacketIdentifierNotFound = 0x92,
+    ReceiveMaximumExceeded = 0x93,
+    TopicAliasInvalid = 0x94,
+    PacketTooLarge = 0x95,
+    MessageRateTooHigh = 0x96,
+    QuotaExceeded = 0x97,
+    AdministrativeAction = 0x98,
+    PayloadFormatInvalid = 0x99,
+    RetainNotSupported = 0x9A,
+    QoSNotSupported = 0x9B,
+    UseAnotherServer = 0x9C,
+    ServerMoved = 0x9D,
+    SharedSubscriptionsNotSupported = 0x9E,
+    ConnectionRateExceeded = 0x9F,
+    MaximumConnectTime = 0xA0,
+    SubscriptionIdentifiersNotSupported = 0xA1,
+    WildcardSubscriptionsNotSupported = 0xA2,
+}
+
+impl ReasonCode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Success),
+            0x01 => Some(Self::GrantedQoS1),
+            0x02 => Some(Self::GrantedQoS2),
+            0x04 => Some(Self::DisconnectWithWillMessage),
+            0x10 => Some(Self::NoMatchingSubscribers),
+            0x11 => Some(Self::NoSubscriptionExisted),
+            0x18 => Some(Self::ContinueAuthentication),
+            0x19 => Some(Self::ReAuthenticate),
+            0x80 => Some(Self::UnspecifiedError),
+            0x81 => Some(Self::MalformedPacket),
+            0x82 => Some(Self::ProtocolError),
+            0x83 => Some(Self::ImplementationSpecificError),
+            0x84 => Some(Self::UnsupportedProtocolVersion),
+            0x85 => Some(Self::ClientIdentifierNotValid),
+            0x86 => Some(Self::BadUserNameOrPassword),
+            0x87 => Some(Self::NotAuthorized),
+            0x88 => Some(Self::ServerUnavailable),
+            0x89 => Some(Self::ServerBusy),
+            0x8A => Some(Self::Banned),
+            0x8B => Some(Self::ServerShuttingDown),
+            0x8C => Some(Self::BadAuthenticationMethod),
+            0x8D => Some(Self::KeepAliveTimeout),
+            0x8E => Some(Self::SessionTakenOver),
+            0x8F => Some(Self::TopicFilterInvalid),
+            0x90 => Some(Self::TopicNameInvalid),
+            0x91 => Some(Self::PacketIdentifierInUse),
+            0x92 => Some(Self::PacketIdentifierNotFound),
+            0x93 => Some(Self::ReceiveMaximumExceeded),
+            0x94 => Some(Self::TopicAliasInvalid),
+            0x95 => Some(Self::PacketTooLarge),
+            0x96 => Some(Self::MessageRateTooHigh),
+            0x97 => Some(Self::QuotaExceeded),
+            0x98 => Some(Self::AdministrativeAction),
+            0x99 => Some(Self::PayloadFormatInvalid),
+            0x9A => Some(Self::RetainNotSupported),
+            0x9B => Some(Self::QoSNotSupported),
+            0x9C => Some(Self::UseAnotherServer),
+            0x9D => Some(Self::ServerMoved),
+            0x9E => Some(Self::SharedSubscriptionsNotSupported),
+            0x9F => Some(Self::ConnectionRateExceeded),
+            0xA0 => Some(Self::MaximumConnectTime),
+            0xA1 => Some(Self::SubscriptionIdentifiersNotSupported),
+            0xA2 => Some(Self::WildcardSubscriptionsNotSupported),
+            _ => None,
+        }
+    }
+
+    /// 是否为错误原因码（数值不小于0x80）
+    pub fn is_error(&self) -> bool {
+        (*self as u8) >= 0x80
+    }
+}
+
+/// MQTT 3.1.1连接返回码到MQTT 5.0原因码的映射
+impl From<ConnectReturnCode> for ReasonCode {
+    fn from(code: ConnectReturnCode) -> Self {
+        match code {
+            ConnectReturnCode::Accepted => Self::Success,
+            ConnectReturnCode::RefusedBadProtocolVersion => Self::UnsupportedProtocolVersion,
+            ConnectReturnCode::RefusedIdentifierRejected => Self::ClientIdentifierNotValid,
+            ConnectReturnCode::RefusedServerUnavailable => Self::ServerUnavailable,
+            ConnectReturnCode::RefusedBadUsernameOrPassword => Self::BadUserNameOrPassword,
+            ConnectReturnCode::RefusedNotAuthorized => Self::NotAuthorized,
+        }
+    }
+}
+
+/// MQTT 5.0原因码到MQTT 3.1.1连接返回码的映射，无对应值时视为未授权
+impl From<ReasonCode> for ConnectReturnCode {
+    fn from(code: ReasonCode) -> Self {
+        match code {
+            ReasonCode::Success => Self::Accepted,
+            ReasonCode::UnsupportedProtocolVersion => Self::RefusedBadProtocolVersion,
+            ReasonCode::ClientIdentifierNotValid => Self::RefusedIdentifierRejected,
+            ReasonCode::ServerUnavailable
+            | ReasonCode::ServerBusy
+            | ReasonCode::ServerShuttingDown => Self::RefusedServerUnavailable,
+            ReasonCode::BadUserNameOrPassword => Self::RefusedBadUsernameOrPassword,
+            _ => Self::RefusedNotAuthorized,
+        }
+    }
+}
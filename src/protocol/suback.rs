@@ -1,27 +1,43 @@
 use super::Packet;
+use super::ProtocolError;
+use super::parse_packet_id;
 use super::write_remaining_length;
-use bytes::{Buf, BufMut, BytesMut};
-use anyhow::Result;
+use super::Properties;
+use super::ProtocolVersion;
+use bytes::{BufMut, BytesMut};
 /// SUBACK数据包
 #[derive(Debug,  PartialEq, Eq)]
 pub struct SubAckPacket {
     pub packet_id: u16,
-    pub return_codes: u8,
+    /// 每个订阅主题过滤器对应一个返回码，顺序与SUBSCRIBE中的过滤器一致
+    pub return_codes: Vec<u8>,
+    /// SUBACK属性（仅MQTT 5.0）
+    pub properties: Properties,
 }
 
 /// 解析SUBACK数据包
-pub fn parse_suback(input: &mut BytesMut) -> Result<SubAckPacket> { 
+pub fn parse_suback(input: &mut BytesMut) -> Result<SubAckPacket, ProtocolError> { 
     SubAckPacket::parse(input, None)
 }
 
 impl Packet for SubAckPacket {
     /// 将SUBACK数据包序列化为字节并写入缓冲区
     fn write(&self, buf: &mut BytesMut) {
+        self.write_versioned(buf, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本序列化SUBACK数据包，MQTT 5.0在数据包ID之后写入属性
+    fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
+        let mut properties = BytesMut::new();
+        if version == ProtocolVersion::V5 {
+            self.properties.write(&mut properties);
+        }
+
         // 计算可变头和载荷长度
-        let variable_header_length = 2; // 数据包ID
+        let variable_header_length = 2 + properties.len(); // 数据包ID + 属性
         
         // 总剩余长度
-        let remaining_length = variable_header_length + 1;
+        let remaining_length = variable_header_length + self.return_codes.len();
         
         // 写入固定头
         let packet_type = 9; // SUBACK
@@ -34,24 +50,35 @@ impl Packet for SubAckPacket {
         
         // 写入可变头（数据包ID）
         buf.put_u16(self.packet_id);
-        buf.put_u8(self.return_codes);
+        buf.put_slice(&properties);
         // 写入载荷（返回码列表）
-       
+        buf.put_slice(&self.return_codes);
     }
     
     /// 从BytesMut解析SUBACK数据包
-    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
-        if input.len() < 2 {
-            return Err(anyhow::format_err!("Insufficient data for SUBACK packet")); 
+    fn parse(input: &mut BytesMut, flags: Option<u8>) -> Result<Self, ProtocolError> {
+        Self::parse_versioned(input, flags, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本解析SUBACK数据包
+    fn parse_versioned(input: &mut BytesMut, _flags: Option<u8>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        let packet_id = parse_packet_id(input)?;
+
+        let properties = if version == ProtocolVersion::V5 {
+            Properties::parse(input)?
+        } else {
+            Properties::default()
+        };
+
+        if input.is_empty() {
+            return Err(ProtocolError::malformed("Insufficient data for SUBACK return code"));
         }
-        
-        let packet_id = input.get_u16();
-        
-        let code = input.get_u8();
+        let return_codes = input.split_to(input.len()).to_vec();
      
         Ok(SubAckPacket {
             packet_id,
-            return_codes: code,
+            return_codes,
+            properties,
         })
     }
 }
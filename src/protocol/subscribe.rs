@@ -1,24 +1,38 @@
 use super::Packet;
+use super::ProtocolError;
 use super::parse_mqtt_string;
+use super::parse_packet_id;
 use super::write_mqtt_string;
 use super::write_remaining_length;
+use super::Properties;
+use super::ProtocolVersion;
 use bytes::{Buf, BufMut, BytesMut};
-use anyhow::Result;
 /// SUBSCRIBE数据包
 #[derive(Debug,  PartialEq, Eq)]
 pub struct SubscribePacket {
     pub packet_id: u16,
+    /// 主题过滤器及订阅选项
+    /// MQTT 3.1.1中选项字节即为QoS，MQTT 5.0中低2位为QoS，高位为No Local等选项
     pub topics: Vec<(String, u8)>,
+    /// SUBSCRIBE属性（仅MQTT 5.0）
+    pub properties: Properties,
 }
 
-
-
-
 impl Packet for SubscribePacket {
     /// 将SUBSCRIBE数据包序列化为字节并写入缓冲区
     fn write(&self, buf: &mut BytesMut) {
+        self.write_versioned(buf, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本序列化SUBSCRIBE数据包，MQTT 5.0在数据包ID之后写入属性
+    fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
+        let mut properties = BytesMut::new();
+        if version == ProtocolVersion::V5 {
+            self.properties.write(&mut properties);
+        }
+
         // 计算可变头和载荷长度
-        let variable_header_length = 2; // 数据包ID
+        let variable_header_length = 2 + properties.len(); // 数据包ID + 属性
         let mut payload_length = 0;
         
         // 计算每个主题和QoS的长度
@@ -41,6 +55,7 @@ impl Packet for SubscribePacket {
         
         // 写入可变头（数据包ID）
         buf.put_u16(self.packet_id);
+        buf.put_slice(&properties);
         
         // 写入载荷（主题列表和QoS级别）
         for (topic, qos) in &self.topics {
@@ -50,29 +65,54 @@ impl Packet for SubscribePacket {
     }
     
     /// 从BytesMut解析SUBSCRIBE数据包
-    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
-        if input.len() < 2 {
-            return Err(anyhow::format_err!("Insufficient data for SUBSCRIBE packet"));  
-        }
-        
-        let packet_id = input.get_u16();
+    fn parse(input: &mut BytesMut, flags: Option<u8>) -> Result<Self, ProtocolError> {
+        Self::parse_versioned(input, flags, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本解析SUBSCRIBE数据包
+    fn parse_versioned(input: &mut BytesMut, _flags: Option<u8>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        let packet_id = parse_packet_id(input)?;
+
+        let properties = if version == ProtocolVersion::V5 {
+            Properties::parse(input)?
+        } else {
+            Properties::default()
+        };
         
         let mut topics = Vec::new();
         
         while !input.is_empty() {
             let topic = parse_mqtt_string(input)?;
-            
+
             if input.is_empty() {
-                return Err(anyhow::format_err!("Insufficient data for QoS level")); 
+                return Err(ProtocolError::malformed("Insufficient data for QoS level"));
             }
-            
+
             let qos = input.get_u8();
+            validate_options(qos, version)?;
             topics.push((topic, qos));
         }
-        
+
+        // SUBSCRIBE至少包含一个主题过滤器
+        if topics.is_empty() {
+            return Err(ProtocolError::violation("SUBSCRIBE contains no topic filter"));
+        }
+
         Ok(SubscribePacket {
             packet_id,
             topics,
+            properties,
         })
     }
 }
+
+/// 检查订阅选项
+///
+/// MQTT 3.1.1中高6位保留，MQTT 5.0中高2位保留且Retain Handling不能为3，QoS都不能为3
+fn validate_options(options: u8, version: ProtocolVersion) -> Result<(), ProtocolError> {
+    let reserved = if version == ProtocolVersion::V5 { 0xC0 } else { 0xFC };
+    if options & reserved != 0 || options & 0x03 == 0x03 || (options >> 4) & 0x03 == 0x03 {
+        return Err(ProtocolError::malformed(format!("Invalid subscription options 0x{:02X}", options)));
+    }
+    Ok(())
+}
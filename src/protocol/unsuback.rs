@@ -1,13 +1,31 @@
 use super::Packet;
+use super::ProtocolError;
+use super::{parse_packet_id, parse_reason_code};
+use super::Properties;
+use super::ProtocolVersion;
+use super::ReasonCode;
 use super::write_remaining_length;
-use bytes::{Buf, BufMut, BytesMut};
-use anyhow::Result;
+use bytes::{BufMut, BytesMut};
 /// UNSUBACK数据包
 #[derive(Debug,  PartialEq, Eq)]
 pub struct UnsubAckPacket {
     pub packet_id: u16,
+    /// 每个主题过滤器对应的原因码（仅MQTT 5.0）
+    pub reason_codes: Vec<ReasonCode>,
+    /// UNSUBACK属性（仅MQTT 5.0）
+    pub properties: Properties,
 }
 
+impl UnsubAckPacket {
+    /// 创建不带原因码和属性的UNSUBACK数据包
+    pub fn new(packet_id: u16) -> Self {
+        Self {
+            packet_id,
+            reason_codes: Vec::new(),
+            properties: Properties::default(),
+        }
+    }
+}
 
 impl Packet for UnsubAckPacket {
     /// 将UNSUBACK数据包序列化为字节并写入缓冲区
@@ -27,14 +45,53 @@ impl Packet for UnsubAckPacket {
         // 写入可变头（数据包ID）
         buf.put_u16(self.packet_id);
     }
+
+    /// 按协议版本序列化UNSUBACK数据包，MQTT 5.0写入属性和原因码列表
+    fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
+        if version != ProtocolVersion::V5 {
+            return self.write(buf);
+        }
+
+        let mut properties = BytesMut::new();
+        self.properties.write(&mut properties);
+
+        // 写入固定头
+        let packet_type = 11; // UNSUBACK
+        buf.put_u8(packet_type << 4);
+        write_remaining_length(buf, 2 + properties.len() + self.reason_codes.len());
+
+        // 写入可变头和载荷
+        buf.put_u16(self.packet_id);
+        buf.put_slice(&properties);
+        for code in &self.reason_codes {
+            buf.put_u8(*code as u8);
+        }
+    }
     
     /// 从BytesMut解析UNSUBACK数据包
-    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
-        if input.len() < 2 {
-            return Err(anyhow::format_err!("Insufficient data for UNSUBACK packet"));   
+    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self, ProtocolError> {
+        let packet_id = parse_packet_id(input)?;
+        Ok(UnsubAckPacket::new(packet_id))
+    }
+
+    /// 按协议版本解析UNSUBACK数据包
+    fn parse_versioned(input: &mut BytesMut, flags: Option<u8>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        if version != ProtocolVersion::V5 {
+            return Self::parse(input, flags);
         }
-        
-        let packet_id = input.get_u16();
-        Ok(UnsubAckPacket { packet_id })
+
+        let packet_id = parse_packet_id(input)?;
+        let properties = Properties::parse(input)?;
+
+        let mut reason_codes = Vec::new();
+        while !input.is_empty() {
+            reason_codes.push(parse_reason_code(input)?);
+        }
+
+        Ok(UnsubAckPacket {
+            packet_id,
+            reason_codes,
+            properties,
+        })
     }
 }
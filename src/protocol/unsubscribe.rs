@@ -1,24 +1,36 @@
 use super::Packet;
+use super::ProtocolError;
 use super::parse_mqtt_string;
+use super::parse_packet_id;
 use super::write_mqtt_string;
 use super::write_remaining_length;
-use bytes::{Buf, BufMut, BytesMut};
-use anyhow::Result;
+use super::Properties;
+use super::ProtocolVersion;
+use bytes::{BufMut, BytesMut};
 /// UNSUBSCRIBE数据包
 #[derive(Debug,  PartialEq, Eq)]
 pub struct UnsubscribePacket {
     pub packet_id: u16,
     pub topics: Vec<String>,
+    /// UNSUBSCRIBE属性（仅MQTT 5.0）
+    pub properties: Properties,
 }
 
-
-
-
 impl Packet for UnsubscribePacket {
     /// 将UNSUBSCRIBE数据包序列化为字节并写入缓冲区
     fn write(&self, buf: &mut BytesMut) {
+        self.write_versioned(buf, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本序列化UNSUBSCRIBE数据包，MQTT 5.0在数据包ID之后写入属性
+    fn write_versioned(&self, buf: &mut BytesMut, version: ProtocolVersion) {
+        let mut properties = BytesMut::new();
+        if version == ProtocolVersion::V5 {
+            self.properties.write(&mut properties);
+        }
+
         // 计算可变头和载荷长度
-        let variable_header_length = 2; // 数据包ID
+        let variable_header_length = 2 + properties.len(); // 数据包ID + 属性
         let mut payload_length = 0;
         
         // 计算每个主题的长度
@@ -40,6 +52,7 @@ impl Packet for UnsubscribePacket {
         
         // 写入可变头（数据包ID）
         buf.put_u16(self.packet_id);
+        buf.put_slice(&properties);
         
         // 写入载荷（主题列表）
         for topic in &self.topics {
@@ -48,12 +61,19 @@ impl Packet for UnsubscribePacket {
     }
     
     /// 从BytesMut解析UNSUBSCRIBE数据包
-    fn parse(input: &mut BytesMut, _flags: Option<u8>) -> Result<Self> {
-        if input.len() < 2 {
-            return Err(anyhow::format_err!("Insufficient data for UNSUBSCRIBE packet"));
-        }
-        
-        let packet_id = input.get_u16();
+    fn parse(input: &mut BytesMut, flags: Option<u8>) -> Result<Self, ProtocolError> {
+        Self::parse_versioned(input, flags, ProtocolVersion::V311)
+    }
+
+    /// 按协议版本解析UNSUBSCRIBE数据包
+    fn parse_versioned(input: &mut BytesMut, _flags: Option<u8>, version: ProtocolVersion) -> Result<Self, ProtocolError> {
+        let packet_id = parse_packet_id(input)?;
+
+        let properties = if version == ProtocolVersion::V5 {
+            Properties::parse(input)?
+        } else {
+            Properties::default()
+        };
         
         let mut topics = Vec::new();
         
@@ -61,10 +81,16 @@ impl Packet for UnsubscribePacket {
             let topic = parse_mqtt_string(input)?;
             topics.push(topic);
         }
-        
+
+        // UNSUBSCRIBE至少包含一个主题过滤器
+        if topics.is_empty() {
+            return Err(ProtocolError::violation("UNSUBSCRIBE contains no topic filter"));
+        }
+
         Ok(UnsubscribePacket {
             packet_id,
             topics,
+            properties,
         })
     }
 }
@@ -1,4 +1,9 @@
-use crate::{ClinetId, protocol::MqttPacket};
+use std::time::Duration;
+
+use crate::{ClinetId, protocol::{MqttPacket, PublishPacket}};
+
+/// 连接标识，同一client_id的每次连接各不相同
+pub type ConnectionId = u64;
 
 #[derive(Debug)]
 pub enum Event {
@@ -6,6 +11,10 @@ pub enum Event {
     ClientConnected(ClinetId),
     /// 客户端断开连接事件
     ClientDisconnected(ClinetId),
+    /// 指定连接已关闭，会话被新连接接管后旧连接的关闭会被忽略
+    ConnectionClosed(ClinetId, ConnectionId),
+    /// 连接异常断开时的遗嘱消息，延迟为0时立即发布，否则在延迟结束且客户端未重连时发布
+    WillMessage(ClinetId, ConnectionId, PublishPacket, Duration),
     /// 消息接收事件
     MessageReceived(ClinetId, MqttPacket),
     /// 消息发送事件
@@ -1,4 +1,5 @@
 pub mod channel;
 pub mod router;
 pub mod event;
-pub mod qos;
\ No newline at end of file
+pub mod qos;
+pub mod shared;
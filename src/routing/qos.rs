@@ -1,45 +1,390 @@
-use crate::protocol::PublishPacket;
-use std::collections::HashMap;
+use crate::protocol::{MqttPacket, PubRelPacket, PublishPacket};
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
 
+/// 排队消息超过上限时的处理策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// 丢弃队列中最早的消息
+    #[default]
+    DropOldest,
+    /// 丢弃新到达的消息
+    DropNewest,
+    /// 断开消费过慢的客户端
+    Disconnect,
+}
+
+/// 每个客户端的QoS 1/2流量控制配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InflightConfig {
+    /// 同时未确认的QoS 1/2消息上限，MQTT 5.0客户端取与Receive Maximum中的较小值
+    pub max_inflight: u16,
+    /// 飞行窗口已满时等待发送的消息上限
+    pub max_queued: usize,
+    /// 等待队列已满时的处理策略
+    pub overflow_policy: OverflowPolicy,
+}
+
+impl Default for InflightConfig {
+    fn default() -> Self {
+        Self {
+            max_inflight: 32,
+            max_queued: 1000,
+            overflow_policy: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+/// 提交一条出站QoS 1/2消息的结果
+// 只作为返回值立即匹配，不值得为Send装箱
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Submit {
+    /// 已分配数据包ID并记录为在途，可以立即发送
+    Send(PublishPacket),
+    /// 飞行窗口已满，消息进入等待队列
+    Queued,
+    /// 等待队列已满，按策略丢弃了一条消息
+    Dropped,
+    /// 等待队列已满且策略为断开客户端
+    Overflow,
+}
+
+/// 出站消息所处的确认阶段
+#[derive(Debug, Clone)]
+enum OutgoingState {
+    /// 已发送PUBLISH，等待PUBACK（QoS 1）或PUBREC（QoS 2）
+    Publish(Box<PublishPacket>),
+    /// 已收到PUBREC并发送PUBREL，等待PUBCOMP
+    PubRel,
+}
+
+/// 一条在途的出站消息
+#[derive(Debug, Clone)]
+struct InflightMessage {
+    state: OutgoingState,
+    /// 最近一次发送时间，用于判断是否需要重发
+    sent_at: Instant,
+    /// 首次发送顺序，重发和重连补发按此顺序进行
+    seq: u64,
+    /// 经共享订阅投递时的共享订阅过滤器，会话结束时转投组内其他成员
+    share: Option<String>,
+}
+
+/// 等待发送的消息
+#[derive(Debug, Clone)]
+struct QueuedMessage {
+    packet: PublishPacket,
+    share: Option<String>,
+}
+
+/// 单个客户端会话的QoS飞行窗口
+///
+/// 每个客户端拥有独立的数据包ID空间：
+/// - `outgoing`：已发送、等待PUBACK/PUBREC/PUBCOMP的消息
+/// - `incoming_qos2`：已收到、等待客户端PUBREL的QoS 2消息
+///
+/// 仍在`outgoing`中的数据包ID不会被重新分配
 #[derive(Debug, Clone)]
 pub struct QoSManager {
-    outgoing_messages: HashMap<u16, PublishPacket>,
+    outgoing: HashMap<u16, InflightMessage>,
     incoming_qos2: HashMap<u16, PublishPacket>,
     next_packet_id: u16,
+    next_seq: u64,
+    /// 飞行窗口已满时按到达顺序等待发送的消息
+    pending: VecDeque<QueuedMessage>,
+    config: InflightConfig,
+    /// 客户端当前连接的Receive Maximum，每次连接重新设置
+    receive_maximum: u16,
 }
 
 impl QoSManager {
     pub fn new() -> Self {
+        Self::with_config(InflightConfig::default())
+    }
+
+    /// 使用指定流量控制配置创建会话状态
+    pub fn with_config(config: InflightConfig) -> Self {
         Self {
-            outgoing_messages: HashMap::new(),
+            outgoing: HashMap::new(),
             incoming_qos2: HashMap::new(),
             next_packet_id: 1,
+            next_seq: 0,
+            pending: VecDeque::new(),
+            config,
+            receive_maximum: u16::MAX,
+        }
+    }
+
+    /// 设置客户端本次连接的Receive Maximum，飞行窗口取其与配置上限中的较小值
+    ///
+    /// 每次连接都会重新设置，持久会话重连时可以放大窗口
+    pub fn set_receive_maximum(&mut self, receive_maximum: u16) {
+        self.receive_maximum = receive_maximum.max(1);
+    }
+
+    /// 当前飞行窗口大小
+    pub fn max_inflight(&self) -> u16 {
+        self.config.max_inflight.min(self.receive_maximum)
+    }
+
+    /// 等待发送的消息数量
+    pub fn queued_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// 提交一条出站QoS 1/2消息
+    ///
+    /// 飞行窗口有空位且没有更早的排队消息时立即分配数据包ID，否则按顺序排队
+    pub fn submit(&mut self, packet: PublishPacket) -> Submit {
+        self.submit_shared(packet, None)
+    }
+
+    /// 提交一条经共享订阅`share`投递的出站QoS 1/2消息，未确认的消息可由`take_shared`取回
+    pub fn submit_shared(&mut self, packet: PublishPacket, share: Option<String>) -> Submit {
+        if self.pending.is_empty() && self.has_window() {
+            return Submit::Send(self.send_now(QueuedMessage { packet, share }));
         }
+        self.push_pending(QueuedMessage { packet, share })
+    }
+
+    /// 将消息放入等待队列，客户端离线时使用，重连后由`dequeue_ready`发出
+    pub fn enqueue(&mut self, packet: PublishPacket) -> Submit {
+        self.push_pending(QueuedMessage { packet, share: None })
     }
 
-    pub fn next_packet_id(&mut self) -> u16 {
-        let id = self.next_packet_id;
-        self.next_packet_id = self.next_packet_id.wrapping_add(1);
-        if self.next_packet_id == 0 {
-            self.next_packet_id = 1;
+    fn push_pending(&mut self, message: QueuedMessage) -> Submit {
+        if self.pending.len() < self.config.max_queued {
+            self.pending.push_back(message);
+            return Submit::Queued;
+        }
+
+        match self.config.overflow_policy {
+            OverflowPolicy::DropOldest => {
+                self.pending.pop_front();
+                self.pending.push_back(message);
+                Submit::Dropped
+            }
+            OverflowPolicy::DropNewest => Submit::Dropped,
+            OverflowPolicy::Disconnect => Submit::Overflow,
         }
-        id
     }
 
+    /// 飞行窗口释放后取出可以发送的排队消息
+    pub fn dequeue_ready(&mut self) -> Vec<PublishPacket> {
+        let mut ready = Vec::new();
+        while self.has_window() {
+            let Some(message) = self.pending.pop_front() else {
+                break;
+            };
+            ready.push(self.send_now(message));
+        }
+        ready
+    }
+
+    /// 飞行窗口是否还有空位
+    fn has_window(&self) -> bool {
+        self.inflight_len() < self.max_inflight() as usize
+    }
+
+    /// 分配数据包ID并记录为在途，调用前需确认飞行窗口有空位
+    fn send_now(&mut self, message: QueuedMessage) -> PublishPacket {
+        let QueuedMessage { mut packet, share } = message;
+        // 窗口不超过65535，有空位时必然有空闲的数据包ID
+        let packet_id = self.next_packet_id().expect("inflight window smaller than packet id space");
+        packet.packet_id = Some(packet_id);
+        self.insert_outgoing(packet_id, packet.clone(), share);
+        packet
+    }
+
+    /// 分配下一个未被占用的数据包ID，65535个ID全部在途时返回None
+    pub fn next_packet_id(&mut self) -> Option<u16> {
+        for _ in 0..u16::MAX {
+            let id = self.next_packet_id;
+            self.next_packet_id = self.next_packet_id.wrapping_add(1);
+            if self.next_packet_id == 0 {
+                self.next_packet_id = 1;
+            }
+            if !self.is_in_use(id) {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// 数据包ID是否仍在等待确认
+    pub fn is_in_use(&self, packet_id: u16) -> bool {
+        self.outgoing.contains_key(&packet_id)
+    }
+
+    /// 在途的出站消息数量（包括等待PUBCOMP的）
+    pub fn inflight_len(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    /// 记录已发送的出站消息
     pub fn store_outgoing(&mut self, packet_id: u16, packet: PublishPacket) {
-        self.outgoing_messages.insert(packet_id, packet);
+        self.insert_outgoing(packet_id, packet, None);
+    }
+
+    fn insert_outgoing(&mut self, packet_id: u16, packet: PublishPacket, share: Option<String>) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.outgoing.insert(packet_id, InflightMessage {
+            state: OutgoingState::Publish(Box::new(packet)),
+            sent_at: Instant::now(),
+            seq,
+            share,
+        });
+    }
+
+    /// 取出经共享订阅投递、客户端尚未收到的消息，按原始顺序返回共享订阅过滤器和消息
+    ///
+    /// 包括等待PUBACK/PUBREC的消息和排队中的消息，已收到PUBREC的QoS 2消息不再转投
+    pub fn take_shared(&mut self) -> Vec<(String, PublishPacket)> {
+        let mut inflight: Vec<(u64, u16)> = self
+            .outgoing
+            .iter()
+            .filter(|(_, message)| message.share.is_some() && matches!(message.state, OutgoingState::Publish(_)))
+            .map(|(packet_id, message)| (message.seq, *packet_id))
+            .collect();
+        inflight.sort_unstable();
+
+        let mut taken = Vec::new();
+        for (_, packet_id) in inflight {
+            let Some(message) = self.outgoing.remove(&packet_id) else {
+                continue;
+            };
+            if let (Some(share), OutgoingState::Publish(packet)) = (message.share, message.state) {
+                taken.push((share, *packet));
+            }
+        }
+        let (shared, pending): (VecDeque<_>, VecDeque<_>) =
+            self.pending.drain(..).partition(|message| message.share.is_some());
+        self.pending = pending;
+        taken.extend(shared.into_iter().filter_map(|message| Some((message.share?, message.packet))));
+
+        for (_, packet) in &mut taken {
+            packet.dup = false;
+            packet.packet_id = None;
+        }
+        taken
     }
 
+    /// 取出客户端尚未收到的全部出站消息，按原始顺序返回，用于停机前持久化
+    ///
+    /// 包括等待PUBACK/PUBREC的消息和排队中的消息；已收到PUBREC的QoS 2消息客户端已经收到，
+    /// 只释放数据包ID
+    pub fn take_unacknowledged(&mut self) -> Vec<PublishPacket> {
+        let mut inflight: Vec<(u64, InflightMessage)> =
+            self.outgoing.drain().map(|(_, message)| (message.seq, message)).collect();
+        inflight.sort_unstable_by_key(|(seq, _)| *seq);
+
+        let mut taken: Vec<PublishPacket> = inflight
+            .into_iter()
+            .filter_map(|(_, message)| match message.state {
+                OutgoingState::Publish(packet) => Some(*packet),
+                OutgoingState::PubRel => None,
+            })
+            .collect();
+        taken.extend(self.pending.drain(..).map(|message| message.packet));
+
+        for packet in &mut taken {
+            packet.dup = false;
+            packet.packet_id = None;
+        }
+        taken
+    }
+
+    /// 收到PUBACK时移除出站消息
     pub fn remove_outgoing(&mut self, packet_id: u16) -> Option<PublishPacket> {
-        self.outgoing_messages.remove(&packet_id)
+        match self.outgoing.entry(packet_id) {
+            Entry::Occupied(entry) if matches!(entry.get().state, OutgoingState::Publish(_)) => {
+                match entry.remove().state {
+                    OutgoingState::Publish(packet) => Some(*packet),
+                    OutgoingState::PubRel => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// 收到PUBREC时将出站消息转入等待PUBCOMP状态，数据包ID继续占用
+    pub fn release_outgoing(&mut self, packet_id: u16) -> bool {
+        match self.outgoing.get_mut(&packet_id) {
+            Some(message) => {
+                message.state = OutgoingState::PubRel;
+                message.sent_at = Instant::now();
+                // 重复的PUBREC仍需回复PUBREL
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 收到PUBCOMP时释放数据包ID
+    pub fn complete_outgoing(&mut self, packet_id: u16) -> bool {
+        match self.outgoing.entry(packet_id) {
+            Entry::Occupied(entry) if matches!(entry.get().state, OutgoingState::PubRel) => {
+                entry.remove();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 取出超过重发间隔仍未确认的消息，按首次发送顺序返回
+    ///
+    /// PUBLISH重发时设置DUP标志，等待PUBCOMP的消息重发PUBREL
+    pub fn retransmit_due(&mut self, now: Instant, retry_interval: Duration) -> Vec<MqttPacket> {
+        self.collect_retransmits(now, |message| now.duration_since(message.sent_at) >= retry_interval)
+    }
+
+    /// 持久会话重连时按原始顺序补发全部在途消息
+    pub fn replay(&mut self, now: Instant) -> Vec<MqttPacket> {
+        self.collect_retransmits(now, |_| true)
+    }
+
+    fn collect_retransmits(
+        &mut self,
+        now: Instant,
+        filter: impl Fn(&InflightMessage) -> bool,
+    ) -> Vec<MqttPacket> {
+        let mut due: Vec<(u64, u16)> = self
+            .outgoing
+            .iter()
+            .filter(|(_, message)| filter(message))
+            .map(|(packet_id, message)| (message.seq, *packet_id))
+            .collect();
+        due.sort_unstable();
+
+        due.into_iter()
+            .filter_map(|(_, packet_id)| {
+                let message = self.outgoing.get_mut(&packet_id)?;
+                message.sent_at = now;
+                Some(match &mut message.state {
+                    OutgoingState::Publish(packet) => {
+                        packet.dup = true;
+                        MqttPacket::Publish(packet.as_ref().clone())
+                    }
+                    OutgoingState::PubRel => MqttPacket::PubRel(PubRelPacket::new(packet_id)),
+                })
+            })
+            .collect()
     }
 
     pub fn store_incoming_qos2(&mut self, packet_id: u16, packet: PublishPacket) -> bool {
-        if self.incoming_qos2.contains_key(&packet_id) {
-            false
-        } else {
-            self.incoming_qos2.insert(packet_id, packet);
-            true
+        match self.incoming_qos2.entry(packet_id) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(packet);
+                true
+            }
         }
     }
 
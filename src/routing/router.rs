@@ -1,64 +1,824 @@
 use crate::ClinetId;
-use crate::routing::event::Event;
-use crate::routing::qos::QoSManager;
-use crate::topic::TopicManager;
+use crate::auth::ClientAttributes;
+use crate::auth::acl::{Acl, AclAction, AclPermission};
+use crate::db::models::session::{OfflineMessage, Session, SessionSubscription};
+use crate::db::session_manager::SessionManager;
+use crate::routing::event::{ConnectionId, Event};
+use crate::routing::qos::{InflightConfig, QoSManager, Submit};
+use crate::routing::shared::SharedStrategy;
+use crate::stats::BrokerStats;
+use crate::topic::{RetainedMessage, SYS_TOPIC_PREFIX, TopicManager, TopicSubscription, is_valid_topic_filter, parse_shared_subscription};
+use chrono::{DateTime, Utc};
 use log::{error, info};
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::{Mutex};
+use tokio_util::sync::CancellationToken;
 use flume::{Receiver, Sender, unbounded};
 use anyhow::Result;
-use crate::protocol::{ConnAckPacket, ConnectReturnCode, MqttPacket, PublishPacket, PubAckPacket, PubRecPacket, PubRelPacket, PubCompPacket};
+use bytes::Bytes;
+use sqlx::SqlitePool;
+use crate::protocol::{ConnAckPacket, ConnectReturnCode, DisconnectPacket, MqttPacket, ProtocolVersion, ReasonCode, PublishPacket, PubAckPacket, PubRecPacket, PubRelPacket, PubCompPacket};
+
+/// SUBACK中表示订阅失败的返回码
+const SUBACK_FAILURE: u8 = 0x80;
+
+/// 未确认QoS 1/2消息的默认重发间隔
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(20);
+
+/// 客户端当前的网络连接
+#[derive(Debug, Clone)]
+struct ClientConnection {
+    id: ConnectionId,
+    sender: Sender<Event>,
+    info: Option<ConnectionInfo>,
+}
+
+/// 客户端连接的网络信息，供管理接口查询
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// 客户端地址
+    pub address: SocketAddr,
+    /// 生效的保活时间（秒）
+    pub keepalive: u16,
+    /// 协议版本
+    pub protocol_version: ProtocolVersion,
+    /// 连接时间
+    pub connected_at: DateTime<Utc>,
+    /// 监听器的挂载点，客户端的主题和订阅都带有此前缀
+    pub mountpoint: Option<String>,
+}
+
+/// 在线客户端的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectedClient {
+    pub client_id: ClinetId,
+    /// 认证通过的用户名
+    pub username: Option<String>,
+    /// 连接信息，直接注册到路由器的客户端没有
+    pub connection: Option<ConnectionInfo>,
+    /// 断开后是否保留会话
+    pub persistent: bool,
+    pub subscriptions: Vec<TopicSubscription>,
+}
+
+/// 持久会话的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistentSession {
+    pub client_id: ClinetId,
+    pub connected: bool,
+    /// 最近一次连接和断开的时间，只有数据库中的会话有记录
+    pub last_connected_at: Option<DateTime<Utc>>,
+    pub last_disconnected_at: Option<DateTime<Utc>>,
+    pub subscriptions: Vec<TopicSubscription>,
+    /// 等待补发的离线消息数
+    pub offline_messages: usize,
+}
+
+/// 注册会话的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// CONNACK中的session present标志
+    pub session_present: bool,
+    /// 本次连接的标识，连接关闭时随`Event::ConnectionClosed`一起上报
+    pub connection_id: ConnectionId,
+}
 
 #[derive(Debug, Clone)]
 pub struct MessageRouter {
     topic_manager: Arc<Mutex<TopicManager>>,
-    qos_manager: Arc<Mutex<QoSManager>>,
-    sender: Arc<Mutex<HashMap<ClinetId, Sender<Event>>>>,
+    /// 每个客户端独立的QoS飞行窗口和数据包ID分配器
+    qos_sessions: Arc<Mutex<HashMap<ClinetId, QoSManager>>>,
+    /// 断开后保留会话状态的客户端（clean_session = false）
+    persistent_sessions: Arc<Mutex<HashSet<ClinetId>>>,
+    /// 未确认消息的重发间隔
+    retry_interval: Duration,
+    /// 每个客户端的飞行窗口和等待队列配置
+    inflight_config: InflightConfig,
+    sender: Arc<Mutex<HashMap<ClinetId, ClientConnection>>>,
+    /// 串行化同一路由器上的会话注册和注销，保证接管时会话状态整体移交
+    registration: Arc<Mutex<()>>,
+    next_connection_id: Arc<AtomicU64>,
+    /// 等待延迟发布的遗嘱，记录留下遗嘱的连接，客户端重连时取消
+    pending_wills: Arc<Mutex<HashMap<ClinetId, ConnectionId>>>,
+    /// 会话持久化数据库，为None时会话只保存在内存中
+    db_pool: Option<SqlitePool>,
+    /// 保留消息的主题数上限，0表示不限制
+    max_retained_messages: usize,
+    /// 每个持久会话在数据库中的离线消息上限，0表示不限制
+    max_offline_messages: usize,
+    /// 主题访问控制
+    acl: Arc<Mutex<Acl>>,
+    /// 在线客户端认证通过后的用户名和属性，用于访问控制
+    client_attributes: Arc<Mutex<HashMap<ClinetId, ClientAttributes>>>,
+    /// 共享订阅组内选择接收者的策略
+    shared_strategy: SharedStrategy,
+    /// 每个共享订阅组的轮询位置
+    shared_cursors: Arc<Mutex<HashMap<String, usize>>>,
+    /// 收发消息和字节数统计
+    stats: Arc<BrokerStats>,
+    /// 开始停机后不再接受新的会话
+    shutting_down: Arc<AtomicBool>,
+    /// 停机完成后结束事件循环
+    stopped: CancellationToken,
     event_sender: Sender<Event>,
     event_receiver: Receiver<Event>,
 }
 
+impl Default for MessageRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MessageRouter {
     pub fn new() -> Self {
         let (tx, rx) = unbounded();
         
         Self {
             topic_manager: Arc::new(Mutex::new(TopicManager::new())),
-            qos_manager: Arc::new(Mutex::new(QoSManager::new())),
+            qos_sessions: Arc::new(Mutex::new(HashMap::new())),
+            persistent_sessions: Arc::new(Mutex::new(HashSet::new())),
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            inflight_config: InflightConfig::default(),
             sender: Arc::new(Mutex::new(HashMap::new())),
+            registration: Arc::new(Mutex::new(())),
+            next_connection_id: Arc::new(AtomicU64::new(1)),
+            pending_wills: Arc::new(Mutex::new(HashMap::new())),
+            db_pool: None,
+            max_retained_messages: 0,
+            max_offline_messages: 0,
+            acl: Arc::new(Mutex::new(Acl::default())),
+            client_attributes: Arc::new(Mutex::new(HashMap::new())),
+            shared_strategy: SharedStrategy::default(),
+            shared_cursors: Arc::new(Mutex::new(HashMap::new())),
+            stats: Arc::new(BrokerStats::new()),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            stopped: CancellationToken::new(),
             event_sender: tx,
             event_receiver: rx,
         }
     }
     
+    /// 设置未确认QoS 1/2消息的重发间隔
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    /// 获取重发间隔
+    pub fn retry_interval(&self) -> Duration {
+        self.retry_interval
+    }
+
+    /// 使用数据库持久化会话的订阅和离线消息
+    pub fn with_db(mut self, pool: SqlitePool) -> Self {
+        self.db_pool = Some(pool);
+        self
+    }
+
+    /// 从数据库恢复持久会话的订阅，服务器启动时调用
+    ///
+    /// 恢复后离线客户端的订阅立即生效，重启期间发布的消息也会进入离线队列
+    pub async fn restore_sessions(&self) -> Result<()> {
+        let Some(pool) = &self.db_pool else {
+            return Ok(());
+        };
+
+        let sessions = SessionManager::load_persistent_sessions(pool).await?;
+        let mut topic_manager = self.topic_manager.lock().await;
+        let mut persistent = self.persistent_sessions.lock().await;
+        for (session, subscriptions) in sessions {
+            for subscription in subscriptions {
+                topic_manager
+                    .add_subscription(session.client_id.clone(), subscription.topic.clone(), subscription.qos_u8())
+                    .await;
+            }
+            persistent.insert(session.client_id);
+        }
+        info!("Restored {} persistent sessions", persistent.len());
+        Ok(())
+    }
+
+    /// 设置保留消息的主题数上限，达到上限后新主题的保留消息被丢弃，0表示不限制
+    pub fn with_max_retained_messages(mut self, max_retained_messages: usize) -> Self {
+        self.max_retained_messages = max_retained_messages;
+        self
+    }
+
+    /// 设置每个持久会话的离线消息上限，超出时丢弃最早的消息，0表示不限制
+    ///
+    /// 没有数据库时离线消息保存在会话的等待队列中，由`InflightConfig::max_queued`限制
+    pub fn with_max_offline_messages(mut self, max_offline_messages: usize) -> Self {
+        self.max_offline_messages = max_offline_messages;
+        self
+    }
+
+    /// 设置共享订阅组内选择接收者的策略，默认轮询
+    pub fn with_shared_strategy(mut self, shared_strategy: SharedStrategy) -> Self {
+        self.shared_strategy = shared_strategy;
+        self
+    }
+
+    /// 收发消息和字节数统计，客户端连接共享同一份
+    pub fn stats(&self) -> Arc<BrokerStats> {
+        self.stats.clone()
+    }
+
+    /// 在线客户端数和客户端总数（包括离线的持久会话）
+    pub async fn client_counts(&self) -> (usize, usize) {
+        let senders = self.sender.lock().await;
+        let persistent = self.persistent_sessions.lock().await;
+        let offline = persistent.iter().filter(|client_id| !senders.contains_key(*client_id)).count();
+        (senders.len(), senders.len() + offline)
+    }
+
+    /// 当前的订阅数，包括共享订阅
+    pub async fn subscription_count(&self) -> usize {
+        self.topic_manager.lock().await.subscription_count()
+    }
+
+    /// 当前保存了保留消息的主题数，不含`$SYS`主题
+    pub async fn retained_count(&self) -> usize {
+        self.topic_manager.lock().await.retained_count()
+    }
+
+    /// 以服务器身份发布消息，不检查访问控制，用于`$SYS`主题等服务器自身的消息
+    pub async fn publish(&self, mut publish: PublishPacket) {
+        publish.packet_id = None;
+        self.handle_publish(String::new(), publish).await;
+    }
+
+    /// 设置主题访问控制，默认允许所有访问
+    pub fn with_acl(mut self, acl: Acl) -> Self {
+        self.acl = Arc::new(Mutex::new(acl));
+        self
+    }
+
+    /// 替换主题访问控制，对所有克隆的路由器生效
+    pub async fn set_acl(&self, acl: Acl) {
+        *self.acl.lock().await = acl;
+    }
+
+    /// 获取当前的主题访问控制
+    pub async fn acl(&self) -> Acl {
+        self.acl.lock().await.clone()
+    }
+
+    /// 记录客户端认证通过后的用户名和属性，在CONNACK之前调用
+    pub async fn set_client_attributes(&self, client_id: &str, attributes: ClientAttributes) {
+        self.client_attributes.lock().await.insert(client_id.to_string(), attributes);
+    }
+
+    /// 记录客户端连接的网络信息，在CONNACK之前调用
+    pub async fn set_connection_info(&self, client_id: &str, info: ConnectionInfo) {
+        if let Some(connection) = self.sender.lock().await.get_mut(client_id) {
+            connection.info = Some(info);
+        }
+    }
+
+    /// 在线客户端列表，按客户端ID排序
+    pub async fn connected_clients(&self) -> Vec<ConnectedClient> {
+        let connections: Vec<(ClinetId, Option<ConnectionInfo>)> = self
+            .sender
+            .lock()
+            .await
+            .iter()
+            .map(|(client_id, connection)| (client_id.clone(), connection.info.clone()))
+            .collect();
+        let usernames: HashMap<ClinetId, String> = {
+            let attributes = self.client_attributes.lock().await;
+            attributes
+                .iter()
+                .filter_map(|(client_id, attributes)| Some((client_id.clone(), attributes.username.clone()?)))
+                .collect()
+        };
+        let persistent = self.persistent_sessions.lock().await.clone();
+
+        let topic_manager = self.topic_manager.lock().await;
+        let mut clients: Vec<ConnectedClient> = connections
+            .into_iter()
+            .map(|(client_id, connection)| ConnectedClient {
+                username: usernames.get(&client_id).cloned(),
+                persistent: persistent.contains(&client_id),
+                subscriptions: topic_manager.client_subscriptions(&client_id),
+                connection,
+                client_id,
+            })
+            .collect();
+        clients.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        clients
+    }
+
+    /// 断开客户端连接，MQTT 5.0客户端收到原因码为0x98的DISCONNECT，返回客户端是否在线
+    pub async fn disconnect_client(&self, client_id: &str) -> bool {
+        let senders = self.sender.lock().await;
+        let Some(connection) = senders.get(client_id) else {
+            return false;
+        };
+        info!("Disconnecting {} by administrative action", client_id);
+        let disconnect = DisconnectPacket::with_reason(ReasonCode::AdministrativeAction);
+        let event = Event::MessageSent(client_id.to_string(), MqttPacket::Disconnect(disconnect));
+        if let Err(e) = connection.sender.try_send(event) {
+            error!("Error sending DISCONNECT to {}: {:?}", client_id, e);
+        }
+        true
+    }
+
+    /// 客户端在线或有持久会话
+    pub async fn has_session(&self, client_id: &str) -> bool {
+        self.sender.lock().await.contains_key(client_id) || self.persistent_sessions.lock().await.contains(client_id)
+    }
+
+    /// 客户端的全部订阅
+    pub async fn client_subscriptions(&self, client_id: &str) -> Vec<TopicSubscription> {
+        self.topic_manager.lock().await.client_subscriptions(client_id)
+    }
+
+    /// 以服务器身份为客户端添加订阅，不检查访问控制
+    ///
+    /// 持久会话的订阅写入数据库，在线客户端立即收到匹配的保留消息
+    pub async fn add_client_subscription(&self, client_id: &str, topic_filter: &str, qos: u8) -> Result<()> {
+        if qos > 2 || !is_valid_topic_filter(topic_filter) {
+            return Err(anyhow::format_err!("Invalid subscription {} (qos {})", topic_filter, qos));
+        }
+        self.topic_manager
+            .lock()
+            .await
+            .add_subscription(client_id.to_string(), topic_filter.to_string(), qos)
+            .await;
+        if let Some(pool) = &self.db_pool
+            && let Err(e) = SessionManager::add_subscription(pool, client_id, topic_filter, qos).await
+        {
+            error!("Error persisting subscription {} for {}: {:?}", topic_filter, client_id, e);
+        }
+        if parse_shared_subscription(topic_filter).is_none() {
+            self.send_retained_messages(client_id.to_string(), topic_filter, qos).await;
+        }
+        Ok(())
+    }
+
+    /// 移除客户端的订阅，返回订阅是否存在
+    pub async fn remove_client_subscription(&self, client_id: &str, topic_filter: &str) -> bool {
+        let removed = self
+            .topic_manager
+            .lock()
+            .await
+            .remove_subscription(client_id.to_string(), topic_filter.to_string())
+            .await;
+        if !removed {
+            return false;
+        }
+
+        if let Some(pool) = &self.db_pool
+            && let Err(e) = SessionManager::remove_subscription(pool, client_id, topic_filter).await
+        {
+            error!("Error removing subscription {} for {}: {:?}", topic_filter, client_id, e);
+        }
+        true
+    }
+
+    /// 持久会话列表：有数据库时读取`sessions`表，否则为内存中的持久会话
+    pub async fn persistent_sessions(&self) -> Result<Vec<PersistentSession>> {
+        let online: HashSet<ClinetId> = self.sender.lock().await.keys().cloned().collect();
+        let mut sessions = Vec::new();
+        if let Some(pool) = &self.db_pool {
+            for session in Session::find_persistent(pool).await? {
+                let subscriptions = SessionSubscription::find_by_session_id(pool, session.id)
+                    .await?
+                    .into_iter()
+                    .map(|subscription| TopicSubscription {
+                        client_id: session.client_id.clone(),
+                        qos: subscription.qos_u8(),
+                        topic: subscription.topic,
+                    })
+                    .collect();
+                sessions.push(PersistentSession {
+                    connected: online.contains(&session.client_id),
+                    last_connected_at: session.last_connected_at,
+                    last_disconnected_at: session.last_disconnected_at,
+                    subscriptions,
+                    offline_messages: OfflineMessage::count_by_session_id(pool, session.id).await? as usize,
+                    client_id: session.client_id,
+                });
+            }
+        } else {
+            let client_ids: Vec<ClinetId> = self.persistent_sessions.lock().await.iter().cloned().collect();
+            let topic_manager = self.topic_manager.lock().await;
+            for client_id in client_ids {
+                sessions.push(PersistentSession {
+                    connected: online.contains(&client_id),
+                    last_connected_at: None,
+                    last_disconnected_at: None,
+                    subscriptions: topic_manager.client_subscriptions(&client_id),
+                    offline_messages: self.queued_len(&client_id).await,
+                    client_id,
+                });
+            }
+        }
+        sessions.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        Ok(sessions)
+    }
+
+    /// 清除离线客户端的持久会话，包括订阅、未确认和离线消息以及数据库中的会话记录
+    ///
+    /// 客户端在线时返回错误，否则返回会话是否存在
+    pub async fn clear_session(&self, client_id: &str) -> Result<bool> {
+        let _registration = self.registration.lock().await;
+        if self.sender.lock().await.contains_key(client_id) {
+            return Err(anyhow::format_err!("Client {} is connected", client_id));
+        }
+        let mut existed = self.persistent_sessions.lock().await.remove(client_id);
+        self.qos_sessions.lock().await.remove(client_id);
+        self.topic_manager.lock().await.remove_client_subscriptions(client_id).await;
+        if let Some(pool) = &self.db_pool {
+            existed |= Session::delete(pool, client_id).await?;
+        }
+        info!("Cleared session of {}", client_id);
+        Ok(existed)
+    }
+
+    /// 与过滤器匹配的保留消息
+    pub async fn retained_messages(&self, topic_filter: &str) -> Vec<(String, RetainedMessage)> {
+        self.topic_manager.lock().await.get_retained_messages(topic_filter).await
+    }
+
+    /// 删除主题的保留消息，返回之前是否存在
+    ///
+    /// `topic`按主题名精确匹配，含通配符时不会删除与之匹配的其他主题
+    pub async fn delete_retained(&self, topic: &str) -> bool {
+        let mut topic_manager = self.topic_manager.lock().await;
+        if !topic_manager.has_retained(topic) {
+            return false;
+        }
+        topic_manager.store_retained_message(topic.to_string(), Bytes::new(), 0).await;
+        true
+    }
+
+    /// 检查客户端能否发布到主题或订阅主题过滤器
+    ///
+    /// 未记录属性的客户端（例如直接注册到路由器的内部客户端）按匿名客户端检查，
+    /// 凭据携带访问规则的客户端只按这些规则检查。
+    /// 规则按客户端发送的主题匹配，挂载的监听器上先去掉挂载点
+    pub async fn authorize(&self, client_id: &str, action: AclAction, topic: &str) -> bool {
+        let mountpoint = {
+            let senders = self.sender.lock().await;
+            senders.get(client_id).and_then(|c| c.info.as_ref()?.mountpoint.clone())
+        };
+        let topic = mountpoint.as_deref().and_then(|m| topic.strip_prefix(m)).unwrap_or(topic);
+        let attributes = self.client_attributes.lock().await.get(client_id).cloned().unwrap_or_default();
+        let permission = match &attributes.acl {
+            Some(acl) => acl.check(client_id, &attributes, action, topic),
+            None => self.acl.lock().await.check(client_id, &attributes, action, topic),
+        };
+        if permission == AclPermission::Deny {
+            info!("ACL denied {:?} on {} for {}", action, topic, client_id);
+        }
+        permission == AclPermission::Allow
+    }
+
+    /// 设置每个客户端的飞行窗口和等待队列
+    pub fn with_inflight_config(mut self, inflight_config: InflightConfig) -> Self {
+        self.inflight_config = inflight_config;
+        self
+    }
+
+    /// 按MQTT 5.0客户端的Receive Maximum设置其飞行窗口，连接时由`register_session`设置
+    pub async fn set_receive_maximum(&self, client_id: &str, receive_maximum: u16) {
+        let mut sessions = self.qos_sessions.lock().await;
+        if let Some(session) = sessions.get_mut(client_id) {
+            session.set_receive_maximum(receive_maximum);
+        }
+    }
+
+    /// 获取客户端等待发送的消息数量
+    pub async fn queued_len(&self, client_id: &str) -> usize {
+        let sessions = self.qos_sessions.lock().await;
+        sessions.get(client_id).map(|s| s.queued_len()).unwrap_or(0)
+    }
+
     pub fn get_sender(&self) -> Sender<Event> {
         self.event_sender.clone()
     }
     
     pub async fn register_client(&self, client_id: &str, sender: Sender<Event>) -> Result<()> {
+        self.register_session(client_id, sender, true, None).await.map(|_| ())
+    }
+
+    /// 注册客户端会话
+    ///
+    /// - `clean_session`为true时丢弃之前的会话状态和订阅，断开后不保留会话
+    /// - `clean_session`为false时恢复之前的会话：恢复订阅，按原始顺序补发全部在途消息，
+    ///   再投递离线期间缓存的消息
+    ///
+    /// 同一client_id已有连接时接管会话：旧连接收到原因码为0x8E的DISCONNECT后关闭，
+    /// 之后旧连接上报的`ConnectionClosed`不会影响新连接
+    ///
+    /// `receive_maximum`为MQTT 5.0客户端本次连接的Receive Maximum，补发消息之前应用到飞行窗口，
+    /// 没有时使用配置的窗口上限
+    pub async fn register_session(
+        &self,
+        client_id: &str,
+        sender: Sender<Event>,
+        clean_session: bool,
+        receive_maximum: Option<u16>,
+    ) -> Result<SessionInfo> {
+        let _registration = self.registration.lock().await;
+        if self.is_shutting_down() {
+            return Err(anyhow::format_err!("Server is shutting down"));
+        }
+        let connection_id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+
         let mut senders = self.sender.lock().await;
-        senders.insert(client_id.to_string(), sender);
-        Ok(())
+        let previous = senders.insert(client_id.to_string(), ClientConnection {
+            id: connection_id,
+            sender: sender.clone(),
+            info: None,
+        });
+        drop(senders);
+
+        // 遗嘱延迟期间重连，取消尚未发布的遗嘱
+        if self.pending_wills.lock().await.remove(client_id).is_some() {
+            info!("Client {} reconnected, delayed will cancelled", client_id);
+        }
+
+        if let Some(previous) = previous {
+            info!("Client {} connected again, taking over session from connection {}", client_id, previous.id);
+            let disconnect = DisconnectPacket::with_reason(ReasonCode::SessionTakenOver);
+            let event = Event::MessageSent(client_id.to_string(), MqttPacket::Disconnect(disconnect));
+            if let Err(e) = previous.sender.try_send(event) {
+                error!("Error sending DISCONNECT to previous connection of {}: {:?}", client_id, e);
+            }
+        }
+
+        let was_persistent = {
+            let mut persistent = self.persistent_sessions.lock().await;
+            if clean_session {
+                persistent.remove(client_id)
+            } else {
+                !persistent.insert(client_id.to_string())
+            }
+        };
+
+        // 数据库中保存的会话
+        let (db_present, subscriptions, offline_messages) = match &self.db_pool {
+            Some(pool) => {
+                let (_, present, subscriptions, offline_messages) =
+                    SessionManager::handle_connect(pool, client_id, clean_session).await?;
+                (present, subscriptions, offline_messages)
+            }
+            None => (false, Vec::new(), Vec::new()),
+        };
+
+        {
+            let mut topic_manager = self.topic_manager.lock().await;
+            if clean_session {
+                topic_manager.remove_client_subscriptions(client_id).await;
+            } else {
+                for subscription in &subscriptions {
+                    topic_manager
+                        .add_subscription(client_id.to_string(), subscription.topic.clone(), subscription.qos_u8())
+                        .await;
+                }
+            }
+        }
+
+        let mut sessions = self.qos_sessions.lock().await;
+        let memory_present = !clean_session && was_persistent && sessions.contains_key(client_id);
+        if clean_session || !sessions.contains_key(client_id) {
+            sessions.insert(client_id.to_string(), QoSManager::with_config(self.inflight_config));
+        }
+        let session = sessions.get_mut(client_id).expect("session inserted above");
+        session.set_receive_maximum(receive_maximum.unwrap_or(u16::MAX));
+
+        // 先补发在途消息，再按顺序投递离线消息
+        let mut outgoing: Vec<MqttPacket> = if clean_session {
+            Vec::new()
+        } else {
+            let mut outgoing = session.replay(Instant::now());
+            outgoing.extend(session.dequeue_ready().into_iter().map(MqttPacket::Publish));
+            outgoing
+        };
+        for message in offline_messages {
+            let publish = PublishPacket {
+                dup: false,
+                qos: message.qos_u8(),
+                retain: false,
+                topic_name: message.topic.clone(),
+                packet_id: None,
+                payload: Bytes::from(message.payload_bytes()),
+                properties: Default::default(),
+            };
+            let submitted = if publish.qos == 0 {
+                Submit::Send(publish)
+            } else {
+                session.submit(publish)
+            };
+            match submitted {
+                Submit::Send(publish) => outgoing.push(MqttPacket::Publish(publish)),
+                Submit::Queued | Submit::Dropped => {}
+                Submit::Overflow => {
+                    // 剩余的离线消息留在数据库中，下次连接时再投递
+                    error!("Queue full while delivering offline messages to {}", client_id);
+                    break;
+                }
+            }
+            if let Some(pool) = &self.db_pool
+                && let Err(e) = SessionManager::delete_offline_message(pool, message.id).await
+            {
+                error!("Error deleting offline message {}: {:?}", message.id, e);
+            }
+        }
+        drop(sessions);
+
+        for packet in outgoing {
+            let event = Event::MessageSent(client_id.to_string(), packet);
+            if let Err(e) = sender.try_send(event) {
+                error!("Error replaying inflight message to {}: {:?}", client_id, e);
+            }
+        }
+        Ok(SessionInfo {
+            session_present: !clean_session && (db_present || memory_present),
+            connection_id,
+        })
+    }
+
+    /// 发布连接异常断开留下的遗嘱
+    ///
+    /// 延迟为0时立即发布；否则延迟结束时客户端仍未重连才发布，
+    /// 会话已被新连接接管时直接丢弃
+    pub async fn publish_will(&self, client_id: &str, connection_id: ConnectionId, will: PublishPacket, delay: Duration) {
+        // 遗嘱在连接关闭前上报，此时仍按该连接的身份检查
+        if !self.authorize(client_id, AclAction::Publish, &will.topic_name).await {
+            info!("Will of {} not authorized, discarded", client_id);
+            return;
+        }
+        if delay.is_zero() {
+            self.handle_publish(client_id.to_string(), will).await;
+            return;
+        }
+
+        {
+            let _registration = self.registration.lock().await;
+            let taken_over = self
+                .sender
+                .lock()
+                .await
+                .get(client_id)
+                .is_some_and(|connection| connection.id != connection_id);
+            if taken_over {
+                info!("Client {} already reconnected, will discarded", client_id);
+                return;
+            }
+            self.pending_wills.lock().await.insert(client_id.to_string(), connection_id);
+        }
+
+        let router = self.clone();
+        let client_id = client_id.to_string();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let due = {
+                let mut pending = router.pending_wills.lock().await;
+                if pending.get(&client_id) == Some(&connection_id) {
+                    pending.remove(&client_id);
+                    true
+                } else {
+                    false
+                }
+            };
+            if due {
+                info!("Publishing delayed will of {}", client_id);
+                router.handle_publish(client_id, will).await;
+            }
+        });
+    }
+
+    /// 连接关闭，只有仍是客户端当前连接时才结束会话
+    pub async fn remove_connection(&self, client_id: &str, connection_id: ConnectionId) {
+        let _registration = self.registration.lock().await;
+        let mut senders = self.sender.lock().await;
+        match senders.get(client_id) {
+            Some(connection) if connection.id == connection_id => {
+                senders.remove(client_id);
+                self.client_attributes.lock().await.remove(client_id);
+            }
+            _ => {
+                info!("Ignoring close of stale connection {} for {}", connection_id, client_id);
+                return;
+            }
+        }
+        drop(senders);
+        self.end_session(client_id).await;
     }
 
     pub async fn remove_client(&self, client_id: &str) {
+        let _registration = self.registration.lock().await;
         let mut senders = self.sender.lock().await;
         senders.remove(client_id);
+        drop(senders);
+        self.client_attributes.lock().await.remove(client_id);
+        self.end_session(client_id).await;
+    }
+
+    /// 客户端下线后更新会话状态
+    async fn end_session(&self, client_id: &str) {
+        if let Some(pool) = &self.db_pool
+            && let Err(e) = SessionManager::handle_disconnect(pool, client_id).await
+        {
+            error!("Error updating session for {}: {:?}", client_id, e);
+        }
+
+        // 非持久会话结束，释放该客户端的订阅、在途消息和数据包ID，
+        // 经共享订阅收到但未确认的消息转投组内其他成员
+        let persistent = self.persistent_sessions.lock().await.contains(client_id);
+        if !persistent {
+            let shared = self
+                .qos_sessions
+                .lock()
+                .await
+                .remove(client_id)
+                .map(|mut session| session.take_shared())
+                .unwrap_or_default();
+            self.topic_manager.lock().await.remove_client_subscriptions(client_id).await;
+            self.redeliver_shared(client_id, shared).await;
+        }
+    }
+
+    /// 缓存发往离线持久会话的消息
+    ///
+    /// 有数据库时写入离线消息表，否则交给内存中的会话，重连时一并补发
+    async fn store_offline(&self, client_id: &str, publish: PublishPacket) {
+        if publish.qos == 0 || !self.persistent_sessions.lock().await.contains(client_id) {
+            return;
+        }
+
+        if let Some(pool) = &self.db_pool {
+            if let Err(e) = SessionManager::store_offline_message(pool, client_id, &publish, self.max_offline_messages).await {
+                error!("Error storing offline message for {}: {:?}", client_id, e);
+            }
+            return;
+        }
+
+        let mut sessions = self.qos_sessions.lock().await;
+        if let Some(session) = sessions.get_mut(client_id)
+            && session.enqueue(publish) == Submit::Overflow
+        {
+            error!("Offline queue full for {}, message dropped", client_id);
+        }
+    }
+
+    /// 重发超过重发间隔仍未确认的消息
+    ///
+    /// 只处理在线客户端，离线持久会话的消息在重连时统一补发
+    pub async fn retry_inflight(&self) {
+        let senders = self.sender.lock().await;
+        let mut sessions = self.qos_sessions.lock().await;
+        let now = Instant::now();
+
+        for (client_id, connection) in senders.iter() {
+            let tx = &connection.sender;
+            let Some(session) = sessions.get_mut(client_id) else {
+                continue;
+            };
+            for packet in session.retransmit_due(now, self.retry_interval) {
+                let event = Event::MessageSent(client_id.clone(), packet);
+                if let Err(e) = tx.try_send(event) {
+                    error!("Error retransmitting to {}: {:?}", client_id, e);
+                }
+            }
+        }
+    }
+
+    /// 获取客户端当前在途的出站消息数量
+    pub async fn inflight_len(&self, client_id: &str) -> usize {
+        let sessions = self.qos_sessions.lock().await;
+        sessions.get(client_id).map(|s| s.inflight_len()).unwrap_or(0)
+    }
+
+    /// 向客户端发送数据包
+    async fn send_to_client(&self, client_id: &str, packet: MqttPacket) {
+        let senders = self.sender.lock().await;
+        if let Some(tx) = senders.get(client_id).map(|c| &c.sender) {
+            let event = Event::MessageSent(client_id.to_string(), packet);
+            if let Err(e) = tx.try_send(event) {
+                error!("Error sending packet to {}: {:?}", client_id, e);
+            }
+        }
     }
 
     pub async fn handle_event(&self, event: Event) {
         match event {
             Event::ClientConnected(client_id) => {
-                let connack_packet = ConnAckPacket {
-                    session_present: false,
-                    return_code: ConnectReturnCode::Accepted,
-                };
+                let connack_packet = ConnAckPacket::new(false, ConnectReturnCode::Accepted);
                 
                 let mqtt_packet = MqttPacket::ConnAck(connack_packet);
                 
                 let senders = self.sender.lock().await;
-                if let Some(tx) = senders.get(&client_id) {
+                if let Some(tx) = senders.get(&client_id).map(|c| &c.sender) {
                     let event = Event::MessageSent(client_id.clone(), mqtt_packet);
                     if let Err(e) = tx.try_send(event) {
                         error!("Error sending CONNACK to {}: {:?}", client_id, e);
@@ -68,6 +828,12 @@ impl MessageRouter {
             Event::ClientDisconnected(client_id) => {
                 self.remove_client(&client_id).await;
             }
+            Event::ConnectionClosed(client_id, connection_id) => {
+                self.remove_connection(&client_id, connection_id).await;
+            }
+            Event::WillMessage(client_id, connection_id, will, delay) => {
+                self.publish_will(&client_id, connection_id, will, delay).await;
+            }
             Event::MessageReceived(client_id, packet) => {
                 match packet {
                     MqttPacket::Subscribe(subscribe_packet) => {
@@ -77,7 +843,11 @@ impl MessageRouter {
                         self.handle_unsubscribe(client_id, unsubscribe_packet).await;
                     }
                     MqttPacket::Publish(publish_packet) => {
-                        self.handle_publish(client_id, publish_packet).await;
+                        if self.authorize(&client_id, AclAction::Publish, &publish_packet.topic_name).await {
+                            self.handle_publish(client_id, publish_packet).await;
+                        } else {
+                            self.reject_publish(client_id, publish_packet).await;
+                        }
                     }
                     MqttPacket::PubAck(puback_packet) => {
                         self.handle_puback(client_id, puback_packet).await;
@@ -104,28 +874,137 @@ impl MessageRouter {
     }
 
     pub async fn start(self) {
-        while let Ok(event) = self.event_receiver.recv_async().await {
-            self.handle_event(event).await;
+        // 以重发间隔的一半检查一次，消息最迟在1.5倍间隔后重发
+        let mut retry_timer = tokio::time::interval((self.retry_interval / 2).max(Duration::from_millis(10)));
+        retry_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                event = self.event_receiver.recv_async() => {
+                    let Ok(event) = event else {
+                        break;
+                    };
+                    let started = Instant::now();
+                    self.handle_event(event).await;
+                    self.stats.record_event(started.elapsed(), self.event_receiver.len());
+                }
+                _ = retry_timer.tick() => {
+                    self.retry_inflight().await;
+                }
+                _ = self.stopped.cancelled() => {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// 是否已开始停机
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// 停机：不再接受新的会话，通知在线客户端断开，等待客户端关闭后保存会话状态，最后结束事件循环
+    ///
+    /// MQTT 5.0客户端收到原因码为0x8B的DISCONNECT。DISCONNECT排在客户端已排队的数据包之后，
+    /// 客户端先发完这些数据包再关闭连接。超过`timeout`仍未断开的客户端不再等待，直接保存会话
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+        let deadline = Instant::now() + timeout;
+
+        {
+            let senders = self.sender.lock().await;
+            info!("Shutting down, disconnecting {} clients", senders.len());
+            for (client_id, connection) in senders.iter() {
+                let disconnect = DisconnectPacket::with_reason(ReasonCode::ServerShuttingDown);
+                let event = Event::MessageSent(client_id.clone(), MqttPacket::Disconnect(disconnect));
+                if let Err(e) = connection.sender.try_send(event) {
+                    error!("Error sending DISCONNECT to {}: {:?}", client_id, e);
+                }
+            }
+        }
+
+        // 连接关闭由事件循环处理，全部处理完后在线列表为空
+        while !self.sender.lock().await.is_empty() {
+            if Instant::now() >= deadline {
+                error!("Shutdown timed out with {} clients still connected", self.sender.lock().await.len());
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        match self.persist_sessions().await {
+            Ok(stored) => info!("Saved {} unacknowledged messages of persistent sessions", stored),
+            Err(e) => error!("Error saving sessions: {:?}", e),
+        }
+        self.stopped.cancel();
+    }
+
+    /// 把会话状态写入数据库，返回保存的消息数
+    ///
+    /// 持久会话中未确认和排队中的QoS 1/2消息保存为离线消息，重启后客户端重连时补发；
+    /// 仍在线的客户端标记为离线。没有数据库时会话只在内存中，不做处理
+    pub async fn persist_sessions(&self) -> Result<usize> {
+        let Some(pool) = &self.db_pool else {
+            return Ok(0);
+        };
+
+        let _registration = self.registration.lock().await;
+        let connected: Vec<ClinetId> = self.sender.lock().await.keys().cloned().collect();
+        for client_id in &connected {
+            SessionManager::handle_disconnect(pool, client_id).await?;
+        }
+
+        let persistent: Vec<ClinetId> = self.persistent_sessions.lock().await.iter().cloned().collect();
+        let mut stored = 0;
+        for client_id in persistent {
+            let messages = self
+                .qos_sessions
+                .lock()
+                .await
+                .get_mut(&client_id)
+                .map(|session| session.take_unacknowledged())
+                .unwrap_or_default();
+            for message in messages {
+                SessionManager::store_offline_message(pool, &client_id, &message, self.max_offline_messages).await?;
+                stored += 1;
+            }
         }
+        Ok(stored)
     }
     
     async fn handle_subscribe(&self, client_id: ClinetId, subscribe_packet: crate::protocol::SubscribePacket) {
-        let mut code = 0x80;
+        let mut return_codes = Vec::with_capacity(subscribe_packet.topics.len());
+        let mut granted = Vec::with_capacity(subscribe_packet.topics.len());
         let mut topic_manager = self.topic_manager.lock().await;
         
-        for (topic_filter, qos) in &subscribe_packet.topics {
-            topic_manager.add_subscription(client_id.clone(), topic_filter.to_string(), *qos).await;
-            code = *qos;
+        for (topic_filter, options) in &subscribe_packet.topics {
+            // 订阅选项低2位为QoS
+            let qos = options & 0x03;
+            if qos > 2 || !is_valid_topic_filter(topic_filter) {
+                info!("Rejecting subscription {} (qos {}) from {}", topic_filter, qos, client_id);
+                return_codes.push(SUBACK_FAILURE);
+                continue;
+            }
+            // 共享订阅按实际的主题过滤器检查权限
+            let acl_filter = parse_shared_subscription(topic_filter).map_or(topic_filter.as_str(), |(_, filter)| filter);
+            if !self.authorize(&client_id, AclAction::Subscribe, acl_filter).await {
+                return_codes.push(SUBACK_FAILURE);
+                continue;
+            }
+            topic_manager.add_subscription(client_id.clone(), topic_filter.to_string(), qos).await;
+            return_codes.push(qos);
+            granted.push((topic_filter, qos));
         }
         
         let suback_packet = crate::protocol::SubAckPacket {
             packet_id: subscribe_packet.packet_id,
-            return_codes: code,
+            return_codes,
+            properties: Default::default(),
         };
         
         let mqtt_packet = MqttPacket::SubAck(suback_packet);
         let senders = self.sender.lock().await;
-        if let Some(tx) = senders.get(&client_id) {
+        if let Some(tx) = senders.get(&client_id).map(|c| &c.sender) {
             let event = Event::MessageSent(client_id.clone(), mqtt_packet);
             if let Err(e) = tx.try_send(event) {
                 error!("Error sending SUBACK to {}: {:?}", client_id, e);
@@ -134,9 +1013,21 @@ impl MessageRouter {
         
         drop(senders);
         drop(topic_manager);
+
+        // 持久会话的订阅写入数据库，重连或服务器重启后恢复
+        if let Some(pool) = &self.db_pool {
+            for (topic_filter, qos) in &granted {
+                if let Err(e) = SessionManager::add_subscription(pool, &client_id, topic_filter, *qos).await {
+                    error!("Error persisting subscription {} for {}: {:?}", topic_filter, client_id, e);
+                }
+            }
+        }
         
-        for (topic_filter, qos) in &subscribe_packet.topics {
-            self.send_retained_messages(client_id.clone(), topic_filter, *qos).await;
+        // 共享订阅不发送保留消息
+        for (topic_filter, qos) in granted {
+            if parse_shared_subscription(topic_filter).is_none() {
+                self.send_retained_messages(client_id.clone(), topic_filter, qos).await;
+            }
         }
     }
     
@@ -150,8 +1041,9 @@ impl MessageRouter {
             return;
         }
         
+        // 与普通消息一样由订阅者的会话分配数据包ID，计入飞行窗口并在未确认时重传
         let senders = self.sender.lock().await;
-        if let Some(tx) = senders.get(&client_id) {
+        if let Some(tx) = senders.get(&client_id).map(|c| &c.sender) {
             for (topic, retained) in retained_messages {
                 let publish_packet = PublishPacket {
                     dup: false,
@@ -160,13 +1052,9 @@ impl MessageRouter {
                     topic_name: topic,
                     packet_id: None,
                     payload: retained.payload,
+                    properties: Default::default(),
                 };
-                
-                let mqtt_packet = MqttPacket::Publish(publish_packet);
-                let event = Event::MessageSent(client_id.clone(), mqtt_packet);
-                if let Err(e) = tx.try_send(event) {
-                    error!("Error sending retained message to {}: {:?}", client_id, e);
-                }
+                self.deliver(tx, &client_id, publish_packet, None).await;
             }
         }
     }
@@ -174,17 +1062,30 @@ impl MessageRouter {
     async fn handle_unsubscribe(&self, client_id: ClinetId, unsubscribe_packet: crate::protocol::UnsubscribePacket) {
         let mut topic_manager = self.topic_manager.lock().await;
         
+        // 按请求顺序为每个过滤器给出原因码，MQTT 3.1.1写出时忽略
+        let mut reason_codes = Vec::with_capacity(unsubscribe_packet.topics.len());
         for topic_filter in &unsubscribe_packet.topics {
-            topic_manager.remove_subscription(client_id.clone(), topic_filter.to_string()).await;
+            let removed = topic_manager.remove_subscription(client_id.clone(), topic_filter.to_string()).await;
+            reason_codes.push(if removed { ReasonCode::Success } else { ReasonCode::NoSubscriptionExisted });
+        }
+        drop(topic_manager);
+
+        if let Some(pool) = &self.db_pool {
+            for topic_filter in &unsubscribe_packet.topics {
+                if let Err(e) = SessionManager::remove_subscription(pool, &client_id, topic_filter).await {
+                    error!("Error removing subscription {} for {}: {:?}", topic_filter, client_id, e);
+                }
+            }
         }
         
         let unsuback_packet = crate::protocol::UnsubAckPacket {
-            packet_id: unsubscribe_packet.packet_id,
+            reason_codes,
+            ..crate::protocol::UnsubAckPacket::new(unsubscribe_packet.packet_id)
         };
         
         let mqtt_packet = MqttPacket::UnsubAck(unsuback_packet);
         let senders = self.sender.lock().await;
-        if let Some(tx) = senders.get(&client_id) {
+        if let Some(tx) = senders.get(&client_id).map(|c| &c.sender) {
             let event = Event::MessageSent(client_id.clone(), mqtt_packet);
             if let Err(e) = tx.try_send(event) {
                 error!("Error sending UNSUBACK to {}: {:?}", client_id, e);
@@ -197,123 +1098,290 @@ impl MessageRouter {
         let retain = publish_packet.retain;
         let qos = publish_packet.qos;
         let payload = publish_packet.payload.clone();
+
+        // 重复的QoS 2消息只回复PUBREC，不再转发
+        if qos == 2
+            && let Some(packet_id) = publish_packet.packet_id
+        {
+            let first_delivery = {
+                let mut sessions = self.qos_sessions.lock().await;
+                sessions
+                    .entry(client_id.clone())
+                    .or_insert_with(|| QoSManager::with_config(self.inflight_config))
+                    .store_incoming_qos2(packet_id, publish_packet.clone())
+            };
+            if !first_delivery {
+                self.send_to_client(&client_id, MqttPacket::PubRec(PubRecPacket::new(packet_id))).await;
+                return;
+            }
+        }
         
         if retain {
             let mut topic_manager = self.topic_manager.lock().await;
-            topic_manager.store_retained_message(topic.clone(), payload, qos).await;
+            // $SYS主题不计入上限，统计主题总能更新
+            let limit_reached = self.max_retained_messages > 0
+                && !payload.is_empty()
+                && !topic.starts_with(SYS_TOPIC_PREFIX)
+                && topic_manager.retained_count() >= self.max_retained_messages
+                && !topic_manager.has_retained(&topic);
+            if limit_reached {
+                info!("Retained message limit reached, not retaining message on {}", topic);
+            } else {
+                topic_manager.store_retained_message(topic.clone(), payload, qos).await;
+            }
         }
         
-        let subscribers = {
+        let (subscribers, shared_groups) = {
             let topic_manager = self.topic_manager.lock().await;
-            topic_manager.find_subscribers(&topic).await
+            (
+                topic_manager.find_subscribers(&topic).await,
+                topic_manager.find_shared_subscribers(&topic).await,
+            )
         };
         
-        if subscribers.is_empty() {
-            return;
-        }
-        
+        let mut offline = Vec::new();
         let senders = self.sender.lock().await;
         for subscriber in subscribers {
-            if let Some(tx) = senders.get(&subscriber.client_id) {
-                let mut msg_packet = publish_packet.clone();
-                msg_packet.qos = subscriber.qos;
-                msg_packet.retain = false;
-                
-                if subscriber.qos > 0 && msg_packet.packet_id.is_none() {
-                    let mut qos_manager = self.qos_manager.lock().await;
-                    msg_packet.packet_id = Some(qos_manager.next_packet_id());
-                }
-                
-                let mqtt_packet = MqttPacket::Publish(msg_packet.clone());
-                let event = Event::MessageSent(subscriber.client_id.clone(), mqtt_packet);
-                if let Err(_e) = tx.try_send(event) {
-                }
-                
-                if subscriber.qos == 1 && msg_packet.packet_id.is_some() {
-                    let mut qos_manager = self.qos_manager.lock().await;
-                    qos_manager.store_outgoing(msg_packet.packet_id.unwrap(), msg_packet);
-                }
+            let msg_packet = subscriber_packet(&publish_packet, subscriber.qos);
+            match senders.get(&subscriber.client_id).map(|c| &c.sender) {
+                Some(tx) => self.deliver(tx, &subscriber.client_id, msg_packet, None).await,
+                // 订阅者离线，持久会话的消息留待重连时投递
+                None => offline.push((subscriber.client_id, msg_packet)),
+            }
+        }
+        // 每个共享订阅组只投递给一个成员
+        for members in shared_groups {
+            if let Some(queued) = self.deliver_shared(&senders, &members, &publish_packet, &client_id, None).await {
+                offline.push(queued);
             }
         }
         
         drop(senders);
+
+        for (subscriber_id, msg_packet) in offline {
+            self.store_offline(&subscriber_id, msg_packet).await;
+        }
         
-        if qos == 1 && publish_packet.packet_id.is_some() {
-            let puback_packet = PubAckPacket {
-                packet_id: publish_packet.packet_id.unwrap(),
-            };
-            let mqtt_packet = MqttPacket::PubAck(puback_packet);
-            let senders = self.sender.lock().await;
-            if let Some(tx) = senders.get(&client_id) {
-                let event = Event::MessageSent(client_id.clone(), mqtt_packet);
-                if let Err(e) = tx.try_send(event) {
-                    error!("Error sending PUBACK to {}: {:?}", client_id, e);
-                }
+        if let Some(packet_id) = publish_packet.packet_id {
+            match qos {
+                1 => self.send_to_client(&client_id, MqttPacket::PubAck(PubAckPacket::new(packet_id))).await,
+                2 => self.send_to_client(&client_id, MqttPacket::PubRec(PubRecPacket::new(packet_id))).await,
+                _ => {}
             }
-        } else if qos == 2 && publish_packet.packet_id.is_some() {
-            let packet_id = publish_packet.packet_id.unwrap();
-            let mut qos_manager = self.qos_manager.lock().await;
-            if qos_manager.store_incoming_qos2(packet_id, publish_packet) {
-                drop(qos_manager);
-                let pubrec_packet = PubRecPacket {
-                    packet_id,
-                };
-                let mqtt_packet = MqttPacket::PubRec(pubrec_packet);
-                let senders = self.sender.lock().await;
-                if let Some(tx) = senders.get(&client_id) {
-                    let event = Event::MessageSent(client_id.clone(), mqtt_packet);
+        }
+    }
+    
+    /// 向在线订阅者投递消息
+    ///
+    /// 数据包ID由订阅者自己的会话分配，飞行窗口已满时排队；经共享订阅投递时记录`share`，
+    /// 订阅者的会话结束前未确认的消息会转投组内其他成员
+    async fn deliver(&self, tx: &Sender<Event>, client_id: &str, mut msg_packet: PublishPacket, share: Option<&str>) {
+        if msg_packet.qos > 0 {
+            let mut sessions = self.qos_sessions.lock().await;
+            let session = sessions
+                .entry(client_id.to_string())
+                .or_insert_with(|| QoSManager::with_config(self.inflight_config));
+            msg_packet = match session.submit_shared(msg_packet, share.map(str::to_string)) {
+                Submit::Send(packet) => packet,
+                Submit::Queued => return,
+                Submit::Dropped => {
+                    info!("Queue full for {}, message dropped", client_id);
+                    self.stats.record_dropped();
+                    return;
+                }
+                Submit::Overflow => {
+                    error!("Queue full for {}, disconnecting slow consumer", client_id);
+                    self.stats.record_dropped();
+                    let disconnect = DisconnectPacket::with_reason(ReasonCode::QuotaExceeded);
+                    let event = Event::MessageSent(client_id.to_string(), MqttPacket::Disconnect(disconnect));
                     if let Err(e) = tx.try_send(event) {
-                        error!("Error sending PUBREC to {}: {:?}", client_id, e);
+                        error!("Error sending DISCONNECT to {}: {:?}", client_id, e);
                     }
+                    return;
                 }
-            }
+            };
+        }
+
+        let event = Event::MessageSent(client_id.to_string(), MqttPacket::Publish(msg_packet));
+        if let Err(e) = tx.try_send(event) {
+            error!("Error sending PUBLISH to {}: {:?}", client_id, e);
+            self.stats.record_dropped();
         }
     }
-    
-    async fn handle_puback(&self, _client_id: ClinetId, puback_packet: PubAckPacket) {
-        let mut qos_manager = self.qos_manager.lock().await;
-        qos_manager.remove_outgoing(puback_packet.packet_id);
+
+    /// 按共享订阅策略选择一个在线成员投递消息，跳过`exclude`和连接已断开的成员
+    ///
+    /// 没有在线成员时返回首选成员和消息，由调用方存入其离线队列（只有持久会话会保存）
+    async fn deliver_shared(
+        &self,
+        senders: &HashMap<ClinetId, ClientConnection>,
+        members: &[TopicSubscription],
+        publish: &PublishPacket,
+        sticky_key: &str,
+        exclude: Option<&str>,
+    ) -> Option<(ClinetId, PublishPacket)> {
+        let share = members.first()?.topic.clone();
+        let loads: Vec<usize> = if self.shared_strategy == SharedStrategy::LeastInflight {
+            let sessions = self.qos_sessions.lock().await;
+            members
+                .iter()
+                .map(|member| {
+                    sessions
+                        .get(&member.client_id)
+                        .map(|session| session.inflight_len() + session.queued_len())
+                        .unwrap_or(0)
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let order = {
+            let mut cursors = self.shared_cursors.lock().await;
+            let cursor = cursors.entry(share.clone()).or_insert(0);
+            self.shared_strategy
+                .order(members.len(), cursor, sticky_key, |index| loads.get(index).copied().unwrap_or(0))
+        };
+
+        let mut fallback = None;
+        for index in order {
+            let member = &members[index];
+            if exclude == Some(member.client_id.as_str()) {
+                continue;
+            }
+            let msg_packet = subscriber_packet(publish, member.qos);
+            match senders.get(&member.client_id).map(|c| &c.sender) {
+                Some(tx) if !tx.is_disconnected() => {
+                    self.deliver(tx, &member.client_id, msg_packet, Some(&share)).await;
+                    return None;
+                }
+                _ => {
+                    fallback.get_or_insert((member.client_id.clone(), msg_packet));
+                }
+            }
+        }
+        info!("No online member in {}, message kept for {:?}", share, fallback.as_ref().map(|(id, _)| id));
+        fallback
     }
-    
-    async fn handle_pubrec(&self, client_id: ClinetId, pubrec_packet: PubRecPacket) {
-        let mut qos_manager = self.qos_manager.lock().await;
-        if qos_manager.remove_outgoing(pubrec_packet.packet_id).is_some() {
-            drop(qos_manager);
-            let pubrel_packet = PubRelPacket {
-                packet_id: pubrec_packet.packet_id,
-            };
-            let mqtt_packet = MqttPacket::PubRel(pubrel_packet);
+
+    /// 把已结束会话中未确认的共享订阅消息转投组内其他成员
+    async fn redeliver_shared(&self, client_id: &str, messages: Vec<(String, PublishPacket)>) {
+        if messages.is_empty() {
+            return;
+        }
+        info!("Redelivering {} shared subscription messages of {}", messages.len(), client_id);
+        let messages: Vec<_> = {
+            let topic_manager = self.topic_manager.lock().await;
+            messages
+                .into_iter()
+                .map(|(share, publish)| (topic_manager.shared_members(&share), publish))
+                .collect()
+        };
+        let mut offline = Vec::new();
+        {
             let senders = self.sender.lock().await;
-            if let Some(tx) = senders.get(&client_id) {
-                let event = Event::MessageSent(client_id.clone(), mqtt_packet);
-                if let Err(e) = tx.try_send(event) {
-                    error!("Error sending PUBREL to {}: {:?}", client_id, e);
+            for (members, publish) in messages {
+                // 原发布者已无从得知，粘性策略按主题选择
+                let sticky_key = publish.topic_name.clone();
+                if let Some(queued) = self
+                    .deliver_shared(&senders, &members, &publish, &sticky_key, Some(client_id))
+                    .await
+                {
+                    offline.push(queued);
                 }
             }
         }
+        for (member_id, msg_packet) in offline {
+            self.store_offline(&member_id, msg_packet).await;
+        }
+    }
+
+    /// 丢弃未授权的PUBLISH，QoS 1/2以原因码0x87确认（MQTT 3.1.1客户端收到普通确认）
+    async fn reject_publish(&self, client_id: ClinetId, publish_packet: PublishPacket) {
+        let Some(packet_id) = publish_packet.packet_id else {
+            return;
+        };
+        match publish_packet.qos {
+            1 => {
+                let mut puback = PubAckPacket::new(packet_id);
+                puback.reason_code = ReasonCode::NotAuthorized;
+                self.send_to_client(&client_id, MqttPacket::PubAck(puback)).await;
+            }
+            2 => {
+                let mut pubrec = PubRecPacket::new(packet_id);
+                pubrec.reason_code = ReasonCode::NotAuthorized;
+                self.send_to_client(&client_id, MqttPacket::PubRec(pubrec)).await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_puback(&self, client_id: ClinetId, puback_packet: PubAckPacket) {
+        let ready = {
+            let mut sessions = self.qos_sessions.lock().await;
+            match sessions.get_mut(&client_id) {
+                Some(session) => match session.remove_outgoing(puback_packet.packet_id) {
+                    Some(_) => session.dequeue_ready(),
+                    None => Vec::new(),
+                },
+                None => Vec::new(),
+            }
+        };
+        self.send_ready(&client_id, ready).await;
+    }
+
+    /// 发送飞行窗口释放后出队的消息
+    async fn send_ready(&self, client_id: &str, ready: Vec<PublishPacket>) {
+        for packet in ready {
+            self.send_to_client(client_id, MqttPacket::Publish(packet)).await;
+        }
+    }
+    
+    async fn handle_pubrec(&self, client_id: ClinetId, pubrec_packet: PubRecPacket) {
+        let released = {
+            let mut sessions = self.qos_sessions.lock().await;
+            sessions
+                .get_mut(&client_id)
+                .is_some_and(|session| session.release_outgoing(pubrec_packet.packet_id))
+        };
+        if released {
+            let pubrel_packet = PubRelPacket::new(pubrec_packet.packet_id);
+            self.send_to_client(&client_id, MqttPacket::PubRel(pubrel_packet)).await;
+        }
     }
     
     async fn handle_pubrel(&self, client_id: ClinetId, pubrel_packet: PubRelPacket) {
-        let mut qos_manager = self.qos_manager.lock().await;
-        if qos_manager.remove_incoming_qos2(pubrel_packet.packet_id).is_some() {
-            drop(qos_manager);
-            let pubcomp_packet = PubCompPacket {
-                packet_id: pubrel_packet.packet_id,
-            };
-            let mqtt_packet = MqttPacket::PubComp(pubcomp_packet);
-            let senders = self.sender.lock().await;
-            if let Some(tx) = senders.get(&client_id) {
-                let event = Event::MessageSent(client_id.clone(), mqtt_packet);
-                if let Err(e) = tx.try_send(event) {
-                    error!("Error sending PUBCOMP to {}: {:?}", client_id, e);
-                }
+        {
+            let mut sessions = self.qos_sessions.lock().await;
+            if let Some(session) = sessions.get_mut(&client_id) {
+                session.remove_incoming_qos2(pubrel_packet.packet_id);
             }
         }
+        // 即使状态已清理也要回复PUBCOMP，以便客户端完成重发的PUBREL
+        let pubcomp_packet = PubCompPacket::new(pubrel_packet.packet_id);
+        self.send_to_client(&client_id, MqttPacket::PubComp(pubcomp_packet)).await;
     }
     
-    async fn handle_pubcomp(&self, _client_id: ClinetId, pubcomp_packet: PubCompPacket) {
-        let mut qos_manager = self.qos_manager.lock().await;
-        qos_manager.remove_outgoing(pubcomp_packet.packet_id);
+    async fn handle_pubcomp(&self, client_id: ClinetId, pubcomp_packet: PubCompPacket) {
+        let ready = {
+            let mut sessions = self.qos_sessions.lock().await;
+            match sessions.get_mut(&client_id) {
+                Some(session) => match session.complete_outgoing(pubcomp_packet.packet_id) {
+                    true => session.dequeue_ready(),
+                    false => Vec::new(),
+                },
+                None => Vec::new(),
+            }
+        };
+        self.send_ready(&client_id, ready).await;
     }
-    
+}
+
+/// 按订阅者的QoS生成转发的消息，数据包ID由订阅者的会话重新分配
+fn subscriber_packet(publish: &PublishPacket, subscriber_qos: u8) -> PublishPacket {
+    let mut msg_packet = publish.clone();
+    msg_packet.qos = std::cmp::min(publish.qos, subscriber_qos);
+    msg_packet.retain = false;
+    msg_packet.dup = false;
+    msg_packet.packet_id = None;
+    msg_packet
 }
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// 共享订阅组内选择接收者的策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum SharedStrategy {
+    /// 按订阅顺序轮流投递
+    #[default]
+    RoundRobin,
+    /// 随机选择成员
+    Random,
+    /// 按发布者客户端ID的哈希固定选择成员，同一发布者的消息总是交给同一成员
+    Sticky,
+    /// 选择在途和排队消息最少的成员
+    LeastInflight,
+}
+
+impl SharedStrategy {
+    /// 按策略给出组内成员的尝试顺序，第一个为首选，首选不可用时依次尝试后面的成员
+    ///
+    /// - `cursor`为该组的轮询位置，只有轮询策略会更新
+    /// - `sticky_key`为粘性策略使用的哈希键，通常是发布者的客户端ID
+    /// - `load`返回成员当前的在途和排队消息数
+    pub fn order(&self, len: usize, cursor: &mut usize, sticky_key: &str, load: impl Fn(usize) -> usize) -> Vec<usize> {
+        if len == 0 {
+            return Vec::new();
+        }
+        let start = match self {
+            Self::RoundRobin => {
+                let start = *cursor % len;
+                *cursor = start + 1;
+                start
+            }
+            Self::Random => rand::random_range(0..len),
+            Self::Sticky => {
+                let mut hasher = DefaultHasher::new();
+                sticky_key.hash(&mut hasher);
+                (hasher.finish() % len as u64) as usize
+            }
+            Self::LeastInflight => {
+                let mut order: Vec<usize> = (0..len).collect();
+                // 负载相同时按订阅顺序
+                order.sort_by_key(|&index| load(index));
+                return order;
+            }
+        };
+        (0..len).map(|offset| (start + offset) % len).collect()
+    }
+}
@@ -1,37 +1,125 @@
+use crate::auth::Authenticator;
+use crate::auth::acl::Acl;
+use crate::client::{BoxedTransport, Transport};
+use crate::config::{BrokerConfig, CertUsername, ListenerConfig, WebSocketConfig};
 use crate::db::connection::DatabaseConnection;
+use crate::db::models::acl_rule::AclRuleRecord;
 use crate::routing::router::MessageRouter;
+use crate::stats::SysPublisher;
 use log::{error, info};
-use std::{net::SocketAddr, thread::{self}};
+use std::{net::SocketAddr, sync::Arc, thread::{self}};
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+
+/// TLS和WebSocket握手超时时间
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 /// MQTT服务器结构体
 #[derive(Debug, Clone)]
 pub struct Server {
-    /// 服务器地址
-    addr: SocketAddr,
+    /// 服务器配置
+    config: Arc<BrokerConfig>,
     /// 消息路由器
     router: MessageRouter,
     /// 数据库连接
     db: Option<DatabaseConnection>,
+    /// 客户端认证方式，未设置时启动时按配置创建
+    authenticator: Option<Arc<dyn Authenticator>>,
+    /// 停机信号，所有克隆共享
+    shutdown: CancellationToken,
 }
 
 impl Server {
-    /// 创建新的MQTT服务器
+    /// 创建新的MQTT服务器，其余配置使用默认值
     pub fn new(addr: SocketAddr) -> Self {
+        Self::from_config(BrokerConfig {
+            listeners: vec![ListenerConfig::tcp(addr)],
+            ..Default::default()
+        })
+    }
+
+    /// 按配置创建MQTT服务器，数据库连接仍通过`with_database`设置
+    pub fn from_config(config: BrokerConfig) -> Self {
         // 创建路由器
-        let router = MessageRouter::new();
+        let router = MessageRouter::new()
+            .with_retry_interval(config.mqtt.retry_interval())
+            .with_inflight_config(config.inflight)
+            .with_shared_strategy(config.mqtt.shared_subscription_strategy)
+            .with_max_retained_messages(config.limits.max_retained_messages)
+            .with_max_offline_messages(config.limits.max_offline_messages)
+            .with_acl(Acl::new(config.acl.rules.clone(), config.acl.no_match));
 
-        Self { addr, router, db: None }
+        Self {
+            config: Arc::new(config),
+            router,
+            db: None,
+            authenticator: None,
+            shutdown: CancellationToken::new(),
+        }
     }
     
     /// 设置数据库连接
     pub fn with_database(mut self, db: DatabaseConnection) -> Self {
+        self.router = self.router.with_db(db.get_pool().clone());
         self.db = Some(db);
         self
     }
 
-    /// 启动服务器
+    /// 注册自定义认证方式，替代配置中的认证方式
+    ///
+    /// 需要同时支持TLS客户端证书身份时，可用`ChainAuthenticator`组合`CertificateAuthenticator`
+    pub fn with_authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// 设置未确认QoS 1/2消息的重发间隔
+    pub fn with_retry_interval(mut self, retry_interval: std::time::Duration) -> Self {
+        Arc::make_mut(&mut self.config).mqtt.retry_interval = retry_interval.as_secs();
+        self.router = self.router.with_retry_interval(retry_interval);
+        self
+    }
+
+    /// 设置每个客户端的QoS 1/2飞行窗口和等待队列
+    pub fn with_inflight_config(mut self, inflight_config: crate::routing::qos::InflightConfig) -> Self {
+        Arc::make_mut(&mut self.config).inflight = inflight_config;
+        self.router = self.router.with_inflight_config(inflight_config);
+        self
+    }
+
+    /// 获取服务器配置
+    pub fn config(&self) -> &BrokerConfig {
+        &self.config
+    }
+
+    /// 通知服务器停机，`start`在客户端断开、会话保存后返回
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// 启动服务器，直到`shutdown`后停机完成才返回
+    ///
+    /// 停机时先停止接受连接，再由路由器断开客户端并保存会话，最长等待`[mqtt] shutdown_timeout`
     pub async fn start(&self) {
+        // 恢复持久会话的订阅
+        if let Err(e) = self.router.restore_sessions().await {
+            error!("Failed to restore persistent sessions: {:?}", e);
+        }
+
+        // 追加数据库中的访问控制规则
+        if self.config.acl.database {
+            let db = self.db.as_ref().expect("ACL rules from database require a database connection");
+            let rules = AclRuleRecord::load_rules(db.get_pool()).await.expect("Failed to load ACL rules");
+            let mut acl = Acl::new(self.config.acl.rules.clone(), self.config.acl.no_match);
+            acl.extend(rules);
+            self.router.set_acl(acl).await;
+        }
+
         // 启动路由器
         let router_clone = self.router.clone();
         thread::spawn(move || {
@@ -43,39 +131,169 @@ impl Server {
                 router_clone.start().await;
             });
         });
-        // 绑定TCP监听器
-        let listener = TcpListener::bind(&self.addr)
-            .await
-            .expect("Failed to bind address");
-    
-        info!("MQTT server started on {}", self.addr);
-        
 
-        // 处理客户端连接
-        while let Ok((socket, addr)) = listener.accept().await {
+        // 辅助服务随服务器停机一起结束
+        let mut services = JoinSet::new();
+
+        // 周期发布$SYS统计主题
+        services.spawn(SysPublisher::new(self.router.clone()).run(self.config.mqtt.sys_interval()));
+
+        // 按配置提供Prometheus指标接口
+        if let Some(bind) = self.config.metrics.bind {
+            let listener = TcpListener::bind(bind).await.expect("Failed to bind metrics address");
+            let router = self.router.clone();
+            services.spawn(async move {
+                if let Err(e) = crate::metrics::serve(listener, router).await {
+                    error!("Metrics endpoint failed: {:?}", e);
+                }
+            });
+        }
+
+        // 按配置提供管理接口
+        if let Some(bind) = self.config.admin.bind {
+            let listener = TcpListener::bind(bind).await.expect("Failed to bind admin address");
+            let router = self.router.clone();
+            let token = self.config.admin.token.clone();
+            services.spawn(async move {
+                if let Err(e) = crate::admin::serve(listener, router, token).await {
+                    error!("Admin API failed: {:?}", e);
+                }
+            });
+        }
+
+        // 每个监听器独立接受连接，未单独配置认证方式的监听器共用全局认证方式
+        let mut authenticator = self.authenticator.clone();
+        let mut listeners = JoinSet::new();
+        for listener_config in &self.config.listeners {
+            // 认证方式和TLS证书配置错误时不启动
+            let authenticator = match &listener_config.auth {
+                Some(auth) => crate::auth::from_config(auth, self.db.as_ref()).expect("Failed to create authenticator"),
+                None => authenticator
+                    .get_or_insert_with(|| {
+                        crate::auth::from_config(&self.config.auth, self.db.as_ref()).expect("Failed to create authenticator")
+                    })
+                    .clone(),
+            };
+            let tls = listener_config.tls.as_ref().map(|tls| {
+                let acceptor = crate::tls::build_acceptor(tls).expect("Failed to load TLS configuration");
+                (acceptor, tls.cert_username)
+            });
+            let context = Arc::new(ListenerContext {
+                tls,
+                websocket: listener_config.websocket.clone(),
+                authenticator,
+                mountpoint: listener_config.mountpoint.clone(),
+                connections: (listener_config.max_connections > 0)
+                    .then(|| Arc::new(Semaphore::new(listener_config.max_connections))),
+            });
+            let listener = Listener::bind(listener_config).await.expect("Failed to bind address");
+            info!("MQTT server started on {}://{}", listener_config.scheme(), listener_config.address());
+            listeners.spawn(self.clone().accept_loop(listener, context));
+        }
+        while listeners.join_next().await.is_some() {}
+
+        info!("MQTT server stopped accepting connections");
+        self.router.shutdown(self.config.mqtt.shutdown_timeout()).await;
+        services.abort_all();
+        info!("MQTT server shut down");
+    }
+
+    /// 处理一个监听器上的客户端连接
+    async fn accept_loop(self, listener: Listener, context: Arc<ListenerContext>) {
+        loop {
+            let (socket, addr) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Error accepting connection: {:?}", e);
+                        break;
+                    }
+                },
+                _ = self.shutdown.cancelled() => break,
+            };
+            // 达到连接数上限时直接关闭，许可随连接一起释放
+            let permit = match &context.connections {
+                Some(connections) => match connections.clone().try_acquire_owned() {
+                    Ok(permit) => Some(permit),
+                    Err(_) => {
+                        info!("Listener reached max connections, closing connection from {}", addr);
+                        continue;
+                    }
+                },
+                None => None,
+            };
             info!("Accepted connection from {}", addr);
-            // 克隆路由器和数据库连接引用
-            let router_clone = self.router.clone();
-            let db_clone = self.db.clone();
-            socket.set_nodelay(true).expect("close Nagle算法");
-            // 处理客户端连接
+            let server = self.clone();
+            let context = context.clone();
+            // 处理客户端连接，TLS和WebSocket握手也在独立任务中进行，不阻塞后续连接
             tokio::spawn(async move {
-                if let Some(db) = db_clone {
-                    if let Ok(client) =
-                        crate::client::create_client_with_connect(socket, addr, &router_clone, &db).await
-                    {
-                        if let Err(e) = client.handle().await {
-                            error!("Error handling client: {:?}", e);
-                        }
+                let _permit = permit;
+                match &context.tls {
+                    Some((acceptor, cert_username)) => {
+                        let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(socket)).await {
+                            Ok(Ok(stream)) => stream,
+                            Ok(Err(e)) => {
+                                error!("TLS handshake with {} failed: {:?}", addr, e);
+                                return;
+                            }
+                            Err(_) => {
+                                error!("TLS handshake with {} timed out", addr);
+                                return;
+                            }
+                        };
+                        let username = crate::tls::peer_username(stream.get_ref().1.peer_certificates(), *cert_username);
+                        server.serve_stream(stream, addr, username, &context).await;
                     }
-                } else {
-                    error!("Database connection not available for client: {}", addr);
-                    // 这里可以添加代码来关闭连接或发送拒绝消息
+                    None => server.serve_stream(socket, addr, None, &context).await,
                 }
             });
         }
     }
 
+    /// WebSocket监听器先完成WebSocket握手，再按普通连接处理
+    async fn serve_stream(
+        &self,
+        stream: impl Transport + 'static,
+        addr: SocketAddr,
+        cert_username: Option<String>,
+        context: &ListenerContext,
+    ) {
+        let Some(websocket) = &context.websocket else {
+            return self.serve_client(stream, addr, cert_username, context).await;
+        };
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, crate::websocket::accept(stream, websocket)).await {
+            Ok(Ok(stream)) => self.serve_client(stream, addr, cert_username, context).await,
+            Ok(Err(e)) => error!("WebSocket handshake with {} failed: {:?}", addr, e),
+            Err(_) => error!("WebSocket handshake with {} timed out", addr),
+        }
+    }
+
+    /// 处理CONNECT并运行客户端直到连接关闭
+    async fn serve_client(
+        &self,
+        socket: impl Transport + 'static,
+        addr: SocketAddr,
+        cert_username: Option<String>,
+        context: &ListenerContext,
+    ) {
+        let Ok(client) = crate::client::create_client_with_connect(
+            socket,
+            addr,
+            &self.router,
+            context.authenticator.as_ref(),
+            &self.config,
+            cert_username,
+            context.mountpoint.clone(),
+        )
+        .await
+        else {
+            return;
+        };
+        if let Err(e) = client.handle().await {
+            error!("Error handling client: {:?}", e);
+        }
+    }
+
     /// 获取路由器实例
     pub fn router(&self) -> &MessageRouter {
         
@@ -85,3 +303,59 @@ impl Server {
 
 
 }
+
+/// 一个监听器的运行时设置
+struct ListenerContext {
+    tls: Option<(TlsAcceptor, CertUsername)>,
+    websocket: Option<WebSocketConfig>,
+    authenticator: Arc<dyn Authenticator>,
+    mountpoint: Option<String>,
+    /// 剩余的连接数，不限制时为None
+    connections: Option<Arc<Semaphore>>,
+}
+
+/// TCP或Unix域套接字监听器
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// 按配置绑定监听地址，Unix域套接字先删除残留的套接字文件，路径是其他类型的文件时返回错误
+    async fn bind(config: &ListenerConfig) -> std::io::Result<Self> {
+        #[cfg(unix)]
+        if let Some(path) = &config.unix {
+            use std::os::unix::fs::FileTypeExt;
+            match std::fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+                Ok(_) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!("{} exists and is not a socket", path.display()),
+                    ));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            return Ok(Self::Unix(UnixListener::bind(path)?));
+        }
+        Ok(Self::Tcp(TcpListener::bind(config.bind).await?))
+    }
+
+    /// 接受一个连接，Unix域套接字的对端没有网络地址，使用未指定地址
+    async fn accept(&self) -> std::io::Result<(BoxedTransport, SocketAddr)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (socket, addr) = listener.accept().await?;
+                socket.set_nodelay(true)?;
+                Ok((Box::new(socket), addr))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener) => {
+                let (socket, _) = listener.accept().await?;
+                Ok((Box::new(socket), SocketAddr::from(([0, 0, 0, 0], 0))))
+            }
+        }
+    }
+}
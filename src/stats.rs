@@ -0,0 +1,332 @@
+use bytes::Bytes;
+use log::info;
+use prometheus::core::Collector;
+use prometheus::{Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use std::time::{Duration, Instant};
+
+use crate::protocol::{MqttPacket, PublishPacket};
+use crate::routing::router::MessageRouter;
+
+/// 服务器版本，发布在`$SYS/broker/version`
+pub const BROKER_VERSION: &str = concat!("mqtt_adapt ", env!("CARGO_PKG_VERSION"));
+
+/// 数据包类型标签
+const PACKET_TYPES: [&str; 15] = [
+    "connect", "connack", "publish", "puback", "pubrec", "pubrel", "pubcomp", "subscribe", "suback",
+    "unsubscribe", "unsuback", "pingreq", "pingresp", "disconnect", "auth",
+];
+
+/// 服务器运行统计，由所有客户端连接共同累加
+///
+/// 计数保存在Prometheus指标中，`$SYS`主题和`/metrics`读取同一份数据
+#[derive(Debug)]
+pub struct BrokerStats {
+    started_at: Instant,
+    registry: Registry,
+    packets_received: IntCounterVec,
+    packets_sent: IntCounterVec,
+    bytes_received: IntCounter,
+    bytes_sent: IntCounter,
+    auth_failures: IntCounter,
+    messages_dropped: IntCounter,
+    connections: IntGauge,
+    subscriptions: IntGauge,
+    event_duration: Histogram,
+    queue_depth: Histogram,
+}
+
+impl Default for BrokerStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BrokerStats {
+    pub fn new() -> Self {
+        let packets_received = IntCounterVec::new(
+            Opts::new("mqtt_packets_received_total", "MQTT packets received from clients"),
+            &["type"],
+        )
+        .unwrap();
+        let packets_sent =
+            IntCounterVec::new(Opts::new("mqtt_packets_sent_total", "MQTT packets sent to clients"), &["type"]).unwrap();
+        // 预先创建各类型的计数，未出现过的类型也输出0
+        for packet_type in PACKET_TYPES {
+            packets_received.with_label_values(&[packet_type]);
+            packets_sent.with_label_values(&[packet_type]);
+        }
+
+        let stats = Self {
+            started_at: Instant::now(),
+            registry: Registry::new(),
+            packets_received,
+            packets_sent,
+            bytes_received: IntCounter::new("mqtt_bytes_received_total", "Bytes read from client connections").unwrap(),
+            bytes_sent: IntCounter::new("mqtt_bytes_sent_total", "Bytes written to client connections").unwrap(),
+            auth_failures: IntCounter::new("mqtt_auth_failures_total", "Connections refused by authentication").unwrap(),
+            messages_dropped: IntCounter::new(
+                "mqtt_messages_dropped_total",
+                "Messages dropped because a subscriber queue was full or closed",
+            )
+            .unwrap(),
+            connections: IntGauge::new("mqtt_connections", "Currently connected clients").unwrap(),
+            subscriptions: IntGauge::new("mqtt_subscriptions", "Current subscriptions").unwrap(),
+            event_duration: Histogram::with_opts(
+                HistogramOpts::new("mqtt_router_event_duration_seconds", "Time spent handling one router event")
+                    .buckets(prometheus::exponential_buckets(0.000_01, 4.0, 10).unwrap()),
+            )
+            .unwrap(),
+            queue_depth: Histogram::with_opts(
+                HistogramOpts::new("mqtt_router_queue_depth", "Events waiting in the router queue")
+                    .buckets(vec![0.0, 1.0, 4.0, 16.0, 64.0, 256.0, 1024.0, 4096.0, 16384.0]),
+            )
+            .unwrap(),
+        };
+
+        let collectors: [Box<dyn Collector>; 10] = [
+            Box::new(stats.packets_received.clone()),
+            Box::new(stats.packets_sent.clone()),
+            Box::new(stats.bytes_received.clone()),
+            Box::new(stats.bytes_sent.clone()),
+            Box::new(stats.auth_failures.clone()),
+            Box::new(stats.messages_dropped.clone()),
+            Box::new(stats.connections.clone()),
+            Box::new(stats.subscriptions.clone()),
+            Box::new(stats.event_duration.clone()),
+            Box::new(stats.queue_depth.clone()),
+        ];
+        for collector in collectors {
+            stats.registry.register(collector).expect("Metric names are unique");
+        }
+        stats
+    }
+
+    /// 记录从客户端收到的数据包
+    pub fn record_received(&self, packet: &MqttPacket) {
+        self.packets_received.with_label_values(&[packet_type(packet)]).inc();
+    }
+
+    /// 记录发给客户端的数据包
+    pub fn record_sent(&self, packet: &MqttPacket) {
+        self.packets_sent.with_label_values(&[packet_type(packet)]).inc();
+    }
+
+    /// 记录从客户端读取的字节数
+    pub fn add_bytes_received(&self, bytes: usize) {
+        self.bytes_received.inc_by(bytes as u64);
+    }
+
+    /// 记录发给客户端的字节数
+    pub fn add_bytes_sent(&self, bytes: usize) {
+        self.bytes_sent.inc_by(bytes as u64);
+    }
+
+    /// 记录一次认证失败
+    pub fn record_auth_failure(&self) {
+        self.auth_failures.inc();
+    }
+
+    /// 记录一条因订阅者队列已满或连接已关闭而丢弃的消息
+    pub fn record_dropped(&self) {
+        self.messages_dropped.inc();
+    }
+
+    /// 记录路由器处理一个事件的耗时和此时仍在排队的事件数
+    pub fn record_event(&self, duration: Duration, queue_depth: usize) {
+        self.event_duration.observe(duration.as_secs_f64());
+        self.queue_depth.observe(queue_depth as f64);
+    }
+
+    /// 更新当前连接数和订阅数
+    pub fn set_gauges(&self, connections: usize, subscriptions: usize) {
+        self.connections.set(connections as i64);
+        self.subscriptions.set(subscriptions as i64);
+    }
+
+    /// 收到的数据包总数
+    pub fn messages_received(&self) -> u64 {
+        PACKET_TYPES.iter().map(|t| self.packets_received.with_label_values(&[t]).get()).sum()
+    }
+
+    /// 发出的数据包总数
+    pub fn messages_sent(&self) -> u64 {
+        PACKET_TYPES.iter().map(|t| self.packets_sent.with_label_values(&[t]).get()).sum()
+    }
+
+    /// 收到的PUBLISH总数
+    pub fn publish_received(&self) -> u64 {
+        self.packets_received.with_label_values(&["publish"]).get()
+    }
+
+    /// 发出的PUBLISH总数
+    pub fn publish_sent(&self) -> u64 {
+        self.packets_sent.with_label_values(&["publish"]).get()
+    }
+
+    /// 收到的字节总数
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.get()
+    }
+
+    /// 发出的字节总数
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.get()
+    }
+
+    /// 认证失败总数
+    pub fn auth_failures(&self) -> u64 {
+        self.auth_failures.get()
+    }
+
+    /// 丢弃的消息总数
+    pub fn messages_dropped(&self) -> u64 {
+        self.messages_dropped.get()
+    }
+
+    /// 服务器运行时间
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// 按Prometheus文本格式输出全部指标
+    pub fn encode(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .expect("Text encoding of gathered metrics cannot fail")
+    }
+}
+
+fn packet_type(packet: &MqttPacket) -> &'static str {
+    match packet {
+        MqttPacket::Connect(_) => "connect",
+        MqttPacket::ConnAck(_) => "connack",
+        MqttPacket::Publish(_) => "publish",
+        MqttPacket::PubAck(_) => "puback",
+        MqttPacket::PubRec(_) => "pubrec",
+        MqttPacket::PubRel(_) => "pubrel",
+        MqttPacket::PubComp(_) => "pubcomp",
+        MqttPacket::Subscribe(_) => "subscribe",
+        MqttPacket::SubAck(_) => "suback",
+        MqttPacket::Unsubscribe(_) => "unsubscribe",
+        MqttPacket::UnsubAck(_) => "unsuback",
+        MqttPacket::PingReq(_) => "pingreq",
+        MqttPacket::PingResp(_) => "pingresp",
+        MqttPacket::Disconnect(_) => "disconnect",
+        MqttPacket::Auth(_) => "auth",
+    }
+}
+
+/// 1、5、15分钟的指数加权平均速率（每分钟）
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+impl LoadAverage {
+    /// 用`elapsed`时间内新增的`count`更新平均速率
+    pub fn update(&mut self, count: u64, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if seconds <= 0.0 {
+            return;
+        }
+        let rate = count as f64 * 60.0 / seconds;
+        for (load, window) in [(&mut self.one, 60.0), (&mut self.five, 300.0), (&mut self.fifteen, 900.0)] {
+            let decay = (-seconds / window).exp();
+            *load = *load * decay + rate * (1.0 - decay);
+        }
+    }
+}
+
+/// 周期发布`$SYS/broker/...`统计主题的状态
+#[derive(Debug)]
+pub struct SysPublisher {
+    router: MessageRouter,
+    last_update: Instant,
+    last_publish_received: u64,
+    last_publish_sent: u64,
+    publish_received_load: LoadAverage,
+    publish_sent_load: LoadAverage,
+}
+
+impl SysPublisher {
+    pub fn new(router: MessageRouter) -> Self {
+        let stats = router.stats();
+        Self {
+            last_update: Instant::now(),
+            last_publish_received: stats.publish_received(),
+            last_publish_sent: stats.publish_sent(),
+            publish_received_load: LoadAverage::default(),
+            publish_sent_load: LoadAverage::default(),
+            router,
+        }
+    }
+
+    /// 按间隔持续发布，间隔为0时不发布
+    pub async fn run(mut self, interval: Duration) {
+        if interval.is_zero() {
+            return;
+        }
+        info!("Publishing $SYS topics every {:?}", interval);
+        let mut timer = tokio::time::interval(interval);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            timer.tick().await;
+            self.publish().await;
+        }
+    }
+
+    /// 更新发布速率并发布一次全部统计主题
+    pub async fn publish(&mut self) {
+        let stats = self.router.stats();
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update);
+        self.last_update = now;
+
+        let publish_received = stats.publish_received();
+        let publish_sent = stats.publish_sent();
+        self.publish_received_load
+            .update(publish_received - self.last_publish_received, elapsed);
+        self.publish_sent_load.update(publish_sent - self.last_publish_sent, elapsed);
+        self.last_publish_received = publish_received;
+        self.last_publish_sent = publish_sent;
+
+        let (connected, total) = self.router.client_counts().await;
+        let mut topics = vec![
+            ("version".to_string(), BROKER_VERSION.to_string()),
+            ("uptime".to_string(), format!("{} seconds", stats.uptime().as_secs())),
+            ("clients/connected".to_string(), connected.to_string()),
+            ("clients/total".to_string(), total.to_string()),
+            ("subscriptions/count".to_string(), self.router.subscription_count().await.to_string()),
+            ("messages/received".to_string(), stats.messages_received().to_string()),
+            ("messages/sent".to_string(), stats.messages_sent().to_string()),
+            ("publish/messages/received".to_string(), publish_received.to_string()),
+            ("publish/messages/sent".to_string(), publish_sent.to_string()),
+            ("bytes/received".to_string(), stats.bytes_received().to_string()),
+            ("bytes/sent".to_string(), stats.bytes_sent().to_string()),
+        ];
+        for (direction, load) in [("received", self.publish_received_load), ("sent", self.publish_sent_load)] {
+            for (window, value) in [("1min", load.one), ("5min", load.five), ("15min", load.fifteen)] {
+                topics.push((format!("load/publish/{}/{}", direction, window), format!("{:.2}", value)));
+            }
+        }
+        // 保留消息数不含$SYS主题
+        let retained = self.router.retained_count().await;
+        topics.push(("retained messages/count".to_string(), retained.to_string()));
+
+        for (topic, payload) in topics {
+            self.router
+                .publish(PublishPacket {
+                    dup: false,
+                    qos: 0,
+                    retain: true,
+                    topic_name: format!("$SYS/broker/{}", topic),
+                    packet_id: None,
+                    payload: Bytes::from(payload),
+                    properties: Default::default(),
+                })
+                .await;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use rustls::RootCertStore;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::config::{CertUsername, TlsConfig};
+
+/// 按监听器的TLS配置创建TLS握手器
+///
+/// 配置了客户端CA时校验客户端证书，`require_client_cert`为false时允许不带证书的客户端
+pub fn build_acceptor(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let certs = load_certs(&config.cert)?;
+    let key = load_key(&config.key)?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &config.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(client_ca)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.require_client_cert {
+                verifier.build()?
+            } else {
+                verifier.allow_unauthenticated().build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let server_config = builder
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// 从已校验的客户端证书中取MQTT用户名
+pub fn peer_username(certs: Option<&[CertificateDer<'_>]>, mode: CertUsername) -> Option<String> {
+    if mode == CertUsername::None {
+        return None;
+    }
+    let cert = certs?.first()?;
+    let (_, cert) = X509Certificate::from_der(cert.as_ref()).ok()?;
+
+    match mode {
+        CertUsername::None => None,
+        CertUsername::Cn => cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string),
+        CertUsername::San => {
+            let san = cert.subject_alternative_name().ok()??;
+            san.value.general_names.iter().find_map(|name| match name {
+                GeneralName::DNSName(name) | GeneralName::RFC822Name(name) | GeneralName::URI(name) => {
+                    Some(name.to_string())
+                }
+                _ => None,
+            })
+        }
+    }
+}
+
+/// 读取PEM格式的证书链
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate file {}", path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow::format_err!("No certificate found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+/// 读取PEM格式的私钥（PKCS#8、PKCS#1或SEC1）
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    rustls_pemfile::private_key(&mut std::io::BufReader::new(file))
+        .with_context(|| format!("Invalid private key file {}", path.display()))?
+        .ok_or_else(|| anyhow::format_err!("No private key found in {}", path.display()))
+}
@@ -3,6 +3,55 @@ use bytes::Bytes;
 use sqlx::SqlitePool;
 use crate::db::models::retained_message::RetainedMessage as DbRetainedMessage;
 
+/// 共享订阅过滤器的前缀
+pub const SHARED_SUBSCRIPTION_PREFIX: &str = "$share/";
+
+/// 服务器统计主题的前缀，这些主题的保留消息不计入保留消息数
+pub const SYS_TOPIC_PREFIX: &str = "$SYS/";
+
+/// 拆分共享订阅`$share/{group}/{filter}`，返回组名和实际的主题过滤器
+///
+/// 不是共享订阅或组名为空、含有通配符时返回None
+pub fn parse_shared_subscription(filter: &str) -> Option<(&str, &str)> {
+    let rest = filter.strip_prefix(SHARED_SUBSCRIPTION_PREFIX)?;
+    let (group, filter) = rest.split_once('/')?;
+    if group.is_empty() || group.contains(['+', '#']) {
+        return None;
+    }
+    Some((group, filter))
+}
+
+/// 校验订阅主题过滤器
+///
+/// - 过滤器不能为空
+/// - `#`只能单独占据最后一级
+/// - `+`只能单独占据一级
+/// - `$share/`开头时必须是合法的共享订阅
+pub fn is_valid_topic_filter(filter: &str) -> bool {
+    if filter.starts_with(SHARED_SUBSCRIPTION_PREFIX) {
+        return parse_shared_subscription(filter).is_some_and(|(_, filter)| is_plain_topic_filter(filter));
+    }
+    is_plain_topic_filter(filter)
+}
+
+fn is_plain_topic_filter(filter: &str) -> bool {
+    if filter.is_empty() {
+        return false;
+    }
+
+    let levels: Vec<&str> = filter.split('/').collect();
+    let last = levels.len() - 1;
+    levels.iter().enumerate().all(|(i, level)| {
+        if level.contains('#') {
+            *level == "#" && i == last
+        } else if level.contains('+') {
+            *level == "+"
+        } else {
+            true
+        }
+    })
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct TopicSubscription {
     pub client_id: String,
@@ -21,6 +70,8 @@ pub struct TopicNode {
     pub topic: String,
     pub children: HashMap<String, TopicNode>,
     pub subscribers: Vec<TopicSubscription>,
+    /// 以本节点为过滤器的共享订阅，按组名保存成员，成员按订阅顺序排列
+    pub shared: HashMap<String, Vec<TopicSubscription>>,
     pub retained_message: Option<RetainedMessage>,
 }
 
@@ -30,6 +81,7 @@ impl TopicNode {
             topic,
             children: HashMap::new(),
             subscribers: Vec::new(),
+            shared: HashMap::new(),
             retained_message: None,
         }
     }
@@ -39,6 +91,14 @@ impl TopicNode {
 pub struct TopicManager {
     pub root: TopicNode,
     db_pool: Option<SqlitePool>,
+    /// 内存中保存了保留消息的主题数，不含`$SYS`主题
+    retained_count: usize,
+}
+
+impl Default for TopicManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TopicManager {
@@ -46,6 +106,7 @@ impl TopicManager {
         Self {
             root: TopicNode::new("root".to_string()),
             db_pool: None,
+            retained_count: 0,
         }
     }
 
@@ -53,6 +114,7 @@ impl TopicManager {
         Self {
             root: TopicNode::new("root".to_string()),
             db_pool: Some(pool),
+            retained_count: 0,
         }
     }
 
@@ -60,17 +122,26 @@ impl TopicManager {
         self.db_pool = Some(pool);
     }
 
+    /// 添加订阅，`$share/{group}/{filter}`加入对应的共享订阅组
     pub async fn add_subscription(&mut self, client_id: String, topic: String, qos: u8) {
+        let (group, filter) = match parse_shared_subscription(&topic) {
+            Some((group, filter)) => (Some(group.to_string()), filter.to_string()),
+            None => (None, topic.clone()),
+        };
         let mut current = &mut self.root;
-        let parts: Vec<&str> = topic.split('/').collect();
+        let parts: Vec<&str> = filter.split('/').collect();
 
         for part in parts {
             current = current.children.entry(part.to_string()).or_insert_with(|| TopicNode::new(part.to_string()));
         }
 
-        let existing_subscription = current.subscribers.iter().find(|s| s.client_id == client_id);
+        let subscribers = match group {
+            Some(group) => current.shared.entry(group).or_default(),
+            None => &mut current.subscribers,
+        };
+        let existing_subscription = subscribers.iter().find(|s| s.client_id == client_id);
         if existing_subscription.is_none() {
-            current.subscribers.push(TopicSubscription {
+            subscribers.push(TopicSubscription {
                 client_id,
                 topic: topic.clone(),
                 qos,
@@ -78,76 +149,136 @@ impl TopicManager {
         }
     }
 
-    pub async fn remove_subscription(&mut self, client_id: String, topic: String) {
+    /// 移除客户端的订阅，返回订阅是否存在
+    pub async fn remove_subscription(&mut self, client_id: String, topic: String) -> bool {
+        let (group, filter) = match parse_shared_subscription(&topic) {
+            Some((group, filter)) => (Some(group), filter),
+            None => (None, topic.as_str()),
+        };
         let mut current = &mut self.root;
-        let parts: Vec<&str> = topic.split('/').collect();
+        let parts: Vec<&str> = filter.split('/').collect();
 
         for part in parts {
             if let Some(child) = current.children.get_mut(part) {
                 current = child;
             } else {
-                return;
+                return false;
+            }
+        }
+
+        match group {
+            Some(group) => {
+                let Some(members) = current.shared.get_mut(group) else {
+                    return false;
+                };
+                let count = members.len();
+                members.retain(|s| s.client_id != client_id);
+                let removed = members.len() != count;
+                if members.is_empty() {
+                    current.shared.remove(group);
+                }
+                removed
+            }
+            None => {
+                let count = current.subscribers.len();
+                current.subscribers.retain(|s| s.client_id != client_id);
+                current.subscribers.len() != count
             }
         }
+    }
 
-        current.subscribers.retain(|s| s.client_id != client_id);
+    /// 移除客户端的全部订阅
+    pub async fn remove_client_subscriptions(&mut self, client_id: &str) {
+        fn remove(node: &mut TopicNode, client_id: &str) {
+            node.subscribers.retain(|s| s.client_id != client_id);
+            node.shared.retain(|_, members| {
+                members.retain(|s| s.client_id != client_id);
+                !members.is_empty()
+            });
+            for child in node.children.values_mut() {
+                remove(child, client_id);
+            }
+        }
+        remove(&mut self.root, client_id);
     }
 
+    /// 查找主题的普通订阅者，不包括共享订阅
     pub async fn find_subscribers(&self, topic: &str) -> Vec<TopicSubscription> {
-        let mut subscribers = Vec::new();
+        let mut nodes = Vec::new();
         let parts: Vec<&str> = topic.split('/').collect();
 
-        self.match_topic(&self.root, &parts, 0, &mut subscribers);
+        self.match_topic(&self.root, &parts, 0, &mut nodes);
 
-        subscribers
+        nodes.into_iter().flat_map(|node| node.subscribers.iter().cloned()).collect()
     }
 
-    fn match_topic(&self, node: &TopicNode, parts: &[&str], index: usize, subscribers: &mut Vec<TopicSubscription>) {
-        if index == parts.len() {
-            subscribers.extend(node.subscribers.clone());
-            return;
-        }
+    /// 查找与主题匹配的共享订阅组，每组返回全部成员，成员的`topic`为完整的`$share/...`过滤器
+    pub async fn find_shared_subscribers(&self, topic: &str) -> Vec<Vec<TopicSubscription>> {
+        let mut nodes = Vec::new();
+        let parts: Vec<&str> = topic.split('/').collect();
 
-        let part = parts[index];
+        self.match_topic(&self.root, &parts, 0, &mut nodes);
 
-        if let Some(child) = node.children.get(part) {
-            self.match_topic(child, parts, index + 1, subscribers);
-        }
+        nodes
+            .into_iter()
+            .flat_map(|node| node.shared.values().cloned())
+            .collect()
+    }
 
-        if let Some(child) = node.children.get("#") {
-            subscribers.extend(child.subscribers.clone());
+    /// 客户端的全部订阅，共享订阅的`topic`为完整的`$share/...`过滤器
+    pub fn client_subscriptions(&self, client_id: &str) -> Vec<TopicSubscription> {
+        fn collect(node: &TopicNode, client_id: &str, subscriptions: &mut Vec<TopicSubscription>) {
+            let shared = node.shared.values().flatten();
+            subscriptions.extend(node.subscribers.iter().chain(shared).filter(|s| s.client_id == client_id).cloned());
+            for child in node.children.values() {
+                collect(child, client_id, subscriptions);
+            }
         }
+        let mut subscriptions = Vec::new();
+        collect(&self.root, client_id, &mut subscriptions);
+        subscriptions.sort_by(|a, b| a.topic.cmp(&b.topic));
+        subscriptions
+    }
 
-        if let Some(child) = node.children.get("+") {
-            self.match_topic(child, parts, index + 1, subscribers);
+    /// 获取共享订阅组`$share/{group}/{filter}`的成员
+    pub fn shared_members(&self, share: &str) -> Vec<TopicSubscription> {
+        let Some((group, filter)) = parse_shared_subscription(share) else {
+            return Vec::new();
+        };
+        let mut current = &self.root;
+        for part in filter.split('/') {
+            match current.children.get(part) {
+                Some(child) => current = child,
+                None => return Vec::new(),
+            }
         }
+        current.shared.get(group).cloned().unwrap_or_default()
     }
 
-    fn find_retained_messages(&self, node: &TopicNode, parts: &[&str], index: usize, messages: &mut Vec<(String, RetainedMessage)>, original_filter: &str) {
+    /// 收集过滤器与主题匹配的节点
+    fn match_topic<'a>(&'a self, node: &'a TopicNode, parts: &[&str], index: usize, nodes: &mut Vec<&'a TopicNode>) {
         if index == parts.len() {
-            if let Some(retained) = &node.retained_message {
-                // 构建完整的主题路径
-                let topic = original_filter.to_string();
-                messages.push((topic, retained.clone()));
-            }
+            nodes.push(node);
             return;
         }
 
         let part = parts[index];
 
         if let Some(child) = node.children.get(part) {
-            self.find_retained_messages(child, parts, index + 1, messages, original_filter);
+            self.match_topic(child, parts, index + 1, nodes);
+        }
+
+        // 第一层的通配符不匹配`$`开头的主题
+        if is_system_level(index, part) {
+            return;
         }
 
         if let Some(child) = node.children.get("#") {
-            if let Some(retained) = &child.retained_message {
-                let topic = original_filter.to_string();
-                messages.push((topic, retained.clone()));
-            }
+            nodes.push(child);
         }
 
         if let Some(child) = node.children.get("+") {
-            self.find_retained_messages(child, parts, index + 1, messages, original_filter);
+            self.match_topic(child, parts, index + 1, nodes);
         }
     }
 
@@ -160,6 +291,7 @@ impl TopicManager {
             current = current.children.entry(part.to_string()).or_insert_with(|| TopicNode::new(part.to_string()));
         }
 
+        let existed = current.retained_message.is_some();
         if payload.is_empty() {
             current.retained_message = None;
         } else {
@@ -168,6 +300,12 @@ impl TopicManager {
                 qos,
             });
         }
+        match (existed, current.retained_message.is_some()) {
+            _ if topic.starts_with(SYS_TOPIC_PREFIX) => {}
+            (false, true) => self.retained_count += 1,
+            (true, false) => self.retained_count -= 1,
+            _ => {}
+        }
 
         // 存储到数据库（如果有）
         if let Some(pool) = &self.db_pool {
@@ -183,18 +321,51 @@ impl TopicManager {
         }
     }
 
+    /// 当前的订阅数，共享订阅的每个成员各算一个
+    pub fn subscription_count(&self) -> usize {
+        fn count(node: &TopicNode) -> usize {
+            node.subscribers.len()
+                + node.shared.values().map(Vec::len).sum::<usize>()
+                + node.children.values().map(count).sum::<usize>()
+        }
+        count(&self.root)
+    }
+
+    /// 保存了保留消息的主题数，不含`$SYS`主题
+    pub fn retained_count(&self) -> usize {
+        self.retained_count
+    }
+
+    /// 主题当前是否有保留消息
+    pub fn has_retained(&self, topic: &str) -> bool {
+        let mut current = &self.root;
+        for part in topic.split('/') {
+            match current.children.get(part) {
+                Some(child) => current = child,
+                None => return false,
+            }
+        }
+        current.retained_message.is_some()
+    }
+
     pub async fn get_retained_messages(&self, topic_filter: &str) -> Vec<(String, RetainedMessage)> {
         let mut messages = Vec::new();
 
         // 从内存中获取
         let parts: Vec<&str> = topic_filter.split('/').collect();
-        self.find_retained_messages(&self.root, &parts, 0, &mut messages, topic_filter);
+        find_retained_messages(&self.root, &parts, 0, "", &mut messages);
 
         // 从数据库中获取（如果有）
         if let Some(pool) = &self.db_pool {
             match DbRetainedMessage::find_matching(pool, topic_filter).await {
                 Ok(db_messages) => {
                     for db_msg in db_messages {
+                        // LIKE只是粗略匹配，并且内存中已有的主题不重复返回
+                        if !topic_matches(topic_filter, &db_msg.topic)
+                            || messages.iter().any(|(topic, _)| *topic == db_msg.topic)
+                        {
+                            continue;
+                        }
                         let topic = db_msg.topic.clone();
                         let payload = db_msg.payload_bytes();
                         let qos = db_msg.qos_u8();
@@ -216,3 +387,82 @@ impl TopicManager {
         messages
     }
 }
+
+/// 主题是否与过滤器匹配，第一层的通配符不匹配`$`开头的主题
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let filter: Vec<&str> = filter.split('/').collect();
+    let topic: Vec<&str> = topic.split('/').collect();
+    for (index, level) in filter.iter().enumerate() {
+        match *level {
+            // "a/#"同时匹配"a"本身
+            "#" => return !topic.first().is_some_and(|level| is_system_level(index, level)),
+            "+" => match topic.get(index) {
+                Some(level) if !is_system_level(index, level) => {}
+                _ => return false,
+            },
+            literal => {
+                if topic.get(index) != Some(&literal) {
+                    return false;
+                }
+            }
+        }
+    }
+    filter.len() == topic.len()
+}
+
+fn is_system_level(index: usize, level: &str) -> bool {
+    index == 0 && level.starts_with('$')
+}
+
+/// 子节点的完整主题
+fn child_topic(topic: &str, index: usize, name: &str) -> String {
+    if index == 0 { name.to_string() } else { format!("{}/{}", topic, name) }
+}
+
+/// 按过滤器遍历主题树，收集保留消息及其实际主题
+fn find_retained_messages(node: &TopicNode, filter: &[&str], index: usize, topic: &str, messages: &mut Vec<(String, RetainedMessage)>) {
+    let Some(part) = filter.get(index) else {
+        if let Some(retained) = &node.retained_message {
+            messages.push((topic.to_string(), retained.clone()));
+        }
+        return;
+    };
+
+    match *part {
+        "#" => {
+            // "a/#"同时匹配"a"本身
+            if index > 0
+                && let Some(retained) = &node.retained_message
+            {
+                messages.push((topic.to_string(), retained.clone()));
+            }
+            collect_retained_messages(node, index, topic, messages);
+        }
+        "+" => {
+            for (name, child) in &node.children {
+                if !is_system_level(index, name) {
+                    find_retained_messages(child, filter, index + 1, &child_topic(topic, index, name), messages);
+                }
+            }
+        }
+        literal => {
+            if let Some(child) = node.children.get(literal) {
+                find_retained_messages(child, filter, index + 1, &child_topic(topic, index, literal), messages);
+            }
+        }
+    }
+}
+
+/// 收集节点下全部后代的保留消息
+fn collect_retained_messages(node: &TopicNode, index: usize, topic: &str, messages: &mut Vec<(String, RetainedMessage)>) {
+    for (name, child) in &node.children {
+        if is_system_level(index, name) {
+            continue;
+        }
+        let topic = child_topic(topic, index, name);
+        if let Some(retained) = &child.retained_message {
+            messages.push((topic.clone(), retained.clone()));
+        }
+        collect_retained_messages(child, index + 1, &topic, messages);
+    }
+}
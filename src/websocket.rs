@@ -0,0 +1,141 @@
+use anyhow::Result;
+use bytes::{Buf, Bytes};
+use futures_util::{Sink, Stream};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{HeaderMap, HeaderValue, StatusCode, header};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+use crate::config::WebSocketConfig;
+
+/// 服务器支持的WebSocket子协议，`mqtt`用于MQTT 3.1.1和5.0，`mqttv3.1`用于MQTT 3.1
+const SUBPROTOCOLS: [&str; 2] = ["mqtt", "mqttv3.1"];
+
+/// 完成WebSocket握手，返回可直接交给客户端读写的字节流
+///
+/// 只接受配置路径上的握手请求，并要求客户端提供`mqtt`子协议
+// 握手回调的错误类型由tungstenite规定
+#[allow(clippy::result_large_err)]
+pub async fn accept<S>(stream: S, config: &WebSocketConfig) -> Result<WsTransport<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let path = config.path.clone();
+    let callback = move |request: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+        if request.uri().path() != path {
+            return Err(error_response(StatusCode::NOT_FOUND, "Not Found"));
+        }
+        let Some(protocol) = select_subprotocol(request.headers()) else {
+            return Err(error_response(StatusCode::BAD_REQUEST, "Missing mqtt subprotocol"));
+        };
+        response
+            .headers_mut()
+            .insert(header::SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(protocol));
+        Ok(response)
+    };
+
+    let stream = tokio_tungstenite::accept_hdr_async(stream, callback).await?;
+    Ok(WsTransport::new(stream))
+}
+
+/// 按客户端给出的顺序选择第一个支持的子协议
+fn select_subprotocol(headers: &HeaderMap) -> Option<&'static str> {
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|offered| SUBPROTOCOLS.into_iter().find(|supported| offered.trim() == *supported))
+}
+
+fn error_response(status: StatusCode, reason: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(reason.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+/// WebSocket上的MQTT字节流
+///
+/// 读取时把二进制帧的内容拼接成连续的字节流，MQTT数据包可以跨多个帧，
+/// 一个帧也可以包含多个数据包；写入的数据以二进制帧发送
+pub struct WsTransport<S> {
+    inner: WebSocketStream<S>,
+    /// 当前帧中尚未读取的数据
+    read_buf: Bytes,
+}
+
+impl<S> WsTransport<S> {
+    fn new(inner: WebSocketStream<S>) -> Self {
+        Self {
+            inner,
+            read_buf: Bytes::new(),
+        }
+    }
+}
+
+impl<S> std::fmt::Debug for WsTransport<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WsTransport")
+            .field("buffered", &self.read_buf.len())
+            .finish_non_exhaustive()
+    }
+}
+
+fn into_io_error(e: WsError) -> io::Error {
+    match e {
+        WsError::Io(e) => e,
+        // 对端已正常关闭时按连接结束处理
+        WsError::ConnectionClosed | WsError::AlreadyClosed => io::ErrorKind::BrokenPipe.into(),
+        e => io::Error::other(e),
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WsTransport<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if !self.read_buf.is_empty() {
+                let n = self.read_buf.len().min(buf.remaining());
+                buf.put_slice(&self.read_buf[..n]);
+                self.read_buf.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => self.read_buf = data,
+                // 关闭帧或连接结束时返回EOF
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                // Ping由tungstenite自动回复Pong
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Text(_))) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "MQTT over WebSocket requires binary frames",
+                    )));
+                }
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WsTransport<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ready!(Pin::new(&mut self.inner).poll_ready(cx)).map_err(into_io_error)?;
+        Pin::new(&mut self.inner)
+            .start_send(Message::binary(buf.to_vec()))
+            .map_err(into_io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx).map_err(into_io_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx).map_err(into_io_error)
+    }
+}
@@ -0,0 +1,227 @@
+use bytes::BytesMut;
+use flume::unbounded;
+use mqtt_adapt::auth::AnonymousAuthenticator;
+use mqtt_adapt::client::create_client_with_connect;
+use mqtt_adapt::config::BrokerConfig;
+use mqtt_adapt::db::models::session::Session;
+use mqtt_adapt::protocol::{ConnectPacket, MqttPacket, PublishPacket};
+use mqtt_adapt::routing::router::MessageRouter;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::net::{TcpListener, TcpStream};
+
+mod common;
+use common::{connect_packet, temp_database};
+
+/// 启动管理接口，返回监听地址
+async fn spawn_admin(router: &MessageRouter, token: Option<&str>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(mqtt_adapt::admin::serve(listener, router.clone(), token.map(str::to_string)));
+    addr
+}
+
+/// 发送一次HTTP请求，返回状态码和JSON响应体（没有响应体时为Null）
+async fn request(addr: SocketAddr, method: &str, path: &str, body: Option<Value>, token: Option<&str>) -> (u16, Value) {
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n", method, path, addr);
+    if let Some(token) = token {
+        request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+    }
+    if !body.is_empty() {
+        request.push_str(&format!("Content-Type: application/json\r\nContent-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+    request.push_str(&body);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, serde_json::from_str(body).unwrap_or(Value::Null))
+}
+
+async fn get(addr: SocketAddr, path: &str) -> (u16, Value) {
+    request(addr, "GET", path, None, None).await
+}
+
+/// 通过内存连接接入一个MQTT 3.1.1客户端并运行处理循环，返回客户端一侧的连接
+async fn connect_client(router: &MessageRouter, client_id: &str, clean_session: bool) -> DuplexStream {
+    let (mut peer, socket) = tokio::io::duplex(4096);
+    let connect = ConnectPacket {
+        connect_flags: if clean_session { 0x02 } else { 0x00 },
+        ..connect_packet(client_id, None, None)
+    };
+    let mut buffer = BytesMut::new();
+    MqttPacket::Connect(connect).write(&mut buffer);
+    peer.write_all(&buffer).await.unwrap();
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 50000));
+    let client = create_client_with_connect(socket, addr, router, &AnonymousAuthenticator, &BrokerConfig::default(), None, None)
+        .await
+        .unwrap();
+    tokio::spawn(client.handle());
+    // 读走CONNACK
+    let mut connack = [0u8; 4];
+    peer.read_exact(&mut connack).await.unwrap();
+    peer
+}
+
+// 测试查询、搜索和断开在线客户端
+#[tokio::test]
+async fn test_clients() {
+    let router = MessageRouter::new();
+    tokio::spawn(router.clone().start());
+    let mut peer = connect_client(&router, "dev-1", true).await;
+    let _other = connect_client(&router, "sensor-7", true).await;
+    let addr = spawn_admin(&router, None).await;
+
+    let (status, clients) = get(addr, "/api/clients").await;
+    assert_eq!(status, 200);
+    assert_eq!(clients.as_array().unwrap().len(), 2);
+    assert_eq!(clients[0]["client_id"], "dev-1");
+    assert_eq!(clients[0]["address"], "127.0.0.1:50000");
+    assert_eq!(clients[0]["keepalive"], 30);
+    assert_eq!(clients[0]["protocol_version"], 4);
+    assert_eq!(clients[0]["persistent"], false);
+
+    let (_, clients) = get(addr, "/api/clients?search=sensor").await;
+    assert_eq!(clients.as_array().unwrap().len(), 1);
+    assert_eq!(clients[0]["client_id"], "sensor-7");
+    assert_eq!(get(addr, "/api/clients/nobody").await.0, 404);
+
+    // 断开后客户端连接关闭，路由器移除客户端
+    assert_eq!(request(addr, "DELETE", "/api/clients/dev-1", None, None).await.0, 204);
+    let mut buffer = [0u8; 16];
+    let read = tokio::time::timeout(Duration::from_secs(2), peer.read(&mut buffer)).await.unwrap();
+    assert_eq!(read.unwrap(), 0);
+    for _ in 0..50 {
+        if get(addr, "/api/clients/dev-1").await.0 == 404 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(get(addr, "/api/clients/dev-1").await.0, 404);
+    assert_eq!(request(addr, "DELETE", "/api/clients/dev-1", None, None).await.0, 404);
+}
+
+// 测试为客户端添加和移除订阅
+#[tokio::test]
+async fn test_subscriptions() {
+    let router = MessageRouter::new();
+    let (tx, rx) = unbounded();
+    router.register_client("c1", tx).await.unwrap();
+    let addr = spawn_admin(&router, None).await;
+
+    let subscription = json!({ "topic": "alerts/+", "qos": 1 });
+    let path = "/api/clients/c1/subscriptions";
+    assert_eq!(request(addr, "POST", path, Some(subscription), None).await.0, 204);
+    let (status, subscriptions) = get(addr, path).await;
+    assert_eq!(status, 200);
+    assert_eq!(subscriptions, json!([{ "topic": "alerts/+", "qos": 1 }]));
+
+    // 管理接口添加的订阅立即生效
+    router
+        .publish(PublishPacket {
+            dup: false,
+            qos: 0,
+            retain: false,
+            topic_name: "alerts/fire".to_string(),
+            packet_id: None,
+            payload: bytes::Bytes::from_static(b"!"),
+            properties: Default::default(),
+        })
+        .await;
+    assert!(matches!(rx.try_recv(), Ok(mqtt_adapt::routing::event::Event::MessageSent(_, MqttPacket::Publish(_)))));
+
+    let invalid = json!({ "topic": "alerts/#/x" });
+    assert_eq!(request(addr, "POST", path, Some(invalid), None).await.0, 400);
+    let unknown = json!({ "topic": "alerts/+" });
+    assert_eq!(request(addr, "POST", "/api/clients/nobody/subscriptions", Some(unknown), None).await.0, 404);
+
+    let remove = "/api/clients/c1/subscriptions?topic=alerts%2F%2B";
+    assert_eq!(request(addr, "DELETE", remove, None, None).await.0, 204);
+    assert_eq!(request(addr, "DELETE", remove, None, None).await.0, 404);
+    assert_eq!(get(addr, path).await.1, json!([]));
+}
+
+// 测试查看和清除数据库中的持久会话
+#[tokio::test]
+async fn test_sessions() {
+    let db = temp_database().await;
+    let router = MessageRouter::new().with_db(db.get_pool().clone());
+    tokio::spawn(router.clone().start());
+    let peer = connect_client(&router, "keeper", false).await;
+    router.add_client_subscription("keeper", "jobs/#", 1).await.unwrap();
+    let addr = spawn_admin(&router, None).await;
+
+    let (status, session) = get(addr, "/api/sessions/keeper").await;
+    assert_eq!(status, 200);
+    assert_eq!(session["connected"], true);
+    assert_eq!(session["subscriptions"], json!([{ "topic": "jobs/#", "qos": 1 }]));
+    assert!(session["last_connected_at"].is_string());
+    // 在线客户端的会话不能清除
+    assert_eq!(request(addr, "DELETE", "/api/sessions/keeper", None, None).await.0, 409);
+
+    drop(peer);
+    for _ in 0..50 {
+        if get(addr, "/api/sessions/keeper").await.1["connected"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let (_, sessions) = get(addr, "/api/sessions").await;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+    assert_eq!(sessions[0]["connected"], false);
+
+    assert_eq!(request(addr, "DELETE", "/api/sessions/keeper", None, None).await.0, 204);
+    assert!(Session::find_by_client_id(db.get_pool(), "keeper").await.unwrap().is_none());
+    assert!(router.client_subscriptions("keeper").await.is_empty());
+    assert_eq!(get(addr, "/api/sessions").await.1, json!([]));
+    assert_eq!(request(addr, "DELETE", "/api/sessions/keeper", None, None).await.0, 404);
+}
+
+// 测试以服务器身份发布消息，浏览和删除保留消息
+#[tokio::test]
+async fn test_publish_and_retained() {
+    let router = MessageRouter::new();
+    let addr = spawn_admin(&router, None).await;
+
+    let message = json!({ "topic": "status/door", "payload": "open", "retain": true });
+    assert_eq!(request(addr, "POST", "/api/publish", Some(message), None).await.0, 204);
+    let wildcard = json!({ "topic": "status/#" });
+    assert_eq!(request(addr, "POST", "/api/publish", Some(wildcard), None).await.0, 400);
+
+    let (status, retained) = get(addr, "/api/retained?topic=status%2F%23").await;
+    assert_eq!(status, 200);
+    assert_eq!(retained, json!([{ "topic": "status/door", "qos": 0, "payload": "open" }]));
+    assert_eq!(get(addr, "/api/retained").await.1.as_array().unwrap().len(), 1);
+
+    // 删除只针对单个主题，通配符被拒绝
+    assert_eq!(request(addr, "DELETE", "/api/retained?topic=status%2F%23", None, None).await.0, 400);
+    assert!(!router.delete_retained("status/#").await);
+    assert_eq!(get(addr, "/api/retained").await.1.as_array().unwrap().len(), 1);
+
+    let delete = "/api/retained?topic=status%2Fdoor";
+    assert_eq!(request(addr, "DELETE", delete, None, None).await.0, 204);
+    assert_eq!(request(addr, "DELETE", delete, None, None).await.0, 404);
+    assert_eq!(get(addr, "/api/retained").await.1, json!([]));
+}
+
+// 测试配置令牌后请求需要携带Bearer令牌
+#[tokio::test]
+async fn test_bearer_token() {
+    let router = MessageRouter::new();
+    let addr = spawn_admin(&router, Some("s3cret")).await;
+
+    let (status, body) = get(addr, "/api/clients").await;
+    assert_eq!(status, 401);
+    assert!(body["error"].is_string());
+    assert_eq!(request(addr, "GET", "/api/clients", None, Some("wrong")).await.0, 401);
+    assert_eq!(request(addr, "GET", "/api/clients", None, Some("s3cret")).await.0, 200);
+}
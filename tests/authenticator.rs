@@ -0,0 +1,201 @@
+use async_trait::async_trait;
+use mqtt_adapt::auth::{
+    AnonymousAuthenticator, AuthDecision, AuthRequest, Authenticator, ChainAuthenticator, ClientAttributes,
+    SqliteAuthenticator, StaticFileAuthenticator,
+};
+use mqtt_adapt::auth::password::{hash_password, verify_and_rehash_async, verify_password};
+use mqtt_adapt::config::{AuthConfig, AuthMode, BrokerConfig};
+use mqtt_adapt::db::models::ag_user::User;
+use mqtt_adapt::protocol::{ConnectPacket, ConnectReturnCode, MqttPacket};
+use mqtt_adapt::routing::router::MessageRouter;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+mod common;
+use common::{authenticate, connect_packet, temp_database};
+
+/// 认证测试使用固定客户端ID的CONNECT
+fn login(username: Option<&str>, password: Option<&str>) -> ConnectPacket {
+    connect_packet("auth-device", username, password)
+}
+
+fn accepted(username: &str) -> AuthDecision {
+    AuthDecision::Accept(ClientAttributes::with_username(Some(username.to_string())))
+}
+
+/// 生成`用户名:argon2哈希`格式的密码文件内容
+fn password_file(users: &[(&str, &str)]) -> String {
+    users
+        .iter()
+        .map(|(username, password)| format!("{}:{}\n", username, hash_password(password).unwrap()))
+        .collect()
+}
+
+// 测试密码文件认证，未知用户交给下一个认证方式
+#[tokio::test]
+async fn test_static_file_authenticator() {
+    let content = format!("# 运维账号\n{}\n", password_file(&[("admin", "secret"), ("sensor", "s3:cret")]));
+    let authenticator = StaticFileAuthenticator::parse(&content).unwrap();
+    assert_eq!(authenticate(&authenticator, &login(Some("admin"), Some("secret"))).await, accepted("admin"));
+    // 只按第一个冒号分隔，密码中可以包含冒号
+    assert_eq!(authenticate(&authenticator, &login(Some("sensor"), Some("s3:cret"))).await, accepted("sensor"));
+    assert_eq!(
+        authenticate(&authenticator, &login(Some("admin"), Some("wrong"))).await,
+        AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword)
+    );
+    assert_eq!(authenticate(&authenticator, &login(Some("nobody"), Some("secret"))).await, AuthDecision::Continue);
+    assert_eq!(authenticate(&authenticator, &login(None, None)).await, AuthDecision::Continue);
+
+    assert!(StaticFileAuthenticator::parse("admin").is_err());
+    // 明文密码不能通过安全审计，加载时拒绝
+    assert!(StaticFileAuthenticator::parse("admin:secret").is_err());
+}
+
+// 测试认证链按顺序尝试，全部无法判断时拒绝
+#[tokio::test]
+async fn test_chain_authenticator() {
+    let file = StaticFileAuthenticator::parse(&password_file(&[("admin", "secret")])).unwrap();
+    let chain = ChainAuthenticator::default().with(file.clone());
+    assert_eq!(
+        authenticate(&chain, &login(None, None)).await,
+        AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword)
+    );
+
+    // 已知用户密码错误时直接拒绝，不再交给匿名认证
+    let chain = chain.with(AnonymousAuthenticator);
+    assert_eq!(
+        authenticate(&chain, &login(Some("admin"), Some("wrong"))).await,
+        AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword)
+    );
+    assert_eq!(
+        authenticate(&chain, &login(None, None)).await,
+        AuthDecision::Accept(ClientAttributes::default())
+    );
+}
+
+// 测试数据库认证，禁用的用户不能连接
+#[tokio::test]
+async fn test_sqlite_authenticator() {
+    let db = temp_database().await;
+    User::create(db.get_pool(), "device", "secret").await.unwrap();
+
+    let authenticator = SqliteAuthenticator::new(db.get_pool().clone());
+    assert_eq!(authenticate(&authenticator, &login(Some("device"), Some("secret"))).await, accepted("device"));
+    assert_eq!(
+        authenticate(&authenticator, &login(Some("device"), Some("wrong"))).await,
+        AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword)
+    );
+    assert_eq!(authenticate(&authenticator, &login(Some("other"), Some("secret"))).await, AuthDecision::Continue);
+
+    User::set_disabled(db.get_pool(), "device", true).await.unwrap();
+    assert_eq!(
+        authenticate(&authenticator, &login(Some("device"), Some("secret"))).await,
+        AuthDecision::Deny(ConnectReturnCode::RefusedNotAuthorized)
+    );
+}
+
+// 测试明文保存的旧密码认证通过后改存哈希
+#[tokio::test]
+async fn test_sqlite_authenticator_rehashes_plaintext() {
+    let db = temp_database().await;
+    sqlx::query("INSERT INTO users (username, password) VALUES ('legacy', 'secret')")
+        .execute(db.get_pool())
+        .await
+        .unwrap();
+
+    let authenticator = SqliteAuthenticator::new(db.get_pool().clone());
+    assert_eq!(authenticate(&authenticator, &login(Some("legacy"), Some("secret"))).await, accepted("legacy"));
+    let user = User::find_by_username(db.get_pool(), "legacy").await.unwrap().unwrap();
+    assert!(user.password.starts_with("$argon2id$"));
+    assert_eq!(authenticate(&authenticator, &login(Some("legacy"), Some("secret"))).await, accepted("legacy"));
+}
+
+// 测试校验时只有通过校验的明文密码返回新的哈希
+#[tokio::test]
+async fn test_verify_and_rehash() {
+    let (verified, rehash) = verify_and_rehash_async("secret", "secret").await.unwrap();
+    assert!(verified);
+    assert!(verify_password("secret", &rehash.unwrap()));
+
+    let argon2_hash = hash_password("secret").unwrap();
+    assert_eq!(verify_and_rehash_async("secret", &argon2_hash).await.unwrap(), (true, None));
+    assert_eq!(verify_and_rehash_async("wrong", &argon2_hash).await.unwrap(), (false, None));
+    assert_eq!(verify_and_rehash_async("wrong", "secret").await.unwrap(), (false, None));
+}
+
+// 测试密码文件中的bcrypt和argon2哈希
+#[tokio::test]
+async fn test_static_file_authenticator_hashes() {
+    let bcrypt_hash = bcrypt::hash("bcrypt-secret", 4).unwrap();
+    let argon2_hash = hash_password("argon2-secret").unwrap();
+    let content = format!("old:{}\nnew:{}\n", bcrypt_hash, argon2_hash);
+    let authenticator = StaticFileAuthenticator::parse(&content).unwrap();
+    assert_eq!(authenticate(&authenticator, &login(Some("old"), Some("bcrypt-secret"))).await, accepted("old"));
+    assert_eq!(authenticate(&authenticator, &login(Some("new"), Some("argon2-secret"))).await, accepted("new"));
+    assert_eq!(
+        authenticate(&authenticator, &login(Some("new"), Some("bcrypt-secret"))).await,
+        AuthDecision::Deny(ConnectReturnCode::RefusedBadUsernameOrPassword)
+    );
+}
+
+/// 只接受本机地址，并附加客户端ID前缀作为属性的自定义认证
+#[derive(Debug)]
+struct LoopbackAuthenticator;
+
+#[async_trait]
+impl Authenticator for LoopbackAuthenticator {
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> anyhow::Result<AuthDecision> {
+        if !request.peer_addr.ip().is_loopback() {
+            return Ok(AuthDecision::Deny(ConnectReturnCode::RefusedNotAuthorized));
+        }
+        let mut attributes = ClientAttributes::with_username(Some("backend".to_string()));
+        attributes.attributes.insert("group".to_string(), request.connect.client_id.clone());
+        Ok(AuthDecision::Accept(attributes))
+    }
+}
+
+// 测试自定义认证方式的属性附加到客户端上
+#[tokio::test]
+async fn test_custom_authenticator_attributes() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut peer = TcpStream::connect(addr).await.unwrap();
+    let (socket, addr) = listener.accept().await.unwrap();
+
+    let mut buffer = bytes::BytesMut::new();
+    MqttPacket::Connect(login(None, None)).write(&mut buffer);
+    peer.write_all(&buffer).await.unwrap();
+
+    let router = MessageRouter::new();
+    let client = mqtt_adapt::client::create_client_with_connect(
+        socket,
+        addr,
+        &router,
+        &LoopbackAuthenticator,
+        &BrokerConfig::default(),
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(client.username(), Some("backend"));
+    assert_eq!(client.attributes().attributes["group"], "auth-device");
+}
+
+// 测试按配置创建认证方式
+#[tokio::test]
+async fn test_authenticator_from_config() {
+    let path = std::env::temp_dir().join(format!("mqtt_adapt_passwd_{}", uuid::Uuid::new_v4()));
+    std::fs::write(&path, password_file(&[("admin", "secret")])).unwrap();
+    let config = AuthConfig {
+        mode: AuthMode::File,
+        password_file: Some(path.clone()),
+        jwt: None,
+    };
+    let authenticator = mqtt_adapt::auth::from_config(&config, None).unwrap();
+    assert_eq!(authenticate(authenticator.as_ref(), &login(Some("admin"), Some("secret"))).await, accepted("admin"));
+    std::fs::remove_file(&path).unwrap();
+
+    // 数据库认证需要数据库连接
+    assert!(mqtt_adapt::auth::from_config(&AuthConfig::default(), None).is_err());
+}
@@ -0,0 +1,198 @@
+use clap::Parser;
+use mqtt_adapt::config::{AuthMode, BrokerConfig, Cli, LogFormat};
+use mqtt_adapt::routing::qos::OverflowPolicy;
+use std::net::SocketAddr;
+
+// 测试默认配置与原先硬编码的值一致
+#[test]
+fn test_default_config() {
+    let config = BrokerConfig::default();
+    config.validate().unwrap();
+    assert_eq!(config.listeners[0].bind, "127.0.0.1:1883".parse::<SocketAddr>().unwrap());
+    assert_eq!(config.database.url, "sqlite://mqtt_adapt.db");
+    assert_eq!(config.log.level, "info");
+    assert_eq!(config.auth.mode, AuthMode::Database);
+}
+
+// 测试示例配置文件可以加载
+#[test]
+fn test_example_config_file() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/mqtt_adapt.example.toml");
+    let config = BrokerConfig::from_file(path).unwrap();
+    assert_eq!(config.listeners[0].bind, "0.0.0.0:1883".parse::<SocketAddr>().unwrap());
+    assert_eq!(config.inflight, BrokerConfig::default().inflight);
+    assert_eq!(config.limits, BrokerConfig::default().limits);
+}
+
+// 测试配置文件只需包含要修改的字段
+#[test]
+fn test_partial_config() {
+    let config = BrokerConfig::from_toml_str(
+        r#"
+        [[listeners]]
+        bind = "0.0.0.0:1883"
+
+        [[listeners]]
+        bind = "0.0.0.0:11883"
+
+        [log]
+        format = "json"
+
+        [inflight]
+        overflow_policy = "disconnect"
+
+        [limits]
+        max_offline_messages = 100
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.listeners.len(), 2);
+    assert_eq!(config.log.format, LogFormat::Json);
+    assert_eq!(config.log.level, "info");
+    assert_eq!(config.inflight.overflow_policy, OverflowPolicy::Disconnect);
+    assert_eq!(config.inflight.max_inflight, 32);
+    assert_eq!(config.limits.max_offline_messages, 100);
+    assert_eq!(config.limits.max_retained_messages, 0);
+}
+
+// 测试拒绝未知字段和非法取值
+#[test]
+fn test_invalid_config_rejected() {
+    assert!(BrokerConfig::from_toml_str("[mqtt]\nmax_keep_alive = 10").is_err());
+    assert!(BrokerConfig::from_toml_str("[log]\nlevel = \"loud\"").is_err());
+    assert!(BrokerConfig::from_toml_str("[mqtt]\nmax_packet_size = 1").is_err());
+    assert!(BrokerConfig::from_toml_str("[auth]\nmode = \"open\"").is_err());
+    assert!(BrokerConfig::from_toml_str("[auth]\nmode = \"file\"").is_err());
+    assert!(BrokerConfig::from_toml_str("listeners = []").is_err());
+}
+
+// 测试命令行参数覆盖配置文件
+#[test]
+fn test_cli_overrides_file() {
+    let path = std::env::temp_dir().join(format!("mqtt_adapt_config_{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "[database]\nurl = \"sqlite://from_file.db\"\n\n[inflight]\nmax_inflight = 16\n").unwrap();
+
+    let cli = Cli::try_parse_from([
+        "mqtt_adapt",
+        "--config",
+        path.to_str().unwrap(),
+        "--bind",
+        "0.0.0.0:1884,[::]:1884",
+        "--max-inflight",
+        "8",
+        "--overflow-policy",
+        "drop_newest",
+        "--auth-mode",
+        "anonymous",
+    ])
+    .unwrap();
+    let config = BrokerConfig::load(&cli).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.database.url, "sqlite://from_file.db");
+    assert_eq!(config.listeners.len(), 2);
+    assert_eq!(config.listeners[1].bind, "[::]:1884".parse::<SocketAddr>().unwrap());
+    assert_eq!(config.inflight.max_inflight, 8);
+    assert_eq!(config.inflight.overflow_policy, OverflowPolicy::DropNewest);
+    assert_eq!(config.auth.mode, AuthMode::Anonymous);
+}
+
+// 测试TLS监听器配置，证书身份映射必须配合客户端CA
+#[test]
+fn test_tls_listener_config() {
+    let config = BrokerConfig::from_toml_str(
+        r#"
+        [[listeners]]
+        bind = "0.0.0.0:8883"
+        [listeners.tls]
+        cert = "server.pem"
+        key = "server.key"
+        client_ca = "ca.pem"
+        cert_username = "san"
+        "#,
+    )
+    .unwrap();
+    let tls = config.listeners[0].tls.as_ref().unwrap();
+    assert!(tls.require_client_cert);
+    assert_eq!(tls.cert_username, mqtt_adapt::config::CertUsername::San);
+
+    let without_ca = r#"
+        [[listeners]]
+        bind = "0.0.0.0:8883"
+        [listeners.tls]
+        cert = "server.pem"
+        key = "server.key"
+        cert_username = "cn"
+    "#;
+    assert!(BrokerConfig::from_toml_str(without_ca).is_err());
+}
+
+// 测试WebSocket监听器配置，同时配置TLS时为wss
+#[test]
+fn test_websocket_listener_config() {
+    let config = BrokerConfig::from_toml_str(
+        r#"
+        [[listeners]]
+        bind = "0.0.0.0:8083"
+        [listeners.websocket]
+
+        [[listeners]]
+        bind = "0.0.0.0:8084"
+        [listeners.websocket]
+        path = "/ws"
+        [listeners.tls]
+        cert = "server.pem"
+        key = "server.key"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.listeners[0].websocket.as_ref().unwrap().path, "/mqtt");
+    assert_eq!(config.listeners[0].scheme(), "ws");
+    assert_eq!(config.listeners[1].websocket.as_ref().unwrap().path, "/ws");
+    assert_eq!(config.listeners[1].scheme(), "wss");
+
+    let relative_path = r#"
+        [[listeners]]
+        bind = "0.0.0.0:8083"
+        [listeners.websocket]
+        path = "mqtt"
+    "#;
+    assert!(BrokerConfig::from_toml_str(relative_path).is_err());
+}
+
+// 测试监听器各自的连接数上限、认证方式、挂载点和Unix域套接字
+#[test]
+fn test_per_listener_settings() {
+    let config = BrokerConfig::from_toml_str(
+        r#"
+        [[listeners]]
+        bind = "127.0.0.1:1883"
+        mountpoint = "backend/"
+        [listeners.auth]
+        mode = "anonymous"
+
+        [[listeners]]
+        bind = "0.0.0.0:8883"
+        max_connections = 1000
+
+        [[listeners]]
+        unix = "/run/mqtt_adapt.sock"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.listeners[0].mountpoint.as_deref(), Some("backend/"));
+    assert_eq!(config.listeners[0].auth.as_ref().unwrap().mode, AuthMode::Anonymous);
+    assert_eq!(config.listeners[0].max_connections, 0);
+    assert!(config.listeners[1].auth.is_none());
+    assert_eq!(config.listeners[1].max_connections, 1000);
+    assert_eq!(config.listeners[2].scheme(), "unix");
+    assert_eq!(config.listeners[2].address(), "/run/mqtt_adapt.sock");
+
+    let wildcard_mountpoint = "[[listeners]]\nmountpoint = \"site/#\"";
+    assert!(BrokerConfig::from_toml_str(wildcard_mountpoint).is_err());
+    let incomplete_auth = "[[listeners]]\n[listeners.auth]\nmode = \"file\"";
+    assert!(BrokerConfig::from_toml_str(incomplete_auth).is_err());
+    let unix_websocket = "[[listeners]]\nunix = \"/tmp/mqtt.sock\"\n[listeners.websocket]";
+    assert!(BrokerConfig::from_toml_str(unix_websocket).is_err());
+}
@@ -58,6 +58,7 @@ async fn test_client_event_handling() {
         topic_name: "test/topic".to_string(),
         packet_id: None,
         payload: Bytes::from_static(b"test payload"),
+        properties: Default::default(),
     };
     
     let event = Event::MessageReceived("test_client".to_string(), MqttPacket::Publish(publish_packet));
@@ -162,3 +163,530 @@ async fn test_client_state_management() {
     // 初始状态应该是Connected
     assert_eq!(client.state(), &ClientState::Connected);
 }
+
+// 测试客户端从TCP流中读取被拆分和合并的数据包
+#[tokio::test]
+async fn test_client_reads_split_and_coalesced_packets() {
+    use mqtt_adapt::protocol::Packet;
+    use bytes::BytesMut;
+    use tokio::io::AsyncWriteExt;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut peer = TcpStream::connect(addr).await.unwrap();
+    let (socket, _) = listener.accept().await.unwrap();
+
+    let (_tx, rx) = unbounded();
+    let (router_tx, router_rx) = unbounded();
+    let client = Client::new(socket, addr, rx, router_tx, "test_client".to_string());
+    tokio::spawn(client.handle());
+
+    let mut encoded = BytesMut::new();
+    for i in 0..3u8 {
+        PublishPacket {
+            dup: false,
+            qos: 0,
+            retain: false,
+            topic_name: "test/topic".to_string(),
+            packet_id: None,
+            payload: Bytes::from(vec![i]),
+            properties: Default::default(),
+        }
+        .write(&mut encoded);
+    }
+
+    // 第一个包与第二个包的前半部分一起发送，剩余部分稍后发送
+    let split = encoded.len() / 2;
+    peer.write_all(&encoded[..split]).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    peer.write_all(&encoded[split..]).await.unwrap();
+
+    for i in 0..3u8 {
+        let event = tokio::time::timeout(std::time::Duration::from_secs(1), router_rx.recv_async())
+            .await
+            .expect("timed out waiting for publish")
+            .unwrap();
+        match event {
+            Event::MessageReceived(_, MqttPacket::Publish(publish)) => {
+                assert_eq!(publish.payload, Bytes::from(vec![i]));
+            }
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+}
+
+// 测试会话被接管时旧连接收到0x8E DISCONNECT并上报自身连接关闭
+#[tokio::test]
+async fn test_client_session_taken_over() {
+    use mqtt_adapt::protocol::{DisconnectPacket, MqttCodec, ProtocolVersion, ReasonCode};
+    use tokio::io::AsyncReadExt;
+    use tokio_util::codec::Decoder;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let socket = TcpStream::connect(addr).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+
+    let (tx, rx) = unbounded();
+    let (router_tx, router_rx) = unbounded();
+    let mut client = Client::new(socket, addr, rx, router_tx, "device".to_string());
+    client.set_protocol_version(ProtocolVersion::V5);
+    let handle = tokio::spawn(client.handle());
+
+    let disconnect = DisconnectPacket::with_reason(ReasonCode::SessionTakenOver);
+    tx.send(Event::MessageSent("device".to_string(), MqttPacket::Disconnect(disconnect))).unwrap();
+    handle.await.unwrap().unwrap();
+
+    let mut received = bytes::BytesMut::new();
+    peer.read_buf(&mut received).await.unwrap();
+    let mut codec = MqttCodec::with_version(ProtocolVersion::V5);
+    assert_eq!(
+        codec.decode(&mut received).unwrap(),
+        Some(MqttPacket::Disconnect(DisconnectPacket::with_reason(ReasonCode::SessionTakenOver)))
+    );
+
+    match router_rx.try_recv() {
+        Ok(Event::ConnectionClosed(client_id, _)) => assert_eq!(client_id, "device"),
+        other => panic!("Expected ConnectionClosed, got {:?}", other),
+    }
+}
+
+/// 创建设置了遗嘱的客户端，返回客户端、对端连接、路由器事件接收端和客户端事件发送端
+async fn client_with_will() -> (Client, TcpStream, flume::Receiver<Event>, flume::Sender<Event>) {
+    use std::time::Duration;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let peer = TcpStream::connect(addr).await.unwrap();
+    let (socket, _) = listener.accept().await.unwrap();
+
+    let (tx, rx) = unbounded();
+    let (router_tx, router_rx) = unbounded();
+    let mut client = Client::new(socket, addr, rx, router_tx, "device".to_string());
+    client.set_protocol_version(mqtt_adapt::protocol::ProtocolVersion::V5);
+    let will = PublishPacket {
+        dup: false,
+        qos: 1,
+        retain: true,
+        topic_name: "status/device".to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(b"offline"),
+        properties: mqtt_adapt::protocol::Properties {
+            message_expiry_interval: Some(60),
+            content_type: Some("text/plain".to_string()),
+            ..Default::default()
+        },
+    };
+    client.set_will(Some(will), Duration::from_secs(5));
+    (client, peer, router_rx, tx)
+}
+
+// 测试正常DISCONNECT不发布遗嘱
+#[tokio::test]
+async fn test_clean_disconnect_suppresses_will() {
+    use mqtt_adapt::protocol::DisconnectPacket;
+    use tokio::io::AsyncWriteExt;
+
+    let (client, mut peer, router_rx, _tx) = client_with_will().await;
+    let handle = tokio::spawn(client.handle());
+
+    let mut buffer = bytes::BytesMut::new();
+    MqttPacket::Disconnect(DisconnectPacket::default())
+        .write_versioned(&mut buffer, mqtt_adapt::protocol::ProtocolVersion::V5);
+    peer.write_all(&buffer).await.unwrap();
+    handle.await.unwrap().unwrap();
+
+    assert!(matches!(router_rx.try_recv(), Ok(Event::ConnectionClosed(_, _))));
+    assert!(router_rx.try_recv().is_err());
+}
+
+// 测试原因码0x04的DISCONNECT仍发布遗嘱
+#[tokio::test]
+async fn test_disconnect_with_will_message() {
+    use mqtt_adapt::protocol::{DisconnectPacket, ReasonCode};
+    use tokio::io::AsyncWriteExt;
+
+    let (client, mut peer, router_rx, _tx) = client_with_will().await;
+    let handle = tokio::spawn(client.handle());
+
+    let mut buffer = bytes::BytesMut::new();
+    MqttPacket::Disconnect(DisconnectPacket::with_reason(ReasonCode::DisconnectWithWillMessage))
+        .write_versioned(&mut buffer, mqtt_adapt::protocol::ProtocolVersion::V5);
+    peer.write_all(&buffer).await.unwrap();
+    handle.await.unwrap().unwrap();
+
+    assert!(matches!(router_rx.try_recv(), Ok(Event::WillMessage(_, _, _, _))));
+    assert!(matches!(router_rx.try_recv(), Ok(Event::ConnectionClosed(_, _))));
+}
+
+// 测试连接异常断开时发布带属性和延迟的遗嘱
+#[tokio::test]
+async fn test_abnormal_close_publishes_will() {
+    let (client, peer, router_rx, _tx) = client_with_will().await;
+    let handle = tokio::spawn(client.handle());
+    drop(peer);
+    handle.await.unwrap().unwrap();
+
+    match router_rx.try_recv() {
+        Ok(Event::WillMessage(client_id, _, will, delay)) => {
+            assert_eq!(client_id, "device");
+            assert_eq!(will.topic_name, "status/device");
+            assert!(will.retain);
+            assert_eq!(will.properties.message_expiry_interval, Some(60));
+            assert_eq!(will.properties.content_type.as_deref(), Some("text/plain"));
+            assert_eq!(delay.as_secs(), 5);
+        }
+        other => panic!("Expected WillMessage, got {:?}", other),
+    }
+    assert!(matches!(router_rx.try_recv(), Ok(Event::ConnectionClosed(_, _))));
+}
+
+/// 读取一直等待、写入总是失败的连接，模拟投递消息时对端已经断开
+#[derive(Debug)]
+struct BrokenPipe;
+
+impl tokio::io::AsyncRead for BrokenPipe {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        _buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Pending
+    }
+}
+
+impl tokio::io::AsyncWrite for BrokenPipe {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        _buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        std::task::Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+    }
+
+    fn poll_flush(self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+// 测试投递消息时写入失败也发布遗嘱并通知路由器连接已关闭
+#[tokio::test]
+async fn test_write_error_publishes_will() {
+    use std::time::Duration;
+
+    let (tx, rx) = unbounded();
+    let (router_tx, router_rx) = unbounded();
+    let addr = "127.0.0.1:1883".parse().unwrap();
+    let mut client = Client::new(BrokenPipe, addr, rx, router_tx, "device".to_string());
+    let will = PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: false,
+        topic_name: "status/device".to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(b"offline"),
+        properties: Default::default(),
+    };
+    client.set_will(Some(will), Duration::ZERO);
+    let handle = tokio::spawn(client.handle());
+
+    let publish = PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: false,
+        topic_name: "jobs".to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(b"job"),
+        properties: Default::default(),
+    };
+    tx.send(Event::MessageSent("device".to_string(), MqttPacket::Publish(publish))).unwrap();
+    assert!(handle.await.unwrap().is_err());
+
+    match router_rx.try_recv() {
+        Ok(Event::WillMessage(client_id, _, will, _)) => {
+            assert_eq!(client_id, "device");
+            assert_eq!(will.topic_name, "status/device");
+        }
+        other => panic!("Expected WillMessage, got {:?}", other),
+    }
+    assert!(matches!(router_rx.try_recv(), Ok(Event::ConnectionClosed(_, _))));
+}
+
+/// 按配置处理一个CONNECT，返回服务器回复的CONNACK
+async fn connect_with_config(connect: mqtt_adapt::protocol::ConnectPacket, config: &mqtt_adapt::config::BrokerConfig) -> mqtt_adapt::protocol::ConnAckPacket {
+    use mqtt_adapt::protocol::{MqttCodec, ProtocolVersion};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::Decoder;
+
+    let path = std::env::temp_dir().join(format!("mqtt_adapt_client_{}.db", uuid::Uuid::new_v4()));
+    let db = mqtt_adapt::db::connection::DatabaseConnection::new(&format!("sqlite://{}?mode=rwc", path.display()))
+        .await
+        .unwrap();
+    let router = mqtt_adapt::routing::router::MessageRouter::new();
+    let authenticator = mqtt_adapt::auth::from_config(&config.auth, Some(&db)).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut peer = TcpStream::connect(addr).await.unwrap();
+    let (socket, _) = listener.accept().await.unwrap();
+
+    let version = ProtocolVersion::from_u8(connect.protocol_level).unwrap();
+    let mut buffer = bytes::BytesMut::new();
+    MqttPacket::Connect(connect).write(&mut buffer);
+    peer.write_all(&buffer).await.unwrap();
+    let _ = mqtt_adapt::client::create_client_with_connect(socket, addr, &router, authenticator.as_ref(), config, None, None).await;
+
+    let mut received = bytes::BytesMut::new();
+    peer.read_buf(&mut received).await.unwrap();
+    match MqttCodec::with_version(version).decode(&mut received).unwrap() {
+        Some(MqttPacket::ConnAck(connack)) => connack,
+        other => panic!("Expected CONNACK, got {:?}", other),
+    }
+}
+
+// 测试保活时间超过配置上限时，MQTT 5.0使用服务器保活时间，旧版本以0x03拒绝连接，旧版本关闭保活时接受
+#[tokio::test]
+async fn test_max_keepalive() {
+    use mqtt_adapt::config::{AuthMode, BrokerConfig};
+    use mqtt_adapt::protocol::{ConnectPacket, ConnectReturnCode, ReasonCode};
+
+    let mut config = BrokerConfig::default();
+    config.auth.mode = AuthMode::Anonymous;
+    config.mqtt.max_keepalive = 60;
+    let connect = |protocol_level, keep_alive| ConnectPacket {
+        protocol_name: "MQTT".to_string(),
+        protocol_level,
+        connect_flags: 0x02,
+        keep_alive,
+        client_id: "device".to_string(),
+        will_topic: None,
+        will_message: None,
+        username: None,
+        password: None,
+        properties: Default::default(),
+        will_properties: Default::default(),
+    };
+
+    let connack = connect_with_config(connect(4, 30), &config).await;
+    assert_eq!(connack.return_code, ConnectReturnCode::Accepted);
+
+    let connack = connect_with_config(connect(4, 600), &config).await;
+    assert_eq!(connack.return_code, ConnectReturnCode::RefusedServerUnavailable);
+
+    let connack = connect_with_config(connect(4, 0), &config).await;
+    assert_eq!(connack.return_code, ConnectReturnCode::Accepted);
+
+    let connack = connect_with_config(connect(5, 0), &config).await;
+    assert_eq!(connack.reason_code(), ReasonCode::Success);
+    assert_eq!(connack.properties.server_keep_alive, Some(60));
+}
+
+// 测试两个客户端ID为空的MQTT 3.1.1客户端同时在线时各自分配不同的ID，Clean Session为0时被拒绝
+#[tokio::test]
+async fn test_empty_client_id_v311() {
+    use mqtt_adapt::auth::AnonymousAuthenticator;
+    use mqtt_adapt::config::BrokerConfig;
+    use mqtt_adapt::protocol::{ConnectPacket, ConnectReturnCode, MqttCodec};
+    use mqtt_adapt::routing::router::MessageRouter;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::Decoder;
+
+    let router = MessageRouter::new();
+    let config = BrokerConfig::default();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut peers = Vec::new();
+    let mut clients = Vec::new();
+    for connect_flags in [0x02, 0x02, 0x00] {
+        let connect = ConnectPacket {
+            protocol_name: "MQTT".to_string(),
+            protocol_level: 4,
+            connect_flags,
+            keep_alive: 60,
+            client_id: String::new(),
+            will_topic: None,
+            will_message: None,
+            username: None,
+            password: None,
+            properties: Default::default(),
+            will_properties: Default::default(),
+        };
+        let mut peer = TcpStream::connect(addr).await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut buffer = bytes::BytesMut::new();
+        MqttPacket::Connect(connect).write(&mut buffer);
+        peer.write_all(&buffer).await.unwrap();
+        let client =
+            mqtt_adapt::client::create_client_with_connect(socket, addr, &router, &AnonymousAuthenticator, &config, None, None)
+                .await;
+
+        let mut received = bytes::BytesMut::new();
+        peer.read_buf(&mut received).await.unwrap();
+        let Some(MqttPacket::ConnAck(connack)) = MqttCodec::new().decode(&mut received).unwrap() else {
+            panic!("Expected CONNACK");
+        };
+        if connect_flags == 0x02 {
+            assert_eq!(connack.return_code, ConnectReturnCode::Accepted);
+            clients.push(client.unwrap());
+        } else {
+            assert_eq!(connack.return_code, ConnectReturnCode::RefusedIdentifierRejected);
+            assert!(client.is_err());
+        }
+        peers.push(peer);
+    }
+
+    assert_ne!(clients[0].client_id(), "");
+    assert_ne!(clients[0].client_id(), clients[1].client_id());
+    // 后连接的客户端不会接管前一个的会话
+    assert_eq!(router.connected_clients().await.len(), 2);
+}
+
+/// 向MQTT 5.0客户端发送一个PUBLISH，返回客户端回复的DISCONNECT原因码和路由器收到的事件
+async fn publish_with_alias(topic: &str, alias: Option<u16>) -> (Option<mqtt_adapt::protocol::ReasonCode>, Vec<Event>) {
+    use mqtt_adapt::protocol::{MqttCodec, ProtocolVersion};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::Decoder;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut peer = TcpStream::connect(addr).await.unwrap();
+    let (socket, _) = listener.accept().await.unwrap();
+
+    let (_tx, rx) = unbounded();
+    let (router_tx, router_rx) = unbounded();
+    let mut client = Client::new(socket, addr, rx, router_tx, "device".to_string());
+    client.set_protocol_version(ProtocolVersion::V5);
+    let handle = tokio::spawn(client.handle());
+
+    let publish = PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: false,
+        topic_name: topic.to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(b"21.5"),
+        properties: mqtt_adapt::protocol::Properties {
+            topic_alias: alias,
+            ..Default::default()
+        },
+    };
+    let mut buffer = bytes::BytesMut::new();
+    MqttPacket::Publish(publish).write_versioned(&mut buffer, ProtocolVersion::V5);
+    peer.write_all(&buffer).await.unwrap();
+
+    let mut received = bytes::BytesMut::new();
+    let reason_code = match tokio::time::timeout(std::time::Duration::from_millis(200), peer.read_buf(&mut received)).await {
+        Ok(_) => match MqttCodec::with_version(ProtocolVersion::V5).decode(&mut received).unwrap() {
+            Some(MqttPacket::Disconnect(disconnect)) => Some(disconnect.reason_code),
+            other => panic!("Expected DISCONNECT, got {:?}", other),
+        },
+        Err(_) => None,
+    };
+    drop(peer);
+    handle.await.unwrap().unwrap();
+    (reason_code, router_rx.drain().collect())
+}
+
+// 测试主题别名：登记的别名转发原主题，主题为空且没有别名时以0x82断开，别名超出上限时以0x94断开
+#[tokio::test]
+async fn test_topic_alias() {
+    use mqtt_adapt::client::TOPIC_ALIAS_MAXIMUM;
+    use mqtt_adapt::protocol::ReasonCode;
+
+    let (reason_code, events) = publish_with_alias("sensors/temp", Some(1)).await;
+    assert_eq!(reason_code, None);
+    match &events[0] {
+        Event::MessageReceived(_, MqttPacket::Publish(publish)) => {
+            assert_eq!(publish.topic_name, "sensors/temp");
+            assert_eq!(publish.properties.topic_alias, None);
+        }
+        other => panic!("Expected MessageReceived, got {:?}", other),
+    }
+
+    let (reason_code, events) = publish_with_alias("", None).await;
+    assert_eq!(reason_code, Some(ReasonCode::ProtocolError));
+    assert!(!events.iter().any(|event| matches!(event, Event::MessageReceived(_, _))));
+
+    let (reason_code, _) = publish_with_alias("", Some(1)).await;
+    assert_eq!(reason_code, Some(ReasonCode::TopicAliasInvalid));
+
+    let (reason_code, _) = publish_with_alias("sensors/temp", Some(TOPIC_ALIAS_MAXIMUM + 1)).await;
+    assert_eq!(reason_code, Some(ReasonCode::TopicAliasInvalid));
+}
+
+// 测试MQTT 5.0的CONNACK告知主题别名上限
+#[tokio::test]
+async fn test_connack_topic_alias_maximum() {
+    use mqtt_adapt::client::TOPIC_ALIAS_MAXIMUM;
+    use mqtt_adapt::config::{AuthMode, BrokerConfig};
+
+    let mut config = BrokerConfig::default();
+    config.auth.mode = AuthMode::Anonymous;
+    let connect = mqtt_adapt::protocol::ConnectPacket {
+        protocol_name: "MQTT".to_string(),
+        protocol_level: 5,
+        connect_flags: 0x02,
+        keep_alive: 30,
+        client_id: "device".to_string(),
+        will_topic: None,
+        will_message: None,
+        username: None,
+        password: None,
+        properties: Default::default(),
+        will_properties: Default::default(),
+    };
+    let connack = connect_with_config(connect, &config).await;
+    assert_eq!(connack.properties.topic_alias_maximum, Some(TOPIC_ALIAS_MAXIMUM));
+}
+
+// 测试监听器的挂载点在注册会话时已经记录，CONNACK之后立即生效
+#[tokio::test]
+async fn test_mountpoint_set_before_register() {
+    use mqtt_adapt::auth::AnonymousAuthenticator;
+    use mqtt_adapt::config::BrokerConfig;
+    use mqtt_adapt::routing::router::MessageRouter;
+    use tokio::io::AsyncWriteExt;
+
+    let (mut peer, socket) = tokio::io::duplex(4096);
+    let connect = mqtt_adapt::protocol::ConnectPacket {
+        protocol_name: "MQTT".to_string(),
+        protocol_level: 4,
+        connect_flags: 0x02,
+        keep_alive: 30,
+        client_id: "device".to_string(),
+        will_topic: None,
+        will_message: None,
+        username: None,
+        password: None,
+        properties: Default::default(),
+        will_properties: Default::default(),
+    };
+    let mut buffer = bytes::BytesMut::new();
+    MqttPacket::Connect(connect).write(&mut buffer);
+    peer.write_all(&buffer).await.unwrap();
+
+    let router = MessageRouter::new();
+    let addr = "127.0.0.1:50000".parse().unwrap();
+    let client = mqtt_adapt::client::create_client_with_connect(
+        socket,
+        addr,
+        &router,
+        &AnonymousAuthenticator,
+        &BrokerConfig::default(),
+        None,
+        Some("devices/".to_string()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(client.mountpoint(), Some("devices/"));
+
+    let clients = router.connected_clients().await;
+    let connection = clients[0].connection.as_ref().unwrap();
+    assert_eq!(connection.mountpoint.as_deref(), Some("devices/"));
+}
@@ -0,0 +1,70 @@
+// 各个测试文件只用到其中一部分辅助函数
+#![allow(dead_code)]
+
+use bytes::{Bytes, BytesMut};
+use mqtt_adapt::auth::{AuthDecision, AuthRequest, Authenticator};
+use mqtt_adapt::db::connection::DatabaseConnection;
+use mqtt_adapt::protocol::{ConnectPacket, MqttCodec, MqttPacket};
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::TcpListener;
+use tokio_util::codec::Decoder;
+
+/// 在临时目录创建一个新的SQLite数据库
+pub async fn temp_database() -> DatabaseConnection {
+    let path = std::env::temp_dir().join(format!("mqtt_adapt_test_{}.db", uuid::Uuid::new_v4()));
+    DatabaseConnection::new(&format!("sqlite://{}?mode=rwc", path.display()))
+        .await
+        .unwrap()
+}
+
+/// 取得一个空闲的本地端口
+pub async fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap()
+}
+
+/// 读取下一个数据包，连接关闭时返回None
+pub async fn read_packet<S: AsyncRead + Unpin>(stream: &mut S, codec: &mut MqttCodec, buffer: &mut BytesMut) -> Option<MqttPacket> {
+    loop {
+        if let Some(packet) = codec.decode(buffer).unwrap() {
+            return Some(packet);
+        }
+        if stream.read_buf(buffer).await.unwrap() == 0 {
+            return None;
+        }
+    }
+}
+
+/// MQTT 3.1.1的CONNECT，清除会话，按是否携带用户名和密码设置连接标志
+pub fn connect_packet(client_id: &str, username: Option<&str>, password: Option<&str>) -> ConnectPacket {
+    let mut connect_flags = 0x02;
+    if username.is_some() {
+        connect_flags |= 0x80;
+    }
+    if password.is_some() {
+        connect_flags |= 0x40;
+    }
+    ConnectPacket {
+        protocol_name: "MQTT".to_string(),
+        protocol_level: 4,
+        connect_flags,
+        keep_alive: 30,
+        client_id: client_id.to_string(),
+        will_topic: None,
+        will_message: None,
+        username: username.map(str::to_string),
+        password: password.map(|password| Bytes::copy_from_slice(password.as_bytes())),
+        properties: Default::default(),
+        will_properties: Default::default(),
+    }
+}
+
+/// 以固定的本地对端地址调用认证器
+pub async fn authenticate(authenticator: &dyn Authenticator, connect: &ConnectPacket) -> AuthDecision {
+    let request = AuthRequest {
+        connect,
+        peer_addr: SocketAddr::from(([127, 0, 0, 1], 50000)),
+        cert_username: None,
+    };
+    authenticator.authenticate(&request).await.unwrap()
+}
@@ -0,0 +1,134 @@
+use bytes::{Bytes, BytesMut};
+use flume::{Receiver, unbounded};
+use mqtt_adapt::auth::AnonymousAuthenticator;
+use mqtt_adapt::config::{BrokerConfig, ListenerConfig};
+use mqtt_adapt::db::models::session::{OfflineMessage, Session};
+use mqtt_adapt::protocol::{
+    ConnectPacket, ConnectReturnCode, MqttCodec, MqttPacket, ProtocolVersion, PublishPacket, ReasonCode,
+    SubscribePacket,
+};
+use mqtt_adapt::routing::{event::Event, router::MessageRouter};
+use mqtt_adapt::server::Server;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+mod common;
+use common::{connect_packet, free_addr, read_packet, temp_database};
+
+fn publish_packet(payload: &'static [u8]) -> PublishPacket {
+    PublishPacket {
+        dup: false,
+        qos: 1,
+        retain: false,
+        topic_name: "jobs".to_string(),
+        packet_id: Some(1),
+        payload: Bytes::from_static(payload),
+        properties: Default::default(),
+    }
+}
+
+fn received_payloads(rx: &Receiver<Event>) -> Vec<Bytes> {
+    rx.try_iter()
+        .filter_map(|event| match event {
+            Event::MessageSent(_, MqttPacket::Publish(publish)) => Some(publish.payload),
+            _ => None,
+        })
+        .collect()
+}
+
+// 测试停机时停止接受连接，MQTT 5.0客户端收到0x8B，服务器在客户端断开后返回
+#[tokio::test]
+async fn test_server_shutdown_disconnects_clients() {
+    let addr = free_addr().await;
+    let server = Server::from_config(BrokerConfig {
+        listeners: vec![ListenerConfig::tcp(addr)],
+        ..Default::default()
+    })
+    .with_authenticator(AnonymousAuthenticator);
+    let running = tokio::spawn({
+        let server = server.clone();
+        async move { server.start().await }
+    });
+
+    let mut stream = None;
+    for _ in 0..100 {
+        if let Ok(connected) = TcpStream::connect(addr).await {
+            stream = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let mut stream = stream.expect("server not listening");
+    let connect = ConnectPacket {
+        protocol_level: 5,
+        ..connect_packet("v5-client", None, None)
+    };
+    let mut buffer = BytesMut::new();
+    MqttPacket::Connect(connect).write_versioned(&mut buffer, ProtocolVersion::V5);
+    stream.write_all(&buffer).await.unwrap();
+
+    let mut codec = MqttCodec::with_version(ProtocolVersion::V5);
+    let mut received = BytesMut::new();
+    let Some(MqttPacket::ConnAck(connack)) = read_packet(&mut stream, &mut codec, &mut received).await else {
+        panic!("expected CONNACK");
+    };
+    assert_eq!(connack.return_code, ConnectReturnCode::Accepted);
+
+    server.shutdown();
+    let Some(MqttPacket::Disconnect(disconnect)) = read_packet(&mut stream, &mut codec, &mut received).await else {
+        panic!("expected DISCONNECT");
+    };
+    assert_eq!(disconnect.reason_code, ReasonCode::ServerShuttingDown);
+    assert!(read_packet(&mut stream, &mut codec, &mut received).await.is_none());
+
+    tokio::time::timeout(Duration::from_secs(5), running).await.unwrap().unwrap();
+    assert!(server.router().is_shutting_down());
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+// 测试超时仍未断开的客户端不再等待，持久会话的未确认消息保存到数据库并在重启后补发
+#[tokio::test]
+async fn test_shutdown_persists_unacknowledged_messages() {
+    let db = temp_database().await;
+    let router = MessageRouter::new().with_db(db.get_pool().clone());
+    tokio::spawn(router.clone().start());
+
+    // 客户端不处理通道中的事件，停机时不会断开
+    let (tx, rx) = unbounded();
+    router.register_session("worker", tx, false, None).await.unwrap();
+    let subscribe = SubscribePacket {
+        packet_id: 1,
+        topics: vec![("jobs".to_string(), 1)],
+        properties: Default::default(),
+    };
+    router
+        .handle_event(Event::MessageReceived("worker".to_string(), MqttPacket::Subscribe(subscribe)))
+        .await;
+    router.publish(publish_packet(b"first")).await;
+    router.publish(publish_packet(b"second")).await;
+    assert_eq!(router.inflight_len("worker").await, 2);
+
+    tokio::time::timeout(Duration::from_secs(5), router.shutdown(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    assert_eq!(received_payloads(&rx).len(), 2);
+    let session = Session::find_by_client_id(db.get_pool(), "worker").await.unwrap().unwrap();
+    assert!(!session.connected);
+    let messages = OfflineMessage::find_by_session_id(db.get_pool(), session.id).await.unwrap();
+    let payloads: Vec<Vec<u8>> = messages.iter().map(|message| message.payload_bytes()).collect();
+    assert_eq!(payloads, [b"first".to_vec(), b"second".to_vec()]);
+    assert_eq!(router.inflight_len("worker").await, 0);
+
+    // 停机后不再接受新的会话
+    let (tx, _rx) = unbounded();
+    assert!(router.register_session("late", tx, true, None).await.is_err());
+
+    // 重启后客户端重连，按原顺序收到未确认的消息
+    let restarted = MessageRouter::new().with_db(db.get_pool().clone());
+    restarted.restore_sessions().await.unwrap();
+    let (tx, rx) = unbounded();
+    let session = restarted.register_session("worker", tx, false, None).await.unwrap();
+    assert!(session.session_present);
+    assert_eq!(received_payloads(&rx), [Bytes::from_static(b"first"), Bytes::from_static(b"second")]);
+}
@@ -0,0 +1,366 @@
+use bytes::{Bytes, BytesMut};
+use mqtt_adapt::auth::acl::{AclAction, AclPermission, AclRule};
+use mqtt_adapt::config::{AclConfig, AuthConfig, AuthMode, BrokerConfig, ListenerConfig};
+use mqtt_adapt::protocol::{ConnectReturnCode, MqttCodec, MqttPacket, PublishPacket, SubscribePacket};
+use mqtt_adapt::server::Server;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_util::codec::Decoder;
+
+mod common;
+use common::{connect_packet, free_addr};
+
+/// 测试用的MQTT 3.1.1客户端
+struct TestClient<S> {
+    stream: S,
+    codec: MqttCodec,
+    buffer: BytesMut,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TestClient<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            codec: MqttCodec::new(),
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// 发送数据包，服务器已关闭连接时忽略写入错误，由`recv`返回None
+    async fn send(&mut self, packet: MqttPacket) {
+        let mut buffer = BytesMut::new();
+        packet.write(&mut buffer);
+        let _ = self.stream.write_all(&buffer).await;
+    }
+
+    /// 读取下一个数据包，连接关闭时返回None
+    async fn recv(&mut self) -> Option<MqttPacket> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(packet) = self.codec.decode(&mut self.buffer).unwrap() {
+                    return Some(packet);
+                }
+                if self.stream.read_buf(&mut self.buffer).await.unwrap_or(0) == 0 {
+                    return None;
+                }
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    /// 发送CONNECT，返回CONNACK中的返回码
+    async fn connect(&mut self, client_id: &str, credentials: Option<(&str, &str)>) -> ConnectReturnCode {
+        let connect = connect_packet(
+            client_id,
+            credentials.map(|(username, _)| username),
+            credentials.map(|(_, password)| password),
+        );
+        self.send(MqttPacket::Connect(connect)).await;
+        match self.recv().await {
+            Some(MqttPacket::ConnAck(connack)) => connack.return_code,
+            other => panic!("expected CONNACK, got {:?}", other),
+        }
+    }
+
+    /// 发送SUBSCRIBE，返回SUBACK中的返回码
+    async fn subscribe(&mut self, filter: &str) -> u8 {
+        let subscribe = SubscribePacket {
+            packet_id: 1,
+            topics: vec![(filter.to_string(), 0)],
+            properties: Default::default(),
+        };
+        self.send(MqttPacket::Subscribe(subscribe)).await;
+        match self.recv().await {
+            Some(MqttPacket::SubAck(suback)) => suback.return_codes[0],
+            other => panic!("expected SUBACK, got {:?}", other),
+        }
+    }
+
+    async fn publish(&mut self, topic: &str, payload: &'static [u8]) {
+        let publish = PublishPacket {
+            dup: false,
+            qos: 0,
+            retain: false,
+            topic_name: topic.to_string(),
+            packet_id: None,
+            payload: Bytes::from_static(payload),
+            properties: Default::default(),
+        };
+        self.send(MqttPacket::Publish(publish)).await;
+    }
+
+    /// 读取下一条PUBLISH，返回主题和负载
+    async fn received(&mut self) -> (String, Bytes) {
+        match self.recv().await {
+            Some(MqttPacket::Publish(publish)) => (publish.topic_name, publish.payload),
+            other => panic!("expected PUBLISH, got {:?}", other),
+        }
+    }
+}
+
+/// 连接TCP监听器，服务器尚未开始监听时重试
+async fn connect_tcp(addr: SocketAddr) -> TestClient<TcpStream> {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(addr).await {
+            return TestClient::new(stream);
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("server not listening on {}", addr);
+}
+
+/// 启动服务器，返回用于停机的句柄
+fn start(listeners: Vec<ListenerConfig>) -> Server {
+    let server = Server::from_config(BrokerConfig {
+        listeners,
+        ..Default::default()
+    });
+    let running = server.clone();
+    tokio::spawn(async move { running.start().await });
+    server
+}
+
+// 测试内部明文监听器和需要密码的设备监听器共享同一个路由器，挂载点隔离设备的主题
+#[tokio::test]
+async fn test_listeners_share_router() {
+    let password_file = std::env::temp_dir().join(format!("mqtt_adapt_listeners_{}.txt", uuid::Uuid::new_v4()));
+    let hash = mqtt_adapt::auth::password::hash_password("secret").unwrap();
+    std::fs::write(&password_file, format!("device:{}\n", hash)).unwrap();
+
+    let internal = free_addr().await;
+    let devices = free_addr().await;
+    let server = start(vec![
+        ListenerConfig {
+            auth: Some(AuthConfig {
+                mode: AuthMode::Anonymous,
+                ..Default::default()
+            }),
+            ..ListenerConfig::tcp(internal)
+        },
+        ListenerConfig {
+            auth: Some(AuthConfig {
+                mode: AuthMode::File,
+                password_file: Some(password_file),
+                ..Default::default()
+            }),
+            mountpoint: Some("site-1/".to_string()),
+            ..ListenerConfig::tcp(devices)
+        },
+    ]);
+
+    let mut backend = connect_tcp(internal).await;
+    assert_eq!(backend.connect("backend", None).await, ConnectReturnCode::Accepted);
+    backend.subscribe("site-1/#").await;
+
+    let mut rejected = connect_tcp(devices).await;
+    assert_eq!(rejected.connect("intruder", None).await, ConnectReturnCode::RefusedBadUsernameOrPassword);
+    let mut device = connect_tcp(devices).await;
+    assert_eq!(device.connect("device", Some(("device", "secret"))).await, ConnectReturnCode::Accepted);
+    device.subscribe("commands/+").await;
+
+    // 设备发布的主题加上挂载点
+    device.publish("telemetry", b"21.5").await;
+    assert_eq!(backend.received().await, ("site-1/telemetry".to_string(), Bytes::from_static(b"21.5")));
+
+    // 发给设备的主题去掉挂载点，挂载点之外的主题设备收不到
+    backend.publish("commands/reboot", b"ignored").await;
+    backend.publish("site-1/commands/reboot", b"now").await;
+    assert_eq!(device.received().await, ("commands/reboot".to_string(), Bytes::from_static(b"now")));
+
+    server.shutdown();
+}
+
+// 测试挂载的监听器上访问控制规则按去掉挂载点的主题匹配
+#[tokio::test]
+async fn test_acl_on_mounted_listener() {
+    let anonymous = Some(AuthConfig {
+        mode: AuthMode::Anonymous,
+        ..Default::default()
+    });
+    let internal = free_addr().await;
+    let devices = free_addr().await;
+    let server = Server::from_config(BrokerConfig {
+        listeners: vec![
+            ListenerConfig {
+                auth: anonymous.clone(),
+                ..ListenerConfig::tcp(internal)
+            },
+            ListenerConfig {
+                auth: anonymous,
+                mountpoint: Some("site-1/".to_string()),
+                ..ListenerConfig::tcp(devices)
+            },
+        ],
+        acl: AclConfig {
+            rules: vec![
+                AclRule {
+                    permission: AclPermission::Allow,
+                    username: None,
+                    client_id: Some("backend".to_string()),
+                    group: None,
+                    action: AclAction::Both,
+                    topic: "#".to_string(),
+                },
+                AclRule {
+                    permission: AclPermission::Allow,
+                    username: None,
+                    client_id: None,
+                    group: None,
+                    action: AclAction::Both,
+                    topic: "devices/%c/#".to_string(),
+                },
+            ],
+            no_match: AclPermission::Deny,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let running = server.clone();
+    tokio::spawn(async move { running.start().await });
+
+    let mut backend = connect_tcp(internal).await;
+    assert_eq!(backend.connect("backend", None).await, ConnectReturnCode::Accepted);
+    backend.subscribe("site-1/#").await;
+
+    let mut device = connect_tcp(devices).await;
+    assert_eq!(device.connect("d1", None).await, ConnectReturnCode::Accepted);
+    assert_eq!(device.subscribe("devices/d1/cmd").await, 0);
+    assert_eq!(device.subscribe("devices/d2/cmd").await, 0x80);
+
+    // 规则中不含挂载点，其他设备的主题被拒绝
+    device.publish("devices/d2/telemetry", b"denied").await;
+    device.publish("devices/d1/telemetry", b"21.5").await;
+    assert_eq!(
+        backend.received().await,
+        ("site-1/devices/d1/telemetry".to_string(), Bytes::from_static(b"21.5"))
+    );
+
+    backend.publish("site-1/devices/d1/cmd", b"reboot").await;
+    assert_eq!(device.received().await, ("devices/d1/cmd".to_string(), Bytes::from_static(b"reboot")));
+
+    server.shutdown();
+}
+
+// 测试达到连接数上限后新连接直接关闭
+#[tokio::test]
+async fn test_max_connections() {
+    let addr = free_addr().await;
+    let server = start(vec![ListenerConfig {
+        max_connections: 1,
+        auth: Some(AuthConfig {
+            mode: AuthMode::Anonymous,
+            ..Default::default()
+        }),
+        ..ListenerConfig::tcp(addr)
+    }]);
+
+    let mut first = connect_tcp(addr).await;
+    assert_eq!(first.connect("first", None).await, ConnectReturnCode::Accepted);
+    let mut second = connect_tcp(addr).await;
+    assert!(second.recv().await.is_none());
+
+    // 连接断开后释放名额
+    drop(first);
+    let mut accepted = false;
+    for _ in 0..50 {
+        let mut third = connect_tcp(addr).await;
+        third.send(MqttPacket::Connect(connect_packet("third", None, None))).await;
+        if third.recv().await.is_some() {
+            accepted = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(accepted);
+
+    server.shutdown();
+}
+
+// 测试迟迟不发送CONNECT的连接超时后释放连接名额
+#[tokio::test]
+async fn test_connect_timeout_releases_permit() {
+    let addr = free_addr().await;
+    let mut config = BrokerConfig {
+        listeners: vec![ListenerConfig {
+            max_connections: 1,
+            auth: Some(AuthConfig {
+                mode: AuthMode::Anonymous,
+                ..Default::default()
+            }),
+            ..ListenerConfig::tcp(addr)
+        }],
+        ..Default::default()
+    };
+    config.mqtt.connect_timeout = 1;
+    let server = Server::from_config(config);
+    let running = server.clone();
+    tokio::spawn(async move { running.start().await });
+
+    let mut idle = connect_tcp(addr).await;
+    let closed = tokio::time::timeout(Duration::from_secs(5), idle.recv()).await.expect("idle connection not closed");
+    assert!(closed.is_none());
+
+    // 名额在连接任务结束时释放，可能略晚于连接关闭
+    let mut accepted = false;
+    for _ in 0..50 {
+        let mut client = connect_tcp(addr).await;
+        client.send(MqttPacket::Connect(connect_packet("after-idle", None, None))).await;
+        if client.recv().await.is_some() {
+            accepted = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(accepted);
+
+    server.shutdown();
+}
+
+// 测试Unix域套接字监听器
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket_listener() {
+    let path = std::env::temp_dir().join(format!("mqtt_adapt_{}.sock", uuid::Uuid::new_v4()));
+    let server = start(vec![ListenerConfig {
+        auth: Some(AuthConfig {
+            mode: AuthMode::Anonymous,
+            ..Default::default()
+        }),
+        ..ListenerConfig::unix(&path)
+    }]);
+
+    let mut stream = None;
+    for _ in 0..100 {
+        if let Ok(connected) = tokio::net::UnixStream::connect(&path).await {
+            stream = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let mut client = TestClient::new(stream.expect("server not listening"));
+    assert_eq!(client.connect("local", None).await, ConnectReturnCode::Accepted);
+    client.subscribe("local/#").await;
+    client.publish("local/echo", b"hi").await;
+    assert_eq!(client.received().await, ("local/echo".to_string(), Bytes::from_static(b"hi")));
+
+    server.shutdown();
+}
+
+// 测试Unix域套接字路径是普通文件时启动失败且不删除该文件
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket_path_not_socket() {
+    let path = std::env::temp_dir().join(format!("mqtt_adapt_{}.sock", uuid::Uuid::new_v4()));
+    std::fs::write(&path, b"keep me").unwrap();
+    let server = Server::from_config(BrokerConfig {
+        listeners: vec![ListenerConfig::unix(&path)],
+        ..Default::default()
+    });
+
+    assert!(tokio::spawn(async move { server.start().await }).await.is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), b"keep me");
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,7 +1,8 @@
 use mqtt_adapt::routing::{router::MessageRouter, event::Event}; 
-use mqtt_adapt::protocol::{MqttPacket, SubscribePacket, UnsubscribePacket, PublishPacket};
+use mqtt_adapt::protocol::{MqttPacket, ReasonCode, SubscribePacket, UnsubscribePacket, PublishPacket};
 use flume::{unbounded};
 use bytes::Bytes;
+use std::time::Duration;
 
 #[tokio::test]
 async fn test_message_router_basic() {
@@ -69,6 +70,7 @@ async fn test_subscribe_event() {
     let subscribe_packet = SubscribePacket {
         packet_id: 1,
         topics,
+        properties: Default::default(),
     };
     
     // 发送订阅事件
@@ -106,6 +108,7 @@ async fn test_unsubscribe_event() {
     let unsubscribe_packet = UnsubscribePacket {
         packet_id: 1,
         topics,
+        properties: Default::default(),
     };
     
     // 发送取消订阅事件
@@ -147,6 +150,7 @@ async fn test_publish_event() {
     let subscribe_packet = SubscribePacket {
         packet_id: 1,
         topics: subscribe_topics,
+        properties: Default::default(),
     };
     let subscribe_event = Event::MessageReceived(subscriber_id.clone(), MqttPacket::Subscribe(subscribe_packet));
     router.handle_event(subscribe_event).await;
@@ -162,6 +166,7 @@ async fn test_publish_event() {
         topic_name: "test/topic".to_string(),
         packet_id: None,
         payload: Bytes::from(vec![1, 2, 3, 4, 5]),
+        properties: Default::default(),
     };
     
     // 发送发布事件
@@ -225,6 +230,7 @@ async fn test_topic_wildcard_matching() {
     let subscribe_packet = SubscribePacket {
         packet_id: 1,
         topics: subscribe_topics,
+        properties: Default::default(),
     };
     let subscribe_event = Event::MessageReceived(subscriber_id.clone(), MqttPacket::Subscribe(subscribe_packet));
     router.handle_event(subscribe_event).await;
@@ -240,6 +246,7 @@ async fn test_topic_wildcard_matching() {
         topic_name: "test/123/topic".to_string(),
         packet_id: None,
         payload: Bytes::from(vec![1, 2, 3]),
+        properties: Default::default(),
     };
     
     let publish_event = Event::MessageReceived(publisher_id.clone(), MqttPacket::Publish(publish_packet));
@@ -279,6 +286,7 @@ async fn test_retained_message() {
         topic_name: "test/retain".to_string(),
         packet_id: None,
         payload: Bytes::from(vec![4, 5, 6]),
+        properties: Default::default(),
     };
     
     let publish_event = Event::MessageReceived(publisher_id.clone(), MqttPacket::Publish(publish_packet));
@@ -294,6 +302,7 @@ async fn test_retained_message() {
     let subscribe_packet = SubscribePacket {
         packet_id: 1,
         topics: subscribe_topics,
+        properties: Default::default(),
     };
     let subscribe_event = Event::MessageReceived(subscriber_id.clone(), MqttPacket::Subscribe(subscribe_packet));
     router.handle_event(subscribe_event).await;
@@ -342,6 +351,7 @@ async fn test_multiple_subscribers() {
     let subscribe_packet1 = SubscribePacket {
         packet_id: 1,
         topics: subscribe_topics1,
+        properties: Default::default(),
     };
     let subscribe_event1 = Event::MessageReceived(subscriber1_id.clone(), MqttPacket::Subscribe(subscribe_packet1));
     router.handle_event(subscribe_event1).await;
@@ -351,6 +361,7 @@ async fn test_multiple_subscribers() {
     let subscribe_packet2 = SubscribePacket {
         packet_id: 2,
         topics: subscribe_topics2,
+        properties: Default::default(),
     };
     let subscribe_event2 = Event::MessageReceived(subscriber2_id.clone(), MqttPacket::Subscribe(subscribe_packet2));
     router.handle_event(subscribe_event2).await;
@@ -367,6 +378,7 @@ async fn test_multiple_subscribers() {
         topic_name: "test/topic".to_string(),
         packet_id: None,
         payload: Bytes::from(vec![7, 8, 9]),
+        properties: Default::default(),
     };
     
     let publish_event = Event::MessageReceived(publisher_id.clone(), MqttPacket::Publish(publish_packet));
@@ -423,6 +435,7 @@ async fn test_qos1_message() {
     let subscribe_packet = SubscribePacket {
         packet_id: 1,
         topics: subscribe_topics,
+        properties: Default::default(),
     };
     let subscribe_event = Event::MessageReceived(subscriber_id.clone(), MqttPacket::Subscribe(subscribe_packet));
     router.handle_event(subscribe_event).await;
@@ -438,6 +451,7 @@ async fn test_qos1_message() {
         topic_name: "test/qos1".to_string(),
         packet_id: Some(123),
         payload: Bytes::from(vec![10, 11, 12]),
+        properties: Default::default(),
     };
     
     let publish_event = Event::MessageReceived(publisher_id.clone(), MqttPacket::Publish(publish_packet));
@@ -459,3 +473,674 @@ async fn test_qos1_message() {
         panic!("Expected MessageSent event for subscriber");
     }
 }
+
+// 测试多个过滤器的SUBSCRIBE按过滤器返回结果，非法过滤器返回0x80
+#[tokio::test]
+async fn test_subscribe_per_filter_return_codes() {
+    let router = MessageRouter::new();
+    let client_id = "gateway".to_string();
+    let (tx, rx) = unbounded();
+    router.register_client(&client_id, tx).await.unwrap();
+
+    let subscribe_packet = SubscribePacket {
+        packet_id: 3,
+        topics: vec![
+            ("sensors/+/temp".to_string(), 1),
+            ("sensors/#/bad".to_string(), 1),
+            ("alerts/#".to_string(), 2),
+            ("".to_string(), 0),
+            ("status/a+".to_string(), 0),
+            ("qos/three".to_string(), 3),
+            ("status".to_string(), 0),
+        ],
+        properties: Default::default(),
+    };
+    let event = Event::MessageReceived(client_id.clone(), MqttPacket::Subscribe(subscribe_packet));
+    router.handle_event(event).await;
+
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::SubAck(suback))) => {
+            assert_eq!(suback.packet_id, 3);
+            assert_eq!(suback.return_codes, vec![1, 0x80, 2, 0x80, 0x80, 0x80, 0]);
+        }
+        other => panic!("Expected SUBACK, got {:?}", other),
+    }
+
+    // 合法的过滤器仍然生效
+    let (pub_tx, _pub_rx) = unbounded();
+    router.register_client("publisher", pub_tx).await.unwrap();
+    let publish_packet = PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: false,
+        topic_name: "alerts/fire".to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(b"hot"),
+        properties: Default::default(),
+    };
+    let event = Event::MessageReceived("publisher".to_string(), MqttPacket::Publish(publish_packet));
+    router.handle_event(event).await;
+
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) => {
+            assert_eq!(publish.topic_name, "alerts/fire");
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+}
+
+/// 订阅主题并清除SUBACK
+async fn subscribe(router: &MessageRouter, client_id: &str, rx: &flume::Receiver<Event>, filter: &str, qos: u8) {
+    let subscribe_packet = SubscribePacket {
+        packet_id: 1,
+        topics: vec![(filter.to_string(), qos)],
+        properties: Default::default(),
+    };
+    let event = Event::MessageReceived(client_id.to_string(), MqttPacket::Subscribe(subscribe_packet));
+    router.handle_event(event).await;
+    let _ = rx.try_recv();
+}
+
+fn qos_publish(topic: &str, qos: u8, packet_id: u16) -> PublishPacket {
+    PublishPacket {
+        dup: false,
+        qos,
+        retain: false,
+        topic_name: topic.to_string(),
+        packet_id: Some(packet_id),
+        payload: Bytes::from_static(b"data"),
+        properties: Default::default(),
+    }
+}
+
+fn received_packet_id(rx: &flume::Receiver<Event>) -> u16 {
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) => publish.packet_id.unwrap(),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+}
+
+// 测试每个客户端独立分配数据包ID，PUBACK只影响发送者自己的在途消息
+#[tokio::test]
+async fn test_packet_ids_are_per_client() {
+    use mqtt_adapt::protocol::PubAckPacket;
+
+    let router = MessageRouter::new();
+    let (a_tx, a_rx) = unbounded();
+    let (b_tx, b_rx) = unbounded();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("a", a_tx).await.unwrap();
+    router.register_client("b", b_tx).await.unwrap();
+    router.register_client("publisher", p_tx).await.unwrap();
+    subscribe(&router, "a", &a_rx, "t", 1).await;
+    subscribe(&router, "b", &b_rx, "t", 1).await;
+
+    let event = Event::MessageReceived("publisher".to_string(), MqttPacket::Publish(qos_publish("t", 1, 500)));
+    router.handle_event(event).await;
+
+    // 两个订阅者各自从1开始分配，不使用发布者的数据包ID
+    assert_eq!(received_packet_id(&a_rx), 1);
+    assert_eq!(received_packet_id(&b_rx), 1);
+    assert_eq!(router.inflight_len("a").await, 1);
+    assert_eq!(router.inflight_len("b").await, 1);
+
+    // a的PUBACK不影响b
+    let event = Event::MessageReceived("a".to_string(), MqttPacket::PubAck(PubAckPacket::new(1)));
+    router.handle_event(event).await;
+    assert_eq!(router.inflight_len("a").await, 0);
+    assert_eq!(router.inflight_len("b").await, 1);
+
+    // 会话结束后清理在途状态
+    router.handle_event(Event::ClientDisconnected("b".to_string())).await;
+    assert_eq!(router.inflight_len("b").await, 0);
+}
+
+// 测试QoS 2出站消息的数据包ID在PUBCOMP之前不会被重用
+#[test]
+fn test_packet_id_not_reused_while_inflight() {
+    use mqtt_adapt::routing::qos::QoSManager;
+
+    let mut session = QoSManager::new();
+    let first = session.next_packet_id().unwrap();
+    session.store_outgoing(first, qos_publish("t", 2, first));
+    assert!(session.release_outgoing(first));
+
+    // 分配一整轮ID，first仍在等待PUBCOMP，不会再次出现
+    for _ in 1..u16::MAX {
+        let id = session.next_packet_id().unwrap();
+        assert_ne!(id, first);
+        assert_ne!(id, 0);
+    }
+
+    assert!(session.complete_outgoing(first));
+    assert_eq!(session.next_packet_id(), Some(first));
+}
+
+// 测试全部ID在途时分配失败
+#[test]
+fn test_packet_id_exhaustion() {
+    use mqtt_adapt::routing::qos::QoSManager;
+
+    let mut session = QoSManager::new();
+    for _ in 0..u16::MAX {
+        let id = session.next_packet_id().unwrap();
+        session.store_outgoing(id, qos_publish("t", 1, id));
+    }
+    assert_eq!(session.next_packet_id(), None);
+
+    session.remove_outgoing(42);
+    assert_eq!(session.next_packet_id(), Some(42));
+}
+
+// 测试重复的QoS 2消息只回复PUBREC，不重复转发
+#[tokio::test]
+async fn test_duplicate_qos2_publish_not_redelivered() {
+    let router = MessageRouter::new();
+    let (s_tx, s_rx) = unbounded();
+    let (p_tx, p_rx) = unbounded();
+    router.register_client("sub", s_tx).await.unwrap();
+    router.register_client("pub", p_tx).await.unwrap();
+    subscribe(&router, "sub", &s_rx, "t", 2).await;
+
+    for _ in 0..2 {
+        let event = Event::MessageReceived("pub".to_string(), MqttPacket::Publish(qos_publish("t", 2, 9)));
+        router.handle_event(event).await;
+        assert!(matches!(p_rx.try_recv(), Ok(Event::MessageSent(_, MqttPacket::PubRec(_)))));
+    }
+
+    assert_eq!(received_packet_id(&s_rx), 1);
+    assert!(s_rx.try_recv().is_err());
+}
+
+// 测试超过重发间隔仍未确认的PUBLISH带DUP标志重发，PUBREC之后重发PUBREL
+#[tokio::test]
+async fn test_retransmit_unacknowledged_messages() {
+    use mqtt_adapt::protocol::PubRecPacket;
+    use std::time::Duration;
+
+    let router = MessageRouter::new().with_retry_interval(Duration::from_millis(30));
+    let (s_tx, s_rx) = unbounded();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("sub", s_tx).await.unwrap();
+    router.register_client("pub", p_tx).await.unwrap();
+    subscribe(&router, "sub", &s_rx, "t", 2).await;
+
+    let event = Event::MessageReceived("pub".to_string(), MqttPacket::Publish(qos_publish("t", 2, 1)));
+    router.handle_event(event).await;
+    let packet_id = received_packet_id(&s_rx);
+
+    // 重发间隔未到，不重发
+    router.retry_inflight().await;
+    assert!(s_rx.try_recv().is_err());
+
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    router.retry_inflight().await;
+    match s_rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) => {
+            assert!(publish.dup);
+            assert_eq!(publish.packet_id, Some(packet_id));
+        }
+        other => panic!("Expected retransmitted PUBLISH, got {:?}", other),
+    }
+
+    // 收到PUBREC后回复PUBREL，之后重发的是PUBREL
+    let event = Event::MessageReceived("sub".to_string(), MqttPacket::PubRec(PubRecPacket::new(packet_id)));
+    router.handle_event(event).await;
+    assert!(matches!(s_rx.try_recv(), Ok(Event::MessageSent(_, MqttPacket::PubRel(_)))));
+
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    router.retry_inflight().await;
+    match s_rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::PubRel(pubrel))) => assert_eq!(pubrel.packet_id, packet_id),
+        other => panic!("Expected retransmitted PUBREL, got {:?}", other),
+    }
+    assert_eq!(router.inflight_len("sub").await, 1);
+}
+
+// 测试持久会话重连后按原始顺序补发在途消息
+#[tokio::test]
+async fn test_replay_inflight_on_persistent_reconnect() {
+    let router = MessageRouter::new();
+    let (s_tx, s_rx) = unbounded();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_session("sub", s_tx, false, None).await.unwrap();
+    router.register_client("pub", p_tx).await.unwrap();
+    subscribe(&router, "sub", &s_rx, "t", 1).await;
+
+    for i in 0..3 {
+        let event = Event::MessageReceived("pub".to_string(), MqttPacket::Publish(qos_publish("t", 1, 10 + i)));
+        router.handle_event(event).await;
+    }
+    let sent: Vec<u16> = (0..3).map(|_| received_packet_id(&s_rx)).collect();
+
+    router.handle_event(Event::ClientDisconnected("sub".to_string())).await;
+    assert_eq!(router.inflight_len("sub").await, 3);
+
+    let (s_tx, s_rx) = unbounded();
+    router.register_session("sub", s_tx, false, None).await.unwrap();
+    let replayed: Vec<u16> = (0..3)
+        .map(|_| match s_rx.try_recv() {
+            Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) => {
+                assert!(publish.dup);
+                publish.packet_id.unwrap()
+            }
+            other => panic!("Expected replayed PUBLISH, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(replayed, sent);
+
+    // clean session重连丢弃之前的在途状态
+    router.handle_event(Event::ClientDisconnected("sub".to_string())).await;
+    let (s_tx, s_rx) = unbounded();
+    router.register_session("sub", s_tx, true, None).await.unwrap();
+    assert!(s_rx.try_recv().is_err());
+    assert_eq!(router.inflight_len("sub").await, 0);
+}
+
+/// 创建带流量控制配置的路由器，订阅者"sub"订阅QoS 1主题"t"
+async fn flow_control_router(
+    config: mqtt_adapt::routing::qos::InflightConfig,
+) -> (MessageRouter, flume::Receiver<Event>) {
+    let router = MessageRouter::new().with_inflight_config(config);
+    let (s_tx, s_rx) = unbounded();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("sub", s_tx).await.unwrap();
+    router.register_client("pub", p_tx).await.unwrap();
+    subscribe(&router, "sub", &s_rx, "t", 1).await;
+    (router, s_rx)
+}
+
+async fn publish_payloads(router: &MessageRouter, payloads: &[&'static [u8]]) {
+    for (i, payload) in payloads.iter().enumerate() {
+        let mut publish = qos_publish("t", 1, i as u16 + 1);
+        publish.payload = Bytes::from_static(payload);
+        let event = Event::MessageReceived("pub".to_string(), MqttPacket::Publish(publish));
+        router.handle_event(event).await;
+    }
+}
+
+fn drain_payloads(rx: &flume::Receiver<Event>) -> Vec<Bytes> {
+    let mut payloads = Vec::new();
+    while let Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) = rx.try_recv() {
+        payloads.push(publish.payload);
+    }
+    payloads
+}
+
+// 测试飞行窗口已满时按顺序排队，确认后依次发送
+#[tokio::test]
+async fn test_inflight_window_queues_in_order() {
+    use mqtt_adapt::protocol::PubAckPacket;
+    use mqtt_adapt::routing::qos::InflightConfig;
+
+    let config = InflightConfig { max_inflight: 2, ..Default::default() };
+    let (router, s_rx) = flow_control_router(config).await;
+    publish_payloads(&router, &[b"1", b"2", b"3", b"4"]).await;
+
+    assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"1"), Bytes::from_static(b"2")]);
+    assert_eq!(router.inflight_len("sub").await, 2);
+    assert_eq!(router.queued_len("sub").await, 2);
+
+    let event = Event::MessageReceived("sub".to_string(), MqttPacket::PubAck(PubAckPacket::new(1)));
+    router.handle_event(event).await;
+    assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"3")]);
+
+    let event = Event::MessageReceived("sub".to_string(), MqttPacket::PubAck(PubAckPacket::new(2)));
+    router.handle_event(event).await;
+    assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"4")]);
+    assert_eq!(router.queued_len("sub").await, 0);
+}
+
+// 测试等待队列溢出时按策略丢弃最早或最新的消息
+#[tokio::test]
+async fn test_queue_overflow_drop_policies() {
+    use mqtt_adapt::protocol::PubAckPacket;
+    use mqtt_adapt::routing::qos::{InflightConfig, OverflowPolicy};
+
+    for (policy, expected) in [
+        (OverflowPolicy::DropOldest, vec![&b"3"[..], &b"4"[..]]),
+        (OverflowPolicy::DropNewest, vec![&b"2"[..], &b"3"[..]]),
+    ] {
+        let config = InflightConfig { max_inflight: 1, max_queued: 2, overflow_policy: policy };
+        let (router, s_rx) = flow_control_router(config).await;
+        publish_payloads(&router, &[b"1", b"2", b"3", b"4"]).await;
+        assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"1")]);
+        assert_eq!(router.queued_len("sub").await, 2);
+
+        let mut delivered = Vec::new();
+        for packet_id in 1..=2 {
+            let event = Event::MessageReceived("sub".to_string(), MqttPacket::PubAck(PubAckPacket::new(packet_id)));
+            router.handle_event(event).await;
+            delivered.extend(drain_payloads(&s_rx));
+        }
+        let expected: Vec<Bytes> = expected.into_iter().map(Bytes::copy_from_slice).collect();
+        assert_eq!(delivered, expected, "{:?}", policy);
+    }
+}
+
+// 测试等待队列溢出时断开消费过慢的客户端
+#[tokio::test]
+async fn test_queue_overflow_disconnects_slow_consumer() {
+    use mqtt_adapt::protocol::ReasonCode;
+    use mqtt_adapt::routing::qos::{InflightConfig, OverflowPolicy};
+
+    let config = InflightConfig { max_inflight: 1, max_queued: 1, overflow_policy: OverflowPolicy::Disconnect };
+    let (router, s_rx) = flow_control_router(config).await;
+    publish_payloads(&router, &[b"1", b"2", b"3"]).await;
+
+    assert!(matches!(s_rx.try_recv(), Ok(Event::MessageSent(_, MqttPacket::Publish(_)))));
+    match s_rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::Disconnect(disconnect))) => {
+            assert_eq!(disconnect.reason_code, ReasonCode::QuotaExceeded);
+        }
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+}
+
+// 测试MQTT 5.0 Receive Maximum收紧飞行窗口
+#[tokio::test]
+async fn test_receive_maximum_limits_window() {
+    use mqtt_adapt::routing::qos::InflightConfig;
+
+    let (router, s_rx) = flow_control_router(InflightConfig::default()).await;
+    router.set_receive_maximum("sub", 1).await;
+    publish_payloads(&router, &[b"1", b"2"]).await;
+
+    assert_eq!(drain_payloads(&s_rx).len(), 1);
+    assert_eq!(router.queued_len("sub").await, 1);
+}
+
+// 测试持久会话重连时先按本次的Receive Maximum设置窗口再补发，窗口可随重连放大
+#[tokio::test]
+async fn test_receive_maximum_applied_before_replay() {
+    let router = MessageRouter::new();
+    let (s_tx, s_rx) = unbounded();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_session("sub", s_tx, false, Some(1)).await.unwrap();
+    router.register_client("pub", p_tx).await.unwrap();
+    subscribe(&router, "sub", &s_rx, "t", 1).await;
+    publish_payloads(&router, &[b"1", b"2", b"3"]).await;
+    assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"1")]);
+    assert_eq!(router.queued_len("sub").await, 2);
+
+    router.handle_event(Event::ClientDisconnected("sub".to_string())).await;
+    let (s_tx, s_rx) = unbounded();
+    router.register_session("sub", s_tx, false, Some(1)).await.unwrap();
+    assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"1")]);
+    assert_eq!(router.queued_len("sub").await, 2);
+
+    router.handle_event(Event::ClientDisconnected("sub".to_string())).await;
+    let (s_tx, s_rx) = unbounded();
+    router.register_session("sub", s_tx, false, Some(10)).await.unwrap();
+    assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"1"), Bytes::from_static(b"2"), Bytes::from_static(b"3")]);
+    assert_eq!(router.inflight_len("sub").await, 3);
+}
+
+// 测试相同client_id再次连接时接管会话，旧连接随后关闭不影响新连接
+#[tokio::test]
+async fn test_session_takeover_ignores_stale_disconnect() {
+    use mqtt_adapt::protocol::ReasonCode;
+
+    let router = MessageRouter::new();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("pub", p_tx).await.unwrap();
+
+    let (old_tx, old_rx) = unbounded();
+    let old = router.register_session("device", old_tx, false, None).await.unwrap();
+    subscribe(&router, "device", &old_rx, "t", 1).await;
+    let event = Event::MessageReceived("pub".to_string(), MqttPacket::Publish(qos_publish("t", 1, 1)));
+    router.handle_event(event).await;
+    let inflight_id = received_packet_id(&old_rx);
+
+    // 新连接接管：旧连接收到0x8E，新连接收到补发的在途消息
+    let (new_tx, new_rx) = unbounded();
+    let new = router.register_session("device", new_tx, false, None).await.unwrap();
+    assert!(new.session_present);
+    assert_ne!(old.connection_id, new.connection_id);
+    match old_rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::Disconnect(disconnect))) => {
+            assert_eq!(disconnect.reason_code, ReasonCode::SessionTakenOver);
+        }
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+    assert_eq!(received_packet_id(&new_rx), inflight_id);
+
+    // 旧连接此后才上报关闭，不能移除新连接的注册
+    router.handle_event(Event::ConnectionClosed("device".to_string(), old.connection_id)).await;
+    let event = Event::MessageReceived("pub".to_string(), MqttPacket::Publish(qos_publish("t", 1, 2)));
+    router.handle_event(event).await;
+    received_packet_id(&new_rx);
+    assert!(old_rx.try_recv().is_err());
+
+    // 新连接自己的关闭正常生效
+    router.handle_event(Event::ConnectionClosed("device".to_string(), new.connection_id)).await;
+    let event = Event::MessageReceived("pub".to_string(), MqttPacket::Publish(qos_publish("t", 1, 3)));
+    router.handle_event(event).await;
+    assert!(new_rx.try_recv().is_err());
+}
+
+// 测试并发注册同一client_id时只有一个连接保留，其余连接都收到接管通知
+#[tokio::test]
+async fn test_concurrent_registration_race() {
+    use std::sync::Arc;
+
+    let router = Arc::new(MessageRouter::new());
+    let mut tasks = Vec::new();
+    for _ in 0..16 {
+        let router = router.clone();
+        tasks.push(tokio::spawn(async move {
+            let (tx, rx) = unbounded();
+            let info = router.register_session("device", tx, true, None).await.unwrap();
+            (info, rx)
+        }));
+    }
+
+    let mut connections = Vec::new();
+    for task in tasks {
+        connections.push(task.await.unwrap());
+    }
+
+    // 每个被接管的连接都收到DISCONNECT，恰好一个连接仍然在线
+    let active: Vec<_> = connections
+        .iter()
+        .filter(|(_, rx)| !matches!(rx.try_recv(), Ok(Event::MessageSent(_, MqttPacket::Disconnect(_)))))
+        .collect();
+    assert_eq!(active.len(), 1);
+
+    // 旧连接全部关闭后，活跃连接依然可以收到消息
+    for (info, _) in &connections {
+        if info.connection_id != active[0].0.connection_id {
+            router.handle_event(Event::ConnectionClosed("device".to_string(), info.connection_id)).await;
+        }
+    }
+    let (_, active_rx) = active[0];
+    subscribe(&router, "device", active_rx, "t", 0).await;
+    let publish = PublishPacket { qos: 0, packet_id: None, ..qos_publish("t", 0, 1) };
+    router.handle_event(Event::MessageReceived("device".to_string(), MqttPacket::Publish(publish))).await;
+    assert!(matches!(active_rx.try_recv(), Ok(Event::MessageSent(_, MqttPacket::Publish(_)))));
+}
+
+fn will_packet() -> PublishPacket {
+    PublishPacket {
+        qos: 0,
+        packet_id: None,
+        payload: Bytes::from_static(b"offline"),
+        ..qos_publish("status/device", 0, 1)
+    }
+}
+
+// 测试遗嘱延迟为0时立即发布
+#[tokio::test]
+async fn test_will_published_immediately() {
+    let router = MessageRouter::new();
+    let (w_tx, w_rx) = unbounded();
+    router.register_client("watcher", w_tx).await.unwrap();
+    subscribe(&router, "watcher", &w_rx, "status/#", 0).await;
+
+    let (d_tx, _d_rx) = unbounded();
+    let device = router.register_session("device", d_tx, true, None).await.unwrap();
+    let event = Event::WillMessage("device".to_string(), device.connection_id, will_packet(), Duration::ZERO);
+    router.handle_event(event).await;
+
+    match w_rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) => assert_eq!(publish.payload, "offline"),
+        other => panic!("Expected will PUBLISH, got {:?}", other),
+    }
+}
+
+// 测试延迟遗嘱在延迟结束后发布，延迟期间重连则取消
+#[tokio::test]
+async fn test_delayed_will_cancelled_by_reconnect() {
+    let router = MessageRouter::new();
+    let (w_tx, w_rx) = unbounded();
+    router.register_client("watcher", w_tx).await.unwrap();
+    subscribe(&router, "watcher", &w_rx, "status/#", 0).await;
+
+    // 延迟结束且未重连：发布遗嘱
+    let (d_tx, _d_rx) = unbounded();
+    let device = router.register_session("device", d_tx, false, None).await.unwrap();
+    let delay = Duration::from_millis(50);
+    router.handle_event(Event::WillMessage("device".to_string(), device.connection_id, will_packet(), delay)).await;
+    router.handle_event(Event::ConnectionClosed("device".to_string(), device.connection_id)).await;
+    assert!(w_rx.try_recv().is_err());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(matches!(w_rx.try_recv(), Ok(Event::MessageSent(_, MqttPacket::Publish(_)))));
+
+    // 延迟期间重连：不发布遗嘱
+    let (d_tx, _d_rx) = unbounded();
+    let device = router.register_session("device", d_tx, false, None).await.unwrap();
+    router.handle_event(Event::WillMessage("device".to_string(), device.connection_id, will_packet(), delay)).await;
+    router.handle_event(Event::ConnectionClosed("device".to_string(), device.connection_id)).await;
+    let (d_tx, _d_rx) = unbounded();
+    router.register_session("device", d_tx, false, None).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(w_rx.try_recv().is_err());
+}
+
+// 测试会话被接管后旧连接上报的延迟遗嘱被丢弃
+#[tokio::test]
+async fn test_delayed_will_discarded_after_takeover() {
+    let router = MessageRouter::new();
+    let (w_tx, w_rx) = unbounded();
+    router.register_client("watcher", w_tx).await.unwrap();
+    subscribe(&router, "watcher", &w_rx, "status/#", 0).await;
+
+    let (old_tx, _old_rx) = unbounded();
+    let old = router.register_session("device", old_tx, false, None).await.unwrap();
+    let (new_tx, _new_rx) = unbounded();
+    router.register_session("device", new_tx, false, None).await.unwrap();
+
+    let delay = Duration::from_millis(50);
+    router.handle_event(Event::WillMessage("device".to_string(), old.connection_id, will_packet(), delay)).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(w_rx.try_recv().is_err());
+}
+
+// 测试保留消息主题数达到上限后不再保留新主题，已有主题仍可更新和清除
+#[tokio::test]
+async fn test_retained_message_limit() {
+    let router = MessageRouter::new().with_max_retained_messages(1);
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("pub", p_tx).await.unwrap();
+
+    for (topic, payload) in [("a", "1"), ("b", "2"), ("a", "3")] {
+        let publish = PublishPacket {
+            retain: true,
+            payload: Bytes::from(payload),
+            ..qos_publish(topic, 0, 1)
+        };
+        router.handle_event(Event::MessageReceived("pub".to_string(), MqttPacket::Publish(publish))).await;
+    }
+
+    let (s_tx, s_rx) = unbounded();
+    router.register_client("sub", s_tx).await.unwrap();
+    subscribe(&router, "sub", &s_rx, "a", 0).await;
+    assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"3")]);
+    subscribe(&router, "sub", &s_rx, "b", 0).await;
+    assert!(drain_payloads(&s_rx).is_empty());
+
+    // 清除已有主题后腾出名额
+    let clear = PublishPacket { retain: true, payload: Bytes::new(), ..qos_publish("a", 0, 1) };
+    router.handle_event(Event::MessageReceived("pub".to_string(), MqttPacket::Publish(clear))).await;
+    let publish = PublishPacket { retain: true, payload: Bytes::from("4"), ..qos_publish("b", 0, 1) };
+    router.handle_event(Event::MessageReceived("pub".to_string(), MqttPacket::Publish(publish))).await;
+    drain_payloads(&s_rx);
+
+    let (s_tx, s_rx) = unbounded();
+    router.register_client("late", s_tx).await.unwrap();
+    subscribe(&router, "late", &s_rx, "a", 0).await;
+    assert!(drain_payloads(&s_rx).is_empty());
+    subscribe(&router, "late", &s_rx, "b", 0).await;
+    assert_eq!(drain_payloads(&s_rx), vec![Bytes::from_static(b"4")]);
+}
+
+// 测试QoS 1订阅收到的保留消息带数据包ID，计入在途消息并可被PUBACK确认
+#[tokio::test]
+async fn test_retained_message_tracked_by_qos() {
+    use mqtt_adapt::protocol::PubAckPacket;
+
+    let router = MessageRouter::new();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("pub", p_tx).await.unwrap();
+    let publish = PublishPacket { retain: true, ..qos_publish("r", 1, 7) };
+    router.handle_event(Event::MessageReceived("pub".to_string(), MqttPacket::Publish(publish))).await;
+
+    let (s_tx, s_rx) = unbounded();
+    router.register_client("sub", s_tx).await.unwrap();
+    subscribe(&router, "sub", &s_rx, "r", 1).await;
+    let publish = match s_rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) => publish,
+        other => panic!("Expected retained PUBLISH, got {:?}", other),
+    };
+    assert!(publish.retain);
+    assert_eq!(publish.qos, 1);
+    assert_eq!(publish.packet_id, Some(1));
+    assert_eq!(router.inflight_len("sub").await, 1);
+
+    // 编码后是完整合法的数据包
+    let mut buf = bytes::BytesMut::new();
+    MqttPacket::Publish(publish).write(&mut buf);
+    assert!(matches!(MqttPacket::read(&mut buf), Ok(MqttPacket::Publish(p)) if p.packet_id == Some(1)));
+
+    let event = Event::MessageReceived("sub".to_string(), MqttPacket::PubAck(PubAckPacket::new(1)));
+    router.handle_event(event).await;
+    assert_eq!(router.inflight_len("sub").await, 0);
+}
+
+// 测试UNSUBACK按请求顺序为每个过滤器给出原因码
+#[tokio::test]
+async fn test_unsuback_reason_codes() {
+    let router = MessageRouter::new();
+    let (tx, rx) = unbounded();
+    router.register_client("c1", tx).await.unwrap();
+    subscribe(&router, "c1", &rx, "a/b", 0).await;
+    subscribe(&router, "c1", &rx, "$share/g/jobs", 0).await;
+
+    let unsubscribe_packet = UnsubscribePacket {
+        packet_id: 3,
+        topics: vec![
+            "unknown".to_string(),
+            "a/b".to_string(),
+            "$share/g/jobs".to_string(),
+            "a/b".to_string(),
+        ],
+        properties: Default::default(),
+    };
+    router
+        .handle_event(Event::MessageReceived("c1".to_string(), MqttPacket::Unsubscribe(unsubscribe_packet)))
+        .await;
+
+    let Ok(Event::MessageSent(_, MqttPacket::UnsubAck(unsuback))) = rx.try_recv() else {
+        panic!("Expected UNSUBACK");
+    };
+    assert_eq!(unsuback.packet_id, 3);
+    assert_eq!(
+        unsuback.reason_codes,
+        vec![
+            ReasonCode::NoSubscriptionExisted,
+            ReasonCode::Success,
+            ReasonCode::Success,
+            ReasonCode::NoSubscriptionExisted,
+        ]
+    );
+}
@@ -0,0 +1,115 @@
+use bytes::{Bytes, BytesMut};
+use mqtt_adapt::auth::{AnonymousAuthenticator, Authenticator, StaticFileAuthenticator};
+use mqtt_adapt::client::create_client_with_connect;
+use mqtt_adapt::config::BrokerConfig;
+use mqtt_adapt::protocol::{ConnectPacket, MqttPacket, PublishPacket, SubscribePacket};
+use mqtt_adapt::routing::{event::Event, router::MessageRouter};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+mod common;
+use common::connect_packet;
+
+/// 通过内存连接发送CONNECT并创建客户端，返回是否被接受
+async fn connect(router: &MessageRouter, authenticator: &dyn Authenticator, connect: ConnectPacket) -> bool {
+    let (mut peer, socket) = tokio::io::duplex(4096);
+    let mut buffer = BytesMut::new();
+    MqttPacket::Connect(connect).write(&mut buffer);
+    peer.write_all(&buffer).await.unwrap();
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 50000));
+    create_client_with_connect(socket, addr, router, authenticator, &BrokerConfig::default(), None, None)
+        .await
+        .is_ok()
+}
+
+/// 发送一次HTTP请求，返回完整的响应
+async fn http_get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+// 测试/metrics输出数据包、字节、认证失败和当前连接数
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let router = MessageRouter::new();
+    assert!(connect(&router, &AnonymousAuthenticator, connect_packet("m1", None, None)).await);
+    let content = format!("admin:{}\n", mqtt_adapt::auth::password::hash_password("secret").unwrap());
+    let authenticator = StaticFileAuthenticator::parse(&content).unwrap();
+    assert!(!connect(&router, &authenticator, connect_packet("m2", Some("admin"), Some("wrong"))).await);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(mqtt_adapt::metrics::serve(listener, router.clone()));
+
+    let response = http_get(addr, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("text/plain; version=0.0.4"));
+    assert!(response.contains("mqtt_packets_received_total{type=\"connect\"} 2"));
+    assert!(response.contains("mqtt_packets_sent_total{type=\"connack\"} 2"));
+    assert!(response.contains("mqtt_packets_received_total{type=\"publish\"} 0"));
+    assert!(response.contains("mqtt_auth_failures_total 1"));
+    assert!(response.contains("mqtt_connections 1"));
+    assert!(response.contains("mqtt_subscriptions 0"));
+    assert!(!response.contains("mqtt_bytes_received_total 0"));
+    assert!(response.contains("mqtt_router_queue_depth_bucket"));
+
+    assert!(http_get(addr, "/other").await.starts_with("HTTP/1.1 404"));
+}
+
+// 测试订阅者通道已关闭时的消息计为丢弃
+#[tokio::test]
+async fn test_dropped_messages() {
+    let router = MessageRouter::new();
+    let (tx, rx) = flume::unbounded();
+    router.register_client("gone", tx).await.unwrap();
+    let subscribe = SubscribePacket {
+        packet_id: 1,
+        topics: vec![("alerts".to_string(), 0)],
+        properties: Default::default(),
+    };
+    router
+        .handle_event(Event::MessageReceived("gone".to_string(), MqttPacket::Subscribe(subscribe)))
+        .await;
+    drop(rx);
+
+    let publish = PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: false,
+        topic_name: "alerts".to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(b"fire"),
+        properties: Default::default(),
+    };
+    router.publish(publish).await;
+    assert_eq!(router.stats().messages_dropped(), 1);
+}
+
+// 测试路由器记录事件处理耗时和队列深度
+#[tokio::test]
+async fn test_router_event_histograms() {
+    let router = MessageRouter::new();
+    tokio::spawn(router.clone().start());
+    let sender = router.get_sender();
+    for _ in 0..3 {
+        sender.send(Event::ClientConnected("h1".to_string())).unwrap();
+    }
+
+    let mut output = String::new();
+    for _ in 0..50 {
+        output = mqtt_adapt::metrics::render(&router).await;
+        if output.contains("mqtt_router_event_duration_seconds_count 3") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(output.contains("mqtt_router_event_duration_seconds_count 3"), "{}", output);
+    assert!(output.contains("mqtt_router_queue_depth_count 3"));
+}
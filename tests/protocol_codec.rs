@@ -0,0 +1,143 @@
+use mqtt_adapt::protocol::{MqttCodec, MqttPacket, Packet, PingReqPacket, PublishPacket, PubAckPacket};
+use bytes::{Bytes, BytesMut, BufMut};
+use tokio_util::codec::Decoder;
+
+fn publish(topic: &str, payload: &'static [u8]) -> PublishPacket {
+    PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: false,
+        topic_name: topic.to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(payload),
+        properties: Default::default(),
+    }
+}
+
+// 测试逐字节输入时解码器等待完整数据包
+#[test]
+fn test_decode_byte_at_a_time() {
+    let packet = publish("test/topic", b"test payload");
+    let mut encoded = BytesMut::new();
+    packet.write(&mut encoded);
+
+    let mut codec = MqttCodec::new();
+    let mut buffer = BytesMut::new();
+    let last = encoded.len() - 1;
+
+    for (i, byte) in encoded.iter().enumerate() {
+        buffer.put_u8(*byte);
+        let result = codec.decode(&mut buffer).unwrap();
+        if i < last {
+            assert!(result.is_none(), "decoded early at byte {}", i);
+        } else {
+            assert_eq!(result, Some(MqttPacket::Publish(packet.clone())));
+        }
+    }
+
+    assert!(buffer.is_empty());
+}
+
+// 测试一次读取中包含多个数据包
+#[test]
+fn test_decode_multiple_packets() {
+    let first = publish("a/b", b"1");
+    let second = publish("c/d", b"22");
+    let mut buffer = BytesMut::new();
+    first.write(&mut buffer);
+    PubAckPacket::new(7).write(&mut buffer);
+    second.write(&mut buffer);
+    PingReqPacket.write(&mut buffer);
+
+    let mut codec = MqttCodec::new();
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(MqttPacket::Publish(first)));
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(MqttPacket::PubAck(PubAckPacket::new(7))));
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(MqttPacket::Publish(second)));
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(MqttPacket::PingReq(PingReqPacket)));
+    assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    assert!(buffer.is_empty());
+}
+
+// 测试完整数据包之后跟随不完整数据包时保留剩余字节
+#[test]
+fn test_decode_keeps_partial_tail() {
+    let first = publish("a/b", b"first");
+    let second = publish("a/b", b"second");
+    let mut encoded = BytesMut::new();
+    first.write(&mut encoded);
+    let first_len = encoded.len();
+    second.write(&mut encoded);
+
+    // 第一次到达：完整的第一个包 + 第二个包的前3个字节
+    let mut buffer = BytesMut::from(&encoded[..first_len + 3]);
+    let mut codec = MqttCodec::new();
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(MqttPacket::Publish(first)));
+    assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    assert_eq!(buffer.len(), 3);
+
+    // 第二次到达：剩余字节
+    buffer.extend_from_slice(&encoded[first_len + 3..]);
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(MqttPacket::Publish(second)));
+    assert!(buffer.is_empty());
+}
+
+// 测试多字节剩余长度被拆分在两次读取之间
+#[test]
+fn test_decode_split_remaining_length() {
+    let payload = vec![0xAB; 300];
+    let packet = PublishPacket {
+        payload: Bytes::from(payload),
+        ..publish("big/topic", b"")
+    };
+    let mut encoded = BytesMut::new();
+    packet.write(&mut encoded);
+    // 300字节载荷需要2字节剩余长度
+    assert_ne!(encoded[1] & 0x80, 0);
+
+    let mut codec = MqttCodec::new();
+    let mut buffer = BytesMut::from(&encoded[..2]);
+    assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    assert_eq!(buffer.len(), 2);
+
+    buffer.extend_from_slice(&encoded[2..]);
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(MqttPacket::Publish(packet)));
+}
+
+// 测试非法数据包类型返回错误
+#[test]
+fn test_decode_invalid_packet_type() {
+    let mut buffer = BytesMut::new();
+    buffer.put_u8(0x00);
+    buffer.put_u8(0x00);
+
+    let mut codec = MqttCodec::new();
+    assert!(codec.decode(&mut buffer).is_err());
+}
+
+// 测试超过4字节的剩余长度返回错误
+#[test]
+fn test_decode_remaining_length_too_long() {
+    let mut buffer = BytesMut::new();
+    buffer.put_u8(0x30);
+    buffer.put_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+
+    let mut codec = MqttCodec::new();
+    assert!(codec.decode(&mut buffer).is_err());
+}
+
+// 测试超过最大长度的数据包在读取载荷前被拒绝
+#[test]
+fn test_decode_rejects_oversized_packet() {
+    let mut codec = MqttCodec::new();
+    codec.set_max_packet_size(Some(16));
+
+    let mut buffer = BytesMut::new();
+    publish("t", b"small").write(&mut buffer);
+    assert!(codec.decode(&mut buffer).unwrap().is_some());
+
+    // 只有固定头到达时就能判断超长
+    let mut buffer = BytesMut::new();
+    publish("test/topic", b"payload larger than limit").write(&mut buffer);
+    buffer.truncate(2);
+    assert!(codec.decode(&mut buffer).is_err());
+}
@@ -155,7 +155,7 @@ fn test_suback_packet_parse() {
     assert!(result.is_ok());
     let packet = result.unwrap();
     assert_eq!(packet.packet_id, 1111);
-    assert_eq!(packet.return_codes, 1);
+    assert_eq!(packet.return_codes, vec![1]);
 }
 
 // 测试UNSUBSCRIBE数据包解析
@@ -233,6 +233,8 @@ fn test_packet_serialization() {
         will_message: None,
         username: None,
         password: None,
+        properties: Default::default(),
+        will_properties: Default::default(),
     };
     
     let mut buffer = BytesMut::new();
@@ -240,10 +242,7 @@ fn test_packet_serialization() {
     assert!(!buffer.is_empty());
     
     // 测试CONNACK数据包序列化
-    let connack_packet = ConnAckPacket {
-        session_present: false,
-        return_code: mqtt_adapt::protocol::ConnectReturnCode::Accepted,
-    };
+    let connack_packet = ConnAckPacket::new(false, mqtt_adapt::protocol::ConnectReturnCode::Accepted);
     
     let mut buffer = BytesMut::new();
     connack_packet.write(&mut buffer);
@@ -257,6 +256,7 @@ fn test_packet_serialization() {
         topic_name: "test/topic".to_string(),
         packet_id: None,
         payload: Bytes::from_static(b"test payload"),
+        properties: Default::default(),
     };
     
     let mut buffer = BytesMut::new();
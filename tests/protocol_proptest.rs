@@ -0,0 +1,431 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use mqtt_adapt::protocol::{
+    AuthPacket, ConnAckPacket, ConnectPacket, ConnectReturnCode, DisconnectPacket, MqttCodec, MqttPacket,
+    PingReqPacket, PingRespPacket, Properties, ProtocolVersion, PubAckPacket, PubCompPacket, PubRecPacket,
+    PubRelPacket, PublishPacket, ReasonCode, SubAckPacket, SubscribePacket, UnsubAckPacket, UnsubscribePacket,
+    write_remaining_length,
+};
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+use proptest::strategy::LazyJust;
+use tokio_util::codec::Decoder;
+
+/// 不含U+0000的MQTT字符串
+fn mqtt_string() -> impl Strategy<Value = String> + Clone {
+    "[^\\x00]{0,16}"
+}
+
+/// 不含通配符的非空主题名
+fn topic_name() -> impl Strategy<Value = String> {
+    "[^\\x00+#]{1,16}"
+}
+
+fn binary() -> impl Strategy<Value = Bytes> + Clone {
+    vec(any::<u8>(), 0..32).prop_map(Bytes::from)
+}
+
+fn packet_id() -> impl Strategy<Value = u16> {
+    1u16..
+}
+
+fn reason_code() -> impl Strategy<Value = ReasonCode> {
+    (0u8..=0xA2).prop_filter_map("not a reason code", ReasonCode::from_u8)
+}
+
+/// 任意合法的MQTT 5.0属性集合
+fn v5_properties() -> impl Strategy<Value = Properties> {
+    let flag = || option::of(0u8..=1);
+    let string = || option::of(mqtt_string());
+    let bytes = || option::of(binary());
+    (
+        (
+            flag(),
+            any::<Option<u32>>(),
+            string(),
+            string(),
+            bytes(),
+            vec(1u32..=268_435_455, 0..3),
+            any::<Option<u32>>(),
+            string(),
+            any::<Option<u16>>(),
+        ),
+        (
+            string(),
+            bytes(),
+            flag(),
+            any::<Option<u32>>(),
+            flag(),
+            string(),
+            string(),
+            string(),
+            option::of(1u16..),
+        ),
+        (
+            any::<Option<u16>>(),
+            any::<Option<u16>>(),
+            flag(),
+            flag(),
+            vec((mqtt_string(), mqtt_string()), 0..3),
+            option::of(1u32..),
+            flag(),
+            flag(),
+            flag(),
+        ),
+    )
+        .prop_map(|(a, b, c)| Properties {
+            payload_format_indicator: a.0,
+            message_expiry_interval: a.1,
+            content_type: a.2,
+            response_topic: a.3,
+            correlation_data: a.4,
+            subscription_identifiers: a.5,
+            session_expiry_interval: a.6,
+            assigned_client_identifier: a.7,
+            server_keep_alive: a.8,
+            authentication_method: b.0,
+            authentication_data: b.1,
+            request_problem_information: b.2,
+            will_delay_interval: b.3,
+            request_response_information: b.4,
+            response_information: b.5,
+            server_reference: b.6,
+            reason_string: b.7,
+            receive_maximum: b.8,
+            topic_alias_maximum: c.0,
+            topic_alias: c.1,
+            maximum_qos: c.2,
+            retain_available: c.3,
+            user_properties: c.4,
+            maximum_packet_size: c.5,
+            wildcard_subscription_available: c.6,
+            subscription_identifier_available: c.7,
+            shared_subscription_available: c.8,
+        })
+}
+
+/// 按协议版本生成属性，MQTT 5.0之前的版本没有属性
+fn properties(version: ProtocolVersion) -> BoxedStrategy<Properties> {
+    if version == ProtocolVersion::V5 {
+        v5_properties().boxed()
+    } else {
+        Just(Properties::default()).boxed()
+    }
+}
+
+/// 连接标志与可选字段一致的CONNECT
+fn connect(version: ProtocolVersion) -> impl Strategy<Value = MqttPacket> {
+    let (protocol_name, protocol_level) = match version {
+        ProtocolVersion::V31 => ("MQIsdp", 3),
+        ProtocolVersion::V311 => ("MQTT", 4),
+        ProtocolVersion::V5 => ("MQTT", 5),
+    };
+    (
+        any::<bool>(),
+        option::of((topic_name(), binary(), 0u8..=2, any::<bool>(), properties(version))),
+        option::of(mqtt_string()),
+        option::of(binary()),
+        any::<u16>(),
+        mqtt_string(),
+        properties(version),
+    )
+        .prop_map(move |(clean_session, will, username, password, keep_alive, client_id, properties)| {
+            // MQTT 5.0之前的版本不允许只带密码
+            let password = password.filter(|_| version == ProtocolVersion::V5 || username.is_some());
+            let mut connect_flags = if clean_session { 0x02 } else { 0x00 };
+            if let Some((_, _, qos, retain, _)) = &will {
+                connect_flags |= 0x04 | (qos << 3) | if *retain { 0x20 } else { 0x00 };
+            }
+            if username.is_some() {
+                connect_flags |= 0x80;
+            }
+            if password.is_some() {
+                connect_flags |= 0x40;
+            }
+            let (will_topic, will_message, will_properties) = match will {
+                Some((topic, message, _, _, will_properties)) => (Some(topic), Some(message), will_properties),
+                None => (None, None, Properties::default()),
+            };
+            MqttPacket::Connect(ConnectPacket {
+                protocol_name: protocol_name.to_string(),
+                protocol_level,
+                connect_flags,
+                keep_alive,
+                client_id,
+                will_topic,
+                will_message,
+                username,
+                password,
+                properties,
+                will_properties,
+            })
+        })
+}
+
+fn connack(version: ProtocolVersion) -> BoxedStrategy<MqttPacket> {
+    if version == ProtocolVersion::V5 {
+        (any::<bool>(), reason_code(), v5_properties())
+            .prop_map(|(session_present, reason_code, properties)| {
+                MqttPacket::ConnAck(ConnAckPacket {
+                    session_present,
+                    return_code: reason_code.into(),
+                    reason_code: Some(reason_code),
+                    properties,
+                })
+            })
+            .boxed()
+    } else {
+        (any::<bool>(), (0u8..=5).prop_filter_map("not a return code", ConnectReturnCode::from_u8))
+            .prop_map(|(session_present, return_code)| {
+                MqttPacket::ConnAck(ConnAckPacket::new(session_present, return_code))
+            })
+            .boxed()
+    }
+}
+
+fn publish(version: ProtocolVersion) -> impl Strategy<Value = MqttPacket> {
+    (any::<bool>(), 0u8..=2, any::<bool>(), topic_name(), packet_id(), binary(), properties(version)).prop_map(
+        |(dup, qos, retain, topic_name, packet_id, payload, properties)| {
+            MqttPacket::Publish(PublishPacket {
+                dup,
+                qos,
+                retain,
+                topic_name,
+                packet_id: (qos > 0).then_some(packet_id),
+                payload,
+                properties,
+            })
+        },
+    )
+}
+
+/// PUBACK/PUBREC/PUBREL/PUBCOMP的字段，MQTT 5.0之前只有数据包ID
+fn ack(version: ProtocolVersion) -> BoxedStrategy<(u16, ReasonCode, Properties)> {
+    if version == ProtocolVersion::V5 {
+        (packet_id(), reason_code(), v5_properties()).boxed()
+    } else {
+        packet_id().prop_map(|id| (id, ReasonCode::Success, Properties::default())).boxed()
+    }
+}
+
+fn subscription_options(version: ProtocolVersion) -> BoxedStrategy<u8> {
+    if version == ProtocolVersion::V5 {
+        (0u8..=2, any::<bool>(), any::<bool>(), 0u8..=2)
+            .prop_map(|(qos, no_local, retain_as_published, retain_handling)| {
+                qos | (no_local as u8) << 2 | (retain_as_published as u8) << 3 | retain_handling << 4
+            })
+            .boxed()
+    } else {
+        (0u8..=2).boxed()
+    }
+}
+
+fn unsuback(version: ProtocolVersion) -> BoxedStrategy<MqttPacket> {
+    if version == ProtocolVersion::V5 {
+        (packet_id(), vec(reason_code(), 0..4), v5_properties())
+            .prop_map(|(packet_id, reason_codes, properties)| {
+                MqttPacket::UnsubAck(UnsubAckPacket {
+                    packet_id,
+                    reason_codes,
+                    properties,
+                })
+            })
+            .boxed()
+    } else {
+        packet_id().prop_map(|id| MqttPacket::UnsubAck(UnsubAckPacket::new(id))).boxed()
+    }
+}
+
+fn disconnect(version: ProtocolVersion) -> BoxedStrategy<MqttPacket> {
+    if version == ProtocolVersion::V5 {
+        (reason_code(), v5_properties())
+            .prop_map(|(reason_code, properties)| {
+                MqttPacket::Disconnect(DisconnectPacket {
+                    reason_code,
+                    properties,
+                })
+            })
+            .boxed()
+    } else {
+        LazyJust::new(|| MqttPacket::Disconnect(DisconnectPacket::default())).boxed()
+    }
+}
+
+/// 指定协议版本下任意合法的数据包，AUTH只存在于MQTT 5.0
+fn packet(version: ProtocolVersion) -> BoxedStrategy<MqttPacket> {
+    let mut packets = vec![
+        connect(version).boxed(),
+        connack(version),
+        publish(version).boxed(),
+        ack(version)
+            .prop_map(|(packet_id, reason_code, properties)| {
+                MqttPacket::PubAck(PubAckPacket {
+                    packet_id,
+                    reason_code,
+                    properties,
+                })
+            })
+            .boxed(),
+        ack(version)
+            .prop_map(|(packet_id, reason_code, properties)| {
+                MqttPacket::PubRec(PubRecPacket {
+                    packet_id,
+                    reason_code,
+                    properties,
+                })
+            })
+            .boxed(),
+        ack(version)
+            .prop_map(|(packet_id, reason_code, properties)| {
+                MqttPacket::PubRel(PubRelPacket {
+                    packet_id,
+                    reason_code,
+                    properties,
+                })
+            })
+            .boxed(),
+        ack(version)
+            .prop_map(|(packet_id, reason_code, properties)| {
+                MqttPacket::PubComp(PubCompPacket {
+                    packet_id,
+                    reason_code,
+                    properties,
+                })
+            })
+            .boxed(),
+        (packet_id(), vec((mqtt_string(), subscription_options(version)), 1..4), properties(version))
+            .prop_map(|(packet_id, topics, properties)| {
+                MqttPacket::Subscribe(SubscribePacket {
+                    packet_id,
+                    topics,
+                    properties,
+                })
+            })
+            .boxed(),
+        (packet_id(), vec(any::<u8>(), 1..4), properties(version))
+            .prop_map(|(packet_id, return_codes, properties)| {
+                MqttPacket::SubAck(SubAckPacket {
+                    packet_id,
+                    return_codes,
+                    properties,
+                })
+            })
+            .boxed(),
+        (packet_id(), vec(mqtt_string(), 1..4), properties(version))
+            .prop_map(|(packet_id, topics, properties)| {
+                MqttPacket::Unsubscribe(UnsubscribePacket {
+                    packet_id,
+                    topics,
+                    properties,
+                })
+            })
+            .boxed(),
+        unsuback(version),
+        LazyJust::new(|| MqttPacket::PingReq(PingReqPacket)).boxed(),
+        LazyJust::new(|| MqttPacket::PingResp(PingRespPacket)).boxed(),
+        disconnect(version),
+    ];
+    if version == ProtocolVersion::V5 {
+        packets.push(
+            (reason_code(), v5_properties())
+                .prop_map(|(reason_code, properties)| {
+                    MqttPacket::Auth(AuthPacket {
+                        reason_code,
+                        properties,
+                    })
+                })
+                .boxed(),
+        );
+    }
+    proptest::strategy::Union::new(packets).boxed()
+}
+
+fn version() -> impl Strategy<Value = ProtocolVersion> {
+    prop_oneof![
+        Just(ProtocolVersion::V31),
+        Just(ProtocolVersion::V311),
+        Just(ProtocolVersion::V5),
+    ]
+}
+
+/// 任意协议版本及该版本下的合法数据包
+fn versioned_packet() -> impl Strategy<Value = (ProtocolVersion, MqttPacket)> {
+    version().prop_flat_map(|version| (Just(version), packet(version)))
+}
+
+/// 首字节和剩余长度正确、内容任意的数据帧，使解析深入到各数据包的可变头
+fn arbitrary_frame() -> impl Strategy<Value = BytesMut> {
+    (any::<u8>(), vec(any::<u8>(), 0..128)).prop_map(|(first_byte, body)| {
+        let mut buffer = BytesMut::new();
+        buffer.put_u8(first_byte);
+        write_remaining_length(&mut buffer, body.len());
+        buffer.put_slice(&body);
+        buffer
+    })
+}
+
+/// 解析成功的数据包重新编码后必须解析出相同的结果
+fn assert_stable(packet: MqttPacket, version: ProtocolVersion) -> Result<(), TestCaseError> {
+    let mut encoded = BytesMut::new();
+    packet.write_versioned(&mut encoded, version);
+    let reparsed = MqttPacket::read_versioned(&mut encoded, version);
+    prop_assert_eq!(reparsed, Ok(packet));
+    prop_assert!(encoded.is_empty());
+    Ok(())
+}
+
+proptest! {
+    // 测试任意合法数据包编码后解析得到原数据包，且恰好消费全部字节
+    #[test]
+    fn test_write_read_round_trip((version, packet) in versioned_packet()) {
+        let mut buffer = BytesMut::new();
+        packet.write_versioned(&mut buffer, version);
+        let parsed = MqttPacket::read_versioned(&mut buffer, version);
+        prop_assert_eq!(parsed, Ok(packet));
+        prop_assert!(buffer.is_empty());
+    }
+
+    // 测试连续的数据包按任意位置拆分后，解码器按顺序还原所有数据包
+    #[test]
+    fn test_codec_split_stream(
+        (version, packets) in version().prop_flat_map(|version| (Just(version), vec(packet(version), 1..4))),
+        split in any::<prop::sample::Index>(),
+    ) {
+        let mut stream = BytesMut::new();
+        for packet in &packets {
+            packet.write_versioned(&mut stream, version);
+        }
+        let tail = stream.split_off(split.index(stream.len() + 1));
+
+        let mut codec = MqttCodec::with_version(version);
+        let mut decoded = Vec::new();
+        while let Some(packet) = codec.decode(&mut stream).unwrap() {
+            decoded.push(packet);
+        }
+        stream.unsplit(tail);
+        while let Some(packet) = codec.decode(&mut stream).unwrap() {
+            decoded.push(packet);
+        }
+        prop_assert_eq!(decoded, packets);
+        prop_assert!(stream.is_empty());
+    }
+
+    // 测试任意字节不会导致解析器panic，解析成功的数据包可以稳定地重新编码
+    #[test]
+    fn test_arbitrary_bytes_never_panic(data in vec(any::<u8>(), 0..256), version in version()) {
+        let mut buffer = BytesMut::from(&data[..]);
+        if let Ok(packet) = MqttPacket::read_versioned(&mut buffer, version) {
+            assert_stable(packet, version)?;
+        }
+    }
+
+    // 测试剩余长度正确的任意数据帧不会导致解析器panic
+    #[test]
+    fn test_arbitrary_frames_never_panic(mut frame in arbitrary_frame(), version in version()) {
+        let mut codec = MqttCodec::with_version(version);
+        let _ = codec.decode(&mut frame.clone());
+        if let Ok(packet) = MqttPacket::read_versioned(&mut frame, version) {
+            assert_stable(packet, version)?;
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use mqtt_adapt::protocol::{
+    AuthPacket, ConnAckPacket, ConnectPacket, ConnectReturnCode, DisconnectPacket, MqttCodec,
+    MqttPacket, Packet, Properties, ProtocolVersion, PubAckPacket, PubRelPacket, PublishPacket,
+    ReasonCode, SubAckPacket, SubscribePacket, UnsubAckPacket, UnsubscribePacket,
+};
+use bytes::{Bytes, BytesMut};
+use tokio_util::codec::Decoder;
+
+/// 按MQTT 5.0编码后再解码
+fn round_trip(packet: MqttPacket) -> MqttPacket {
+    let mut buffer = BytesMut::new();
+    packet.write_versioned(&mut buffer, ProtocolVersion::V5);
+
+    let mut codec = MqttCodec::with_version(ProtocolVersion::V5);
+    let decoded = codec.decode(&mut buffer).unwrap().unwrap();
+    assert!(buffer.is_empty());
+    decoded
+}
+
+fn user_properties() -> Properties {
+    Properties {
+        reason_string: Some("because".to_string()),
+        user_properties: vec![
+            ("k1".to_string(), "v1".to_string()),
+            ("k1".to_string(), "v2".to_string()),
+        ],
+        ..Default::default()
+    }
+}
+
+// 测试属性编解码往返
+#[test]
+fn test_properties_round_trip() {
+    let properties = Properties {
+        payload_format_indicator: Some(1),
+        message_expiry_interval: Some(3600),
+        content_type: Some("application/json".to_string()),
+        response_topic: Some("reply/to".to_string()),
+        correlation_data: Some(Bytes::from_static(b"\x01\x02")),
+        subscription_identifiers: vec![1, 268_435_455],
+        topic_alias: Some(5),
+        user_properties: vec![("a".to_string(), "b".to_string())],
+        ..Default::default()
+    };
+
+    let mut buffer = BytesMut::new();
+    properties.write(&mut buffer);
+    assert_eq!(buffer.len(), properties.encoded_len());
+
+    let parsed = Properties::parse(&mut buffer).unwrap();
+    assert_eq!(parsed, properties);
+    assert!(buffer.is_empty());
+}
+
+// 测试重复的单值属性和未知属性被拒绝
+#[test]
+fn test_properties_reject_duplicate_and_unknown() {
+    // 长度6：两次Topic Alias
+    let mut buffer = BytesMut::from(&[0x06, 0x23, 0x00, 0x01, 0x23, 0x00, 0x02][..]);
+    assert!(Properties::parse(&mut buffer).is_err());
+
+    let mut buffer = BytesMut::from(&[0x02, 0x7F, 0x00][..]);
+    assert!(Properties::parse(&mut buffer).is_err());
+}
+
+// 测试MQTT 5.0 CONNECT携带连接属性和遗嘱属性
+#[test]
+fn test_connect_v5_round_trip() {
+    let connect = ConnectPacket {
+        protocol_name: "MQTT".to_string(),
+        protocol_level: 5,
+        connect_flags: 0x02 | 0x04 | 0x80 | 0x40,
+        keep_alive: 30,
+        client_id: "v5_client".to_string(),
+        will_topic: Some("will/topic".to_string()),
+        will_message: Some(Bytes::from_static(b"bye")),
+        username: Some("user".to_string()),
+        password: Some(Bytes::from_static(b"pass")),
+        properties: Properties {
+            session_expiry_interval: Some(120),
+            receive_maximum: Some(10),
+            topic_alias_maximum: Some(4),
+            ..Default::default()
+        },
+        will_properties: Properties {
+            will_delay_interval: Some(5),
+            content_type: Some("text/plain".to_string()),
+            ..Default::default()
+        },
+    };
+
+    let mut buffer = BytesMut::new();
+    connect.write(&mut buffer);
+    let decoded = MqttPacket::read(&mut buffer).unwrap();
+    assert_eq!(decoded, MqttPacket::Connect(connect));
+}
+
+// 测试MQTT 5.0 CONNACK使用原因码和属性
+#[test]
+fn test_connack_v5_round_trip() {
+    let mut connack = ConnAckPacket::new(true, ConnectReturnCode::Accepted);
+    connack.properties.assigned_client_identifier = Some("auto-1".to_string());
+    connack.properties.maximum_qos = Some(1);
+
+    let decoded = round_trip(MqttPacket::ConnAck(connack));
+    let MqttPacket::ConnAck(decoded) = decoded else { panic!("Expected CONNACK") };
+    assert!(decoded.session_present);
+    assert_eq!(decoded.reason_code(), ReasonCode::Success);
+    assert_eq!(decoded.properties.assigned_client_identifier.as_deref(), Some("auto-1"));
+    assert_eq!(decoded.properties.maximum_qos, Some(1));
+}
+
+// 测试MQTT 5.0 PUBLISH属性位于数据包ID之后
+#[test]
+fn test_publish_v5_round_trip() {
+    let publish = PublishPacket {
+        dup: false,
+        qos: 1,
+        retain: true,
+        topic_name: "a/b".to_string(),
+        packet_id: Some(42),
+        payload: Bytes::from_static(b"payload"),
+        properties: Properties {
+            message_expiry_interval: Some(60),
+            topic_alias: Some(3),
+            ..Default::default()
+        },
+    };
+
+    assert_eq!(round_trip(MqttPacket::Publish(publish.clone())), MqttPacket::Publish(publish));
+}
+
+// 测试MQTT 5.0确认包携带原因码和属性
+#[test]
+fn test_ack_v5_round_trip() {
+    let puback = PubAckPacket {
+        reason_code: ReasonCode::NoMatchingSubscribers,
+        properties: user_properties(),
+        ..PubAckPacket::new(9)
+    };
+    let expected = PubAckPacket {
+        reason_code: ReasonCode::NoMatchingSubscribers,
+        properties: user_properties(),
+        ..PubAckPacket::new(9)
+    };
+    assert_eq!(round_trip(MqttPacket::PubAck(puback)), MqttPacket::PubAck(expected));
+
+    let pubrel = PubRelPacket {
+        reason_code: ReasonCode::PacketIdentifierNotFound,
+        ..PubRelPacket::new(10)
+    };
+    let expected = PubRelPacket {
+        reason_code: ReasonCode::PacketIdentifierNotFound,
+        ..PubRelPacket::new(10)
+    };
+    assert_eq!(round_trip(MqttPacket::PubRel(pubrel)), MqttPacket::PubRel(expected));
+}
+
+// 测试成功且无属性的MQTT 5.0确认包使用2字节短格式
+#[test]
+fn test_ack_v5_short_form() {
+    let mut buffer = BytesMut::new();
+    PubAckPacket::new(7).write_versioned(&mut buffer, ProtocolVersion::V5);
+    assert_eq!(&buffer[..], &[0x40, 0x02, 0x00, 0x07]);
+
+    let mut codec = MqttCodec::with_version(ProtocolVersion::V5);
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(MqttPacket::PubAck(PubAckPacket::new(7))));
+}
+
+// 测试MQTT 5.0 SUBSCRIBE/SUBACK/UNSUBSCRIBE/UNSUBACK
+#[test]
+fn test_subscribe_family_v5_round_trip() {
+    let properties = Properties {
+        subscription_identifiers: vec![7],
+        ..Default::default()
+    };
+    let subscribe = SubscribePacket {
+        packet_id: 1,
+        topics: vec![("a/+".to_string(), 0x01 | 0x04)],
+        properties: properties.clone(),
+    };
+    let expected = SubscribePacket {
+        packet_id: 1,
+        topics: vec![("a/+".to_string(), 0x01 | 0x04)],
+        properties,
+    };
+    assert_eq!(round_trip(MqttPacket::Subscribe(subscribe)), MqttPacket::Subscribe(expected));
+
+    let suback = SubAckPacket {
+        packet_id: 1,
+        return_codes: vec![0x01, 0x80],
+        properties: user_properties(),
+    };
+    let expected = SubAckPacket {
+        packet_id: 1,
+        return_codes: vec![0x01, 0x80],
+        properties: user_properties(),
+    };
+    assert_eq!(round_trip(MqttPacket::SubAck(suback)), MqttPacket::SubAck(expected));
+
+    let unsubscribe = UnsubscribePacket {
+        packet_id: 2,
+        topics: vec!["a/+".to_string(), "b".to_string()],
+        properties: user_properties(),
+    };
+    let expected = UnsubscribePacket {
+        packet_id: 2,
+        topics: vec!["a/+".to_string(), "b".to_string()],
+        properties: user_properties(),
+    };
+    assert_eq!(round_trip(MqttPacket::Unsubscribe(unsubscribe)), MqttPacket::Unsubscribe(expected));
+
+    let unsuback = UnsubAckPacket {
+        reason_codes: vec![ReasonCode::Success, ReasonCode::NoSubscriptionExisted],
+        ..UnsubAckPacket::new(2)
+    };
+    let expected = UnsubAckPacket {
+        reason_codes: vec![ReasonCode::Success, ReasonCode::NoSubscriptionExisted],
+        ..UnsubAckPacket::new(2)
+    };
+    assert_eq!(round_trip(MqttPacket::UnsubAck(unsuback)), MqttPacket::UnsubAck(expected));
+}
+
+// 测试MQTT 5.0 DISCONNECT原因码，正常断开时省略可变头
+#[test]
+fn test_disconnect_v5() {
+    let mut buffer = BytesMut::new();
+    DisconnectPacket::default().write_versioned(&mut buffer, ProtocolVersion::V5);
+    assert_eq!(&buffer[..], &[0xE0, 0x00]);
+
+    let disconnect = DisconnectPacket::with_reason(ReasonCode::SessionTakenOver);
+    assert_eq!(
+        round_trip(MqttPacket::Disconnect(disconnect)),
+        MqttPacket::Disconnect(DisconnectPacket::with_reason(ReasonCode::SessionTakenOver))
+    );
+}
+
+// 测试AUTH数据包往返
+#[test]
+fn test_auth_round_trip() {
+    let auth = AuthPacket {
+        reason_code: ReasonCode::ContinueAuthentication,
+        properties: Properties {
+            authentication_method: Some("SCRAM-SHA-1".to_string()),
+            authentication_data: Some(Bytes::from_static(b"client-first")),
+            ..Default::default()
+        },
+    };
+    let expected = AuthPacket {
+        reason_code: ReasonCode::ContinueAuthentication,
+        properties: Properties {
+            authentication_method: Some("SCRAM-SHA-1".to_string()),
+            authentication_data: Some(Bytes::from_static(b"client-first")),
+            ..Default::default()
+        },
+    };
+    assert_eq!(round_trip(MqttPacket::Auth(auth)), MqttPacket::Auth(expected));
+}
+
+// 测试MQTT 3.1.1编码不包含属性
+#[test]
+fn test_v311_ignores_properties() {
+    let publish = PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: false,
+        topic_name: "t".to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(b"x"),
+        properties: Properties {
+            message_expiry_interval: Some(1),
+            ..Default::default()
+        },
+    };
+
+    let mut buffer = BytesMut::new();
+    publish.write(&mut buffer);
+    // 类型 + 剩余长度 + 主题长度(2) + 主题(1) + 载荷(1)
+    assert_eq!(buffer.len(), 6);
+}
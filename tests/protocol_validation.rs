@@ -0,0 +1,304 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use mqtt_adapt::auth::AnonymousAuthenticator;
+use mqtt_adapt::client::create_client_with_connect;
+use mqtt_adapt::config::BrokerConfig;
+use mqtt_adapt::protocol::{
+    ConnectPacket, ConnectReturnCode, FixedHeader, MqttCodec, MqttPacket, Packet, ProtocolError, ProtocolVersion, PublishPacket,
+    ReasonCode, SubscribePacket, UnsubscribePacket,
+};
+use mqtt_adapt::routing::router::MessageRouter;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::codec::Decoder;
+
+mod common;
+use common::{connect_packet, read_packet};
+
+/// 按给定的首字节和剩余部分构造完整数据包
+fn frame(first_byte: u8, body: &[u8]) -> BytesMut {
+    let mut buffer = BytesMut::new();
+    buffer.put_u8(first_byte);
+    buffer.put_u8(body.len() as u8);
+    buffer.put_slice(body);
+    buffer
+}
+
+fn connect(flags: u8, protocol_level: u8) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_u16(4);
+    body.put_slice(b"MQTT");
+    body.put_u8(protocol_level);
+    body.put_u8(flags);
+    body.put_u16(60);
+    if protocol_level == 5 {
+        body.put_u8(0);
+    }
+    body.put_u16(1);
+    body.put_slice(b"c");
+    if flags & 0x40 != 0 {
+        body.put_u16(2);
+        body.put_slice(b"pw");
+    }
+    body
+}
+
+// 测试固定头保留标志位：SUBSCRIBE/UNSUBSCRIBE/PUBREL必须为0x02，其余数据包必须为0
+#[test]
+fn test_fixed_header_reserved_flags() {
+    for first_byte in [0x80, 0x83, 0xA0, 0x60, 0xC1, 0xE8, 0x11] {
+        let mut buffer = frame(first_byte, &[]);
+        assert!(matches!(
+            FixedHeader::parse(&mut buffer).unwrap_err(),
+            ProtocolError::MalformedPacket(_)
+        ));
+    }
+    for first_byte in [0x82, 0xA2, 0x62, 0xC0, 0x3B] {
+        let mut buffer = frame(first_byte, &[]);
+        assert!(FixedHeader::parse(&mut buffer).is_ok());
+    }
+}
+
+// 测试PUBLISH的QoS不能为3
+#[test]
+fn test_publish_rejects_qos_3() {
+    let mut buffer = frame(0x36, &[0x00, 0x01, b't', 0x00, 0x01]);
+    let error = MqttPacket::read(&mut buffer).unwrap_err();
+    assert_eq!(error.reason_code(), ReasonCode::MalformedPacket);
+
+    let mut body = BytesMut::from(&[0x00, 0x01, b't', 0x00, 0x01][..]);
+    assert!(PublishPacket::parse(&mut body, Some(0x06)).is_err());
+}
+
+// 测试PUBLISH主题名不能包含通配符，QoS大于0时数据包ID不能为0
+#[test]
+fn test_publish_topic_and_packet_id() {
+    let mut buffer = frame(0x30, &[0x00, 0x03, b'a', b'/', b'#']);
+    assert!(matches!(
+        MqttPacket::read(&mut buffer).unwrap_err(),
+        ProtocolError::ProtocolViolation(_)
+    ));
+
+    let mut buffer = frame(0x32, &[0x00, 0x01, b't', 0x00, 0x00]);
+    assert_eq!(MqttPacket::read(&mut buffer).unwrap_err().reason_code(), ReasonCode::ProtocolError);
+
+    // MQTT 3.1.1的主题名不能为空，MQTT 5.0可以用主题别名代替
+    let mut buffer = frame(0x30, &[0x00, 0x00]);
+    assert!(MqttPacket::read(&mut buffer).is_err());
+    let mut buffer = frame(0x30, &[0x00, 0x00, 0x03, 0x23, 0x00, 0x01]);
+    assert!(MqttPacket::read_versioned(&mut buffer, ProtocolVersion::V5).is_ok());
+}
+
+// 测试非法UTF-8和U+0000被拒绝
+#[test]
+fn test_strings_reject_invalid_utf8_and_null() {
+    let mut buffer = frame(0x30, &[0x00, 0x02, 0xC3, 0x28]);
+    assert_eq!(MqttPacket::read(&mut buffer).unwrap_err(), ProtocolError::InvalidUtf8);
+
+    let mut buffer = frame(0x30, &[0x00, 0x03, b'a', 0x00, b'b']);
+    assert_eq!(MqttPacket::read(&mut buffer).unwrap_err(), ProtocolError::InvalidUtf8);
+
+    // 合法的多字节字符不受影响
+    let mut buffer = frame(0x30, &[0x00, 0x03, 0xE6, 0xB8, 0xA9]);
+    let Ok(MqttPacket::Publish(publish)) = MqttPacket::read(&mut buffer) else {
+        panic!("expected PUBLISH");
+    };
+    assert_eq!(publish.topic_name, "温");
+}
+
+// 测试CONNECT连接标志：保留位、没有遗嘱时的遗嘱QoS和保留位、遗嘱QoS 3、没有用户名的密码
+#[test]
+fn test_connect_flags() {
+    for flags in [0x03, 0x0A, 0x22, 0x1E, 0x42] {
+        let mut body = connect(flags, 4);
+        assert!(
+            matches!(ConnectPacket::parse(&mut body, None).unwrap_err(), ProtocolError::MalformedPacket(_)),
+            "flags 0x{:02X}",
+            flags
+        );
+    }
+
+    // MQTT 5.0允许只带密码
+    let mut body = connect(0x42, 5);
+    assert!(ConnectPacket::parse(&mut body, None).is_ok());
+}
+
+// 测试SUBSCRIBE/UNSUBSCRIBE至少包含一个过滤器，订阅选项的保留位和QoS 3被拒绝
+#[test]
+fn test_subscribe_validation() {
+    let mut buffer = frame(0x82, &[0x00, 0x01]);
+    assert!(matches!(
+        MqttPacket::read(&mut buffer).unwrap_err(),
+        ProtocolError::ProtocolViolation(_)
+    ));
+    let mut buffer = frame(0xA2, &[0x00, 0x01]);
+    assert!(MqttPacket::read(&mut buffer).is_err());
+
+    for options in [0x03, 0x04, 0x80] {
+        let mut body = BytesMut::from(&[0x00, 0x01, 0x00, 0x01, b't', options][..]);
+        assert!(SubscribePacket::parse(&mut body, None).is_err(), "options 0x{:02X}", options);
+    }
+
+    // MQTT 5.0的No Local、Retain As Published和Retain Handling有效，Retain Handling不能为3
+    let mut body = BytesMut::from(&[0x00, 0x01, 0x00, 0x00, 0x01, b't', 0x2D][..]);
+    let subscribe = SubscribePacket::parse_versioned(&mut body, None, ProtocolVersion::V5).unwrap();
+    assert_eq!(subscribe.topics, vec![("t".to_string(), 0x2D)]);
+    let mut body = BytesMut::from(&[0x00, 0x01, 0x00, 0x00, 0x01, b't', 0x31][..]);
+    assert!(SubscribePacket::parse_versioned(&mut body, None, ProtocolVersion::V5).is_err());
+
+    let mut body = BytesMut::from(&[0x00, 0x00, 0x00, 0x01, b't'][..]);
+    assert!(UnsubscribePacket::parse(&mut body, None).is_err());
+}
+
+// 测试没有可变头的数据包携带多余数据时被拒绝
+#[test]
+fn test_empty_packets_reject_payload() {
+    for first_byte in [0xC0, 0xD0, 0xE0] {
+        let mut buffer = frame(first_byte, &[0x00]);
+        assert!(MqttPacket::read(&mut buffer).is_err());
+    }
+}
+
+// 测试MQTT 5.0属性取值：布尔属性只能为0或1，接收最大值不能为0
+#[test]
+fn test_property_values() {
+    // PUBLISH属性：载荷格式指示为2
+    let mut buffer = frame(0x30, &[0x00, 0x01, b't', 0x02, 0x01, 0x02]);
+    assert!(MqttPacket::read_versioned(&mut buffer, ProtocolVersion::V5).is_err());
+
+    // CONNECT属性：接收最大值为0
+    let mut body = BytesMut::new();
+    body.put_u16(4);
+    body.put_slice(b"MQTT");
+    body.put_slice(&[5, 0x02, 0x00, 0x3C, 0x03, 0x21, 0x00, 0x00, 0x00, 0x01, b'c']);
+    let error = ConnectPacket::parse(&mut body, None).unwrap_err();
+    assert_eq!(error.reason_code(), ReasonCode::ProtocolError);
+}
+
+// 测试解码器对超长数据包返回PayloadTooLarge
+#[test]
+fn test_codec_payload_too_large() {
+    let mut codec = MqttCodec::new();
+    codec.set_max_packet_size(Some(16));
+    let mut buffer = frame(0x30, &[0u8; 20]);
+    let error = codec.decode(&mut buffer).unwrap_err().downcast::<ProtocolError>().unwrap();
+    assert_eq!(error, ProtocolError::PayloadTooLarge { size: 22, max: 16 });
+    assert_eq!(error.reason_code(), ReasonCode::PacketTooLarge);
+}
+
+// 测试MQTT 5.0客户端发送超长数据包时收到0x95 DISCONNECT后连接关闭
+#[tokio::test]
+async fn test_oversized_packet_disconnects_v5_client() {
+    let router = MessageRouter::new();
+    tokio::spawn(router.clone().start());
+    let mut config = BrokerConfig::default();
+    config.mqtt.max_packet_size = 64;
+
+    let (mut peer, socket) = tokio::io::duplex(4096);
+    let connect = ConnectPacket {
+        protocol_level: 5,
+        ..connect_packet("big", None, None)
+    };
+    let mut buffer = BytesMut::new();
+    MqttPacket::Connect(connect).write_versioned(&mut buffer, ProtocolVersion::V5);
+    peer.write_all(&buffer).await.unwrap();
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 50000));
+    let client = create_client_with_connect(socket, addr, &router, &AnonymousAuthenticator, &config, None, None)
+        .await
+        .unwrap();
+    let handle = tokio::spawn(client.handle());
+
+    let mut codec = MqttCodec::with_version(ProtocolVersion::V5);
+    let mut received = BytesMut::new();
+    let Some(MqttPacket::ConnAck(connack)) = read_packet(&mut peer, &mut codec, &mut received).await else {
+        panic!("expected CONNACK");
+    };
+    assert_eq!(connack.properties.maximum_packet_size, Some(64));
+
+    let publish = PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: false,
+        topic_name: "big".to_string(),
+        packet_id: None,
+        payload: Bytes::from(vec![0u8; 100]),
+        properties: Default::default(),
+    };
+    let mut buffer = BytesMut::new();
+    MqttPacket::Publish(publish).write_versioned(&mut buffer, ProtocolVersion::V5);
+    peer.write_all(&buffer).await.unwrap();
+
+    let Some(MqttPacket::Disconnect(disconnect)) = read_packet(&mut peer, &mut codec, &mut received).await else {
+        panic!("expected DISCONNECT");
+    };
+    assert_eq!(disconnect.reason_code, ReasonCode::PacketTooLarge);
+    let result = tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    assert!(result.is_err());
+    assert!(read_packet(&mut peer, &mut codec, &mut received).await.is_none());
+}
+
+// 测试数据不完整时返回Incomplete且不消费缓冲区，补齐数据后可以重新解析
+#[test]
+fn test_incomplete_packet() {
+    let mut buffer = BytesMut::new();
+    assert_eq!(FixedHeader::parse(&mut buffer).unwrap_err(), ProtocolError::Incomplete { needed: 1 });
+
+    let mut complete = frame(0x30, &[0x00, 0x01, b't', b'h', b'i']);
+    let mut buffer = complete.split_to(4);
+    assert_eq!(MqttPacket::read(&mut buffer).unwrap_err(), ProtocolError::Incomplete { needed: 3 });
+    assert_eq!(buffer.len(), 4);
+
+    buffer.unsplit(complete);
+    let Ok(MqttPacket::Publish(publish)) = MqttPacket::read(&mut buffer) else {
+        panic!("expected PUBLISH");
+    };
+    assert_eq!(publish.payload, Bytes::from_static(b"hi"));
+    assert!(buffer.is_empty());
+
+    // 完整数据包内部的数据不足是格式错误
+    let mut buffer = frame(0x40, &[0x00]);
+    assert!(matches!(MqttPacket::read(&mut buffer).unwrap_err(), ProtocolError::MalformedPacket(_)));
+}
+
+// 测试协议名与协议级别不匹配或级别不受支持时返回UnsupportedVersion
+#[test]
+fn test_unsupported_version() {
+    for (name, level) in [(&b"MQTT"[..], 6), (&b"MQTT"[..], 3), (&b"MQIsdp"[..], 4)] {
+        let mut body = BytesMut::new();
+        body.put_u16(name.len() as u16);
+        body.put_slice(name);
+        body.put_slice(&[level, 0x02, 0x00, 0x3C, 0x00, 0x01, b'c']);
+        let error = ConnectPacket::parse(&mut body, None).unwrap_err();
+        assert_eq!(error, ProtocolError::UnsupportedVersion(level));
+        assert_eq!(error.reason_code(), ReasonCode::UnsupportedProtocolVersion);
+    }
+}
+
+// 测试不支持的协议版本收到CONNACK 0x01，其余格式错误的CONNECT直接断开
+#[tokio::test]
+async fn test_connect_protocol_errors() {
+    let router = MessageRouter::new();
+    let addr = SocketAddr::from(([127, 0, 0, 1], 50000));
+    let config = BrokerConfig::default();
+
+    let (mut peer, socket) = tokio::io::duplex(4096);
+    let mut body = connect(0x02, 4);
+    body[6] = 6;
+    peer.write_all(&frame(0x10, &body)).await.unwrap();
+    let result = create_client_with_connect(socket, addr, &router, &AnonymousAuthenticator, &config, None, None).await;
+    assert!(result.is_err());
+    let mut codec = MqttCodec::new();
+    let mut received = BytesMut::new();
+    let Some(MqttPacket::ConnAck(connack)) = read_packet(&mut peer, &mut codec, &mut received).await else {
+        panic!("expected CONNACK");
+    };
+    assert_eq!(connack.return_code, ConnectReturnCode::RefusedBadProtocolVersion);
+
+    let (mut peer, socket) = tokio::io::duplex(4096);
+    peer.write_all(&frame(0x10, &connect(0x03, 4))).await.unwrap();
+    let result = create_client_with_connect(socket, addr, &router, &AnonymousAuthenticator, &config, None, None).await;
+    assert!(result.is_err());
+    let mut buffer = [0u8; 4];
+    assert_eq!(peer.read(&mut buffer).await.unwrap(), 0);
+}
@@ -0,0 +1,178 @@
+use mqtt_adapt::db::connection::DatabaseConnection;
+use mqtt_adapt::db::models::session::{OfflineMessage, Session};
+use mqtt_adapt::protocol::{MqttPacket, PublishPacket, SubscribePacket};
+use mqtt_adapt::routing::{event::Event, router::MessageRouter};
+use bytes::Bytes;
+use flume::{Receiver, unbounded};
+
+mod common;
+use common::temp_database;
+
+async fn subscribe(router: &MessageRouter, client_id: &str, rx: &Receiver<Event>, filter: &str, qos: u8) {
+    let subscribe_packet = SubscribePacket {
+        packet_id: 1,
+        topics: vec![(filter.to_string(), qos)],
+        properties: Default::default(),
+    };
+    let event = Event::MessageReceived(client_id.to_string(), MqttPacket::Subscribe(subscribe_packet));
+    router.handle_event(event).await;
+    let _ = rx.try_recv();
+}
+
+async fn publish(router: &MessageRouter, topic: &str, payload: &'static [u8]) {
+    let publish_packet = PublishPacket {
+        dup: false,
+        qos: 1,
+        retain: false,
+        topic_name: topic.to_string(),
+        packet_id: Some(1),
+        payload: Bytes::from_static(payload),
+        properties: Default::default(),
+    };
+    let event = Event::MessageReceived("publisher".to_string(), MqttPacket::Publish(publish_packet));
+    router.handle_event(event).await;
+}
+
+fn received_payloads(rx: &Receiver<Event>) -> Vec<Bytes> {
+    let mut payloads = Vec::new();
+    while let Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) = rx.try_recv() {
+        payloads.push(publish.payload);
+    }
+    payloads
+}
+
+async fn offline_count(db: &DatabaseConnection, client_id: &str) -> usize {
+    let session = Session::find_by_client_id(db.get_pool(), client_id).await.unwrap().unwrap();
+    OfflineMessage::find_by_session_id(db.get_pool(), session.id).await.unwrap().len()
+}
+
+// 测试持久会话离线期间的消息在重连后按顺序投递并从数据库删除
+#[tokio::test]
+async fn test_offline_messages_delivered_on_reconnect() {
+    let db = temp_database().await;
+    let router = MessageRouter::new().with_db(db.get_pool().clone());
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("publisher", p_tx).await.unwrap();
+
+    let (tx, rx) = unbounded();
+    assert!(!router.register_session("device", tx, false, None).await.unwrap().session_present);
+    subscribe(&router, "device", &rx, "cmd/device", 1).await;
+    router.handle_event(Event::ClientDisconnected("device".to_string())).await;
+
+    publish(&router, "cmd/device", b"first").await;
+    publish(&router, "cmd/device", b"second").await;
+    assert_eq!(offline_count(&db, "device").await, 2);
+
+    let (tx, rx) = unbounded();
+    assert!(router.register_session("device", tx, false, None).await.unwrap().session_present);
+    assert_eq!(received_payloads(&rx), vec![Bytes::from_static(b"first"), Bytes::from_static(b"second")]);
+    assert_eq!(offline_count(&db, "device").await, 0);
+}
+
+// 测试服务器重启后从数据库恢复订阅，并为离线客户端缓存消息
+#[tokio::test]
+async fn test_subscriptions_restored_after_restart() {
+    let db = temp_database().await;
+    {
+        let router = MessageRouter::new().with_db(db.get_pool().clone());
+        let (tx, rx) = unbounded();
+        router.register_session("device", tx, false, None).await.unwrap();
+        subscribe(&router, "device", &rx, "cmd/+", 1).await;
+        router.handle_event(Event::ClientDisconnected("device".to_string())).await;
+    }
+
+    // 新的路由器模拟服务器重启
+    let router = MessageRouter::new().with_db(db.get_pool().clone());
+    router.restore_sessions().await.unwrap();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("publisher", p_tx).await.unwrap();
+    publish(&router, "cmd/reboot", b"now").await;
+    assert_eq!(offline_count(&db, "device").await, 1);
+
+    let (tx, rx) = unbounded();
+    assert!(router.register_session("device", tx, false, None).await.unwrap().session_present);
+    assert_eq!(received_payloads(&rx), vec![Bytes::from_static(b"now")]);
+
+    // 恢复的订阅在线时同样生效
+    publish(&router, "cmd/led", b"on").await;
+    assert_eq!(received_payloads(&rx), vec![Bytes::from_static(b"on")]);
+}
+
+// 测试clean session连接丢弃之前的会话
+#[tokio::test]
+async fn test_clean_session_discards_state() {
+    let db = temp_database().await;
+    let router = MessageRouter::new().with_db(db.get_pool().clone());
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("publisher", p_tx).await.unwrap();
+
+    let (tx, rx) = unbounded();
+    router.register_session("device", tx, false, None).await.unwrap();
+    subscribe(&router, "device", &rx, "cmd/device", 1).await;
+    router.handle_event(Event::ClientDisconnected("device".to_string())).await;
+    publish(&router, "cmd/device", b"lost").await;
+
+    let (tx, rx) = unbounded();
+    assert!(!router.register_session("device", tx, true, None).await.unwrap().session_present);
+    assert!(received_payloads(&rx).is_empty());
+    assert_eq!(offline_count(&db, "device").await, 0);
+
+    // 订阅也已清除
+    publish(&router, "cmd/device", b"ignored").await;
+    assert!(received_payloads(&rx).is_empty());
+
+    // 非持久会话断开后再以持久会话连接，不报告session present
+    router.handle_event(Event::ClientDisconnected("device".to_string())).await;
+    let (tx, _rx) = unbounded();
+    assert!(!router.register_session("device", tx, false, None).await.unwrap().session_present);
+}
+
+// 测试不使用数据库时持久会话的离线消息保存在内存中
+#[tokio::test]
+async fn test_offline_messages_in_memory() {
+    let router = MessageRouter::new();
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("publisher", p_tx).await.unwrap();
+
+    let (tx, rx) = unbounded();
+    router.register_session("device", tx, false, None).await.unwrap();
+    subscribe(&router, "device", &rx, "cmd/device", 1).await;
+    router.handle_event(Event::ClientDisconnected("device".to_string())).await;
+    publish(&router, "cmd/device", b"queued").await;
+
+    let (tx, rx) = unbounded();
+    assert!(router.register_session("device", tx, false, None).await.unwrap().session_present);
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::Publish(publish))) => {
+            assert_eq!(publish.payload, Bytes::from_static(b"queued"));
+            // 离线期间从未发送过，不带DUP标志
+            assert!(!publish.dup);
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+}
+
+// 测试离线消息超过上限时丢弃最早的消息
+#[tokio::test]
+async fn test_offline_message_limit_drops_oldest() {
+    let db = temp_database().await;
+    let router = MessageRouter::new()
+        .with_db(db.get_pool().clone())
+        .with_max_offline_messages(2);
+    let (p_tx, _p_rx) = unbounded();
+    router.register_client("publisher", p_tx).await.unwrap();
+
+    let (tx, rx) = unbounded();
+    router.register_session("device", tx, false, None).await.unwrap();
+    subscribe(&router, "device", &rx, "cmd/device", 1).await;
+    router.handle_event(Event::ClientDisconnected("device".to_string())).await;
+
+    publish(&router, "cmd/device", b"first").await;
+    publish(&router, "cmd/device", b"second").await;
+    publish(&router, "cmd/device", b"third").await;
+    assert_eq!(offline_count(&db, "device").await, 2);
+
+    let (tx, rx) = unbounded();
+    router.register_session("device", tx, false, None).await.unwrap();
+    assert_eq!(received_payloads(&rx), vec![Bytes::from_static(b"second"), Bytes::from_static(b"third")]);
+}
@@ -0,0 +1,212 @@
+use bytes::Bytes;
+use flume::{Receiver, unbounded};
+use mqtt_adapt::protocol::{MqttPacket, PubAckPacket, PublishPacket, SubscribePacket};
+use mqtt_adapt::routing::shared::SharedStrategy;
+use mqtt_adapt::routing::{event::Event, router::MessageRouter};
+use mqtt_adapt::topic::{TopicManager, is_valid_topic_filter, parse_shared_subscription};
+
+const WORKERS: &str = "$share/workers/+/+/telemetry";
+
+fn publish_packet(topic: &str, qos: u8, packet_id: Option<u16>) -> PublishPacket {
+    PublishPacket {
+        dup: false,
+        qos,
+        retain: false,
+        topic_name: topic.to_string(),
+        packet_id,
+        payload: Bytes::from_static(b"data"),
+        properties: Default::default(),
+    }
+}
+
+async fn connect(router: &MessageRouter, client_id: &str) -> Receiver<Event> {
+    let (tx, rx) = unbounded();
+    router.register_client(client_id, tx).await.unwrap();
+    rx
+}
+
+async fn subscribe(router: &MessageRouter, client_id: &str, rx: &Receiver<Event>, filter: &str, qos: u8) {
+    let subscribe_packet = SubscribePacket {
+        packet_id: 1,
+        topics: vec![(filter.to_string(), qos)],
+        properties: Default::default(),
+    };
+    router
+        .handle_event(Event::MessageReceived(client_id.to_string(), MqttPacket::Subscribe(subscribe_packet)))
+        .await;
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::SubAck(suback))) => assert_eq!(suback.return_codes, vec![qos]),
+        other => panic!("Expected SUBACK, got {:?}", other),
+    }
+}
+
+async fn publish(router: &MessageRouter, publisher: &str, topic: &str, qos: u8) {
+    let packet_id = (qos > 0).then_some(1);
+    router
+        .handle_event(Event::MessageReceived(
+            publisher.to_string(),
+            MqttPacket::Publish(publish_packet(topic, qos, packet_id)),
+        ))
+        .await;
+}
+
+/// 取出客户端收到的PUBLISH
+fn received(rx: &Receiver<Event>) -> Vec<PublishPacket> {
+    rx.try_iter()
+        .filter_map(|event| match event {
+            Event::MessageSent(_, MqttPacket::Publish(publish)) => Some(publish),
+            _ => None,
+        })
+        .collect()
+}
+
+async fn workers(router: &MessageRouter, count: usize, qos: u8) -> Vec<Receiver<Event>> {
+    let mut receivers = Vec::new();
+    for index in 0..count {
+        let client_id = format!("worker-{}", index);
+        let rx = connect(router, &client_id).await;
+        subscribe(router, &client_id, &rx, WORKERS, qos).await;
+        receivers.push(rx);
+    }
+    receivers
+}
+
+// 测试共享订阅过滤器的解析和校验
+#[test]
+fn test_shared_subscription_filter() {
+    assert_eq!(parse_shared_subscription(WORKERS), Some(("workers", "+/+/telemetry")));
+    assert_eq!(parse_shared_subscription("sensors/#"), None);
+    assert!(is_valid_topic_filter(WORKERS));
+    assert!(is_valid_topic_filter("$share/g/#"));
+    assert!(!is_valid_topic_filter("$share/g"));
+    assert!(!is_valid_topic_filter("$share//a"));
+    assert!(!is_valid_topic_filter("$share/g+/a"));
+    assert!(!is_valid_topic_filter("$share/g/a/#/b"));
+}
+
+// 测试共享订阅不作为普通订阅匹配，取消订阅后移除成员
+#[tokio::test]
+async fn test_topic_manager_shared_groups() {
+    let mut topic_manager = TopicManager::new();
+    topic_manager.add_subscription("w1".to_string(), WORKERS.to_string(), 1).await;
+    topic_manager.add_subscription("w2".to_string(), WORKERS.to_string(), 1).await;
+    topic_manager.add_subscription("audit".to_string(), "$share/audit/#".to_string(), 0).await;
+    topic_manager.add_subscription("plain".to_string(), "+/+/telemetry".to_string(), 0).await;
+
+    let subscribers = topic_manager.find_subscribers("site/d1/telemetry").await;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].client_id, "plain");
+
+    let mut groups = topic_manager.find_shared_subscribers("site/d1/telemetry").await;
+    groups.sort_by_key(|members| members.len());
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0][0].topic, "$share/audit/#");
+    let members: Vec<&str> = groups[1].iter().map(|member| member.client_id.as_str()).collect();
+    assert_eq!(members, ["w1", "w2"]);
+
+    topic_manager.remove_subscription("w1".to_string(), WORKERS.to_string()).await;
+    assert_eq!(topic_manager.shared_members(WORKERS).len(), 1);
+    topic_manager.remove_client_subscriptions("w2").await;
+    assert!(topic_manager.shared_members(WORKERS).is_empty());
+    assert_eq!(topic_manager.find_shared_subscribers("site/d1/telemetry").await.len(), 1);
+}
+
+// 测试轮询策略下每条消息只投递给组内一个成员，不同组和普通订阅各自收到
+#[tokio::test]
+async fn test_round_robin() {
+    let router = MessageRouter::new();
+    let receivers = workers(&router, 3, 0).await;
+    let audit = connect(&router, "audit").await;
+    subscribe(&router, "audit", &audit, "$share/audit/+/+/telemetry", 0).await;
+    let plain = connect(&router, "plain").await;
+    subscribe(&router, "plain", &plain, "+/+/telemetry", 0).await;
+
+    for _ in 0..6 {
+        publish(&router, "d1", "site/d1/telemetry", 0).await;
+    }
+    for rx in &receivers {
+        assert_eq!(received(rx).len(), 2);
+    }
+    assert_eq!(received(&audit).len(), 6);
+    assert_eq!(received(&plain).len(), 6);
+}
+
+// 测试随机策略和粘性策略
+#[tokio::test]
+async fn test_random_and_sticky() {
+    let router = MessageRouter::new().with_shared_strategy(SharedStrategy::Random);
+    let receivers = workers(&router, 3, 0).await;
+    for _ in 0..30 {
+        publish(&router, "d1", "site/d1/telemetry", 0).await;
+    }
+    let counts: Vec<usize> = receivers.iter().map(|rx| received(rx).len()).collect();
+    assert_eq!(counts.iter().sum::<usize>(), 30);
+
+    // 同一发布者的消息总是交给同一成员
+    let router = MessageRouter::new().with_shared_strategy(SharedStrategy::Sticky);
+    let receivers = workers(&router, 3, 0).await;
+    for _ in 0..5 {
+        publish(&router, "d1", "site/d1/telemetry", 0).await;
+    }
+    let counts: Vec<usize> = receivers.iter().map(|rx| received(rx).len()).collect();
+    assert!(counts.contains(&5), "Expected one member to receive all messages, got {:?}", counts);
+}
+
+// 测试最少在途策略选择未确认消息最少的成员
+#[tokio::test]
+async fn test_least_inflight() {
+    let router = MessageRouter::new().with_shared_strategy(SharedStrategy::LeastInflight);
+    let receivers = workers(&router, 2, 1).await;
+
+    publish(&router, "d1", "site/d1/telemetry", 1).await;
+    publish(&router, "d1", "site/d1/telemetry", 1).await;
+    let first = received(&receivers[0]);
+    let second = received(&receivers[1]);
+    assert_eq!((first.len(), second.len()), (1, 1));
+
+    // worker-0确认后负载更低，下一条消息交给它
+    let ack = PubAckPacket::new(first[0].packet_id.unwrap());
+    router
+        .handle_event(Event::MessageReceived("worker-0".to_string(), MqttPacket::PubAck(ack)))
+        .await;
+    publish(&router, "d1", "site/d1/telemetry", 1).await;
+    assert_eq!(received(&receivers[0]).len(), 1);
+    assert!(received(&receivers[1]).is_empty());
+}
+
+// 测试选中的成员断开时转投其他成员，未确认的消息在会话结束后转投
+#[tokio::test]
+async fn test_redelivery_to_other_member() {
+    let router = MessageRouter::new();
+    let mut receivers = workers(&router, 2, 1).await;
+
+    // worker-0的连接已断开但路由器尚未处理关闭事件
+    drop(receivers.remove(0));
+    for _ in 0..2 {
+        publish(&router, "d1", "site/d1/telemetry", 1).await;
+    }
+    assert_eq!(received(&receivers[0]).len(), 2);
+
+    // worker-1收到消息后未确认就断开，消息转投重新加入的worker-0
+    let rx = connect(&router, "worker-0").await;
+    subscribe(&router, "worker-0", &rx, WORKERS, 1).await;
+    router.remove_client("worker-1").await;
+    let redelivered = received(&rx);
+    assert_eq!(redelivered.len(), 2);
+    assert!(redelivered.iter().all(|publish| !publish.dup && publish.qos == 1));
+    assert_eq!(router.inflight_len("worker-0").await, 2);
+}
+
+// 测试共享订阅不接收保留消息
+#[tokio::test]
+async fn test_no_retained_for_shared_subscription() {
+    let router = MessageRouter::new();
+    let mut retained = publish_packet("site/d1/telemetry", 0, None);
+    retained.retain = true;
+    router
+        .handle_event(Event::MessageReceived("d1".to_string(), MqttPacket::Publish(retained)))
+        .await;
+
+    let receivers = workers(&router, 1, 0).await;
+    assert!(received(&receivers[0]).is_empty());
+}
@@ -0,0 +1,201 @@
+use bytes::Bytes;
+use flume::{Receiver, unbounded};
+use mqtt_adapt::protocol::{MqttPacket, PublishPacket, SubscribePacket};
+use mqtt_adapt::routing::{event::Event, router::MessageRouter};
+use mqtt_adapt::stats::{BROKER_VERSION, LoadAverage, SysPublisher};
+use mqtt_adapt::topic::{TopicManager, topic_matches};
+use std::collections::HashMap;
+use std::time::Duration;
+
+async fn connect(router: &MessageRouter, client_id: &str) -> Receiver<Event> {
+    let (tx, rx) = unbounded();
+    router.register_client(client_id, tx).await.unwrap();
+    rx
+}
+
+async fn subscribe(router: &MessageRouter, client_id: &str, filter: &str) {
+    let subscribe_packet = SubscribePacket {
+        packet_id: 1,
+        topics: vec![(filter.to_string(), 0)],
+        properties: Default::default(),
+    };
+    router
+        .handle_event(Event::MessageReceived(client_id.to_string(), MqttPacket::Subscribe(subscribe_packet)))
+        .await;
+}
+
+/// 取出客户端收到的PUBLISH，按主题索引负载
+fn received(rx: &Receiver<Event>) -> HashMap<String, String> {
+    rx.try_iter()
+        .filter_map(|event| match event {
+            Event::MessageSent(_, MqttPacket::Publish(publish)) => {
+                Some((publish.topic_name, String::from_utf8_lossy(&publish.payload).into_owned()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// 测试$SYS主题的发布内容和保留标志
+#[tokio::test]
+async fn test_sys_publisher() {
+    let router = MessageRouter::new();
+    let rx = connect(&router, "monitor").await;
+    subscribe(&router, "monitor", "$SYS/#").await;
+    let _other = connect(&router, "sensor").await;
+    subscribe(&router, "sensor", "sensors/#").await;
+
+    let mut publisher = SysPublisher::new(router.clone());
+    publisher.publish().await;
+
+    let topics = received(&rx);
+    assert_eq!(topics["$SYS/broker/version"], BROKER_VERSION);
+    assert_eq!(topics["$SYS/broker/clients/connected"], "2");
+    assert_eq!(topics["$SYS/broker/clients/total"], "2");
+    assert_eq!(topics["$SYS/broker/subscriptions/count"], "2");
+    assert!(topics["$SYS/broker/uptime"].ends_with(" seconds"));
+    assert_eq!(topics["$SYS/broker/load/publish/received/1min"], "0.00");
+    assert!(topics.contains_key("$SYS/broker/bytes/sent"));
+    assert!(topics.contains_key("$SYS/broker/retained messages/count"));
+
+    // 之后订阅的客户端立即收到上一次发布的保留值
+    let late = connect(&router, "late").await;
+    subscribe(&router, "late", "$SYS/broker/clients/+").await;
+    let topics = received(&late);
+    assert_eq!(topics["$SYS/broker/clients/connected"], "2");
+    assert_eq!(topics.len(), 2);
+    // $SYS主题的保留消息不计入保留消息数
+    assert_eq!(router.retained_count().await, 0);
+}
+
+// 测试第一层的通配符不匹配$开头的主题
+#[tokio::test]
+async fn test_wildcards_skip_system_topics() {
+    let mut topic_manager = TopicManager::new();
+    topic_manager.add_subscription("all".to_string(), "#".to_string(), 0).await;
+    topic_manager.add_subscription("plus".to_string(), "+/broker/uptime".to_string(), 0).await;
+    topic_manager.add_subscription("sys".to_string(), "$SYS/#".to_string(), 0).await;
+    topic_manager.add_subscription("sys_plus".to_string(), "$SYS/+/uptime".to_string(), 0).await;
+
+    let subscribers: Vec<String> = topic_manager
+        .find_subscribers("$SYS/broker/uptime")
+        .await
+        .into_iter()
+        .map(|subscription| subscription.client_id)
+        .collect();
+    assert_eq!(subscribers.len(), 2);
+    assert!(subscribers.contains(&"sys".to_string()));
+    assert!(subscribers.contains(&"sys_plus".to_string()));
+
+    assert_eq!(topic_manager.find_subscribers("app/broker/uptime").await.len(), 2);
+    assert_eq!(topic_manager.subscription_count(), 4);
+
+    assert!(topic_matches("#", "a/b"));
+    assert!(topic_matches("a/#", "a"));
+    assert!(!topic_matches("#", "$SYS/broker"));
+    assert!(!topic_matches("+/broker", "$SYS/broker"));
+    assert!(topic_matches("$SYS/+", "$SYS/broker"));
+    assert!(!topic_matches("a/+", "a/b/c"));
+}
+
+// 测试通配符过滤器取得保留消息的实际主题
+#[tokio::test]
+async fn test_retained_wildcard_lookup() {
+    let mut topic_manager = TopicManager::new();
+    for topic in ["sensors", "sensors/1/temp", "sensors/2/temp", "sensors/2/humidity", "$SYS/broker/uptime"] {
+        topic_manager
+            .store_retained_message(topic.to_string(), Bytes::from_static(b"v"), 0)
+            .await;
+    }
+
+    let topics = |messages: Vec<(String, _)>| {
+        let mut topics: Vec<String> = messages.into_iter().map(|(topic, _)| topic).collect();
+        topics.sort();
+        topics
+    };
+    assert_eq!(
+        topics(topic_manager.get_retained_messages("sensors/+/temp").await),
+        ["sensors/1/temp", "sensors/2/temp"]
+    );
+    assert_eq!(
+        topics(topic_manager.get_retained_messages("sensors/#").await),
+        ["sensors", "sensors/1/temp", "sensors/2/humidity", "sensors/2/temp"]
+    );
+    assert_eq!(topic_manager.get_retained_messages("#").await.len(), 4);
+    assert_eq!(topic_manager.get_retained_messages("+/+/uptime").await.len(), 0);
+    assert_eq!(
+        topics(topic_manager.get_retained_messages("$SYS/#").await),
+        ["$SYS/broker/uptime"]
+    );
+}
+
+// 测试发布速率的指数加权平均
+#[test]
+fn test_load_average() {
+    let mut load = LoadAverage::default();
+    load.update(60, Duration::from_secs(60));
+    // 一分钟窗口更快接近当前速率
+    assert!(load.one > load.five && load.five > load.fifteen);
+    assert!((load.one - 60.0 * (1.0 - (-1.0f64).exp())).abs() < 1e-9);
+
+    for _ in 0..120 {
+        load.update(60, Duration::from_secs(60));
+    }
+    assert!((load.fifteen - 60.0).abs() < 0.1);
+
+    let before = load;
+    load.update(10, Duration::ZERO);
+    assert_eq!(load, before);
+}
+
+// 测试服务器作为发布者发布的消息
+#[tokio::test]
+async fn test_broker_publish() {
+    let router = MessageRouter::new();
+    let rx = connect(&router, "c1").await;
+    subscribe(&router, "c1", "notice").await;
+    router
+        .publish(PublishPacket {
+            dup: false,
+            qos: 1,
+            retain: false,
+            topic_name: "notice".to_string(),
+            packet_id: Some(7),
+            payload: Bytes::from_static(b"hello"),
+            properties: Default::default(),
+        })
+        .await;
+    assert_eq!(received(&rx)["notice"], "hello");
+    assert_eq!(router.client_counts().await, (1, 1));
+}
+
+// 测试$SYS主题的保留消息不占用保留消息数上限
+#[tokio::test]
+async fn test_sys_topics_exempt_from_retained_limit() {
+    let router = MessageRouter::new().with_max_retained_messages(1);
+    let retain = |topic: &str| PublishPacket {
+        dup: false,
+        qos: 0,
+        retain: true,
+        topic_name: topic.to_string(),
+        packet_id: None,
+        payload: Bytes::from_static(b"v"),
+        properties: Default::default(),
+    };
+    router.publish(retain("sensors/1")).await;
+    assert_eq!(router.retained_count().await, 1);
+
+    // 用户主题已达上限时统计主题仍被保留
+    let mut publisher = SysPublisher::new(router.clone());
+    publisher.publish().await;
+    let rx = connect(&router, "monitor").await;
+    subscribe(&router, "monitor", "$SYS/broker/retained messages/count").await;
+    assert_eq!(received(&rx)["$SYS/broker/retained messages/count"], "1");
+    assert_eq!(router.retained_count().await, 1);
+
+    // 统计主题不占名额，新的用户主题仍受上限约束
+    router.publish(retain("sensors/2")).await;
+    let rx = connect(&router, "late").await;
+    subscribe(&router, "late", "sensors/#").await;
+    assert_eq!(received(&rx).len(), 1);
+}
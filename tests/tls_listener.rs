@@ -0,0 +1,169 @@
+use bytes::BytesMut;
+use mqtt_adapt::client::{Client, create_client_with_connect};
+use mqtt_adapt::config::{BrokerConfig, CertUsername, TlsConfig};
+use mqtt_adapt::protocol::{ConnAckPacket, ConnectReturnCode, MqttCodec, MqttPacket};
+use mqtt_adapt::routing::router::MessageRouter;
+use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair, SanType};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+use tokio_util::codec::Decoder;
+
+mod common;
+use common::{connect_packet, temp_database};
+
+/// 测试用的自签名CA、服务器证书和客户端证书
+struct TestPki {
+    dir: PathBuf,
+    ca: CertificateDer<'static>,
+    client_cert: CertificateDer<'static>,
+    client_key: Vec<u8>,
+}
+
+impl TestPki {
+    fn generate() -> Self {
+        let dir = std::env::temp_dir().join(format!("mqtt_adapt_tls_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.distinguished_name.push(DnType::CommonName, "mqtt_adapt test ca");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let mut server_params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        server_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        let server = server_params.signed_by(&server_key, &ca, &ca_key).unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        client_params.distinguished_name.push(DnType::CommonName, "device-42");
+        client_params.subject_alt_names = vec![SanType::Rfc822Name("device42@example.com".try_into().unwrap())];
+        client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        std::fs::write(dir.join("server.pem"), server.pem()).unwrap();
+        std::fs::write(dir.join("server.key"), server_key.serialize_pem()).unwrap();
+
+        Self {
+            dir,
+            ca: ca.der().clone(),
+            client_cert: client.der().clone(),
+            client_key: client_key.serialize_der(),
+        }
+    }
+
+    fn tls_config(&self, mutual: bool, cert_username: CertUsername) -> TlsConfig {
+        TlsConfig {
+            cert: self.dir.join("server.pem"),
+            key: self.dir.join("server.key"),
+            client_ca: mutual.then(|| self.dir.join("ca.pem")),
+            require_client_cert: true,
+            cert_username,
+        }
+    }
+
+    fn connector(&self, with_client_cert: bool) -> TlsConnector {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(self.ca.clone()).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = if with_client_cert {
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(self.client_key.clone()));
+            builder.with_client_auth_cert(vec![self.client_cert.clone()], key).unwrap()
+        } else {
+            builder.with_no_client_auth()
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+}
+
+impl Drop for TestPki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// 在TLS监听器上接受一个连接，返回服务器端创建的客户端和客户端收到的CONNACK
+async fn tls_connect(pki: &TestPki, tls: TlsConfig, with_client_cert: bool) -> (anyhow::Result<Client>, Option<ConnAckPacket>) {
+    let db = temp_database().await;
+    let router = MessageRouter::new();
+    let acceptor = mqtt_adapt::tls::build_acceptor(&tls).unwrap();
+    let config = BrokerConfig::default();
+    let authenticator = mqtt_adapt::auth::from_config(&config.auth, Some(&db)).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (socket, addr) = listener.accept().await.unwrap();
+        let stream = acceptor.accept(socket).await?;
+        let username = mqtt_adapt::tls::peer_username(stream.get_ref().1.peer_certificates(), tls.cert_username);
+        create_client_with_connect(stream, addr, &router, authenticator.as_ref(), &config, username, None).await
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let connack = match pki.connector(with_client_cert).connect(server_name, tcp).await {
+        Ok(mut stream) => {
+            let mut buffer = BytesMut::new();
+            MqttPacket::Connect(connect_packet("tls-device", None, None)).write(&mut buffer);
+            let written = stream.write_all(&buffer).await;
+            let mut received = BytesMut::new();
+            match written {
+                Ok(()) if stream.read_buf(&mut received).await.unwrap_or(0) > 0 => {
+                    match MqttCodec::new().decode(&mut received).unwrap() {
+                        Some(MqttPacket::ConnAck(connack)) => Some(connack),
+                        other => panic!("Expected CONNACK, got {:?}", other),
+                    }
+                }
+                _ => None,
+            }
+        }
+        Err(_) => None,
+    };
+    (server.await.unwrap(), connack)
+}
+
+// 测试TLS监听器上的CONNECT仍按用户名密码认证
+#[tokio::test]
+async fn test_tls_without_client_cert_uses_password_auth() {
+    let pki = TestPki::generate();
+    let (client, connack) = tls_connect(&pki, pki.tls_config(false, CertUsername::None), false).await;
+    assert!(client.is_err());
+    assert_eq!(connack.unwrap().return_code, ConnectReturnCode::RefusedBadUsernameOrPassword);
+}
+
+// 测试双向TLS时以客户端证书的CN作为用户名
+#[tokio::test]
+async fn test_mutual_tls_maps_common_name() {
+    let pki = TestPki::generate();
+    let (client, connack) = tls_connect(&pki, pki.tls_config(true, CertUsername::Cn), true).await;
+    assert_eq!(connack.unwrap().return_code, ConnectReturnCode::Accepted);
+    assert_eq!(client.unwrap().username(), Some("device-42"));
+}
+
+// 测试双向TLS时以客户端证书的SAN作为用户名
+#[tokio::test]
+async fn test_mutual_tls_maps_subject_alt_name() {
+    let pki = TestPki::generate();
+    let (client, connack) = tls_connect(&pki, pki.tls_config(true, CertUsername::San), true).await;
+    assert_eq!(connack.unwrap().return_code, ConnectReturnCode::Accepted);
+    assert_eq!(client.unwrap().username(), Some("device42@example.com"));
+}
+
+// 测试要求客户端证书时拒绝不带证书的连接
+#[tokio::test]
+async fn test_mutual_tls_rejects_missing_client_cert() {
+    let pki = TestPki::generate();
+    let (client, connack) = tls_connect(&pki, pki.tls_config(true, CertUsername::Cn), false).await;
+    assert!(client.is_err());
+    assert!(connack.is_none());
+}
@@ -0,0 +1,289 @@
+use bytes::Bytes;
+use flume::{Receiver, unbounded};
+use bytes::BytesMut;
+use mqtt_adapt::auth::acl::{Acl, AclAction, AclPermission, AclRule, GROUP_ATTRIBUTE};
+use mqtt_adapt::auth::{ClientAttributes, SqliteAuthenticator};
+use mqtt_adapt::client::create_client_with_connect;
+use mqtt_adapt::config::BrokerConfig;
+use mqtt_adapt::db::models::acl_rule::AclRuleRecord;
+use mqtt_adapt::db::models::ag_user::User;
+use mqtt_adapt::protocol::{ConnectReturnCode, MqttCodec, MqttPacket, PublishPacket, ReasonCode, SubscribePacket};
+use mqtt_adapt::routing::{event::Event, router::MessageRouter};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+mod common;
+use common::{connect_packet, read_packet, temp_database};
+
+fn rule(permission: AclPermission, action: AclAction, topic: &str) -> AclRule {
+    AclRule {
+        permission,
+        username: None,
+        client_id: None,
+        group: None,
+        action,
+        topic: topic.to_string(),
+    }
+}
+
+fn user(username: &str) -> ClientAttributes {
+    ClientAttributes::with_username(Some(username.to_string()))
+}
+
+/// 设备只能访问自己的主题，其余默认拒绝
+fn device_acl() -> Acl {
+    Acl::new(
+        vec![
+            rule(AclPermission::Allow, AclAction::Both, "devices/%c/#"),
+            rule(AclPermission::Allow, AclAction::Subscribe, "users/%u/inbox"),
+        ],
+        AclPermission::Deny,
+    )
+}
+
+// 测试%c和%u替换
+#[test]
+fn test_acl_substitution() {
+    let acl = device_acl();
+    let alice = user("alice");
+    assert_eq!(acl.check("d1", &alice, AclAction::Publish, "devices/d1/telemetry"), AclPermission::Allow);
+    assert_eq!(acl.check("d1", &alice, AclAction::Subscribe, "devices/d1/+"), AclPermission::Allow);
+    assert_eq!(acl.check("d1", &alice, AclAction::Publish, "devices/d2/telemetry"), AclPermission::Deny);
+    // 订阅范围超出自己的主题
+    assert_eq!(acl.check("d1", &alice, AclAction::Subscribe, "devices/+/telemetry"), AclPermission::Deny);
+    assert_eq!(acl.check("d1", &alice, AclAction::Subscribe, "users/alice/inbox"), AclPermission::Allow);
+    assert_eq!(acl.check("d1", &alice, AclAction::Publish, "users/alice/inbox"), AclPermission::Deny);
+
+    // 没有用户名或客户端ID含有通配符时替换规则不适用
+    let anonymous = ClientAttributes::default();
+    assert_eq!(acl.check("d1", &anonymous, AclAction::Subscribe, "users//inbox"), AclPermission::Deny);
+    assert_eq!(acl.check("+", &alice, AclAction::Subscribe, "devices/+/telemetry"), AclPermission::Deny);
+    assert_eq!(acl.check("#", &alice, AclAction::Publish, "devices/#"), AclPermission::Deny);
+}
+
+// 测试拒绝规则对有交集的订阅生效，第一层通配符不匹配$主题
+#[test]
+fn test_acl_deny_and_system_topics() {
+    let acl = Acl::new(
+        vec![
+            rule(AclPermission::Deny, AclAction::Both, "secret/#"),
+            rule(AclPermission::Allow, AclAction::Both, "#"),
+        ],
+        AclPermission::Deny,
+    );
+    let attributes = ClientAttributes::default();
+    assert_eq!(acl.check("c", &attributes, AclAction::Subscribe, "#"), AclPermission::Deny);
+    assert_eq!(acl.check("c", &attributes, AclAction::Subscribe, "+/key"), AclPermission::Deny);
+    assert_eq!(acl.check("c", &attributes, AclAction::Subscribe, "public/#"), AclPermission::Allow);
+    assert_eq!(acl.check("c", &attributes, AclAction::Publish, "secret"), AclPermission::Deny);
+    assert_eq!(acl.check("c", &attributes, AclAction::Publish, "$SYS/broker/uptime"), AclPermission::Deny);
+
+    let acl = Acl::new(vec![rule(AclPermission::Allow, AclAction::Subscribe, "$SYS/#")], AclPermission::Deny);
+    assert_eq!(acl.check("c", &attributes, AclAction::Subscribe, "$SYS/broker/+"), AclPermission::Allow);
+}
+
+// 测试按用户名、客户端ID和用户组匹配
+#[test]
+fn test_acl_subjects() {
+    let mut admin = rule(AclPermission::Allow, AclAction::Both, "#");
+    admin.username = Some("admin".to_string());
+    let mut backend = rule(AclPermission::Allow, AclAction::Subscribe, "+/+/telemetry");
+    backend.group = Some("backend".to_string());
+    let mut gateway = rule(AclPermission::Allow, AclAction::Publish, "gateway/#");
+    gateway.client_id = Some("gw-1".to_string());
+    let acl = Acl::new(vec![admin, backend, gateway], AclPermission::Deny);
+
+    assert_eq!(acl.check("x", &user("admin"), AclAction::Publish, "any/topic"), AclPermission::Allow);
+    assert_eq!(acl.check("x", &user("bob"), AclAction::Publish, "any/topic"), AclPermission::Deny);
+
+    let mut worker = user("worker");
+    worker.attributes.insert(GROUP_ATTRIBUTE.to_string(), "ops, backend".to_string());
+    assert_eq!(acl.check("w", &worker, AclAction::Subscribe, "a/b/telemetry"), AclPermission::Allow);
+    assert_eq!(acl.check("w", &worker, AclAction::Publish, "a/b/telemetry"), AclPermission::Deny);
+
+    assert_eq!(acl.check("gw-1", &user("bob"), AclAction::Publish, "gateway/status"), AclPermission::Allow);
+    assert_eq!(acl.check("gw-2", &user("bob"), AclAction::Publish, "gateway/status"), AclPermission::Deny);
+}
+
+fn publish_packet(topic: &str, qos: u8, packet_id: Option<u16>) -> PublishPacket {
+    PublishPacket {
+        dup: false,
+        qos,
+        retain: false,
+        topic_name: topic.to_string(),
+        packet_id,
+        payload: Bytes::from_static(b"data"),
+        properties: Default::default(),
+    }
+}
+
+async fn connect(router: &MessageRouter, client_id: &str, username: &str) -> Receiver<Event> {
+    let (tx, rx) = unbounded();
+    router.register_client(client_id, tx).await.unwrap();
+    router.set_client_attributes(client_id, user(username)).await;
+    rx
+}
+
+// 测试订阅被拒绝时SUBACK返回0x80
+#[tokio::test]
+async fn test_subscribe_denied() {
+    let router = MessageRouter::new().with_acl(device_acl());
+    let rx = connect(&router, "d1", "alice").await;
+
+    let subscribe_packet = SubscribePacket {
+        packet_id: 7,
+        topics: vec![("devices/d1/cmd".to_string(), 1), ("devices/d2/cmd".to_string(), 1)],
+        properties: Default::default(),
+    };
+    router
+        .handle_event(Event::MessageReceived("d1".to_string(), MqttPacket::Subscribe(subscribe_packet)))
+        .await;
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::SubAck(suback))) => assert_eq!(suback.return_codes, vec![1, 0x80]),
+        other => panic!("Expected SUBACK, got {:?}", other),
+    }
+}
+
+// 测试发布被拒绝时丢弃消息并以原因码0x87确认
+#[tokio::test]
+async fn test_publish_denied() {
+    let router = MessageRouter::new().with_acl(Acl::new(
+        vec![
+            rule(AclPermission::Allow, AclAction::Subscribe, "#"),
+            rule(AclPermission::Allow, AclAction::Publish, "devices/%c/#"),
+        ],
+        AclPermission::Deny,
+    ));
+    let subscriber_rx = connect(&router, "observer", "ops").await;
+    let subscribe_packet = SubscribePacket {
+        packet_id: 1,
+        topics: vec![("devices/#".to_string(), 1)],
+        properties: Default::default(),
+    };
+    router
+        .handle_event(Event::MessageReceived("observer".to_string(), MqttPacket::Subscribe(subscribe_packet)))
+        .await;
+    let _ = subscriber_rx.try_recv();
+
+    let rx = connect(&router, "d1", "alice").await;
+    let denied = publish_packet("devices/d2/cmd", 1, Some(5));
+    router.handle_event(Event::MessageReceived("d1".to_string(), MqttPacket::Publish(denied))).await;
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::PubAck(puback))) => {
+            assert_eq!(puback.packet_id, 5);
+            assert_eq!(puback.reason_code, ReasonCode::NotAuthorized);
+        }
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    assert!(subscriber_rx.try_recv().is_err());
+
+    let denied = publish_packet("devices/d2/cmd", 2, Some(6));
+    router.handle_event(Event::MessageReceived("d1".to_string(), MqttPacket::Publish(denied))).await;
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::PubRec(pubrec))) => assert_eq!(pubrec.reason_code, ReasonCode::NotAuthorized),
+        other => panic!("Expected PUBREC, got {:?}", other),
+    }
+
+    let allowed = publish_packet("devices/d1/telemetry", 1, Some(8));
+    router.handle_event(Event::MessageReceived("d1".to_string(), MqttPacket::Publish(allowed))).await;
+    match rx.try_recv() {
+        Ok(Event::MessageSent(_, MqttPacket::PubAck(puback))) => assert_eq!(puback.reason_code, ReasonCode::Success),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    assert!(matches!(subscriber_rx.try_recv(), Ok(Event::MessageSent(_, MqttPacket::Publish(_)))));
+
+    // 未授权的遗嘱同样被丢弃
+    let will = publish_packet("devices/d2/status", 0, None);
+    router.publish_will("d1", 0, will, Duration::ZERO).await;
+    assert!(subscriber_rx.try_recv().is_err());
+}
+
+// 测试从配置文件和数据库加载规则
+#[tokio::test]
+async fn test_acl_rules_from_config_and_database() {
+    let config = BrokerConfig::from_toml_str(
+        r##"
+        [acl]
+        no_match = "deny"
+        database = true
+
+        [[acl.rules]]
+        permission = "allow"
+        username = "admin"
+        topic = "#"
+
+        [[acl.rules]]
+        permission = "allow"
+        action = "publish"
+        topic = "devices/%c/#"
+        "##,
+    )
+    .unwrap();
+    assert_eq!(config.acl.rules.len(), 2);
+    assert_eq!(config.acl.rules[0].action, AclAction::Both);
+    assert!(BrokerConfig::from_toml_str("[[acl.rules]]\npermission = \"allow\"\ntopic = \"a/#/b\"").is_err());
+
+    let db = temp_database().await;
+    let mut staff = rule(AclPermission::Allow, AclAction::Subscribe, "users/%u/#");
+    staff.group = Some("staff".to_string());
+    let record = AclRuleRecord::create(db.get_pool(), &staff).await.unwrap();
+    assert_eq!(record.action, "subscribe");
+    let rules = AclRuleRecord::load_rules(db.get_pool()).await.unwrap();
+    assert_eq!(rules, vec![staff]);
+
+    assert!(AclRuleRecord::delete(db.get_pool(), record.id).await.unwrap());
+    assert!(AclRuleRecord::load_rules(db.get_pool()).await.unwrap().is_empty());
+}
+
+/// 用密码登录并订阅一个过滤器，返回SUBACK中的返回码
+async fn login_and_subscribe(router: &MessageRouter, authenticator: &SqliteAuthenticator, username: &str, filter: &str) -> u8 {
+    let (mut peer, socket) = tokio::io::duplex(4096);
+    let mut buffer = BytesMut::new();
+    MqttPacket::Connect(connect_packet(username, Some(username), Some("secret"))).write(&mut buffer);
+    let subscribe = SubscribePacket {
+        packet_id: 1,
+        topics: vec![(filter.to_string(), 0)],
+        properties: Default::default(),
+    };
+    MqttPacket::Subscribe(subscribe).write(&mut buffer);
+    peer.write_all(&buffer).await.unwrap();
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 50000));
+    let client = create_client_with_connect(socket, addr, router, authenticator, &BrokerConfig::default(), None, None)
+        .await
+        .unwrap();
+    tokio::spawn(client.handle());
+
+    let mut codec = MqttCodec::new();
+    let mut received = BytesMut::new();
+    let Some(MqttPacket::ConnAck(connack)) = read_packet(&mut peer, &mut codec, &mut received).await else {
+        panic!("Expected CONNACK");
+    };
+    assert_eq!(connack.return_code, ConnectReturnCode::Accepted);
+    let Some(MqttPacket::SubAck(suback)) = read_packet(&mut peer, &mut codec, &mut received).await else {
+        panic!("Expected SUBACK");
+    };
+    suback.return_codes[0]
+}
+
+// 测试数据库用户的组经认证写入客户端属性，按组限定的规则对该用户生效
+#[tokio::test]
+async fn test_group_rules_from_database_users() {
+    let db = temp_database().await;
+    let pool = db.get_pool();
+    User::create(pool, "alice", "secret").await.unwrap();
+    User::create(pool, "bob", "secret").await.unwrap();
+    assert!(User::set_groups(pool, "alice", &["ops".to_string(), "staff".to_string()]).await.unwrap());
+    assert!(!User::set_groups(pool, "nobody", &["staff".to_string()]).await.unwrap());
+    assert!(User::set_groups(pool, "bob", &["a,b".to_string()]).await.is_err());
+
+    let mut staff = rule(AclPermission::Allow, AclAction::Subscribe, "reports/#");
+    staff.group = Some("staff".to_string());
+    let router = MessageRouter::new().with_acl(Acl::new(vec![staff], AclPermission::Deny));
+    tokio::spawn(router.clone().start());
+    let authenticator = SqliteAuthenticator::new(pool.clone());
+
+    assert_eq!(login_and_subscribe(&router, &authenticator, "alice", "reports/#").await, 0);
+    assert_eq!(login_and_subscribe(&router, &authenticator, "bob", "reports/#").await, 0x80);
+}
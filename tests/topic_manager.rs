@@ -190,3 +190,17 @@ async fn test_subscription_edge_cases() {
     topic_manager.remove_subscription("client1".to_string(), "non_existent_topic".to_string()).await;
     // 应该不会崩溃
 }
+
+// 测试订阅主题过滤器校验
+#[test]
+fn test_topic_filter_validation() {
+    use mqtt_adapt::topic::is_valid_topic_filter;
+
+    for filter in ["a", "a/b", "#", "a/#", "+", "+/+", "a/+/c", "/", "a//b", "+/#"] {
+        assert!(is_valid_topic_filter(filter), "{} should be valid", filter);
+    }
+
+    for filter in ["", "#/a", "a/#/b", "a#", "a/b#", "a+", "a/+b/c", "++", "##"] {
+        assert!(!is_valid_topic_filter(filter), "{} should be invalid", filter);
+    }
+}
@@ -0,0 +1,75 @@
+use mqtt_adapt::db::connection::DatabaseConnection;
+use mqtt_adapt::db::models::ag_user::User;
+
+mod common;
+use common::temp_database;
+
+// 测试创建用户时只保存密码哈希
+#[tokio::test]
+async fn test_create_user_stores_hash() {
+    let db = temp_database().await;
+    let pool = db.get_pool();
+    let user = User::create(pool, "alice", "secret").await.unwrap();
+    assert!(user.password.starts_with("$argon2id$"));
+    assert!(!user.password.contains("secret"));
+
+    let mut stored = User::find_by_username(pool, "alice").await.unwrap().unwrap();
+    assert_eq!(stored.id, user.id);
+    assert!(!stored.disabled);
+    assert!(stored.verify(pool, "secret").await.unwrap());
+    assert!(!stored.verify(pool, "wrong").await.unwrap());
+
+    // 用户名唯一
+    assert!(User::create(pool, "alice", "other").await.is_err());
+}
+
+// 测试修改密码、禁用、列出和删除用户
+#[tokio::test]
+async fn test_user_management() {
+    let db = temp_database().await;
+    let pool = db.get_pool();
+    User::create(pool, "bob", "old").await.unwrap();
+    User::create(pool, "alice", "secret").await.unwrap();
+
+    assert!(User::set_password(pool, "bob", "new").await.unwrap());
+    let mut bob = User::find_by_username(pool, "bob").await.unwrap().unwrap();
+    assert!(bob.verify(pool, "new").await.unwrap());
+    assert!(!bob.verify(pool, "old").await.unwrap());
+
+    assert!(User::set_disabled(pool, "bob", true).await.unwrap());
+    let users = User::list(pool).await.unwrap();
+    let names: Vec<_> = users.iter().map(|user| (user.username.as_str(), user.disabled)).collect();
+    assert_eq!(names, vec![("alice", false), ("bob", true)]);
+
+    assert!(User::delete(pool, "bob").await.unwrap());
+    assert!(User::find_by_username(pool, "bob").await.unwrap().is_none());
+
+    // 不存在的用户
+    assert!(!User::delete(pool, "bob").await.unwrap());
+    assert!(!User::set_password(pool, "bob", "x").await.unwrap());
+    assert!(!User::set_disabled(pool, "bob", false).await.unwrap());
+}
+
+// 测试为早期没有disabled和group_names列的users表补充这些列
+#[tokio::test]
+async fn test_legacy_users_table_migrated() {
+    let path = std::env::temp_dir().join(format!("mqtt_adapt_users_{}.db", uuid::Uuid::new_v4()));
+    let url = format!("sqlite://{}?mode=rwc", path.display());
+    {
+        let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+        sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT NOT NULL UNIQUE, password TEXT NOT NULL, created_at TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO users (username, password) VALUES ('legacy', 'secret')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+    }
+
+    let db = DatabaseConnection::new(&url).await.unwrap();
+    let user = User::find_by_username(db.get_pool(), "legacy").await.unwrap().unwrap();
+    assert!(!user.disabled);
+    assert!(user.group_names.is_empty());
+}
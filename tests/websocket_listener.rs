@@ -0,0 +1,116 @@
+use bytes::{Bytes, BytesMut};
+use futures_util::{SinkExt, StreamExt};
+use mqtt_adapt::auth::AnonymousAuthenticator;
+use mqtt_adapt::client::create_client_with_connect;
+use mqtt_adapt::config::{BrokerConfig, WebSocketConfig};
+use mqtt_adapt::protocol::{ConnectReturnCode, MqttCodec, MqttPacket, PingReqPacket};
+use mqtt_adapt::routing::router::MessageRouter;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_util::codec::Decoder;
+
+mod common;
+use common::connect_packet;
+
+fn encode(packet: MqttPacket) -> Bytes {
+    let mut buffer = BytesMut::new();
+    packet.write(&mut buffer);
+    buffer.freeze()
+}
+
+/// 启动一个只接受一次连接的WebSocket监听器，匿名认证后运行客户端处理循环
+async fn spawn_listener(path: &str) -> (std::net::SocketAddr, JoinHandle<anyhow::Result<()>>) {
+    let router = MessageRouter::new();
+    let config = BrokerConfig::default();
+    let websocket = WebSocketConfig { path: path.to_string() };
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (socket, addr) = listener.accept().await?;
+        let stream = mqtt_adapt::websocket::accept(socket, &websocket).await?;
+        let client = create_client_with_connect(stream, addr, &router, &AnonymousAuthenticator, &config, None, None).await?;
+        client.handle().await
+    });
+    (addr, server)
+}
+
+async fn ws_connect(
+    addr: std::net::SocketAddr,
+    path: &str,
+    subprotocol: Option<&str>,
+) -> Result<WebSocketStream<TcpStream>, WsError> {
+    let mut request = format!("ws://{}{}", addr, path).into_client_request().unwrap();
+    if let Some(subprotocol) = subprotocol {
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_str(subprotocol).unwrap());
+    }
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, response) = tokio_tungstenite::client_async(request, tcp).await?;
+    assert_eq!(response.headers()["Sec-WebSocket-Protocol"], "mqtt");
+    Ok(stream)
+}
+
+/// 读取下一个二进制帧并解码为一个MQTT数据包
+async fn next_packet(stream: &mut WebSocketStream<TcpStream>) -> MqttPacket {
+    match stream.next().await {
+        Some(Ok(Message::Binary(data))) => MqttCodec::new().decode(&mut BytesMut::from(&data[..])).unwrap().unwrap(),
+        other => panic!("Expected binary frame, got {:?}", other),
+    }
+}
+
+// 测试跨多个二进制帧的MQTT数据包能正确拼接，响应以二进制帧返回
+#[tokio::test]
+async fn test_packets_split_across_frames() {
+    let (addr, _server) = spawn_listener("/mqtt").await;
+    let mut stream = ws_connect(addr, "/mqtt", Some("mqtt")).await.unwrap();
+
+    let connect = encode(MqttPacket::Connect(connect_packet("ws-device", None, None)));
+    let (head, tail) = connect.split_at(5);
+    stream.send(Message::binary(head.to_vec())).await.unwrap();
+    stream.send(Message::binary(tail.to_vec())).await.unwrap();
+    match next_packet(&mut stream).await {
+        MqttPacket::ConnAck(connack) => assert_eq!(connack.return_code, ConnectReturnCode::Accepted),
+        other => panic!("Expected CONNACK, got {:?}", other),
+    }
+
+    // 连接建立后由客户端处理循环读取，PINGREQ逐字节分帧发送
+    for byte in encode(MqttPacket::PingReq(PingReqPacket)) {
+        stream.send(Message::binary(vec![byte])).await.unwrap();
+    }
+    assert!(matches!(next_packet(&mut stream).await, MqttPacket::PingResp(_)));
+}
+
+// 测试客户端提供多个子协议时选择mqtt
+#[tokio::test]
+async fn test_selects_mqtt_subprotocol() {
+    let (addr, _server) = spawn_listener("/mqtt").await;
+    assert!(ws_connect(addr, "/mqtt", Some("wamp, mqtt")).await.is_ok());
+}
+
+// 测试配置路径以外的握手请求返回404
+#[tokio::test]
+async fn test_rejects_unknown_path() {
+    let (addr, server) = spawn_listener("/mqtt").await;
+    match ws_connect(addr, "/other", Some("mqtt")).await {
+        Err(WsError::Http(response)) => assert_eq!(response.status(), 404),
+        other => panic!("Expected HTTP error, got {:?}", other.map(|_| ())),
+    }
+    assert!(server.await.unwrap().is_err());
+}
+
+// 测试未提供mqtt子协议的握手被拒绝
+#[tokio::test]
+async fn test_rejects_missing_subprotocol() {
+    let (addr, server) = spawn_listener("/mqtt").await;
+    match ws_connect(addr, "/mqtt", None).await {
+        Err(WsError::Http(response)) => assert_eq!(response.status(), 400),
+        other => panic!("Expected HTTP error, got {:?}", other.map(|_| ())),
+    }
+    assert!(server.await.unwrap().is_err());
+}